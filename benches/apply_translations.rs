@@ -0,0 +1,45 @@
+//! Times [`apply_translations`] on a flat object with 5,000 keys.
+//!
+//! Run with `cargo bench --bench apply_translations`.
+
+use q_translate::filter::KeyFilter;
+use q_translate::utils::apply_translations;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::Instant;
+
+const KEYS: usize = 5_000;
+const RUNS: u32 = 20;
+
+fn main() {
+    let source: Map<String, Value> = (0..KEYS)
+        .map(|i| (format!("key{i}"), Value::from(format!("Phrase {i}"))))
+        .collect();
+    let source = Value::Object(source);
+    let translations: HashMap<String, String> = (0..KEYS)
+        .map(|i| (format!("Phrase {i}"), format!("Satz {i}")))
+        .collect();
+    let filter = KeyFilter::default();
+
+    // Every other key is already translated, the rest is filled in.
+    let existing: Map<String, Value> = (0..KEYS)
+        .step_by(2)
+        .map(|i| (format!("key{i}"), Value::from(format!("Satz {i}"))))
+        .collect();
+
+    for (name, target) in [
+        ("empty target", Map::new()),
+        ("half filled target", existing),
+    ] {
+        let start = Instant::now();
+
+        for _ in 0..RUNS {
+            let mut target = target.clone();
+            apply_translations(&source, &mut target, &translations, false, &filter);
+            black_box(target);
+        }
+
+        println!("{name}: {:?} per run", start.elapsed() / RUNS);
+    }
+}
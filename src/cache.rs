@@ -0,0 +1,110 @@
+use crate::files::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+/// Default location of the on-disk translation cache.
+pub const DEFAULT_CACHE_PATH: &str = ".q-translate-cache.json";
+
+/// Persistent cache of translated phrases keyed by `(source_phrase, target_lang)`.
+///
+/// The cache is stored as a JSON object mapping each target language to an
+/// object of `source phrase -> translation` pairs:
+///
+/// ```json
+/// {
+///   "de": { "Save": "Speichern" },
+///   "pl": { "Save": "Zapisz" }
+/// }
+/// ```
+///
+/// Entries are kept sorted so the cache file produces stable diffs.
+///
+/// The entries are guarded by a mutex, so a single cache can be shared by
+/// reference between concurrent runs, such as the target languages of
+/// [`translate_targets`], and is saved once they are all done.
+///
+/// [`translate_targets`]: crate::pipeline::translate_targets
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TranslationCache {
+    entries: Mutex<BTreeMap<String, BTreeMap<String, String>>>,
+}
+
+impl Clone for TranslationCache {
+    fn clone(&self) -> Self {
+        Self {
+            entries: Mutex::new(self.entries().clone()),
+        }
+    }
+}
+
+impl TranslationCache {
+    /// Loads the cache from `path`, returning an empty cache if the file does
+    /// not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but cannot be read or is not a
+    /// valid cache file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        if !fs::exists(path)? {
+            return Ok(Self::default());
+        }
+
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Atomically writes the cache to `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cache file cannot be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        write_atomic(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Returns the cached translation of `phrase` into `target_lang`.
+    pub fn get(&self, phrase: &str, target_lang: &str) -> Option<String> {
+        self.entries().get(target_lang)?.get(phrase).cloned()
+    }
+
+    /// Stores the translation of `phrase` into `target_lang`.
+    pub fn insert(&self, phrase: String, target_lang: &str, translation: String) {
+        self.entries()
+            .entry(target_lang.to_string())
+            .or_default()
+            .insert(phrase, translation);
+    }
+
+    /// Removes the translations into `target_lang` and returns them as a
+    /// cache of their own. Use [`TranslationCache::merge`] to add them back.
+    pub fn split_off(&mut self, target_lang: &str) -> Self {
+        let entries = self
+            .entries()
+            .remove_entry(target_lang)
+            .into_iter()
+            .collect();
+        Self {
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Adds all translations of `other`, replacing existing ones.
+    pub fn merge(&mut self, other: Self) {
+        let mut entries = self.entries();
+        for (target_lang, translations) in other.entries.into_inner().unwrap() {
+            entries.entry(target_lang).or_default().extend(translations);
+        }
+    }
+
+    /// Locks the entries for reading or writing.
+    fn entries(&self) -> MutexGuard<'_, BTreeMap<String, BTreeMap<String, String>>> {
+        self.entries.lock().unwrap()
+    }
+}
@@ -0,0 +1,94 @@
+use crate::placeholders::Placeholders;
+
+/// Casing of a source phrase that is carried over to its translation, see
+/// [`match_case`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Casing {
+    /// Every letter is uppercase, e.g. `SAVE CHANGES`.
+    Upper,
+    /// Every word starts with an uppercase letter, e.g. `Save Changes` or
+    /// `Save`.
+    Title,
+    /// Any other casing, or no letters with a case at all.
+    Other,
+}
+
+impl Casing {
+    /// Detects the casing of `phrase`.
+    ///
+    /// A phrase needs at least two cased letters to count as uppercase, so a
+    /// single capital such as `A` is title case. Words not starting with a
+    /// letter, such as numbers, are ignored, while a lowercase word like the
+    /// `of` in `Terms of Service` makes the phrase [`Casing::Other`].
+    pub fn of(phrase: &str) -> Self {
+        let cased = phrase.chars().filter(|c| c.is_uppercase() || c.is_lowercase());
+        let (upper, lower) = cased.fold((0, 0), |(upper, lower), c| match c.is_uppercase() {
+            true => (upper + 1, lower),
+            false => (upper, lower + 1),
+        });
+        if upper > 1 && lower == 0 {
+            return Casing::Upper;
+        }
+
+        let initials: Vec<char> = phrase
+            .split_whitespace()
+            .filter_map(|word| word.chars().next())
+            .filter(|c| c.is_alphabetic())
+            .collect();
+        match !initials.is_empty() && initials.iter().all(|c| c.is_uppercase()) {
+            true => Casing::Title,
+            false => Casing::Other,
+        }
+    }
+
+    /// Applies the casing to `text`. Title case only uppercases the first
+    /// letter of every word and keeps the rest of it as it is.
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            Casing::Upper => text.to_uppercase(),
+            Casing::Title => {
+                let mut titled = String::with_capacity(text.len());
+                let mut word_start = true;
+                for c in text.chars() {
+                    match word_start {
+                        true => titled.extend(c.to_uppercase()),
+                        false => titled.push(c),
+                    }
+                    word_start = c.is_whitespace();
+                }
+                titled
+            }
+            Casing::Other => text.to_string(),
+        }
+    }
+}
+
+/// Gives `translated` the casing of its source `phrase`: uppercase if the
+/// phrase is all uppercase, title case if it is in title case, and unchanged
+/// otherwise. The placeholders of `translated` keep their casing.
+///
+/// The case mapping is that of Unicode, not of the target language: `ß`
+/// becomes `SS` and the Turkish `i` becomes `I` rather than `İ`. Languages
+/// without case, such as Japanese, are left unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::casing::match_case;
+/// use q_translate::placeholders::Placeholders;
+///
+/// let placeholders = Placeholders::default();
+///
+/// assert_eq!(match_case(&placeholders, "{count} FILES", "{count} dateien"), "{count} DATEIEN");
+/// assert_eq!(match_case(&placeholders, "Save File", "datei speichern"), "Datei Speichern");
+/// assert_eq!(match_case(&placeholders, "Save file", "Datei speichern"), "Datei speichern");
+/// ```
+pub fn match_case(placeholders: &Placeholders, phrase: &str, translated: &str) -> String {
+    let casing = Casing::of(&placeholders.protect(phrase).stripped());
+    if casing == Casing::Other {
+        return translated.to_string();
+    }
+
+    let protected = placeholders.protect(translated);
+    protected.restore(&casing.apply(&protected.text))
+}
@@ -0,0 +1,85 @@
+use crate::error::QTranslateError;
+use crate::providers::Provider;
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::num::NonZeroU16;
+use std::path::{Path, PathBuf};
+
+/// Name of the configuration file looked up by [`Config::discover`].
+pub const CONFIG_FILE: &str = "q-translate.toml";
+
+/// Project defaults read from a `q-translate.toml` file.
+///
+/// Every field is optional. Values given on the command line take precedence
+/// over the configuration file, which in turn takes precedence over the
+/// built-in defaults.
+///
+/// # Examples
+///
+/// ```toml
+/// provider = "deepl"
+/// api_key_env = "MY_DEEPL_KEY"
+/// assets_dir = "web/i18n"
+/// concurrency = 8
+/// batch_size = 25
+/// target_langs = ["de", "pl", "fr"]
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Translation backend.
+    pub provider: Option<Provider>,
+    /// Environment variable holding the API key, instead of the backend's default.
+    pub api_key_env: Option<String>,
+    /// Directory containing the locale files.
+    pub assets_dir: Option<String>,
+    /// Maximum number of requests in flight at the same time.
+    pub concurrency: Option<NonZeroU16>,
+    /// Maximum number of phrases per request, capped at the backend's limit.
+    pub batch_size: Option<NonZeroU16>,
+    /// Target language codes.
+    pub target_langs: Option<Vec<String>>,
+}
+
+impl Config {
+    /// Reads the configuration file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a valid
+    /// configuration.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, QTranslateError> {
+        let path = path.as_ref();
+
+        toml::from_str(&fs::read_to_string(path)?).map_err(|source| {
+            QTranslateError::InvalidConfig {
+                path: path.display().to_string(),
+                source,
+            }
+        })
+    }
+
+    /// Looks for [`CONFIG_FILE`] in the current directory and then in `$HOME`,
+    /// and reads the first one found. Without a configuration file, every
+    /// setting is left unset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a configuration file exists but cannot be read or
+    /// parsed.
+    pub fn discover() -> Result<Self, QTranslateError> {
+        let candidates = [
+            Some(PathBuf::from(CONFIG_FILE)),
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(CONFIG_FILE)),
+        ];
+
+        for path in candidates.into_iter().flatten() {
+            if fs::exists(&path)? {
+                return Self::load(path);
+            }
+        }
+
+        Ok(Self::default())
+    }
+}
@@ -0,0 +1,188 @@
+use crate::filter::KeyFilter;
+use crate::placeholders::Protected;
+use crate::providers::{BatchContext, BatchResult, TranslationProvider};
+use crate::utils::source_strings;
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Hints passed to the providers about what the phrases are used for, e.g. to
+/// tell the verb "Post" from the noun.
+///
+/// Only providers returning `true` from
+/// [`TranslationProvider::supports_context`] make use of the hints; for the
+/// others they are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TranslationContext {
+    description: Option<String>,
+    /// Notes by source phrase.
+    notes: HashMap<String, String>,
+}
+
+impl TranslationContext {
+    /// Creates a context describing the whole project, e.g. `"mobile banking
+    /// app UI"`.
+    pub fn new(description: Option<String>) -> Self {
+        Self {
+            description,
+            notes: HashMap::new(),
+        }
+    }
+
+    /// Adds the notes of `key_notes`, mapping key paths of `source` to notes
+    /// on their strings. Phrases occurring under several key paths receive
+    /// all of their distinct notes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use q_translate::context::TranslationContext;
+    /// use serde_json::json;
+    /// use std::collections::HashMap;
+    ///
+    /// let source = json!({"feed": {"post": "Post"}, "title": "Welcome"});
+    /// let key_notes = HashMap::from([("feed.post".to_string(), "verb".to_string())]);
+    ///
+    /// let context = TranslationContext::new(None).with_key_notes(&source, &key_notes);
+    ///
+    /// assert_eq!(context.note("Post"), Some("verb"));
+    /// assert_eq!(context.note("Welcome"), None);
+    /// ```
+    pub fn with_key_notes(mut self, source: &Value, key_notes: &HashMap<String, String>) -> Self {
+        for (key_path, phrase) in source_strings(source, &KeyFilter::default()) {
+            let Some(key_note) = key_notes.get(&key_path) else {
+                continue;
+            };
+
+            match self.notes.get_mut(phrase) {
+                Some(note) if note.split("; ").any(|n| n == key_note) => {}
+                Some(note) => {
+                    note.push_str("; ");
+                    note.push_str(key_note);
+                }
+                None => {
+                    self.notes.insert(phrase.to_string(), key_note.clone());
+                }
+            }
+        }
+        self
+    }
+
+    /// Description of the whole project, if any.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Note on the source `phrase`, if any.
+    pub fn note(&self, phrase: &str) -> Option<&str> {
+        self.notes.get(phrase).map(String::as_str)
+    }
+}
+
+/// Reads a file mapping key paths to context notes, a JSON object such as
+/// `{"feed.post": "verb, publishes a post"}`.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or is not a JSON object of
+/// strings.
+pub fn read_key_notes(path: impl AsRef<Path>) -> io::Result<HashMap<String, String>> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Provider passing a [`TranslationContext`] to
+/// [`TranslationProvider::translate_batch_with_context`] of the wrapped
+/// provider.
+///
+/// Notes are looked up by the protected text actually sent, which may stand
+/// for several source phrases.
+pub(crate) struct InContext<'a> {
+    provider: &'a dyn TranslationProvider,
+    description: Option<&'a str>,
+    notes: HashMap<String, &'a str>,
+    /// The [`TranslationProvider::fallback`] of `provider`, wrapped alike.
+    fallback: Option<Box<InContext<'a>>>,
+}
+
+impl<'a> InContext<'a> {
+    /// Wraps `provider`, resolving the notes of `context` for the `protected`
+    /// texts, which map to the source phrases they were protected from.
+    pub(crate) fn new(
+        provider: &'a dyn TranslationProvider,
+        context: &'a TranslationContext,
+        protected: &HashMap<String, Vec<(String, Protected)>>,
+    ) -> Self {
+        let notes = protected
+            .iter()
+            .filter_map(|(text, originals)| {
+                originals
+                    .iter()
+                    .find_map(|(phrase, _)| context.note(phrase))
+                    .map(|note| (text.clone(), note))
+            })
+            .collect();
+
+        Self::wrap(provider, context.description(), notes)
+    }
+
+    /// Wraps `provider` and every fallback behind it.
+    fn wrap(
+        provider: &'a dyn TranslationProvider,
+        description: Option<&'a str>,
+        notes: HashMap<String, &'a str>,
+    ) -> Self {
+        let fallback = provider
+            .fallback()
+            .map(|fallback| Box::new(Self::wrap(fallback, description, notes.clone())));
+
+        Self {
+            provider,
+            description,
+            notes,
+            fallback,
+        }
+    }
+}
+
+#[async_trait]
+impl TranslationProvider for InContext<'_> {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> BatchResult {
+        let context = BatchContext {
+            description: self.description,
+            notes: phrases
+                .iter()
+                .map(|phrase| self.notes.get(phrase).copied())
+                .collect(),
+        };
+
+        self.provider
+            .translate_batch_with_context(phrases, source_lang, target_lang, &context)
+            .await
+    }
+
+    fn max_batch_size(&self) -> usize {
+        self.provider.max_batch_size()
+    }
+
+    fn max_chars_per_request(&self) -> usize {
+        self.provider.max_chars_per_request()
+    }
+
+    fn supports_context(&self) -> bool {
+        true
+    }
+
+    fn fallback(&self) -> Option<&dyn TranslationProvider> {
+        self.fallback
+            .as_deref()
+            .map(|fallback| fallback as &dyn TranslationProvider)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::layout::{DEFAULT_DELIMITER, flatten};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// A key whose value differs between two versions of a locale file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KeyChange {
+    /// Key path, joined with [`DEFAULT_DELIMITER`].
+    pub key: String,
+    /// Value before the change, `None` for added keys.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    /// Value after the change, `None` for removed keys.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+/// Keys added, changed and removed between two versions of a locale file,
+/// in the order they appear in the respective version.
+///
+/// Its [`Display`](fmt::Display) implementation prints one line per key,
+/// marked with `+`, `~` or `-`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TargetDiff {
+    pub added: Vec<KeyChange>,
+    pub changed: Vec<KeyChange>,
+    pub removed: Vec<KeyChange>,
+}
+
+impl TargetDiff {
+    /// Compares `before` and `after` key path by key path; nested objects are
+    /// compared by their leaves, arrays as a whole.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use q_translate::diff::TargetDiff;
+    /// use serde_json::json;
+    ///
+    /// let before = json!({"home": {"title": "Start"}, "old": "Alt"});
+    /// let after = json!({"home": {"title": "Anfang", "intro": "Hallo"}});
+    /// let diff = TargetDiff::new(
+    ///     before.as_object().unwrap().clone(),
+    ///     after.as_object().unwrap().clone(),
+    /// );
+    ///
+    /// assert_eq!(
+    ///     diff.to_string(),
+    ///     "+ home.intro: \"Hallo\"\n~ home.title: \"Start\" -> \"Anfang\"\n- old: \"Alt\"\n"
+    /// );
+    /// ```
+    pub fn new(before: Map<String, Value>, after: Map<String, Value>) -> Self {
+        let mut before = flatten(before, DEFAULT_DELIMITER);
+        let mut diff = Self::default();
+
+        for (key, after) in flatten(after, DEFAULT_DELIMITER) {
+            match before.shift_remove(&key) {
+                None => diff.added.push(KeyChange {
+                    key,
+                    before: None,
+                    after: Some(after),
+                }),
+                Some(before) if before != after => diff.changed.push(KeyChange {
+                    key,
+                    before: Some(before),
+                    after: Some(after),
+                }),
+                Some(_) => {}
+            }
+        }
+        diff.removed = before
+            .into_iter()
+            .map(|(key, before)| KeyChange {
+                key,
+                before: Some(before),
+                after: None,
+            })
+            .collect();
+
+        diff
+    }
+
+    /// Whether both versions are the same.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl fmt::Display for TargetDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in self.added.iter().chain(&self.changed).chain(&self.removed) {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the change as a line of [`TargetDiff`], without the line break.
+impl fmt::Display for KeyChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.before, &self.after) {
+            (None, after) => write!(f, "+ {}: {}", self.key, value(after)),
+            (before, None) => write!(f, "- {}: {}", self.key, value(before)),
+            (before, after) => {
+                let (before, after) = (value(before), value(after));
+                write!(f, "~ {}: {before} -> {after}", self.key)
+            }
+        }
+    }
+}
+
+fn value(value: &Option<Value>) -> String {
+    value.as_ref().map(Value::to_string).unwrap_or_default()
+}
@@ -0,0 +1,208 @@
+use crate::format::FormatError;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Errors reported by the q-translate pipeline.
+///
+/// Every variant maps to a distinct process exit code (see
+/// [`QTranslateError::exit_code`]) so scripts can tell failure classes apart.
+#[derive(Debug, Error)]
+pub enum QTranslateError {
+    #[error("Assets directory not found (looked for src/assets/i18n and assets/i18n)")]
+    AssetsNotFound,
+
+    #[error("Assets directory {0} does not exist")]
+    AssetsDirMissing(String),
+
+    #[error("Source file {0} does not exist")]
+    SourceMissing(String),
+
+    #[error("Unsupported locale file format: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Invalid locale file {path}: {source}")]
+    InvalidFile { path: String, source: FormatError },
+
+    /// The target of `lang` holds something else than a string where the
+    /// source holds one, see [`FormatError::NotAString`].
+    #[error("Invalid target for {lang}: {source}")]
+    InvalidTarget { lang: String, source: FormatError },
+
+    #[error("{0} environment variable is not set")]
+    MissingApiKey(String),
+
+    #[error("Invalid configuration file {path}: {source}")]
+    InvalidConfig {
+        path: String,
+        source: toml::de::Error,
+    },
+
+    #[error("Invalid placeholder pattern: {0}")]
+    InvalidPlaceholderPattern(#[from] regex::Error),
+
+    #[error("Cannot read glossary file {path}: {source}")]
+    GlossaryFile { path: String, source: io::Error },
+
+    #[error("Cannot read termbase file {path}: {source}")]
+    TermbaseFile { path: String, source: io::Error },
+
+    #[error("Invalid proxy {url}: {message}")]
+    InvalidProxy { url: String, message: String },
+
+    #[error("Cannot read git revision {rev}: {message}")]
+    InvalidRevision { rev: String, message: String },
+
+    #[error("Translation failed: {0}")]
+    Translation(String),
+
+    #[error("{failed} of {total} target languages failed")]
+    TargetsFailed { failed: usize, total: usize },
+
+    #[error("{failed} of {total} files failed")]
+    FilesFailed { failed: usize, total: usize },
+
+    #[error("{missing} translations are missing or outdated")]
+    Incomplete { missing: usize },
+
+    #[error("{failed} keys could not be translated")]
+    PhrasesFailed { failed: usize },
+
+    #[error("Timed out after {secs}s with {done} of {total} phrases translated")]
+    TimedOut { secs: u64, done: usize, total: usize },
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl QTranslateError {
+    /// Process exit code used when the CLI terminates with this error.
+    ///
+    /// | Code | Failure class                            |
+    /// |------|------------------------------------------|
+    /// | 1    | Translations failed                      |
+    /// | 2    | `--verify` found missing translations    |
+    /// | 3    | Assets directory or source file missing  |
+    /// | 4    | Invalid JSON or YAML input               |
+    /// | 5    | Missing API key or invalid configuration |
+    /// | 6    | Other I/O errors                         |
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            QTranslateError::Translation(_)
+            | QTranslateError::TargetsFailed { .. }
+            | QTranslateError::FilesFailed { .. }
+            | QTranslateError::PhrasesFailed { .. }
+            | QTranslateError::TimedOut { .. } => 1,
+            QTranslateError::Incomplete { .. } => 2,
+            QTranslateError::AssetsNotFound
+            | QTranslateError::AssetsDirMissing(_)
+            | QTranslateError::SourceMissing(_) => 3,
+            QTranslateError::UnsupportedFormat(_)
+            | QTranslateError::InvalidFile { .. }
+            | QTranslateError::InvalidTarget { .. } => 4,
+            QTranslateError::MissingApiKey(_)
+            | QTranslateError::InvalidConfig { .. }
+            | QTranslateError::InvalidPlaceholderPattern(_)
+            | QTranslateError::GlossaryFile { .. }
+            | QTranslateError::TermbaseFile { .. }
+            | QTranslateError::InvalidProxy { .. }
+            | QTranslateError::InvalidRevision { .. } => 5,
+            QTranslateError::Io(_) => 6,
+        }
+    }
+}
+
+/// Errors of a single translation request.
+///
+/// Cheap to clone, so the error of a failed batch can be reported for every
+/// phrase of that batch.
+#[derive(Debug, Clone, Error)]
+pub enum TranslateError {
+    #[error("Request timed out after {} seconds", .0.as_secs_f32())]
+    Timeout(Duration),
+
+    /// The request could not be sent or its response not be read.
+    #[error(transparent)]
+    Http(Arc<reqwest::Error>),
+
+    /// The provider answered with an unsuccessful status, asking to wait
+    /// `retry_after` before trying again if it sent a `Retry-After` header.
+    #[error("Provider responded with status {status}: {body}")]
+    Api {
+        status: u16,
+        body: String,
+        retry_after: Option<Duration>,
+    },
+
+    /// The body of a successful response is not what the provider returns.
+    #[error("Cannot parse the provider's response: {0}")]
+    Parse(Arc<serde_json::Error>),
+
+    #[error("{0} environment variable is not set")]
+    MissingApiKey(String),
+
+    #[error("Provider returned {actual} translations for {expected} phrases")]
+    CountMismatch { expected: usize, actual: usize },
+
+    #[error("Provider returned an invalid response: {0}")]
+    InvalidResponse(String),
+}
+
+impl TranslateError {
+    /// Converts a `reqwest` error, reporting timeouts as
+    /// [`TranslateError::Timeout`] after `timeout`. The URL of the request
+    /// is left out, since it may carry an API key as query parameter.
+    pub fn from_reqwest(err: reqwest::Error, timeout: Duration) -> Self {
+        match err.is_timeout() {
+            true => TranslateError::Timeout(timeout),
+            false => TranslateError::Http(Arc::new(err.without_url())),
+        }
+    }
+
+    /// HTTP status code of the response, if the server answered with an
+    /// error.
+    pub fn status_code(&self) -> Option<u16> {
+        match self {
+            TranslateError::Timeout(_)
+            | TranslateError::Parse(_)
+            | TranslateError::MissingApiKey(_)
+            | TranslateError::CountMismatch { .. }
+            | TranslateError::InvalidResponse(_) => None,
+            TranslateError::Http(err) => err.status().map(|status| status.as_u16()),
+            TranslateError::Api { status, .. } => Some(*status),
+        }
+    }
+
+    /// Delay the provider asked for before the request is sent again, from
+    /// the `Retry-After` header of its response.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            TranslateError::Api { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if sending the same request again may succeed.
+    ///
+    /// Timeouts, connection failures, rate limiting (`429`) and server errors
+    /// (`5xx`) are considered transient.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            TranslateError::Timeout(_) => true,
+            TranslateError::Http(err) => {
+                err.is_connect()
+                    || err.status().is_some_and(|status| {
+                        status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    })
+            }
+            TranslateError::Api { status, .. } => {
+                *status >= 500 || *status == reqwest::StatusCode::TOO_MANY_REQUESTS.as_u16()
+            }
+            TranslateError::Parse(_)
+            | TranslateError::MissingApiKey(_)
+            | TranslateError::CountMismatch { .. }
+            | TranslateError::InvalidResponse(_) => false,
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::format::Format;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes `contents` to `path` atomically.
+///
+/// The data is first written and flushed to a temporary file in the same
+/// directory, which is then renamed over `path`. Readers therefore observe
+/// either the previous content or the new one, never a partially written file.
+///
+/// # Errors
+///
+/// Returns an error if the temporary file cannot be written or renamed. In
+/// that case `path` is left untouched.
+pub fn write_atomic(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let path = path.as_ref();
+    let temp_path = temp_path_for(path);
+
+    let result = File::create(&temp_path).and_then(|mut file| {
+        file.write_all(contents.as_ref())?;
+        file.sync_all()
+    });
+
+    match result.and_then(|_| fs::rename(&temp_path, path)) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(err)
+        }
+    }
+}
+
+/// Makes the serialized file `contents` end with exactly one newline if
+/// `final_newline` is set, as expected by most linters, or without one
+/// otherwise. Empty contents are left empty.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::files::with_final_newline;
+///
+/// assert_eq!(with_final_newline("{}".to_string(), true), "{}\n");
+/// assert_eq!(with_final_newline("{}\n".to_string(), false), "{}");
+/// ```
+pub fn with_final_newline(contents: String, final_newline: bool) -> String {
+    let trimmed = contents.trim_end_matches('\n');
+
+    match final_newline && !trimmed.is_empty() {
+        true => format!("{trimmed}\n"),
+        false => trimmed.to_string(),
+    }
+}
+
+/// Returns the hidden temporary file used while writing `path`,
+/// e.g. `assets/i18n/.de.json.tmp` for `assets/i18n/de.json`.
+fn temp_path_for(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    path.with_file_name(format!(".{file_name}.tmp"))
+}
+
+/// Copies the file at `path` to a backup before it is overwritten: to
+/// `path` with `.bak` appended, e.g. `assets/i18n/de.json.bak`, or to a file
+/// of that name in `backup_dir`, which is created if needed.
+///
+/// Returns the path of the backup, or `None` if there is no file at `path`
+/// yet.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be copied. The backup is written with
+/// [`write_atomic`], so an existing backup is never left half-written.
+pub fn backup(path: impl AsRef<Path>, backup_dir: Option<&Path>) -> io::Result<Option<PathBuf>> {
+    let path = path.as_ref();
+
+    if !fs::exists(path)? {
+        return Ok(None);
+    }
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let backup_path = match backup_dir {
+        Some(dir) => {
+            fs::create_dir_all(dir)?;
+            dir.join(format!("{file_name}.bak"))
+        }
+        None => path.with_file_name(format!("{file_name}.bak")),
+    };
+
+    write_atomic(&backup_path, fs::read(path)?)?;
+    Ok(Some(backup_path))
+}
+
+/// Lists the locale files below `dir`, relative to it and sorted, for
+/// translating a whole directory of source files.
+///
+/// Subdirectories are searched as well. Files and directories whose name
+/// starts with a `.` are skipped, as are files of unknown formats and CSV
+/// files, which hold all languages at once.
+///
+/// # Errors
+///
+/// Returns an error if `dir` or one of its subdirectories cannot be read.
+pub fn source_files(dir: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    let mut files = vec![];
+    let mut pending = vec![PathBuf::new()];
+
+    while let Some(relative) = pending.pop() {
+        for entry in fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = relative.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else if Format::from_path(&path).is_some_and(|format| format != Format::Csv) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
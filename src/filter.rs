@@ -0,0 +1,244 @@
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Default location of the file listing the key paths never to touch.
+pub const DEFAULT_IGNORE_PATH: &str = ".q-translateignore";
+
+/// Selects the key paths of a locale file that take part in a run.
+///
+/// Key paths join nested keys with dots, as in `checkout.button.pay`. Every
+/// pattern is a glob over such a path: `*` matches within a single segment,
+/// `**` matches any number of segments and `?` matches a single character.
+/// Arrays are matched as a whole by the path of their key.
+///
+/// A path is selected if it matches at least one include pattern, or if there
+/// are no include patterns at all, and matches no exclude pattern. With
+/// [`KeyFilter::with_namespaces`], its first segment has to be one of the
+/// namespaces as well, and with [`KeyFilter::with_ignore`] it must not be
+/// ignored. The default filter selects every path.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::filter::KeyFilter;
+///
+/// let filter = KeyFilter::new(&["checkout.**"], &["checkout.legal.**"]);
+///
+/// assert!(filter.matches("checkout.button.pay"));
+/// assert!(!filter.matches("checkout.legal.terms"));
+/// assert!(!filter.matches("home.title"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KeyFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    namespaces: Vec<String>,
+    ignore: IgnoreRules,
+    paths: Option<HashSet<String>>,
+}
+
+impl KeyFilter {
+    /// Creates a filter from `include` and `exclude` glob patterns.
+    pub fn new(include: &[impl AsRef<str>], exclude: &[impl AsRef<str>]) -> Self {
+        Self {
+            include: include
+                .iter()
+                .map(|glob| glob_regex(glob.as_ref()))
+                .collect(),
+            exclude: exclude
+                .iter()
+                .map(|glob| glob_regex(glob.as_ref()))
+                .collect(),
+            namespaces: vec![],
+            ignore: IgnoreRules::default(),
+            paths: None,
+        }
+    }
+
+    /// Restricts the filter to the key paths under the top-level keys
+    /// `namespaces`, such as `billing` for `billing.invoice.title`. An empty
+    /// list leaves the filter unrestricted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use q_translate::filter::KeyFilter;
+    ///
+    /// let filter = KeyFilter::default().with_namespaces(&["auth", "billing"]);
+    ///
+    /// assert!(filter.matches("billing.invoice.title"));
+    /// assert!(filter.matches("auth"));
+    /// assert!(!filter.matches("dashboard.title"));
+    /// assert!(!filter.matches("authentication.title"));
+    /// ```
+    pub fn with_namespaces(self, namespaces: &[impl AsRef<str>]) -> Self {
+        Self {
+            namespaces: namespaces
+                .iter()
+                .map(|namespace| namespace.as_ref().to_string())
+                .collect(),
+            ..self
+        }
+    }
+
+    /// Leaves out the key paths ignored by `ignore`, whatever the include
+    /// patterns.
+    pub fn with_ignore(self, ignore: IgnoreRules) -> Self {
+        Self { ignore, ..self }
+    }
+
+    /// Restricts the filter to the key paths `paths` and those below them,
+    /// such as the keys whose source changed since a git revision.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use q_translate::filter::KeyFilter;
+    ///
+    /// let paths = ["home.title".to_string(), "steps".to_string()];
+    /// let filter = KeyFilter::default().with_paths(paths);
+    ///
+    /// assert!(filter.matches("home.title"));
+    /// assert!(filter.matches("steps.0"));
+    /// assert!(!filter.matches("home.intro"));
+    /// assert!(!filter.matches("home"));
+    /// ```
+    pub fn with_paths(self, paths: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            paths: Some(paths.into_iter().collect()),
+            ..self
+        }
+    }
+
+    /// Returns `true` if the key path `path` takes part in the run.
+    pub fn matches(&self, path: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|glob| glob.is_match(path)))
+            && !self.exclude.iter().any(|glob| glob.is_match(path))
+            && self.in_namespaces(path)
+            && !self.ignore.is_ignored(path)
+            && self.in_paths(path)
+    }
+
+    /// Returns `true` if `path` or one of its parents is among the paths of
+    /// [`KeyFilter::with_paths`], or if there are none.
+    fn in_paths(&self, path: &str) -> bool {
+        let Some(paths) = &self.paths else {
+            return true;
+        };
+        let parents = path.match_indices('.').map(|(i, _)| &path[..i]);
+        parents.chain([path]).any(|path| paths.contains(path))
+    }
+
+    /// Returns `true` if the first segment of `path` is one of the namespaces,
+    /// or if there are none.
+    fn in_namespaces(&self, path: &str) -> bool {
+        let namespace = path.split('.').next().unwrap_or(path);
+
+        self.namespaces.is_empty() || self.namespaces.iter().any(|n| n == namespace)
+    }
+}
+
+/// Key paths whose values are never written, removed or pruned, read from a
+/// `.q-translateignore` file.
+///
+/// The file lists one glob per line, in the syntax of [`KeyFilter`]; blank
+/// lines and lines starting with `#` are skipped. As in a `.gitignore` file, a
+/// pattern also covers everything below the paths it matches, a pattern
+/// starting with `!` takes back what earlier patterns ignored, and the last
+/// matching pattern decides.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::filter::IgnoreRules;
+///
+/// let rules = IgnoreRules::parse("# hand-crafted\nlegal\n!legal.footer\n*.slogan\n");
+///
+/// assert!(rules.is_ignored("legal.terms.title"));
+/// assert!(!rules.is_ignored("legal.footer"));
+/// assert!(rules.is_ignored("home.slogan"));
+/// assert!(!rules.is_ignored("home.title"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    /// Patterns in file order, with whether they ignore (`true`) or take
+    /// back (`false`) the paths they match.
+    rules: Vec<(Regex, bool)>,
+}
+
+impl IgnoreRules {
+    /// Parses the `contents` of an ignore file.
+    pub fn parse(contents: &str) -> Self {
+        let rules = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| match line.strip_prefix('!') {
+                Some(glob) => (glob_regex(glob), false),
+                None => (glob_regex(line), true),
+            })
+            .collect();
+
+        Self { rules }
+    }
+
+    /// Reads the ignore file at `path`, returning no rules if the file does
+    /// not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but cannot be read.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        match fs::exists(path)? {
+            true => Ok(Self::parse(&fs::read_to_string(path)?)),
+            false => Ok(Self::default()),
+        }
+    }
+
+    /// Returns `true` if the key path `path`, or one of the paths above it,
+    /// is ignored by the last pattern matching it.
+    pub fn is_ignored(&self, path: &str) -> bool {
+        let ancestors = path
+            .match_indices('.')
+            .map(|(i, _)| &path[..i])
+            .chain([path]);
+
+        self.rules
+            .iter()
+            .rev()
+            .find(|(glob, _)| ancestors.clone().any(|ancestor| glob.is_match(ancestor)))
+            .is_some_and(|(_, ignored)| *ignored)
+    }
+}
+
+/// Translates a key path glob into an anchored regular expression.
+fn glob_regex(glob: &str) -> Regex {
+    let mut pattern = String::from("^");
+    let mut rest = glob;
+
+    while let Some(c) = rest.chars().next() {
+        let (regex, len) = if rest.starts_with("**.") {
+            // `**.` may also match no segment at all, so `**.title` matches `title`.
+            ("(?:.*\\.)?".to_string(), 3)
+        } else if rest.starts_with("**") {
+            (".*".to_string(), 2)
+        } else if c == '*' {
+            ("[^.]*".to_string(), 1)
+        } else if c == '?' {
+            ("[^.]".to_string(), 1)
+        } else {
+            (regex::escape(&c.to_string()), c.len_utf8())
+        };
+
+        pattern.push_str(&regex);
+        rest = &rest[len..];
+    }
+    pattern.push('$');
+
+    Regex::new(&pattern).expect("Escaped glob is a valid regular expression")
+}
@@ -0,0 +1,139 @@
+use crate::format::FormatError;
+use serde_json::{Map, Value};
+
+/// Parses a CSV file with a header row into an object mapping the value in
+/// the first column of every row to the row, as an object mapping every
+/// header to its cell.
+///
+/// Quoted cells may contain commas, quotes and line breaks. Every row must
+/// have as many cells as the header row, and the values in the first column
+/// must be unique.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::format::csv;
+/// use serde_json::json;
+///
+/// let rows = csv::parse("key,en,de\ngreeting,\"Hello, world\",\n").unwrap();
+///
+/// assert_eq!(
+///     serde_json::Value::Object(rows),
+///     json!({"greeting": {"key": "greeting", "en": "Hello, world", "de": ""}})
+/// );
+/// ```
+pub fn parse(contents: &str) -> Result<Map<String, Value>, FormatError> {
+    let mut reader = ::csv::Reader::from_reader(contents.as_bytes());
+    let headers = reader.headers()?.clone();
+    let mut rows = Map::new();
+
+    for record in reader.records() {
+        let record = record?;
+        let row: Map<String, Value> = headers
+            .iter()
+            .zip(&record)
+            .map(|(header, cell)| (header.to_string(), Value::String(cell.to_string())))
+            .collect();
+        let key = record.get(0).unwrap_or_default().to_string();
+        if rows.insert(key.clone(), Value::Object(row)).is_some() {
+            return Err(FormatError::DuplicateKey(key));
+        }
+    }
+    Ok(rows)
+}
+
+/// Serializes `rows`, as parsed by [`parse`], as CSV.
+///
+/// The header row lists the columns of the first row, followed by those only
+/// found in later rows. Missing cells are left empty, other values than
+/// strings are written as JSON, and cells are quoted only where needed. Empty
+/// `rows` are written as an empty file.
+///
+/// # Errors
+///
+/// Returns [`FormatError::NotAnObject`] if a row is not an object.
+pub fn serialize(rows: &Map<String, Value>) -> Result<String, FormatError> {
+    let mut headers: Vec<&String> = vec![];
+    for row in rows.values() {
+        let row = row.as_object().ok_or(FormatError::NotAnObject)?;
+        for header in row.keys() {
+            if !headers.contains(&header) {
+                headers.push(header);
+            }
+        }
+    }
+
+    let mut writer = ::csv::Writer::from_writer(vec![]);
+    if !headers.is_empty() {
+        writer.write_record(&headers)?;
+    }
+    for row in rows.values() {
+        let cells = headers.iter().map(|header| match row.get(*header) {
+            Some(Value::String(cell)) => cell.clone(),
+            None | Some(Value::Null) => String::new(),
+            Some(other) => other.to_string(),
+        });
+        writer.write_record(cells)?;
+    }
+
+    let contents = writer
+        .into_inner()
+        .map_err(|err| ::csv::Error::from(err.into_error()))?;
+    Ok(String::from_utf8(contents).expect("CSV cells are valid UTF-8"))
+}
+
+/// Returns the non-empty cells of `column` in `rows`, as parsed by [`parse`],
+/// by the value in the first column of their row.
+///
+/// # Errors
+///
+/// Returns [`FormatError::MissingColumn`] if the header row does not list
+/// `column`.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::format::csv;
+/// use serde_json::json;
+///
+/// let rows = csv::parse("key,en,de\ngreeting,Hello,Hallo\nfarewell,Goodbye,\n").unwrap();
+///
+/// assert_eq!(
+///     serde_json::Value::Object(csv::column(&rows, "de").unwrap()),
+///     json!({"greeting": "Hallo"})
+/// );
+/// assert!(csv::column(&rows, "fr").is_err());
+/// ```
+pub fn column(rows: &Map<String, Value>, column: &str) -> Result<Map<String, Value>, FormatError> {
+    let has_column = rows
+        .values()
+        .next()
+        .is_none_or(|row| row.get(column).is_some());
+    if !has_column {
+        return Err(FormatError::MissingColumn(column.to_string()));
+    }
+
+    Ok(rows
+        .iter()
+        .filter_map(|(key, row)| {
+            let cell = row.get(column)?.as_str().filter(|cell| !cell.is_empty())?;
+            Some((key.clone(), Value::String(cell.to_string())))
+        })
+        .collect())
+}
+
+/// Sets the cells of `column` in `rows`, as parsed by [`parse`], to the
+/// strings in `values` by the value in the first column of their row, and
+/// empties those of the rows missing from `values`.
+///
+/// A column that does not exist yet is added after the last one. Keys of
+/// `values` without a row are ignored, so rows are neither added nor
+/// reordered.
+pub fn set_column(rows: &mut Map<String, Value>, column: &str, values: &Map<String, Value>) {
+    for (key, row) in rows.iter_mut() {
+        let cell = values.get(key).and_then(Value::as_str).unwrap_or_default();
+        if let Value::Object(row) = row {
+            row.insert(column.to_string(), Value::String(cell.to_string()));
+        }
+    }
+}
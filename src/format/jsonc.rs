@@ -0,0 +1,362 @@
+use crate::format::{FormatError, JsonStyle, strip_bom};
+use crate::utils::key_path;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::mem;
+
+/// Parses JSON with comments (JSONC), as used by `tsconfig.json` and VS Code
+/// settings, into a JSON value.
+///
+/// `//` line comments and `/* */` block comments are skipped, and so are
+/// trailing commas before a closing `}` or `]`. Use [`Comments::parse`] to
+/// keep the comments.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::format::jsonc;
+/// use serde_json::json;
+///
+/// let value = jsonc::parse("{\n  // Shown on the login page\n  \"title\": \"Welcome\",\n}").unwrap();
+///
+/// assert_eq!(value, json!({"title": "Welcome"}));
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if `contents` is not valid JSON once the comments are
+/// removed.
+pub fn parse(contents: &str) -> Result<Value, FormatError> {
+    Ok(serde_json::from_str(&strip_comments(strip_bom(contents)))?)
+}
+
+/// Returns `contents` with its comments blanked out with spaces and without
+/// trailing commas. Line breaks are kept, so parse errors point at the right
+/// line.
+pub fn strip_comments(contents: &str) -> String {
+    let bytes = contents.as_bytes();
+    let mut stripped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let end = match bytes[i] {
+            b'"' => string_end(bytes, i),
+            b'/' => comment_end(bytes, i).unwrap_or(i + 1),
+            _ => i + 1,
+        };
+        match bytes[i] {
+            b'/' if end > i + 1 => {
+                stripped.extend(bytes[i..end].iter().map(|&b| match b {
+                    b'\n' => b'\n',
+                    _ => b' ',
+                }));
+            }
+            b'}' | b']' => {
+                let last = stripped.iter().rposition(|b: &u8| !b.is_ascii_whitespace());
+                if let Some(last) = last.filter(|&last| stripped[last] == b',') {
+                    stripped[last] = b' ';
+                }
+                stripped.push(bytes[i]);
+            }
+            _ => stripped.extend_from_slice(&bytes[i..end]),
+        }
+        i = end;
+    }
+
+    String::from_utf8(stripped).expect("only ASCII bytes are replaced")
+}
+
+/// Comments of a JSONC file, attached to the keys of its objects.
+///
+/// A comment on the lines before a key leads it, and a comment following a
+/// key on the same line trails it. Comments before the top-level value are
+/// kept as well, while those elsewhere, such as between array elements, are
+/// dropped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Comments {
+    /// Comments before the key at every key path, `""` standing for the
+    /// top-level value.
+    leading: HashMap<String, Vec<String>>,
+    /// Comment at the end of the line of the key at every key path.
+    trailing: HashMap<String, String>,
+}
+
+/// Object or array the scanner of [`Comments::parse`] is in.
+enum Frame {
+    Object { path: String, key: String },
+    Array { path: String, index: usize },
+}
+
+impl Comments {
+    /// Collects the comments of the JSONC file `contents` by the key paths
+    /// of their keys. Malformed contents yield the comments found up to the
+    /// error.
+    pub fn parse(contents: &str) -> Self {
+        let contents = strip_bom(contents);
+        let bytes = contents.as_bytes();
+        let mut comments = Self::default();
+        let mut frames: Vec<Frame> = vec![];
+        let mut pending: Vec<String> = vec![];
+        // Key path and line of the last key.
+        let mut last_key: Option<(String, usize)> = None;
+        let mut expect_key = false;
+        let mut line = 0;
+        let mut i = 0;
+
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\n' => line += 1,
+                b'/' => {
+                    if let Some(end) = comment_end(bytes, i) {
+                        let comment = contents[i..end].to_string();
+                        match &last_key {
+                            Some((path, key_line))
+                                if *key_line == line && !comments.trailing.contains_key(path) =>
+                            {
+                                comments.trailing.insert(path.clone(), comment.clone());
+                            }
+                            _ => pending.push(comment.clone()),
+                        }
+                        line += comment.matches('\n').count();
+                        i = end;
+                        continue;
+                    }
+                }
+                b'"' => {
+                    let end = string_end(bytes, i);
+                    if let (true, Some(Frame::Object { path, key })) =
+                        (expect_key, frames.last_mut())
+                    {
+                        *key = serde_json::from_str(&contents[i..end]).unwrap_or_default();
+                        let path = key_path(path, key.as_str());
+                        if !pending.is_empty() {
+                            comments
+                                .leading
+                                .insert(path.clone(), mem::take(&mut pending));
+                        }
+                        last_key = Some((path, line));
+                        expect_key = false;
+                    }
+                    pending.clear();
+                    i = end;
+                    continue;
+                }
+                b'{' | b'[' => {
+                    let path = value_path(&frames);
+                    if frames.is_empty() && !pending.is_empty() {
+                        comments
+                            .leading
+                            .insert(path.clone(), mem::take(&mut pending));
+                    }
+                    pending.clear();
+                    frames.push(match bytes[i] {
+                        b'{' => Frame::Object {
+                            path,
+                            key: String::new(),
+                        },
+                        _ => Frame::Array { path, index: 0 },
+                    });
+                    expect_key = bytes[i] == b'{';
+                }
+                b',' => match frames.last_mut() {
+                    Some(Frame::Array { index, .. }) => *index += 1,
+                    Some(Frame::Object { .. }) => expect_key = true,
+                    None => {}
+                },
+                b'}' | b']' => {
+                    frames.pop();
+                    pending.clear();
+                    expect_key = false;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        comments
+    }
+}
+
+/// Serializes `value` as JSONC, laid out in `style`.
+///
+/// With a `template`, usually the source file, its [`Comments`] are written
+/// with the keys at the same key paths, so notes for translators carry over
+/// to the target files. Compact files are written without comments.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::format::{JsonStyle, jsonc};
+/// use serde_json::json;
+///
+/// let template = "{\n  // Shown on the login page\n  \"title\": \"Welcome\" // keep it short\n}\n";
+/// let value = json!({"title": "Willkommen"});
+///
+/// assert_eq!(
+///     jsonc::serialize(&value, Some(template), JsonStyle::default()).unwrap(),
+///     "{\n  // Shown on the login page\n  \"title\": \"Willkommen\" // keep it short\n}\n"
+/// );
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if `value` cannot be serialized.
+pub fn serialize(
+    value: &Value,
+    template: Option<&str>,
+    style: JsonStyle,
+) -> Result<String, FormatError> {
+    let indent = match style {
+        JsonStyle::Compact => return Ok(serde_json::to_string(value)?),
+        JsonStyle::Pretty { indent } => " ".repeat(indent),
+    };
+    let comments = template.map(Comments::parse).unwrap_or_default();
+
+    let mut jsonc = String::new();
+    for comment in comments.leading.get("").into_iter().flatten() {
+        jsonc.push_str(comment);
+        jsonc.push('\n');
+    }
+    let mut writer = Writer {
+        jsonc,
+        indent: &indent,
+        comments: &comments,
+    };
+    writer.value(value, "", 0, None)?;
+    writer.jsonc.push('\n');
+    Ok(writer.jsonc)
+}
+
+/// Key path, key if in an object, and value of an entry of an object or
+/// array.
+type Entry<'a> = (String, Option<&'a String>, &'a Value);
+
+/// Pretty printer of [`serialize`], laying out values like
+/// [`serde_json::to_string_pretty`] with the comments added.
+struct Writer<'a> {
+    jsonc: String,
+    indent: &'a str,
+    comments: &'a Comments,
+}
+
+impl Writer<'_> {
+    /// Writes `value` at `path`, nested `depth` levels deep. The `trailing`
+    /// comment of its key follows the opening bracket of an object or array.
+    fn value(
+        &mut self,
+        value: &Value,
+        path: &str,
+        depth: usize,
+        trailing: Option<&str>,
+    ) -> Result<(), FormatError> {
+        let (open, close, entries): (char, char, Vec<Entry>) = match value {
+            Value::Object(map) if !map.is_empty() => {
+                let entries = map
+                    .iter()
+                    .map(|(key, value)| (key_path(path, key), Some(key), value));
+                ('{', '}', entries.collect())
+            }
+            Value::Array(items) if !items.is_empty() => {
+                let entries = items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| (key_path(path, i), None, item));
+                ('[', ']', entries.collect())
+            }
+            _ => {
+                self.jsonc.push_str(&serde_json::to_string(value)?);
+                self.comment(trailing);
+                return Ok(());
+            }
+        };
+
+        self.jsonc.push(open);
+        self.comment(trailing);
+        let count = entries.len();
+        for (i, (path, key, value)) in entries.into_iter().enumerate() {
+            self.jsonc.push('\n');
+            let pad = self.indent.repeat(depth + 1);
+            let leading = key.and_then(|_| self.comments.leading.get(&path));
+            for comment in leading.into_iter().flatten() {
+                self.jsonc.push_str(&pad);
+                self.jsonc.push_str(comment);
+                self.jsonc.push('\n');
+            }
+            self.jsonc.push_str(&pad);
+            if let Some(key) = key {
+                self.jsonc.push_str(&serde_json::to_string(key)?);
+                self.jsonc.push_str(": ");
+            }
+
+            let trailing = key.and_then(|_| self.comments.trailing.get(&path));
+            let nested = matches!(value, Value::Object(map) if !map.is_empty())
+                || matches!(value, Value::Array(items) if !items.is_empty());
+            match nested {
+                true => {
+                    self.value(value, &path, depth + 1, trailing.map(String::as_str))?;
+                    if i + 1 < count {
+                        self.jsonc.push(',');
+                    }
+                }
+                false => {
+                    self.jsonc.push_str(&serde_json::to_string(value)?);
+                    if i + 1 < count {
+                        self.jsonc.push(',');
+                    }
+                    self.comment(trailing.map(String::as_str));
+                }
+            }
+        }
+        self.jsonc.push('\n');
+        self.jsonc.push_str(&self.indent.repeat(depth));
+        self.jsonc.push(close);
+        Ok(())
+    }
+
+    /// Appends `comment` to the current line.
+    fn comment(&mut self, comment: Option<&str>) {
+        if let Some(comment) = comment {
+            self.jsonc.push(' ');
+            self.jsonc.push_str(comment);
+        }
+    }
+}
+
+/// Key path of the value the scanner is about to read.
+fn value_path(frames: &[Frame]) -> String {
+    match frames.last() {
+        None => String::new(),
+        Some(Frame::Object { path, key }) => key_path(path, key),
+        Some(Frame::Array { path, index }) => key_path(path, index),
+    }
+}
+
+/// Returns the index after the string literal starting at `start`.
+fn string_end(bytes: &[u8], start: usize) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// Returns the index after the comment starting at `start`, leaving out the
+/// line break ending a line comment, or `None` if no comment starts there.
+fn comment_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let rest = &bytes[start..];
+    if rest.starts_with(b"//") {
+        let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+        Some(start + end)
+    } else if rest.starts_with(b"/*") {
+        let end = rest[2..]
+            .windows(2)
+            .position(|window| window == b"*/")
+            .map_or(rest.len(), |end| end + 4);
+        Some(start + end)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,187 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+use serde_json::ser::{PrettyFormatter, Serializer};
+use std::path::Path;
+use thiserror::Error;
+
+pub mod csv;
+pub mod jsonc;
+pub mod po;
+pub mod properties;
+
+/// File formats supported for locale files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// JSON (`.json`)
+    Json,
+    /// JSON with comments (`.jsonc`), whose comments are kept, see [`jsonc`]
+    Jsonc,
+    /// YAML (`.yaml`, `.yml`)
+    Yaml,
+    /// Java properties (`.properties`), always a flat map of strings
+    Properties,
+    /// CSV (`.csv`) with a header row, one row per key and one column per
+    /// language, see [`csv`]
+    Csv,
+}
+
+/// Byte order mark some editors put at the start of UTF-8 files.
+const BOM: char = '\u{feff}';
+
+/// Returns `contents` without a leading byte order mark.
+///
+/// Locale files are always written as UTF-8 without one.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::format::strip_bom;
+///
+/// assert_eq!(strip_bom("\u{feff}{}"), "{}");
+/// assert_eq!(strip_bom("{}"), "{}");
+/// ```
+pub fn strip_bom(contents: &str) -> &str {
+    contents.strip_prefix(BOM).unwrap_or(contents)
+}
+
+/// Layout of written JSON files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonStyle {
+    /// One entry per line, indented by `indent` spaces per level, ending with
+    /// a newline.
+    Pretty { indent: usize },
+    /// A single line without any whitespace.
+    Compact,
+}
+
+impl Default for JsonStyle {
+    fn default() -> Self {
+        JsonStyle::Pretty { indent: 2 }
+    }
+}
+
+/// Error raised when a locale file cannot be parsed or serialized.
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("expected an object at the top level")]
+    NotAnObject,
+
+    #[error("malformed \\uXXXX escape on line {0}")]
+    MalformedEscape(usize),
+
+    #[error(transparent)]
+    Csv(#[from] ::csv::Error),
+
+    #[error("no column named {0:?}")]
+    MissingColumn(String),
+
+    #[error("key {0:?} appears in more than one row")]
+    DuplicateKey(String),
+
+    #[error("expected a string at {0}, as in the source file")]
+    NotAString(String),
+}
+
+impl Format {
+    /// Recognized file extensions, in the order they are probed.
+    pub const EXTENSIONS: [(&'static str, Format); 6] = [
+        ("json", Format::Json),
+        ("jsonc", Format::Jsonc),
+        ("yaml", Format::Yaml),
+        ("yml", Format::Yaml),
+        ("properties", Format::Properties),
+        ("csv", Format::Csv),
+    ];
+
+    /// Detects the format from the extension of `path`.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Format> {
+        let extension = path.as_ref().extension()?.to_str()?;
+
+        Self::EXTENSIONS
+            .iter()
+            .find(|(ext, _)| ext.eq_ignore_ascii_case(extension))
+            .map(|(_, format)| *format)
+    }
+
+    /// Parses `contents` into a JSON value, preserving key order.
+    ///
+    /// YAML documents and properties files are loaded directly into
+    /// [`serde_json::Value`], so the traversal functions work identically for
+    /// all formats. A leading byte order mark, as written by some Windows
+    /// tools, is skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `contents` is not valid in this format.
+    pub fn parse(self, contents: &str) -> Result<Value, FormatError> {
+        let contents = strip_bom(contents);
+        Ok(match self {
+            Format::Json => serde_json::from_str(contents)?,
+            Format::Jsonc => jsonc::parse(contents)?,
+            Format::Yaml => serde_yaml::from_str(contents)?,
+            Format::Properties => Value::Object(properties::parse(contents)?),
+            Format::Csv => Value::Object(csv::parse(contents)?),
+        })
+    }
+
+    /// Serializes `value` in this format, preserving key order. JSON is
+    /// written in the default [`JsonStyle`].
+    ///
+    /// Properties and JSONC files are written without comments; use
+    /// [`properties::serialize`] and [`jsonc::serialize`] to keep those of a
+    /// template.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `value` cannot be represented in this format.
+    pub fn serialize(self, value: &Value) -> Result<String, FormatError> {
+        self.serialize_with(value, JsonStyle::default())
+    }
+
+    /// Serializes `value` like [`Format::serialize`], writing JSON in
+    /// `json_style`, and JSONC likewise. The other formats ignore `json_style`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `value` cannot be represented in this format.
+    pub fn serialize_with(
+        self,
+        value: &Value,
+        json_style: JsonStyle,
+    ) -> Result<String, FormatError> {
+        Ok(match self {
+            Format::Json => serialize_json(value, json_style)?,
+            Format::Jsonc => jsonc::serialize(value, None, json_style)?,
+            Format::Yaml => serde_yaml::to_string(value)?,
+            Format::Properties => {
+                properties::serialize(value.as_object().ok_or(FormatError::NotAnObject)?, None)
+            }
+            Format::Csv => csv::serialize(value.as_object().ok_or(FormatError::NotAnObject)?)?,
+        })
+    }
+}
+
+/// Serializes `value` as JSON laid out in `style`.
+fn serialize_json(value: &Value, style: JsonStyle) -> Result<String, serde_json::Error> {
+    match style {
+        JsonStyle::Compact => serde_json::to_string(value),
+        JsonStyle::Pretty { indent } => {
+            let indent = " ".repeat(indent);
+            let formatter = PrettyFormatter::with_indent(indent.as_bytes());
+            let mut serializer = Serializer::with_formatter(vec![], formatter);
+            value.serialize(&mut serializer)?;
+
+            let mut json = String::from_utf8(serializer.into_inner())
+                .expect("serde_json only writes valid UTF-8");
+            json.push('\n');
+            Ok(json)
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use crate::format::strip_bom;
+use crate::utils::key_path;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Renders the strings of `source` and their translations in `target` as a
+/// gettext `.po` file for `lang`.
+///
+/// Every distinct source string becomes one `msgid`, in the order of its first
+/// occurrence, with the key paths it appears under listed as `#:` references.
+/// The `msgstr` is the value at its first key path in `target`, or empty if
+/// there is none.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::format::po;
+/// use serde_json::json;
+///
+/// let source = json!({"menu": {"save": "Save"}, "toolbar": ["Save"]});
+/// let target = json!({"menu": {"save": "Speichern"}});
+///
+/// let po = po::render(&source, target.as_object().unwrap(), "de");
+/// assert!(po.ends_with("#: menu.save\n#: toolbar.0\nmsgid \"Save\"\nmsgstr \"Speichern\"\n"));
+/// ```
+pub fn render(source: &Value, target: &Map<String, Value>, lang: &str) -> String {
+    let mut entries: Vec<Entry> = vec![];
+    let mut index: HashMap<&str, usize> = HashMap::new();
+
+    if let Value::Object(source) = source {
+        for (key, value) in source {
+            collect(value, target.get(key), key, &mut entries, &mut index);
+        }
+    }
+
+    let mut po = format!(
+        "msgid \"\"\nmsgstr \"\"\n\"Content-Type: text/plain; charset=UTF-8\\n\"\n\"Language: {}\\n\"\n",
+        escape(lang)
+    );
+
+    for entry in entries {
+        po.push('\n');
+        for path in &entry.paths {
+            po.push_str(&format!("#: {path}\n"));
+        }
+        po.push_str(&format!("msgid \"{}\"\n", escape(entry.msgid)));
+        po.push_str(&format!("msgstr \"{}\"\n", escape(entry.msgstr)));
+    }
+
+    po
+}
+
+/// Parses the `msgid` to `msgstr` pairs of a `.po` file.
+///
+/// The header and untranslated entries with an empty `msgstr` are left out,
+/// as are plural forms and entries with a `msgctxt`.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::format::po;
+///
+/// let translations = po::parse("#: menu.save\nmsgid \"Save\"\nmsgstr \"\"\n\"Speichern\"\n");
+/// assert_eq!(translations["Save"], "Speichern");
+/// ```
+pub fn parse(contents: &str) -> HashMap<String, String> {
+    let contents = strip_bom(contents);
+    let mut translations = HashMap::new();
+    let mut msgid: Option<String> = None;
+    let mut msgstr: Option<String> = None;
+    let mut skip = false;
+
+    let mut finish = |msgid: &mut Option<String>, msgstr: &mut Option<String>, skip: bool| {
+        if let (Some(id), Some(text)) = (msgid.take(), msgstr.take())
+            && !skip
+            && !id.is_empty()
+            && !text.is_empty()
+        {
+            translations.insert(id, text);
+        }
+    };
+
+    for line in contents.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("msgid ") {
+            finish(&mut msgid, &mut msgstr, skip);
+            skip = false;
+            msgid = Some(unquote(rest));
+        } else if let Some(rest) = line.strip_prefix("msgstr ") {
+            msgstr = Some(unquote(rest));
+        } else if line.starts_with('"') {
+            let field = match msgstr.is_some() {
+                true => &mut msgstr,
+                false => &mut msgid,
+            };
+            if let Some(field) = field {
+                field.push_str(&unquote(line));
+            }
+        } else if line.starts_with("msgctxt") || line.starts_with("msgid_plural") {
+            finish(&mut msgid, &mut msgstr, skip);
+            skip = true;
+        }
+    }
+    finish(&mut msgid, &mut msgstr, skip);
+
+    translations
+}
+
+/// A `msgid` with the key paths it was found under.
+struct Entry<'a> {
+    msgid: &'a str,
+    msgstr: &'a str,
+    paths: Vec<String>,
+}
+
+/// Collects the strings of `source` into `entries`, pairing each with the
+/// value at the same position in `target`. `path` is the key path of `source`.
+fn collect<'a>(
+    source: &'a Value,
+    target: Option<&'a Value>,
+    path: &str,
+    entries: &mut Vec<Entry<'a>>,
+    index: &mut HashMap<&'a str, usize>,
+) {
+    match source {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let target = target.and_then(|target| target.get(key));
+                collect(value, target, &key_path(path, key), entries, index)
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                let target = target.and_then(|target| target.get(i));
+                collect(item, target, &key_path(path, i), entries, index)
+            }
+        }
+        Value::String(msgid) => {
+            let position = *index.entry(msgid).or_insert_with(|| {
+                entries.push(Entry {
+                    msgid,
+                    msgstr: target.and_then(Value::as_str).unwrap_or_default(),
+                    paths: vec![],
+                });
+                entries.len() - 1
+            });
+            entries[position].paths.push(path.to_string());
+        }
+        _ => {}
+    }
+}
+
+/// Escapes `text` for use inside a quoted PO string.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Returns the content of a quoted PO string with its escapes resolved.
+fn unquote(quoted: &str) -> String {
+    let inner = quoted
+        .trim()
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .unwrap_or_default();
+
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => text.push('\n'),
+                Some('r') => text.push('\r'),
+                Some('t') => text.push('\t'),
+                Some(other) => text.push(other),
+                None => {}
+            },
+            c => text.push(c),
+        }
+    }
+
+    text
+}
@@ -0,0 +1,255 @@
+use crate::format::{FormatError, strip_bom};
+use crate::layout::{self, DEFAULT_DELIMITER};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+
+/// Parses a Java `.properties` file into a flat map of string values.
+///
+/// Follows the rules of `java.util.Properties`: `#` and `!` start comments,
+/// keys end at the first unescaped `=`, `:` or whitespace, lines ending in an
+/// odd number of backslashes continue on the next line, and escapes such as
+/// `\=`, `\:`, `\n` and `\u00e9` are resolved.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::format::properties;
+///
+/// let map = properties::parse("# Greetings\nhello = Hello,\\\n    world\ncaf\\u00e9:Caf\\u00e9").unwrap();
+///
+/// assert_eq!(map["hello"], "Hello,world");
+/// assert_eq!(map["café"], "Café");
+/// ```
+///
+/// # Errors
+///
+/// Returns [`FormatError::MalformedEscape`] if a `\u` escape is not followed
+/// by four hexadecimal digits.
+pub fn parse(contents: &str) -> Result<Map<String, Value>, FormatError> {
+    let mut map = Map::new();
+
+    for line in logical_lines(contents) {
+        if let Line::Entry { number, text } = line {
+            let (key, value) = split_entry(&text);
+            map.insert(
+                unescape(key, number)?,
+                Value::String(unescape(value, number)?),
+            );
+        }
+    }
+
+    Ok(map)
+}
+
+/// Serializes `map` as a `.properties` file.
+///
+/// Nested objects are flattened with dots first, and values other than
+/// strings are written as their JSON representation. Characters outside of
+/// printable ASCII are written as `\uXXXX` escapes, so the file can be read
+/// both as ISO-8859-1 and as UTF-8.
+///
+/// With a `template`, usually the source file, its comments and blank lines
+/// are kept and the entries are written in its order. Keys missing from `map`
+/// are left out, keys missing from the template are appended at the end.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::format::properties;
+/// use serde_json::json;
+///
+/// let map = json!({"greeting": "Grüß Gott", "bye": "Tschüss"});
+/// let written = properties::serialize(map.as_object().unwrap(), Some("# Hi\ngreeting=Hello\n"));
+///
+/// assert_eq!(written, "# Hi\ngreeting=Gr\\u00fc\\u00df Gott\nbye=Tsch\\u00fcss\n");
+/// ```
+pub fn serialize(map: &Map<String, Value>, template: Option<&str>) -> String {
+    let map = layout::flatten(map.clone(), DEFAULT_DELIMITER);
+    let mut written = HashSet::new();
+    let mut contents = String::new();
+
+    for line in template.map(strip_bom).map(logical_lines).unwrap_or_default() {
+        match line {
+            Line::Raw(text) => {
+                contents.push_str(text);
+                contents.push('\n');
+            }
+            Line::Entry { number, text } => {
+                let (key, _) = split_entry(&text);
+                let Ok(key) = unescape(key, number) else {
+                    continue;
+                };
+
+                if let Some(value) = map.get(&key) {
+                    contents.push_str(&entry(&key, value));
+                    written.insert(key);
+                }
+            }
+        }
+    }
+
+    for (key, value) in &map {
+        if !written.contains(key) {
+            contents.push_str(&entry(key, value));
+        }
+    }
+
+    contents
+}
+
+/// A logical line of a `.properties` file.
+enum Line<'a> {
+    /// A comment or blank line.
+    Raw(&'a str),
+    /// A key-value pair with continuation lines joined, starting on the
+    /// 1-based line `number`.
+    Entry { number: usize, text: String },
+}
+
+/// Splits `contents` into comments, blank lines and entries.
+fn logical_lines(contents: &str) -> Vec<Line<'_>> {
+    let mut lines = vec![];
+    let mut natural = contents.lines().enumerate();
+
+    while let Some((i, line)) = natural.next() {
+        let trimmed = line.trim_start();
+
+        if trimmed.is_empty() || trimmed.starts_with(['#', '!']) {
+            lines.push(Line::Raw(line));
+            continue;
+        }
+
+        let mut text = trimmed.to_string();
+        while continues(&text) {
+            text.pop();
+            match natural.next() {
+                Some((_, next)) => text.push_str(next.trim_start()),
+                None => break,
+            }
+        }
+
+        lines.push(Line::Entry {
+            number: i + 1,
+            text,
+        });
+    }
+
+    lines
+}
+
+/// Returns `true` if `line` ends in an odd number of backslashes.
+fn continues(line: &str) -> bool {
+    line.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
+}
+
+/// Splits a logical line into its raw key and value.
+fn split_entry(text: &str) -> (&str, &str) {
+    let mut escaped = false;
+    let mut end = text.len();
+
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '=' | ':' | ' ' | '\t' | '\x0c' => {
+                end = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let rest = text[end..].trim_start_matches([' ', '\t', '\x0c']);
+    let rest = rest.strip_prefix(['=', ':']).unwrap_or(rest);
+
+    (&text[..end], rest.trim_start_matches([' ', '\t', '\x0c']))
+}
+
+/// Resolves the escapes of a raw key or value found on line `number`.
+fn unescape(raw: &str, number: usize) -> Result<String, FormatError> {
+    let mut text = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => text.push('\t'),
+            Some('n') => text.push('\n'),
+            Some('r') => text.push('\r'),
+            Some('f') => text.push('\x0c'),
+            Some('u') => {
+                let unit = |chars: &mut std::str::Chars| {
+                    let hex: String = chars.take(4).collect();
+                    u16::from_str_radix(&hex, 16)
+                        .ok()
+                        .filter(|_| hex.len() == 4)
+                        .ok_or(FormatError::MalformedEscape(number))
+                };
+
+                let high = unit(&mut chars)?;
+                let decoded = match (0xD800..0xDC00).contains(&high) {
+                    // A surrogate pair encodes a character outside the BMP.
+                    true if chars.as_str().starts_with("\\u") => {
+                        chars.nth(1);
+                        let low = unit(&mut chars)?;
+                        char::decode_utf16([high, low]).next()
+                    }
+                    _ => char::decode_utf16([high]).next(),
+                };
+                text.push(
+                    decoded
+                        .and_then(Result::ok)
+                        .unwrap_or(char::REPLACEMENT_CHARACTER),
+                );
+            }
+            Some(other) => text.push(other),
+            None => {}
+        }
+    }
+
+    Ok(text)
+}
+
+/// Formats a single `key=value` line.
+fn entry(key: &str, value: &Value) -> String {
+    let value = match value {
+        Value::String(value) => value.clone(),
+        other => other.to_string(),
+    };
+
+    format!("{}={}\n", escape(key, true), escape(&value, false))
+}
+
+/// Escapes a key or value for writing. Leading whitespace of a value and any
+/// whitespace in a key is escaped so it survives parsing.
+fn escape(text: &str, is_key: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for (i, c) in text.chars().enumerate() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\x0c' => escaped.push_str("\\f"),
+            '=' | ':' | '#' | '!' if is_key || i == 0 => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' ' if is_key || i == 0 => escaped.push_str("\\ "),
+            ' '..='~' => escaped.push(c),
+            c => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    escaped.push_str(&format!("\\u{unit:04x}"));
+                }
+            }
+        }
+    }
+
+    escaped
+}
@@ -0,0 +1,62 @@
+use crate::error::QTranslateError;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Returns the contents of the file at `path` as of the git revision `rev`,
+/// such as a branch, tag or commit hash, or `None` if the file did not exist
+/// in that revision.
+///
+/// `git` is run in the directory of `path`, so `path` may be relative to the
+/// current directory and lie in any repository.
+///
+/// # Errors
+///
+/// Returns [`QTranslateError::InvalidRevision`] if the directory of `path` is
+/// not in a git repository or `rev` does not name a commit, and an I/O error
+/// if `git` cannot be run.
+pub fn read_at_revision(
+    rev: &str,
+    path: impl AsRef<Path>,
+) -> Result<Option<String>, QTranslateError> {
+    let path = path.as_ref();
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path names no file"))?;
+
+    let commit = git(
+        dir,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{rev}^{{commit}}"),
+        ],
+    )?;
+    if !commit.status.success() {
+        let stderr = String::from_utf8_lossy(&commit.stderr).trim().to_string();
+        return Err(QTranslateError::InvalidRevision {
+            rev: rev.to_string(),
+            message: match stderr.is_empty() {
+                true => "no such commit".to_string(),
+                false => stderr,
+            },
+        });
+    }
+
+    let spec = format!("{rev}:./{}", file_name.to_string_lossy());
+    let show = git(dir, &["show", &spec])?;
+    match show.status.success() {
+        true => Ok(Some(String::from_utf8_lossy(&show.stdout).into_owned())),
+        false => Ok(None),
+    }
+}
+
+/// Runs `git` with `args` in `dir`.
+fn git(dir: &Path, args: &[&str]) -> io::Result<Output> {
+    Command::new("git").current_dir(dir).args(args).output()
+}
@@ -0,0 +1,111 @@
+use crate::files::write_atomic;
+use crate::filter::{IgnoreRules, KeyFilter};
+use crate::utils::{remove_changed, source_strings};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Default location of the source hash sidecar file.
+pub const DEFAULT_HASHES_PATH: &str = ".q-translate-hashes.json";
+
+/// Hashes of the source strings each target file was translated from, keyed by
+/// `(target_lang, key_path)`.
+///
+/// The sidecar is stored as a JSON object mapping each target language to an
+/// object of `key path -> hash` pairs:
+///
+/// ```json
+/// {
+///   "de": { "menu.file": "a1b2c3d4e5f60718" }
+/// }
+/// ```
+///
+/// When the source string under a key path no longer matches its stored hash,
+/// the source text was edited since the target was translated and the target
+/// value is outdated. Key paths without a stored hash are never considered
+/// outdated, so existing target files are taken as up to date on the first
+/// run.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SourceHashes {
+    entries: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl SourceHashes {
+    /// Loads the hashes from `path`, returning an empty set if the file does
+    /// not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but cannot be read or is not a
+    /// valid hashes file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        if !fs::exists(path)? {
+            return Ok(Self::default());
+        }
+
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Atomically writes the hashes to `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        write_atomic(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Returns `true` if `phrase`, the source string at `key_path`, differs
+    /// from the one the `target_lang` value was translated from.
+    pub fn is_outdated(&self, target_lang: &str, key_path: &str, phrase: &str) -> bool {
+        self.entries
+            .get(target_lang)
+            .and_then(|hashes| hashes.get(key_path))
+            .is_some_and(|stored| *stored != hash(phrase))
+    }
+
+    /// Removes the outdated values from `target`, the `target_lang`
+    /// translation of `source`, so they are translated again. Values at key
+    /// paths ignored by `ignore` are kept. Returns the key paths of the
+    /// removed values, see [`remove_changed`].
+    pub fn remove_outdated(
+        &self,
+        source: &Value,
+        target: &mut Map<String, Value>,
+        target_lang: &str,
+        ignore: &IgnoreRules,
+    ) -> Vec<String> {
+        remove_changed(source, target, |key_path, phrase| {
+            !ignore.is_ignored(key_path) && self.is_outdated(target_lang, key_path, phrase)
+        })
+    }
+
+    /// Records the strings of `source` selected by `filter` as the ones the
+    /// `target_lang` file is now translated from. Hashes of other key paths
+    /// are kept.
+    pub fn record(&mut self, source: &Value, target_lang: &str, filter: &KeyFilter) {
+        let hashes = self.entries.entry(target_lang.to_string()).or_default();
+
+        for (key_path, phrase) in source_strings(source, filter) {
+            hashes.insert(key_path, hash(phrase));
+        }
+    }
+}
+
+/// Hashes `phrase` with 64-bit FNV-1a, which, unlike the standard library's
+/// hasher, is stable across Rust versions and platforms.
+pub(crate) fn hash(phrase: &str) -> String {
+    let hash = phrase
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+
+    format!("{hash:016x}")
+}
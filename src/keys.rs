@@ -0,0 +1,229 @@
+use crate::filter::KeyFilter;
+use crate::utils::key_path;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Collects the object keys of `source` whose key path is selected by
+/// `filter` into `translations`, as untranslated entries.
+///
+/// Keys are collected in objects nested in arrays as well, where the index is
+/// part of the key path, as in `steps.0.title`.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::filter::KeyFilter;
+/// use q_translate::keys::gather_keys;
+/// use serde_json::json;
+/// use std::collections::HashMap;
+///
+/// let source = json!({"status": {"active": "Active", "archived": "Archived"}, "title": "Jobs"});
+/// let mut translations = HashMap::new();
+///
+/// gather_keys(&source, &KeyFilter::new(&["status.*"], &[] as &[&str]), &mut translations);
+///
+/// assert_eq!(translations.len(), 2);
+/// assert_eq!(translations["active"], "");
+/// ```
+pub fn gather_keys(source: &Value, filter: &KeyFilter, translations: &mut HashMap<String, String>) {
+    walk_objects(source, "", &mut |parent, map| {
+        for key in map.keys() {
+            if filter.matches(&key_path(parent, key)) {
+                translations.entry(key.clone()).or_default();
+            }
+        }
+    });
+}
+
+/// Translated object keys of a source file, see
+/// [`TranslationOptions::translate_keys`](crate::utils::TranslationOptions::translate_keys).
+///
+/// Target files are read and merged with the keys of the source, so
+/// [`KeyTranslations::untranslate`] puts the source keys back before a run and
+/// [`KeyTranslations::translate`] renames them again afterwards, keeping the
+/// order of every object.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::filter::KeyFilter;
+/// use q_translate::keys::KeyTranslations;
+/// use serde_json::json;
+/// use std::collections::HashMap;
+///
+/// let source = json!({"status": {"active": "Active", "archived": "Archived"}});
+/// let filter = KeyFilter::new(&["status.*"], &[] as &[&str]);
+/// let translations = HashMap::from([
+///     ("active".to_string(), "aktiv".to_string()),
+///     ("archived".to_string(), "archiviert".to_string()),
+/// ]);
+/// let keys = KeyTranslations::new(&source, &filter, &translations);
+///
+/// let target = json!({"status": {"active": "Aktiv", "archived": "Archiviert"}});
+/// let translated = keys.translate(target.as_object().unwrap().clone());
+/// assert_eq!(
+///     Value::Object(translated.clone()),
+///     json!({"status": {"aktiv": "Aktiv", "archiviert": "Archiviert"}})
+/// );
+/// assert_eq!(Value::Object(keys.untranslate(translated)), target);
+/// # use serde_json::Value;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyTranslations {
+    /// Translated keys by the key path of their source key.
+    translated: HashMap<String, String>,
+    /// Source keys by the key path of their object and their translation.
+    sources: HashMap<(String, String), String>,
+}
+
+impl KeyTranslations {
+    /// Takes the translations of the keys of `source` selected by `filter`
+    /// from `translations`, as filled by [`gather_keys`] and translated.
+    ///
+    /// Keys without a translation keep their name. So does a key whose
+    /// translation equals another key of the same object, or the translation
+    /// of another key, since objects cannot hold the same key twice; this is
+    /// logged as a warning.
+    pub fn new(source: &Value, filter: &KeyFilter, translations: &HashMap<String, String>) -> Self {
+        let mut keys = Self::default();
+
+        walk_objects(source, "", &mut |parent, map| {
+            let mut names: Vec<(&String, Option<&String>)> = map
+                .keys()
+                .map(|key| {
+                    let translated = translations
+                        .get(key)
+                        .filter(|t| !t.is_empty() && *t != key)
+                        .filter(|_| filter.matches(&key_path(parent, key)));
+                    (key, translated)
+                })
+                .collect();
+
+            // Reverting a clashing key to its source name may clash with
+            // another translation, so repeat until every name is unique.
+            loop {
+                let mut counts: HashMap<&String, usize> = HashMap::new();
+                for (key, translated) in &names {
+                    *counts.entry(translated.unwrap_or(key)).or_default() += 1;
+                }
+                let mut reverted = false;
+                for (key, translated) in &mut names {
+                    if let Some(name) = translated.filter(|name| counts[name] > 1) {
+                        let path = key_path(parent, key);
+                        warn!(key = %path, name = %name, "translated key clashes with another key, keeping it");
+                        *translated = None;
+                        reverted = true;
+                    }
+                }
+                if !reverted {
+                    break;
+                }
+            }
+
+            for (key, translated) in names {
+                if let Some(translated) = translated {
+                    keys.translated
+                        .insert(key_path(parent, key), translated.clone());
+                    keys.sources
+                        .insert((parent.to_string(), translated.clone()), key.clone());
+                }
+            }
+        });
+        keys
+    }
+
+    /// Returns `true` if no key is translated.
+    pub fn is_empty(&self) -> bool {
+        self.translated.is_empty()
+    }
+
+    /// Renames the source keys of `map` to their translations. A source key
+    /// is kept if its object holds a key named like its translation already.
+    pub fn translate(&self, map: Map<String, Value>) -> Map<String, Value> {
+        self.rename(map, "", &|parent, key, map| {
+            let path = key_path(parent, key);
+            let name = self
+                .translated
+                .get(&path)
+                .filter(|name| !map.contains_key(*name))
+                .map_or(key, String::as_str)
+                .to_string();
+            (name, path)
+        })
+    }
+
+    /// Renames the translated keys of `map` back to their source keys. A
+    /// translated key is kept if its object holds the source key as well.
+    pub fn untranslate(&self, map: Map<String, Value>) -> Map<String, Value> {
+        self.rename(map, "", &|parent, key, map| {
+            let source = self
+                .sources
+                .get(&(parent.to_string(), key.to_string()))
+                .filter(|source| !map.contains_key(*source))
+                .map_or(key, String::as_str)
+                .to_string();
+            let path = key_path(parent, &source);
+            (source, path)
+        })
+    }
+
+    /// Rebuilds `map` and the objects nested in it with the keys returned by
+    /// `name`, which is called with the key path of the object, the key and
+    /// the object and returns the new key and its key path in the source.
+    fn rename(
+        &self,
+        map: Map<String, Value>,
+        parent: &str,
+        name: &impl Fn(&str, &str, &Map<String, Value>) -> (String, String),
+    ) -> Map<String, Value> {
+        let names: Vec<(String, String)> = map.keys().map(|key| name(parent, key, &map)).collect();
+
+        map.into_iter()
+            .zip(names)
+            .map(|((_, value), (key, path))| (key, self.rename_value(value, &path, name)))
+            .collect()
+    }
+
+    fn rename_value(
+        &self,
+        value: Value,
+        path: &str,
+        name: &impl Fn(&str, &str, &Map<String, Value>) -> (String, String),
+    ) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(self.rename(map, path, name)),
+            Value::Array(items) => Value::Array(
+                items
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, item)| self.rename_value(item, &key_path(path, i), name))
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+}
+
+/// Calls `visit` with the key path and contents of every object in `value`,
+/// including `value` itself.
+fn walk_objects<'a>(
+    value: &'a Value,
+    path: &str,
+    visit: &mut impl FnMut(&str, &'a Map<String, Value>),
+) {
+    match value {
+        Value::Object(map) => {
+            visit(path, map);
+            for (key, value) in map {
+                walk_objects(value, &key_path(path, key), visit);
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                walk_objects(item, &key_path(path, i), visit);
+            }
+        }
+        _ => {}
+    }
+}
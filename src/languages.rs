@@ -0,0 +1,317 @@
+use thiserror::Error;
+
+/// Primary language subtags known to the translation providers, with their
+/// English names: the ISO 639-1 codes and the ISO 639-2/3 codes of languages
+/// without one that are supported by at least one provider.
+pub const KNOWN_LANGUAGES: &[(&str, &str)] = &[
+    ("aa", "Afar"),
+    ("ab", "Abkhazian"),
+    ("af", "Afrikaans"),
+    ("ak", "Akan"),
+    ("am", "Amharic"),
+    ("an", "Aragonese"),
+    ("ar", "Arabic"),
+    ("as", "Assamese"),
+    ("av", "Avaric"),
+    ("ay", "Aymara"),
+    ("az", "Azerbaijani"),
+    ("ba", "Bashkir"),
+    ("be", "Belarusian"),
+    ("bg", "Bulgarian"),
+    ("bho", "Bhojpuri"),
+    ("bi", "Bislama"),
+    ("bm", "Bambara"),
+    ("bn", "Bengali"),
+    ("bo", "Tibetan"),
+    ("br", "Breton"),
+    ("bs", "Bosnian"),
+    ("ca", "Catalan"),
+    ("ce", "Chechen"),
+    ("ceb", "Cebuano"),
+    ("ch", "Chamorro"),
+    ("ckb", "Central Kurdish"),
+    ("co", "Corsican"),
+    ("cr", "Cree"),
+    ("cs", "Czech"),
+    ("cv", "Chuvash"),
+    ("cy", "Welsh"),
+    ("da", "Danish"),
+    ("de", "German"),
+    ("doi", "Dogri"),
+    ("dv", "Dhivehi"),
+    ("dz", "Dzongkha"),
+    ("ee", "Ewe"),
+    ("el", "Greek"),
+    ("en", "English"),
+    ("eo", "Esperanto"),
+    ("es", "Spanish"),
+    ("et", "Estonian"),
+    ("eu", "Basque"),
+    ("fa", "Persian"),
+    ("ff", "Fulah"),
+    ("fi", "Finnish"),
+    ("fil", "Filipino"),
+    ("fj", "Fijian"),
+    ("fo", "Faroese"),
+    ("fr", "French"),
+    ("fy", "Western Frisian"),
+    ("ga", "Irish"),
+    ("gd", "Scottish Gaelic"),
+    ("gl", "Galician"),
+    ("gn", "Guarani"),
+    ("gom", "Konkani"),
+    ("gu", "Gujarati"),
+    ("gv", "Manx"),
+    ("ha", "Hausa"),
+    ("haw", "Hawaiian"),
+    ("he", "Hebrew"),
+    ("hi", "Hindi"),
+    ("hmn", "Hmong"),
+    ("ho", "Hiri Motu"),
+    ("hr", "Croatian"),
+    ("ht", "Haitian Creole"),
+    ("hu", "Hungarian"),
+    ("hy", "Armenian"),
+    ("hz", "Herero"),
+    ("ia", "Interlingua"),
+    ("id", "Indonesian"),
+    ("ie", "Interlingue"),
+    ("ig", "Igbo"),
+    ("ii", "Sichuan Yi"),
+    ("ik", "Inupiaq"),
+    ("ilo", "Ilocano"),
+    ("io", "Ido"),
+    ("is", "Icelandic"),
+    ("it", "Italian"),
+    ("iu", "Inuktitut"),
+    ("ja", "Japanese"),
+    ("jv", "Javanese"),
+    ("ka", "Georgian"),
+    ("kg", "Kongo"),
+    ("ki", "Kikuyu"),
+    ("kj", "Kuanyama"),
+    ("kk", "Kazakh"),
+    ("kl", "Kalaallisut"),
+    ("km", "Khmer"),
+    ("kn", "Kannada"),
+    ("ko", "Korean"),
+    ("kr", "Kanuri"),
+    ("kri", "Krio"),
+    ("ks", "Kashmiri"),
+    ("ku", "Kurdish"),
+    ("kv", "Komi"),
+    ("kw", "Cornish"),
+    ("ky", "Kyrgyz"),
+    ("la", "Latin"),
+    ("lb", "Luxembourgish"),
+    ("lg", "Ganda"),
+    ("li", "Limburgish"),
+    ("ln", "Lingala"),
+    ("lo", "Lao"),
+    ("lt", "Lithuanian"),
+    ("lu", "Luba-Katanga"),
+    ("lus", "Mizo"),
+    ("lv", "Latvian"),
+    ("mai", "Maithili"),
+    ("mg", "Malagasy"),
+    ("mh", "Marshallese"),
+    ("mi", "Maori"),
+    ("mk", "Macedonian"),
+    ("ml", "Malayalam"),
+    ("mn", "Mongolian"),
+    ("mni", "Meitei"),
+    ("mr", "Marathi"),
+    ("ms", "Malay"),
+    ("mt", "Maltese"),
+    ("my", "Burmese"),
+    ("na", "Nauru"),
+    ("nb", "Norwegian Bokmål"),
+    ("nd", "North Ndebele"),
+    ("ne", "Nepali"),
+    ("ng", "Ndonga"),
+    ("nl", "Dutch"),
+    ("nn", "Norwegian Nynorsk"),
+    ("no", "Norwegian"),
+    ("nr", "South Ndebele"),
+    ("nso", "Northern Sotho"),
+    ("nv", "Navajo"),
+    ("ny", "Chichewa"),
+    ("oc", "Occitan"),
+    ("oj", "Ojibwa"),
+    ("om", "Oromo"),
+    ("or", "Odia"),
+    ("os", "Ossetian"),
+    ("pa", "Punjabi"),
+    ("pi", "Pali"),
+    ("pl", "Polish"),
+    ("ps", "Pashto"),
+    ("pt", "Portuguese"),
+    ("qu", "Quechua"),
+    ("rm", "Romansh"),
+    ("rn", "Rundi"),
+    ("ro", "Romanian"),
+    ("ru", "Russian"),
+    ("rw", "Kinyarwanda"),
+    ("sa", "Sanskrit"),
+    ("sc", "Sardinian"),
+    ("sd", "Sindhi"),
+    ("se", "Northern Sami"),
+    ("sg", "Sango"),
+    ("si", "Sinhala"),
+    ("sk", "Slovak"),
+    ("sl", "Slovenian"),
+    ("sm", "Samoan"),
+    ("sn", "Shona"),
+    ("so", "Somali"),
+    ("sq", "Albanian"),
+    ("sr", "Serbian"),
+    ("ss", "Swati"),
+    ("st", "Southern Sotho"),
+    ("su", "Sundanese"),
+    ("sv", "Swedish"),
+    ("sw", "Swahili"),
+    ("ta", "Tamil"),
+    ("te", "Telugu"),
+    ("tg", "Tajik"),
+    ("th", "Thai"),
+    ("ti", "Tigrinya"),
+    ("tk", "Turkmen"),
+    ("tl", "Tagalog"),
+    ("tn", "Tswana"),
+    ("to", "Tongan"),
+    ("tr", "Turkish"),
+    ("ts", "Tsonga"),
+    ("tt", "Tatar"),
+    ("tw", "Twi"),
+    ("ty", "Tahitian"),
+    ("ug", "Uyghur"),
+    ("uk", "Ukrainian"),
+    ("ur", "Urdu"),
+    ("uz", "Uzbek"),
+    ("ve", "Venda"),
+    ("vi", "Vietnamese"),
+    ("vo", "Volapük"),
+    ("wa", "Walloon"),
+    ("wo", "Wolof"),
+    ("xh", "Xhosa"),
+    ("yi", "Yiddish"),
+    ("yo", "Yoruba"),
+    ("yue", "Cantonese"),
+    ("za", "Zhuang"),
+    ("zh", "Chinese"),
+    ("zu", "Zulu"),
+];
+
+/// Maximum number of close matches listed in an [`UnknownLanguage`] error.
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Error raised for a language code whose primary subtag is not in
+/// [`KNOWN_LANGUAGES`] or which is not a well-formed language tag.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unknown language code {code:?}{}", did_you_mean(.suggestions))]
+pub struct UnknownLanguage {
+    /// The code as it was given.
+    pub code: String,
+    /// Known codes close to `code`, with their names, e.g. `de (German)`.
+    pub suggestions: Vec<String>,
+}
+
+fn did_you_mean(suggestions: &[String]) -> String {
+    match suggestions.is_empty() {
+        true => String::new(),
+        false => format!(", did you mean {}?", suggestions.join(", ")),
+    }
+}
+
+/// Validates the language code `code` and returns it in its canonical BCP 47
+/// form: underscores become hyphens, the language is lowercase, a script is
+/// titlecase and a region uppercase.
+///
+/// # Errors
+///
+/// Returns [`UnknownLanguage`] with close matches if the primary language is
+/// unknown or a subtag is malformed.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::languages::normalize;
+///
+/// assert_eq!(normalize("en_us").unwrap(), "en-US");
+/// assert_eq!(normalize("zh-hant-tw").unwrap(), "zh-Hant-TW");
+///
+/// let err = normalize("german").unwrap_err();
+/// assert_eq!(err.suggestions, ["de (German)"]);
+/// ```
+pub fn normalize(code: &str) -> Result<String, UnknownLanguage> {
+    let unknown = || UnknownLanguage {
+        code: code.to_string(),
+        suggestions: suggestions(code),
+    };
+
+    let mut subtags = code.split(['-', '_']);
+    let language = subtags.next().unwrap_or_default().to_lowercase();
+    if !KNOWN_LANGUAGES.iter().any(|(known, _)| *known == language) {
+        return Err(unknown());
+    }
+
+    let mut normalized = language;
+    for subtag in subtags {
+        let canonical = match subtag.len() {
+            // Script, e.g. `Hant`.
+            4 if subtag.chars().all(|c| c.is_ascii_alphabetic()) => {
+                let (first, rest) = subtag.split_at(1);
+                first.to_uppercase() + &rest.to_lowercase()
+            }
+            // Region, e.g. `US` or `419`.
+            2 if subtag.chars().all(|c| c.is_ascii_alphabetic()) => subtag.to_uppercase(),
+            3 if subtag.chars().all(|c| c.is_ascii_digit()) => subtag.to_string(),
+            _ => return Err(unknown()),
+        };
+        normalized.push('-');
+        normalized.push_str(&canonical);
+    }
+    Ok(normalized)
+}
+
+/// Known languages whose name starts with `code`, as in `german`, or whose
+/// code is at most a single edit away from the primary subtag of `code`,
+/// closest first.
+fn suggestions(code: &str) -> Vec<String> {
+    let code = code.to_lowercase();
+    let language = code.split(['-', '_']).next().unwrap_or_default();
+
+    let mut matches: Vec<(usize, &str, &str)> = KNOWN_LANGUAGES
+        .iter()
+        .filter_map(
+            |(known, name)| match name.to_lowercase().starts_with(&code) {
+                true => Some((0, *known, *name)),
+                false => Some((edit_distance(known, language), *known, *name))
+                    .filter(|(distance, _, _)| *distance <= 1),
+            },
+        )
+        .collect();
+    matches.sort_by_key(|(distance, _, _)| *distance);
+
+    matches
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, known, name)| format!("{known} ({name})"))
+        .collect()
+}
+
+/// Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
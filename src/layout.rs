@@ -0,0 +1,204 @@
+use serde_json::{Map, Value};
+
+/// Delimiter separating the segments of a flat key, as in `home.header.title`.
+pub const DEFAULT_DELIMITER: &str = ".";
+
+/// Converts nested objects into a single level of flat keys.
+///
+/// Nested keys are joined with `delimiter`, so `{"home": {"title": "Hi"}}`
+/// becomes `{"home.title": "Hi"}`. Keys keep the order in which they appear
+/// in the nested structure. Arrays and other values are kept as they are,
+/// empty objects are dropped. An already flat map is returned unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::layout::flatten;
+/// use serde_json::{json, Value};
+///
+/// let nested = json!({"home": {"header": {"title": "Welcome"}}, "ok": "OK"});
+/// let flat = flatten(nested.as_object().unwrap().clone(), ".");
+///
+/// assert_eq!(Value::Object(flat), json!({"home.header.title": "Welcome", "ok": "OK"}));
+/// ```
+pub fn flatten(map: Map<String, Value>, delimiter: &str) -> Map<String, Value> {
+    let mut flat = Map::new();
+    flatten_into(&mut flat, map, None, delimiter);
+    flat
+}
+
+fn flatten_into(
+    flat: &mut Map<String, Value>,
+    map: Map<String, Value>,
+    prefix: Option<&str>,
+    delimiter: &str,
+) {
+    for (key, value) in map {
+        let key = match prefix {
+            Some(prefix) => format!("{prefix}{delimiter}{key}"),
+            None => key,
+        };
+
+        match value {
+            Value::Object(map) => flatten_into(flat, map, Some(&key), delimiter),
+            value => {
+                flat.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Converts flat keys into nested objects, the inverse of [`flatten`].
+///
+/// Every key is split at `delimiter`, so `{"home.title": "Hi"}` becomes
+/// `{"home": {"title": "Hi"}}`. Keys whose path is already taken by a
+/// non-object value, such as `a.b` next to a string `a`, are kept unsplit.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::layout::expand;
+/// use serde_json::{json, Value};
+///
+/// let flat = json!({"home.header.title": "Welcome", "home.ok": "OK"});
+/// let nested = expand(flat.as_object().unwrap().clone(), ".");
+///
+/// assert_eq!(Value::Object(nested), json!({"home": {"header": {"title": "Welcome"}, "ok": "OK"}}));
+/// ```
+pub fn expand(map: Map<String, Value>, delimiter: &str) -> Map<String, Value> {
+    let mut nested = Map::new();
+
+    for (key, value) in map {
+        let value = match value {
+            Value::Object(map) => Value::Object(expand(map, delimiter)),
+            value => value,
+        };
+
+        if let Err(value) = insert_path(&mut nested, &key, delimiter, value) {
+            nested.insert(key, value);
+        }
+    }
+
+    nested
+}
+
+/// Inserts `value` at the path `key` split at `delimiter`, creating the
+/// intermediate objects. Gives the value back if the path is blocked by a
+/// non-object value.
+fn insert_path(
+    map: &mut Map<String, Value>,
+    key: &str,
+    delimiter: &str,
+    value: Value,
+) -> Result<(), Value> {
+    let Some((head, rest)) = key.split_once(delimiter).filter(|_| !delimiter.is_empty()) else {
+        return match map.get_mut(key) {
+            Some(Value::Object(existing)) => match value {
+                Value::Object(value) => {
+                    existing.extend(value);
+                    Ok(())
+                }
+                value => Err(value),
+            },
+            Some(_) => Err(value),
+            None => {
+                map.insert(key.to_string(), value);
+                Ok(())
+            }
+        };
+    };
+
+    match map.entry(head).or_insert_with(|| Value::Object(Map::new())) {
+        Value::Object(child) => insert_path(child, rest, delimiter, value),
+        _ => Err(value),
+    }
+}
+
+/// Sorts the keys of `map` alphabetically, recursing into nested objects and
+/// the objects inside arrays. Array elements keep their order.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::layout::sort_keys;
+/// use serde_json::{json, Value};
+///
+/// let map = json!({"menu": {"save": "Save", "open": "Open"}, "about": "About"});
+/// let sorted = sort_keys(map.as_object().unwrap().clone());
+///
+/// assert_eq!(
+///     serde_json::to_string(&Value::Object(sorted)).unwrap(),
+///     r#"{"about":"About","menu":{"open":"Open","save":"Save"}}"#
+/// );
+/// ```
+pub fn sort_keys(map: Map<String, Value>) -> Map<String, Value> {
+    let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    entries
+        .into_iter()
+        .map(|(key, value)| (key, sort_value(value)))
+        .collect()
+}
+
+fn sort_value(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(sort_keys(map)),
+        Value::Array(items) => Value::Array(items.into_iter().map(sort_value).collect()),
+        value => value,
+    }
+}
+
+/// Deep-merges `other` into `map`: nested objects are merged key by key, and
+/// any other value of `other` replaces the one in `map`.
+///
+/// Returns the key paths, joined with [`DEFAULT_DELIMITER`], whose existing
+/// value was replaced by a different one.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::layout::merge;
+/// use serde_json::{json, Value};
+///
+/// let mut map = json!({"home": {"title": "Home"}, "ok": "OK"}).as_object().unwrap().clone();
+/// let other = json!({"home": {"title": "Start", "intro": "Hi"}, "ok": "OK"});
+/// let conflicts = merge(&mut map, other.as_object().unwrap().clone());
+///
+/// assert_eq!(Value::Object(map), json!({"home": {"title": "Start", "intro": "Hi"}, "ok": "OK"}));
+/// assert_eq!(conflicts, ["home.title"]);
+/// ```
+pub fn merge(map: &mut Map<String, Value>, other: Map<String, Value>) -> Vec<String> {
+    let mut conflicts = vec![];
+    merge_into(map, other, "", &mut conflicts);
+    conflicts
+}
+
+fn merge_into(
+    map: &mut Map<String, Value>,
+    other: Map<String, Value>,
+    prefix: &str,
+    conflicts: &mut Vec<String>,
+) {
+    for (key, value) in other {
+        let path = match prefix {
+            "" => key.clone(),
+            prefix => format!("{prefix}{DEFAULT_DELIMITER}{key}"),
+        };
+
+        match (map.get_mut(&key), value) {
+            (Some(Value::Object(existing)), Value::Object(value)) => {
+                merge_into(existing, value, &path, conflicts);
+            }
+            (Some(existing), value) => {
+                if *existing != value {
+                    conflicts.push(path);
+                }
+                *existing = value;
+            }
+            (None, value) => {
+                map.insert(key, value);
+            }
+        }
+    }
+}
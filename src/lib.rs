@@ -1,2 +1,21 @@
+pub mod cache;
+pub mod casing;
+pub mod config;
+pub mod context;
+pub mod diff;
+pub mod error;
+pub mod files;
+pub mod filter;
+pub mod format;
+pub mod git;
+pub mod hashes;
+pub mod keys;
+pub mod languages;
+pub mod layout;
+pub mod pipeline;
+pub mod placeholders;
+pub mod providers;
+pub mod rate_limit;
 pub mod translate;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
+pub mod whitespace;
@@ -1,24 +1,488 @@
-use clap::Parser;
-use q_translate::utils;
-use std::collections::HashMap;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, ValueEnum};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use owo_colors::{OwoColorize, Stream, Style};
+use q_translate::cache::{DEFAULT_CACHE_PATH, TranslationCache};
+use q_translate::config::Config;
+use q_translate::context::{TranslationContext, read_key_notes};
+use q_translate::diff::TargetDiff;
+use q_translate::error::QTranslateError;
+use q_translate::files::{backup, source_files, with_final_newline, write_atomic};
+use q_translate::filter::{DEFAULT_IGNORE_PATH, IgnoreRules, KeyFilter};
+use q_translate::format::{Format, FormatError, JsonStyle, csv, jsonc, po, properties};
+use q_translate::git;
+use q_translate::hashes::{DEFAULT_HASHES_PATH, SourceHashes};
+use q_translate::languages;
+use q_translate::layout::{self, DEFAULT_DELIMITER};
+use q_translate::pipeline::{self, TranslateSummary};
+use q_translate::placeholders::{
+    DEFAULT_PATTERNS, HTML_TAG_PATTERN, Placeholders, URL_PATTERN, VERBATIM_PATTERNS, read_glossary,
+    read_termbase,
+};
+use q_translate::providers::{
+    AUTO_DETECT, CachedProvider, DEFAULT_BATCH_CACHE_DIR, DETECTED_LANGUAGES_TARGET,
+    FallbackProvider, Provider, ProviderSettings, TextFormat, TranslationProvider,
+    is_same_language, set_proxy,
+};
+use q_translate::rate_limit::RateLimiter;
+use q_translate::translate::split_batches;
+use q_translate::utils::{self, OnError, TranslationOptions};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::fs::File;
-use std::io::Write;
+use std::io::{self, Write};
+use std::num::{NonZeroU16, NonZeroU32};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use tracing_subscriber::EnvFilter;
 
+/// Default number of translation requests in flight at the same time.
+const DEFAULT_CONCURRENCY: u16 = 5;
+
+/// Number of files of `--source-dir` translated at the same time by default.
+const DEFAULT_CONCURRENT_FILES: u16 = 4;
+
+/// Exit code of invalid command-line arguments, `EX_USAGE` of `sysexits.h`.
+/// Clap exits with 2 instead, which is reserved for `--verify` finding
+/// missing translations.
+const USAGE_EXIT_CODE: i32 = 64;
+
+/// Format of the written target files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// The format of the source file
+    #[default]
+    Source,
+    /// Gettext `.po` files with the source phrases as `msgid` and their key paths as references
+    Po,
+}
+
+/// Layout of written JSON target files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum OutputStyle {
+    /// Indented, one entry per line
+    #[default]
+    Pretty,
+    /// Minified on a single line
+    Compact,
+}
+
+/// Prints a status line of `args`' run to stdout, or to stderr if the
+/// translated file is written to stdout.
+macro_rules! status {
+    ($args:expr, $($arg:tt)*) => {
+        match $args.writes_stdout() {
+            true => eprintln!($($arg)*),
+            false => println!($($arg)*),
+        }
+    };
+}
+
+// Settings that can also be given in the configuration file are optional
+// here; see `Args::apply_config` for how both are combined.
 #[derive(Parser)]
 struct Args {
+    /// Source language code, or `auto` to let the provider detect it
     #[arg(short, long)]
     source_lang: String,
 
+    /// Path of the source locale file, instead of `{assets_dir}/{source_lang}.{ext}`; repeat to
+    /// deep-merge several files in the same format, later files winning on conflicting keys
+    #[arg(long, required_if_eq("source_lang", AUTO_DETECT))]
+    source_file: Vec<String>,
+
+    /// Path of the target locale file, instead of `{assets_dir}/{target_lang}.{ext}`; requires a
+    /// single target language
+    #[arg(long, conflicts_with_all = ["stdin", "stdout"])]
+    target_file: Option<String>,
+
+    /// Path the translated file is written to, e.g. `/tmp/de-preview.json`, while the existing
+    /// translations are still read from the target file; requires a single target language
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["stdin", "stdout"])]
+    output: Option<String>,
+
+    /// Create the missing directories of `--output`
+    #[arg(long, requires = "output")]
+    mkdir: bool,
+
+    /// Format of the locale files, instead of detecting it from the source file's extension
+    #[arg(long, value_enum)]
+    input_format: Option<Format>,
+
+    /// Directory of source files to translate instead of a single source file; every file below
+    /// it is written to the same relative path in the sibling directory named after each target
+    /// language, e.g. `locales/en/common.json` to `locales/de/common.json`
+    #[arg(long, conflicts_with_all = [
+        "source_file", "target_file", "output", "stdin", "stdout", "output_format", "dry_run",
+        "verify", "sample", "prune", "diff", "translate_keys", "since", "timeout_total",
+        "flatten", "expand", "output_style", "indent", "no_final_newline", "sort_keys", "backup",
+        "backup_dir", "hashes_path", "context_file",
+    ])]
+    source_dir: Option<String>,
+
+    /// Maximum number of files of `--source-dir` translated at the same time [default: 4]
+    #[arg(long, requires = "source_dir", value_parser = clap::value_parser!(u16).range(1..))]
+    max_concurrent_files: Option<u16>,
+
+    /// Column of a CSV locale file holding the source strings [default: the source language]
+    #[arg(long)]
+    source_column: Option<String>,
+
+    /// Column of a CSV locale file the translations are written to, added if missing; requires a
+    /// single target language [default: the target language]
+    #[arg(long)]
+    target_column: Option<String>,
+
+    /// Read the source file from standard input, as JSON unless `--input-format` is given, and
+    /// write the translation to standard output instead of the assets directory; implies `--stdout`
+    #[arg(long, conflicts_with = "source_file")]
+    stdin: bool,
+
+    /// Write the translated file to standard output instead of the target file, printing the
+    /// status messages to stderr; requires a single target language
+    #[arg(long)]
+    stdout: bool,
+
+    /// Target language codes, either comma-separated (`de,pl,fr`) or repeated [default: `target_langs` from the config file]
+    #[arg(short, long, value_delimiter = ',')]
+    target_lang: Vec<String>,
+
+    /// Send the language codes to the provider as given, instead of checking them against the
+    /// known languages and normalizing them (e.g. `en_us` to `en-US`)
+    #[arg(long)]
+    no_validate_lang: bool,
+
+    /// Translate target languages that are the same as the source language, e.g. `en-GB` from
+    /// `en-US`, instead of failing
+    #[arg(long)]
+    allow_same_lang: bool,
+
+    /// Translation backend used for all requests [default: google]
+    #[arg(short, long, value_enum)]
+    provider: Option<Provider>,
+
+    /// Translation backend that batches are sent to after the previous backend failed them
+    /// with all retries; may be repeated to try several in order. Uses the API key from the
+    /// backend's default environment variable and its default endpoint
+    #[arg(long, value_enum, value_name = "PROVIDER")]
+    fallback: Vec<Provider>,
+
+    /// API key of the provider, instead of reading it from an environment variable
+    #[arg(long)]
+    api_key: Option<String>,
+
+    /// Environment variable holding the API key, instead of the provider's default
+    #[arg(long)]
+    api_key_env: Option<String>,
+
+    /// Endpoint URL of the Google Translate API (overriding `GOOGLE_TRANSLATE_BASE_URL`), of the
+    /// LibreTranslate instance used by `--provider libre`, of the Translator resource used by
+    /// `--provider azure`, or of the Chat Completions API used by `--provider openai`
+    #[arg(long)]
+    endpoint: Option<String>,
+
+    /// Model used by `--provider openai` [default: gpt-4o-mini]
+    #[arg(long)]
+    model: Option<String>,
+
+    /// File containing the system prompt used by `--provider openai`, where `{source_lang}` and
+    /// `{target_lang}` are replaced with the language codes
+    #[arg(long)]
+    prompt_file: Option<String>,
+
+    /// Description of what the strings are used for (e.g. "mobile banking app UI"), passed to
+    /// providers that support context, currently `--provider openai`
+    #[arg(long)]
+    context: Option<String>,
+
+    /// JSON file mapping key paths to notes on their strings, passed along like `--context`
+    /// [default: `{source}.context.json` next to the source file, if present]
+    #[arg(long)]
+    context_file: Option<String>,
+
+    /// Whether the phrases are plain text or contain inline HTML markup to keep intact
+    #[arg(long = "format", value_enum, default_value_t = TextFormat::Text)]
+    text_format: TextFormat,
+
+    /// Translate every line of multi-line strings separately and join them with the original line
+    /// breaks, so that paragraphs and blank lines are kept
+    #[arg(long)]
+    preserve_newlines: bool,
+
+    /// Collapse runs of spaces, tabs and line breaks inside source strings to a single space
+    /// before translating; line breaks are kept with `--preserve-newlines`. Off by default
+    #[arg(long)]
+    normalize_whitespace: bool,
+
+    /// Uppercase the translations of uppercase source strings and title-case those of Title Case
+    /// ones, as providers tend to change the capitalization of short labels
+    #[arg(long)]
+    match_case: bool,
+
+    /// Additional regular expression matching placeholders that must not be translated
+    #[arg(long = "placeholder-pattern")]
+    placeholder_patterns: Vec<String>,
+
+    /// Fail a target language if a translation lost or gained a placeholder, instead of keeping
+    /// the phrase as failed according to `--on-error`
+    #[arg(long)]
+    strict_placeholders: bool,
+
+    /// Additional regular expression matching whole values that are copied instead of translated
+    #[arg(long = "verbatim-pattern")]
+    verbatim_patterns: Vec<String>,
+
+    /// Translate values that consist of a single URL instead of copying them
+    #[arg(long)]
+    translate_urls: bool,
+
+    /// File with terms that must never be translated, as a JSON array or one term per line
+    #[arg(long)]
+    glossary_file: Option<String>,
+
+    /// Match glossary terms case-insensitively
+    #[arg(long)]
+    glossary_ignore_case: bool,
+
+    /// Forced translations of terms into one target language, as `LANG:FILE` where the file is a
+    /// JSON object mapping source terms to their translations, e.g. `de:termbase-de.json`; may
+    /// be repeated
+    #[arg(long, value_name = "LANG:FILE", value_parser = parse_termbase)]
+    termbase: Vec<(String, String)>,
+
+    /// Do not read or write the translation cache
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Location of the translation cache file
+    #[arg(long, default_value = DEFAULT_CACHE_PATH)]
+    cache_path: String,
+
+    /// Store the response to every batch in `.q-translate-cache/batches`, keyed by the hash of the
+    /// request, and replay it when the same batch is sent again
+    #[arg(long)]
+    cache_batches: bool,
+
+    /// Do not track which source strings the target files were translated from
+    #[arg(long)]
+    no_hashes: bool,
+
+    /// Location of the file storing the hashes of the translated source strings
+    #[arg(long, default_value = DEFAULT_HASHES_PATH)]
+    hashes_path: String,
+
+    /// Directory containing the locale files; skips probing of `src/assets/i18n` and `assets/i18n`
+    #[arg(long)]
+    assets_dir: Option<String>,
+
+    /// Translate only the source phrases missing from the target files, keeping the values
+    /// already present; this is the default
+    #[arg(long, conflicts_with = "overwrite")]
+    only_missing: bool,
+
+    /// Re-translate every source phrase, replacing values already present in the target files
+    #[arg(long, visible_alias = "all")]
+    overwrite: bool,
+
+    /// Remove keys from the target files that no longer exist in the source file
+    #[arg(long)]
+    prune: bool,
+
+    /// Only translate and write the keys matching this glob, e.g. `checkout.**`; may be repeated
+    #[arg(long)]
+    include: Vec<String>,
+
+    /// Leave the keys matching this glob untouched, even if they match `--include`; may be repeated
+    #[arg(long)]
+    exclude: Vec<String>,
+
+    /// Only translate the keys whose source string was added or changed since this git revision,
+    /// e.g. `origin/main`; their target values are translated again
+    #[arg(long, value_name = "REV", conflicts_with_all = ["stdin", "verify"])]
+    since: Option<String>,
+
+    /// Key paths of the source strings changed since `--since`.
+    #[arg(skip)]
+    changed_since: Option<HashSet<String>>,
+
+    /// Also translate the object keys whose key path matches this glob, e.g. `status.*` for the
+    /// keys of `status`; may be repeated. Off by default
+    #[arg(long = "translate-keys", conflicts_with_all = ["dry_run", "verify"])]
+    translate_keys: Vec<String>,
+
+    /// File listing key path globs whose values are never written, removed or pruned, one per line;
+    /// `!` takes back earlier patterns, as in a `.gitignore` file
+    #[arg(long, default_value = DEFAULT_IGNORE_PATH)]
+    ignore_file: String,
+
+    /// Rules read from `--ignore-file`.
+    #[arg(skip)]
+    ignore: IgnoreRules,
+
+    /// Top-level keys to translate, e.g. `billing dashboard`; keys outside of them are left untouched
+    namespaces: Vec<String>,
+
+    /// What to do with phrases whose translation request failed after all retries
+    #[arg(long, value_enum, default_value_t = OnError::KeepSource)]
+    on_error: OnError,
+
+    /// Abort at the first failing batch of any target language without writing a file; same as `--on-error fail`
+    #[arg(long, conflicts_with_all = ["on_error", "continue_on_error"])]
+    fail_fast: bool,
+
+    /// Finish all other batches when one fails and exit with code 1 at the end if any key failed
+    #[arg(long)]
+    continue_on_error: bool,
+
+    /// Write flat `home.header.title` keys to the target files, flattening a nested source
+    #[arg(long, visible_alias = "flat", conflicts_with = "expand")]
+    flatten: bool,
+
+    /// Write nested objects to the target files, splitting the flat keys of the source
+    #[arg(long)]
+    expand: bool,
+
+    /// Format of the written target files
+    #[arg(long, value_enum, default_value_t = OutputFormat::Source)]
+    output_format: OutputFormat,
+
+    /// Layout of written JSON target files
+    #[arg(long, visible_alias = "format-json", value_enum, default_value_t = OutputStyle::Pretty)]
+    output_style: OutputStyle,
+
+    /// Number of spaces per indentation level with `--output-style pretty`
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(1..=8))]
+    indent: u8,
+
+    /// Do not end written target files with a newline
+    #[arg(long)]
+    no_final_newline: bool,
+
+    /// Sort the keys of written target files alphabetically at every level, instead of following
+    /// the order of the source file
+    #[arg(long)]
+    sort_keys: bool,
+
+    /// Copy every existing target file to `{file}.bak` before overwriting it
+    #[arg(long)]
+    backup: bool,
+
+    /// Directory the backups are written to instead of next to the target files; implies `--backup`
+    #[arg(long)]
+    backup_dir: Option<String>,
+
+    /// Delimiter between the segments of flat keys, used by `--flatten` and `--expand`
+    #[arg(long, default_value = DEFAULT_DELIMITER)]
+    key_delimiter: String,
+
+    /// Report untranslated phrases and the number of requests without calling the API or writing files
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Translate at most this many missing phrases per target language, leaving the others for a
+    /// later run
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Fail a target language without sending any request if its phrases add up to more than
+    /// this many characters, guarding against translating a huge file by accident
+    #[arg(long)]
+    max_chars_total: Option<usize>,
+
+    /// Check that every source string is translated in the target files, failing if any is missing
+    #[arg(long, conflicts_with = "dry_run")]
+    verify: bool,
+
+    /// Translate this many randomly picked source phrases and print them with their translations
+    /// for review, without reading or writing any target file
+    #[arg(long, conflicts_with_all = ["dry_run", "verify"])]
+    sample: Option<usize>,
+
+    /// Seed of the random choice of `--sample`, to pick the same phrases on every run
+    #[arg(long, requires = "sample")]
+    seed: Option<u64>,
+
+    /// Proxy all requests are sent through, e.g. `http://proxy.example.com:8080`, instead of
+    /// those of the `HTTPS_PROXY`, `HTTP_PROXY` and `ALL_PROXY` environment variables
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+
+    /// Seconds after which a single translation request is abandoned and retried
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    request_timeout_secs: u64,
+
+    /// Seconds after which the translation of the whole run is aborted; no target file is written
+    /// unless `--partial-write` is given
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    timeout_total: Option<u64>,
+
+    /// Write the phrases translated before `--timeout-total` ran out to the target files, leaving
+    /// the others missing
+    #[arg(long, requires = "timeout_total")]
+    partial_write: bool,
+
+    /// Whether `--timeout-total` ran out.
+    #[arg(skip)]
+    timed_out: bool,
+
+    /// Maximum number of translation requests in flight at the same time [default: 5]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    concurrency: Option<u16>,
+
+    /// Maximum number of phrases per request, capped at the provider's limit [default: the provider's limit]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    batch_size: Option<u16>,
+
+    /// Maximum number of characters per request, capped at the provider's limit [default: the provider's limit]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_chars_per_request: Option<u32>,
+
+    /// Maximum number of translation requests sent per second, including retries
+    #[arg(long)]
+    max_rps: Option<NonZeroU32>,
+
+    /// Maximum number of characters sent to the provider per second
+    #[arg(long)]
+    max_cps: Option<NonZeroU32>,
+
+    /// Configuration file to use instead of `q-translate.toml` in the current or home directory
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Write the statistics of the run to this file as JSON
+    #[arg(long)]
+    report_json: Option<String>,
+
+    /// Print the keys added, changed and removed in every target file
+    #[arg(long)]
+    diff: bool,
+
+    /// Color the output of `--diff` and the summary even when not writing to a terminal, unless
+    /// `NO_COLOR` is set
+    #[arg(long, requires = "diff")]
+    pretty_diff: bool,
+
+    /// Write the keys added, changed and removed in every target file to this file as JSON
+    #[arg(long)]
+    diff_json: Option<String>,
+
+    /// Do not display the progress bar
     #[arg(short, long)]
-    target_lang: String,
+    quiet: bool,
+
+    /// Log level of the diagnostics written to stderr (`error`, `warn`, `info`, `debug` or `trace`); overrides `RUST_LOG`
+    #[arg(long)]
+    log_level: Option<tracing::Level>,
 }
 
 /// # Description
 ///
 /// This tool recreates an existing translation file by automatically translating all text values into a chosen target language.
 ///
-/// It reads a structured JSON file (for example, an i18n resource file), walks through it recursively, and produces a new file with the same structure and key order. All string values are translated into the target language, while non-string values (numbers, booleans, arrays, nulls) are preserved as-is.
+/// It reads a structured JSON file (for example, an i18n resource file), walks through it recursively, and produces a new file with the same structure and key order. All string values, including those inside arrays, are translated into the target language, while non-string values (numbers, booleans, nulls) are preserved as-is.
 ///
 /// The result is a ready-to-use translation file that mirrors the original exactly, but with all human-readable text translated.
 ///
@@ -29,40 +493,1662 @@ struct Args {
 /// - Translates only string values
 /// - Copies non-string values without modification
 /// - Outputs a fully reconstructed file in the target language
+/// - Translates into several target languages in one run, reusing the parsed source
 #[tokio::main]
-async fn main() -> std::io::Result<()> {
-    let args = Args::parse();
+async fn main() -> ExitCode {
+    let args = Args::try_parse().unwrap_or_else(|err| exit_with(err));
+    init_logging(args.log_level);
+    // Colors are otherwise only used in a terminal, see `paint`.
+    if args.pretty_diff && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) {
+        owo_colors::set_override(true);
+    }
+
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+/// Prints the command-line error `err` and exits, with [`USAGE_EXIT_CODE`]
+/// unless `err` is the output of `--help` or `--version`.
+fn exit_with(err: clap::Error) -> ! {
+    let _ = err.print();
+    match err.use_stderr() {
+        true => std::process::exit(USAGE_EXIT_CODE),
+        false => std::process::exit(0),
+    }
+}
+
+/// Exits with a usage error of `kind` reporting `message`.
+fn usage_error(kind: ErrorKind, message: impl fmt::Display) -> ! {
+    exit_with(Args::command().error(kind, message))
+}
+
+/// Sends `tracing` events to stderr.
+///
+/// The filter is taken from `--log-level`, then from `RUST_LOG`; without
+/// either, only warnings and errors of q-translate itself are shown, along
+/// with the source languages detected with `--source-lang auto`.
+fn init_logging(log_level: Option<tracing::Level>) {
+    let filter = match log_level {
+        Some(level) => EnvFilter::new(format!("q_translate={level}")),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+            EnvFilter::new(format!("q_translate=warn,{DETECTED_LANGUAGES_TARGET}=info"))
+        }),
+    };
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+impl Args {
+    /// Fills the settings not given on the command line from `config`.
+    ///
+    /// Command-line flags take precedence over the configuration file, which
+    /// takes precedence over the built-in defaults.
+    fn apply_config(&mut self, config: Config) {
+        self.provider = self.provider.or(config.provider);
+        self.api_key_env = self.api_key_env.take().or(config.api_key_env);
+        self.assets_dir = self.assets_dir.take().or(config.assets_dir);
+        self.concurrency = self.concurrency.or(config.concurrency.map(NonZeroU16::get));
+        self.batch_size = self.batch_size.or(config.batch_size.map(NonZeroU16::get));
+
+        if self.target_lang.is_empty() {
+            self.target_lang = config.target_langs.unwrap_or_default();
+        }
+    }
+
+    /// Converts `map` into the key layout selected with `--flatten` or
+    /// `--expand`; without either flag it is returned unchanged.
+    fn convert_layout(&self, map: Map<String, Value>) -> Map<String, Value> {
+        match (self.flatten, self.expand) {
+            (true, _) => layout::flatten(map, &self.key_delimiter),
+            (_, true) => layout::expand(map, &self.key_delimiter),
+            _ => map,
+        }
+    }
+
+    /// The provider followed by the `--fallback` providers.
+    fn providers(&self) -> impl Iterator<Item = Provider> {
+        std::iter::once(self.provider.unwrap_or_default()).chain(self.fallback.iter().copied())
+    }
+
+    /// Maximum number of phrases per request accepted by all providers.
+    fn max_batch_size(&self) -> usize {
+        self.providers()
+            .map(Provider::max_batch_size)
+            .min()
+            .expect("at least one provider")
+    }
+
+    /// Number of phrases sent per request.
+    fn batch_size(&self) -> usize {
+        let max_batch_size = self.max_batch_size();
+
+        self.batch_size
+            .map_or(max_batch_size, |size| max_batch_size.min(size.into()))
+    }
+
+    /// Maximum number of characters sent per request.
+    fn max_chars_per_request(&self) -> usize {
+        let max_chars = self
+            .providers()
+            .map(Provider::max_chars_per_request)
+            .min()
+            .expect("at least one provider");
+
+        self.max_chars_per_request
+            .map_or(max_chars, |chars| max_chars.min(chars as usize))
+    }
+
+    /// Layout of written JSON files selected with `--output-style` and `--indent`.
+    fn json_style(&self) -> JsonStyle {
+        match self.output_style {
+            OutputStyle::Pretty => JsonStyle::Pretty {
+                indent: self.indent.into(),
+            },
+            OutputStyle::Compact => JsonStyle::Compact,
+        }
+    }
+
+    /// Language code sent to the provider for the `lang` given on the command
+    /// line, normalized by [`languages::normalize`] unless
+    /// `--no-validate-lang` is set. Exits with a usage error listing close
+    /// matches if the code is unknown.
+    fn language_code(&self, lang: &str) -> String {
+        if self.no_validate_lang || lang == AUTO_DETECT {
+            return lang.to_string();
+        }
+        languages::normalize(lang)
+            .unwrap_or_else(|err| usage_error(ErrorKind::InvalidValue, err))
+    }
+
+    /// Stream that [`status!`] writes to.
+    fn status_stream(&self) -> Stream {
+        match self.writes_stdout() {
+            true => Stream::Stderr,
+            false => Stream::Stdout,
+        }
+    }
+
+    /// Whether the translated file is written to standard output, with
+    /// `--stdout` or `--stdin`.
+    fn writes_stdout(&self) -> bool {
+        self.stdin || self.stdout
+    }
+
+    /// Whether the target files are compared with their previous contents,
+    /// with `--diff` or `--diff-json`.
+    fn wants_diff(&self) -> bool {
+        self.diff || self.diff_json.is_some()
+    }
+
+    /// Key paths taking part in the run, selected by `--include`, `--exclude`,
+    /// `--since` and the namespaces, without those of `--ignore-file`.
+    fn key_filter(&self) -> KeyFilter {
+        let filter = KeyFilter::new(&self.include, &self.exclude)
+            .with_namespaces(&self.namespaces)
+            .with_ignore(self.ignore.clone());
+        match &self.changed_since {
+            Some(changed) => filter.with_paths(changed.iter().cloned()),
+            None => filter,
+        }
+    }
+
+    /// Removes the values of `target` whose source string changed since
+    /// `--since`, so they are translated again, and returns their key paths.
+    fn remove_changed_since(&self, source: &Value, target: &mut Map<String, Value>) -> Vec<String> {
+        let Some(changed) = &self.changed_since else {
+            return vec![];
+        };
+        let changed = KeyFilter::default()
+            .with_paths(changed.iter().cloned())
+            .with_ignore(self.ignore.clone());
+        utils::remove_changed(source, target, |path, _| changed.matches(path))
+    }
+
+    /// Maximum number of requests in flight, over all target languages.
+    fn concurrency(&self) -> u16 {
+        self.concurrency.unwrap_or(DEFAULT_CONCURRENCY)
+    }
+
+    /// Whether phrases already translated are translated again.
+    fn mode(&self) -> Mode {
+        match self.overwrite {
+            true => Mode::Overwrite,
+            false => Mode::OnlyMissing,
+        }
+    }
+
+    /// Maximum number of files of `--source-dir` translated at the same time.
+    fn max_concurrent_files(&self) -> usize {
+        self.max_concurrent_files.unwrap_or(DEFAULT_CONCURRENT_FILES).into()
+    }
+
+    /// Limiter shared by all target languages, enforcing `--max-rps`,
+    /// `--max-cps` and the concurrency.
+    fn rate_limiter(&self) -> RateLimiter {
+        let concurrency =
+            NonZeroU32::new(self.concurrency().into()).expect("Concurrency is at least 1");
+        let mut limiter = RateLimiter::default().with_max_in_flight(concurrency);
+        if let Some(limit) = self.max_rps {
+            limiter = limiter.with_requests_per_second(limit);
+        }
+        if let Some(limit) = self.max_cps {
+            limiter = limiter.with_characters_per_second(limit);
+        }
+        limiter
+    }
+}
+
+async fn run(mut args: Args) -> Result<(), QTranslateError> {
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::discover()?,
+    };
+    args.apply_config(config);
+    args.ignore = IgnoreRules::load(&args.ignore_file)?;
+    if let Some(proxy) = &args.proxy {
+        set_proxy(proxy)?;
+    }
+    if args.fail_fast {
+        args.on_error = OnError::Fail;
+    }
+    if !args.translate_keys.is_empty() && args.output_format == OutputFormat::Po {
+        usage_error(
+            ErrorKind::ArgumentConflict,
+            "--translate-keys cannot be combined with --output-format po, whose entries \
+             are matched by their source keys",
+        );
+    }
+    if args.continue_on_error && args.on_error == OnError::Fail {
+        usage_error(
+            ErrorKind::ArgumentConflict,
+            "--continue-on-error cannot be combined with --on-error fail",
+        );
+    }
+
+    if args.target_lang.is_empty() {
+        usage_error(
+            ErrorKind::MissingRequiredArgument,
+            "no target language given with --target-lang or in the config file",
+        );
+    }
+    if args.target_file.is_some() && args.target_lang.len() > 1 {
+        usage_error(
+            ErrorKind::ArgumentConflict,
+            "--target-file names a single file and accepts only one target language",
+        );
+    }
+    if args.output.is_some() && args.target_lang.len() > 1 {
+        usage_error(
+            ErrorKind::ArgumentConflict,
+            "--output names a single file and accepts only one target language",
+        );
+    }
+    if args.target_column.is_some() && args.target_lang.len() > 1 {
+        usage_error(
+            ErrorKind::ArgumentConflict,
+            "--target-column names a single column and accepts only one target language",
+        );
+    }
+    if args.writes_stdout() && args.target_lang.len() > 1 {
+        usage_error(
+            ErrorKind::ArgumentConflict,
+            "--stdin and --stdout write a single file and accept only one target language",
+        );
+    }
+
+    for target_lang in &args.target_lang {
+        if !is_same_language(&args.source_lang, target_lang) {
+            continue;
+        }
+        if !args.allow_same_lang {
+            usage_error(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "target language {target_lang} is the same as the source language {}; \
+                     pass --allow-same-lang to translate it anyway",
+                    args.source_lang
+                ),
+            );
+        }
+        eprintln!("{target_lang}: same language as the source, translating anyway");
+    }
+
+    let max_batch_size = args.max_batch_size();
+    if let Some(size) = args.batch_size.filter(|size| usize::from(*size) > max_batch_size) {
+        eprintln!(
+            "Batch size {size} exceeds the provider's maximum of {max_batch_size} phrases \
+             per request, using {max_batch_size}"
+        );
+    }
+
+    let source_code = args.language_code(&args.source_lang);
+    let target_codes: Vec<String> = args
+        .target_lang
+        .iter()
+        .map(|lang| args.language_code(lang))
+        .collect();
+
+    let html_patterns = match args.text_format {
+        TextFormat::Text => None,
+        TextFormat::Html => Some(HTML_TAG_PATTERN),
+    };
+    let patterns: Vec<&str> = html_patterns
+        .into_iter()
+        .chain(DEFAULT_PATTERNS)
+        .chain(args.placeholder_patterns.iter().map(String::as_str))
+        .collect();
+    let verbatim_patterns: Vec<&str> = VERBATIM_PATTERNS
+        .into_iter()
+        .filter(|pattern| !(args.translate_urls && *pattern == URL_PATTERN))
+        .chain(args.verbatim_patterns.iter().map(String::as_str))
+        .collect();
+    let mut placeholders = Placeholders::new(&patterns)?.with_verbatim(&verbatim_patterns)?;
+
+    if let Some(glossary_file) = &args.glossary_file {
+        let terms = read_glossary(glossary_file).map_err(|source| QTranslateError::GlossaryFile {
+            path: glossary_file.to_string(),
+            source,
+        })?;
+        placeholders = placeholders.with_glossary(&terms, !args.glossary_ignore_case)?;
+    }
+    for (lang, termbase_file) in &args.termbase {
+        let terms = read_termbase(termbase_file).map_err(|source| QTranslateError::TermbaseFile {
+            path: termbase_file.to_string(),
+            source,
+        })?;
+        placeholders = placeholders.with_termbase(lang, &terms)?;
+    }
+
+    if let Some(source_dir) = &args.source_dir {
+        let source_dir = Path::new(source_dir);
+        return translate_dir(source_dir, &placeholders, &source_code, &target_codes, &args).await;
+    }
+
+    let (input, output) = (args.input_format, args.output_format);
+    let mut locales = match args.source_file.first() {
+        _ if args.stdin => LocaleFiles::from_stdin(input, output)?,
+        // With both files named, or a CSV file holding all languages, the
+        // assets directory is not used.
+        Some(source_file)
+            if args.target_file.is_some()
+                || input.or_else(|| Format::from_path(source_file)) == Some(Format::Csv) =>
+        {
+            LocaleFiles::for_source_file(String::new(), source_file, input, output)?
+        }
+        Some(source_file) => {
+            let assets_path = resolve_assets_dir(args.assets_dir.as_deref())?;
+            LocaleFiles::for_source_file(assets_path, source_file, input, output)?
+        }
+        None => {
+            let assets_path = resolve_assets_dir(args.assets_dir.as_deref())?;
+            LocaleFiles::locate(assets_path, &args.source_lang, input, output)?
+        }
+    };
+    locales.stdout = args.writes_stdout();
+    locales.target_file = args.target_file.clone();
+    locales.output = args.output.clone();
+    locales.source_column = args.source_column.clone().unwrap_or(args.source_lang.clone());
+    locales.target_column = args.target_column.clone();
+    check_csv_args(&locales, &args);
+    let mut source_json = locales.read_source()?;
+    for source_file in args.source_file.iter().skip(1) {
+        source_json = merge_source(source_json, source_file, &locales)?;
+    }
+    let source_json = match source_json {
+        Value::Object(map) => Value::Object(args.convert_layout(map)),
+        other => other,
+    };
+
+    if let Some(rev) = &args.since {
+        let changed = changed_since(rev, &source_json, &locales, &args)?;
+        status!(args, "{} keys added or changed since {rev}", changed.len());
+        args.changed_since = Some(changed);
+    }
+
+    // Without a target file, there is nothing the hashes could refer to.
+    let mut hashes = match args.no_hashes || args.stdin {
+        true => None,
+        false => Some(SourceHashes::load(&args.hashes_path)?),
+    };
+
+    if args.dry_run {
+        for target_lang in &args.target_lang {
+            report_missing(&source_json, &locales, target_lang, hashes.as_ref(), &args)?;
+        }
+        return Ok(());
+    }
+
+    if args.verify {
+        let mut missing = 0;
+        for target_lang in &args.target_lang {
+            missing += verify_target(&source_json, &locales, target_lang, hashes.as_ref(), &args)?;
+        }
+        return match missing {
+            0 => Ok(()),
+            missing => Err(QTranslateError::Incomplete { missing }),
+        };
+    }
+
+    let provider = create_provider(&args)?;
+    let filter = args.key_filter();
+    let key_names = KeyFilter::new(&args.translate_keys, &[] as &[&str]);
+    let rate_limiter = args.rate_limiter();
+    let context = read_context(&source_json, &locales, &args)?;
+    let options = TranslationOptions {
+        provider: &provider,
+        placeholders: &placeholders,
+        source_lang: &source_code,
+        concurrency: args.concurrency().into(),
+        batch_size: Some(args.batch_size()),
+        max_chars_per_request: Some(args.max_chars_per_request()),
+        overwrite: args.overwrite,
+        filter: &filter,
+        on_error: args.on_error,
+        rate_limiter: Some(&rate_limiter),
+        context: Some(&context),
+        limit: args.limit,
+        max_chars_total: args.max_chars_total,
+        preserve_newlines: args.preserve_newlines,
+        normalize_whitespace: args.normalize_whitespace,
+        strict_placeholders: args.strict_placeholders,
+        match_case: args.match_case,
+        translate_keys: (!args.translate_keys.is_empty()).then_some(&key_names),
+    };
+
+    if let Some(count) = args.sample {
+        return print_sample(&source_json, &options, &target_codes, count, &args).await;
+    }
+    if let Some(output) = &args.output {
+        check_output_dir(output, args.mkdir)?;
+    }
 
-    let assets_path = if fs::exists("src/assets")? {
-        "src/assets/i18n"
-    } else if fs::exists("assets")? {
-        "assets/i18n"
-    } else {
-        panic!("Assets directory not found!");
+    // `--partial-write` takes the phrases translated so far from the cache, so
+    // one is kept in memory even with `--no-cache`.
+    let cache = match (args.no_cache, args.partial_write) {
+        (false, _) => Some(TranslationCache::load(&args.cache_path)?),
+        (true, true) => Some(TranslationCache::default()),
+        (true, false) => None,
     };
 
-    let source_path = format!("{}/{}.json", assets_path, args.source_lang);
-    let target_path = format!("{}/{}.json", assets_path, args.target_lang);
+    let started = Instant::now();
+    let mut summaries = vec![];
+    let mut failed = vec![];
+    let mut targets = vec![];
+    // Locale files are named after the languages as given, while the
+    // normalized codes are sent to the provider.
+    let mut file_langs = vec![];
+    // Target files as they were before the run, kept for `--diff`.
+    let mut previous = vec![];
 
-    if !fs::exists(&source_path)? {
-        panic!("Source file {} does not exists!", source_path);
+    for (target_lang, code) in args.target_lang.iter().zip(&target_codes) {
+        match locales.read_or_empty(target_lang, &source_json) {
+            Ok(target_json) => {
+                let mut target_json = args.convert_layout(target_json);
+                previous.push(args.wants_diff().then(|| target_json.clone()));
+                let outdated = hashes.as_ref().map_or(vec![], |hashes| {
+                    let ignore = &args.ignore;
+                    hashes.remove_outdated(&source_json, &mut target_json, target_lang, ignore)
+                });
+                for key_path in outdated {
+                    status!(args, "{target_lang}: source of {key_path} changed, translating again");
+                }
+                for key_path in args.remove_changed_since(&source_json, &mut target_json) {
+                    status!(args, "{target_lang}: source of {key_path} changed, translating again");
+                }
+                targets.push((code.as_str(), target_json));
+                file_langs.push(target_lang.as_str());
+            }
+            Err(err) => failed.push((target_lang.as_str(), err)),
+        }
     }
 
-    let source_json = serde_json::from_str(&fs::read_to_string(source_path)?)?;
+    let bars = match args.quiet {
+        true => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+        false => MultiProgress::new(),
+    };
+    let progress: Vec<ProgressBar> = file_langs
+        .iter()
+        .map(|target_lang| bars.add(progress_bar(target_lang)))
+        .collect();
+
+    // Targets as they were before the run, filled in from the cache once
+    // `--timeout-total` runs out.
+    let originals = args.partial_write.then(|| targets.clone());
+    let translation = pipeline::translate_targets(
+        &source_json,
+        &mut targets,
+        &options,
+        cache.as_ref(),
+        |i, done, total| {
+            progress[i].set_length(total as u64);
+            progress[i].set_position(done as u64);
+        },
+    );
+    let results = match args.timeout_total {
+        Some(secs) => tokio::time::timeout(Duration::from_secs(secs), translation).await.ok(),
+        None => Some(translation.await),
+    };
+    // Finishing the bars fills them up, so count the phrases done before.
+    let done = progress.iter().map(|bar| bar.position() as usize).sum();
+    let total = progress.iter().filter_map(ProgressBar::length).sum::<u64>() as usize;
+    progress.iter().for_each(ProgressBar::finish_and_clear);
+
+    if let Some(cache) = cache.as_ref().filter(|_| !args.no_cache) {
+        cache.save(&args.cache_path)?;
+    }
+
+    let mut timed_out = None;
+    let results = match (results, originals) {
+        (Some(results), _) => results,
+        (None, originals) => {
+            let error = QTranslateError::TimedOut {
+                secs: args.timeout_total.unwrap_or_default(),
+                done,
+                total,
+            };
+            let Some(originals) = originals else {
+                return Err(error);
+            };
+            status!(args, "Timed out, writing the phrases translated so far to the target files");
+            args.timed_out = true;
+            timed_out = Some(error);
+            // Phrases missing from the cache are left out, as with `--limit`,
+            // without sending a request.
+            let cached_only = TranslationOptions {
+                limit: Some(0),
+                max_chars_total: None,
+                ..options
+            };
+            targets = originals;
+            let (source, cache) = (&source_json, cache.as_ref());
+            pipeline::translate_targets(source, &mut targets, &cached_only, cache, |_, _, _| {})
+                .await
+        }
+    };
+
+    // `--on-error fail` aborts the whole run at the first failure, so no target is written.
+    let abort = args.on_error == OnError::Fail && results.iter().any(Result::is_err);
+
+    let mut diffs = Map::new();
+    let finished = file_langs.into_iter().zip(previous).zip(targets).zip(results);
+    for (((target_lang, previous), (_, target_json)), result) in finished {
+        let summary = match result {
+            Ok(_) if abort => continue,
+            Ok(summary) => summary,
+            Err(err) => {
+                failed.push((target_lang, err));
+                continue;
+            }
+        };
+
+        let target = finish_target(
+            &source_json,
+            &locales,
+            target_lang,
+            target_json,
+            previous,
+            &summary,
+            &args,
+        );
+        match target {
+            Ok(diff) => {
+                summaries.push((target_lang, summary));
+                if let Some(diff) = diff {
+                    print_diff(target_lang, &diff, &args);
+                    let diff = serde_json::to_value(diff).expect("Diff serializes to JSON");
+                    diffs.insert(target_lang.to_string(), diff);
+                }
+            }
+            Err(err) => {
+                failed.push((target_lang, err));
+                continue;
+            }
+        }
+        // The target file the hashes describe is left as it is with `--output`.
+        if let Some(hashes) = hashes.as_mut().filter(|_| args.output.is_none()) {
+            hashes.record(&source_json, target_lang, &filter);
+        }
+    }
+
+    if let Some(hashes) = &hashes {
+        hashes.save(&args.hashes_path)?;
+    }
+
+    let failed_targets = failed.iter().map(|(lang, _)| *lang).collect();
+    let served = match args.fallback.is_empty() {
+        true => Vec::new(),
+        false => provider.served(),
+    };
+    let report = Report::new(summaries, failed_targets, served, args.mode(), started.elapsed());
+    report.print(args.writes_stdout());
+    if let Some(path) = &args.report_json {
+        let json = serde_json::to_string_pretty(&report).expect("Report serializes to JSON");
+        write_atomic(path, json + "\n")?;
+    }
+    if let Some(path) = &args.diff_json {
+        let json = serde_json::to_string_pretty(&diffs).expect("Diffs serialize to JSON");
+        write_atomic(path, json + "\n")?;
+    }
+
+    let translated = format!(
+        "Translated {} of {} target languages, {}.",
+        report.targets.len(),
+        args.target_lang.len(),
+        report.mode
+    );
+    let style = match failed.is_empty() && timed_out.is_none() {
+        true => Style::new().green(),
+        false => Style::new().yellow(),
+    };
+    status!(args, "{}", paint(&translated, style, args.status_stream()));
+    for (target_lang, err) in &failed {
+        let message = format!("Failed to translate into {target_lang}: {err}");
+        eprintln!("{}", paint(&message, Style::new().red(), Stream::Stderr));
+    }
+
+    if let Some(err) = take_invalid_file(&mut failed) {
+        return Err(err);
+    }
+    if !failed.is_empty() {
+        return Err(QTranslateError::TargetsFailed {
+            failed: failed.len(),
+            total: args.target_lang.len(),
+        });
+    }
+    if let Some(error) = timed_out {
+        return Err(error);
+    }
+    match args.continue_on_error && report.failed_keys > 0 {
+        true => Err(QTranslateError::PhrasesFailed {
+            failed: report.failed_keys,
+        }),
+        false => Ok(()),
+    }
+}
+
+/// Translates every locale file below `source_dir` into the sibling directory
+/// of every target language, with `--source-dir`.
+///
+/// Files are translated like with [`pipeline::translate_file`], sharing the
+/// cache and the rate limiter, and reported one by one.
+async fn translate_dir(
+    source_dir: &Path,
+    placeholders: &Placeholders,
+    source_code: &str,
+    target_codes: &[String],
+    args: &Args,
+) -> Result<(), QTranslateError> {
+    if !fs::exists(source_dir)? {
+        return Err(QTranslateError::SourceMissing(source_dir.display().to_string()));
+    }
+    let files = source_files(source_dir)?;
+
+    let provider = create_provider(args)?;
+    let filter = args.key_filter();
+    let rate_limiter = args.rate_limiter();
+    let context = TranslationContext::new(args.context.clone());
+    let options = TranslationOptions {
+        provider: &provider,
+        placeholders,
+        source_lang: source_code,
+        concurrency: args.concurrency().into(),
+        batch_size: Some(args.batch_size()),
+        max_chars_per_request: Some(args.max_chars_per_request()),
+        overwrite: args.overwrite,
+        filter: &filter,
+        on_error: args.on_error,
+        rate_limiter: Some(&rate_limiter),
+        context: Some(&context),
+        limit: args.limit,
+        max_chars_total: args.max_chars_total,
+        preserve_newlines: args.preserve_newlines,
+        normalize_whitespace: args.normalize_whitespace,
+        strict_placeholders: args.strict_placeholders,
+        match_case: args.match_case,
+        translate_keys: None,
+    };
+    let cache = match args.no_cache {
+        true => None,
+        false => Some(TranslationCache::load(&args.cache_path)?),
+    };
+
+    let started = Instant::now();
+    let mut target_paths = vec![];
+    let mut results = vec![];
+    for (target_lang, code) in args.target_lang.iter().zip(target_codes) {
+        let target_dir = source_dir.with_file_name(target_lang);
+        let jobs: Vec<(PathBuf, PathBuf)> = files
+            .iter()
+            .map(|file| (source_dir.join(file), target_dir.join(file)))
+            .collect();
+        let max_concurrent = args.max_concurrent_files();
+        results.extend(
+            pipeline::translate_files(&jobs, code, &options, cache.as_ref(), max_concurrent).await,
+        );
+        target_paths.extend(jobs.into_iter().map(|(_, target)| target.display().to_string()));
+    }
+
+    if let Some(cache) = &cache {
+        cache.save(&args.cache_path)?;
+    }
 
-    let mut target_json = match fs::exists(&target_path)? {
-        true => serde_json::from_str(&fs::read_to_string(&target_path)?)?,
-        false => serde_json::from_str("{}")?,
+    let mut summaries = vec![];
+    let mut failed = vec![];
+    for (target_path, result) in target_paths.iter().zip(results) {
+        match result {
+            Ok(summary) => {
+                status!(
+                    args,
+                    "{target_path}: {} keys, {} strings translated, {} failed keys",
+                    summary.keys,
+                    summary.translated,
+                    summary.failed_keys.len()
+                );
+                summaries.push((target_path.as_str(), summary));
+            }
+            Err(err) => failed.push((target_path.as_str(), err)),
+        }
+    }
+
+    let failed_files = failed.iter().map(|(path, _)| *path).collect();
+    let served = match args.fallback.is_empty() {
+        true => Vec::new(),
+        false => provider.served(),
+    };
+    let report = Report::new(summaries, failed_files, served, args.mode(), started.elapsed());
+    report.print(false);
+    if let Some(path) = &args.report_json {
+        let json = serde_json::to_string_pretty(&report).expect("Report serializes to JSON");
+        write_atomic(path, json + "\n")?;
+    }
+
+    let translated = format!(
+        "Translated {} of {} files, {}.",
+        report.targets.len(),
+        target_paths.len(),
+        report.mode
+    );
+    let style = match failed.is_empty() {
+        true => Style::new().green(),
+        false => Style::new().yellow(),
     };
+    status!(args, "{}", paint(&translated, style, args.status_stream()));
+    for (target_path, err) in &failed {
+        let message = format!("Failed to translate {target_path}: {err}");
+        eprintln!("{}", paint(&message, Style::new().red(), Stream::Stderr));
+    }
 
+    if let Some(err) = take_invalid_file(&mut failed) {
+        return Err(err);
+    }
+    if !failed.is_empty() {
+        return Err(QTranslateError::FilesFailed {
+            failed: failed.len(),
+            total: target_paths.len(),
+        });
+    }
+    match args.continue_on_error && report.failed_keys > 0 {
+        true => Err(QTranslateError::PhrasesFailed {
+            failed: report.failed_keys,
+        }),
+        false => Ok(()),
+    }
+}
+
+/// Removes the first error of an invalid locale file from `failed`, for the
+/// run to exit with, so it keeps its own exit code.
+fn take_invalid_file(failed: &mut Vec<(&str, QTranslateError)>) -> Option<QTranslateError> {
+    let i = failed.iter().position(|(_, err)| {
+        matches!(
+            err,
+            QTranslateError::InvalidFile { .. } | QTranslateError::InvalidTarget { .. }
+        )
+    })?;
+    Some(failed.swap_remove(i).1)
+}
+
+/// Statistics of a whole run, printed at its end and written with
+/// `--report-json`.
+#[derive(Serialize)]
+struct Report<'a> {
+    targets: Vec<TargetReport<'a>>,
+    failed_targets: Vec<&'a str>,
+    keys: usize,
+    translated: usize,
+    cache_hits: usize,
+    requests: usize,
+    characters: usize,
+    failed_keys: usize,
+    mode: Mode,
+    /// Phrases translated by every provider, if `--fallback` is given.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    served: Vec<ServedReport>,
+    elapsed: f64,
+}
+
+/// Which source phrases a run translates, selected with `--only-missing`
+/// or `--overwrite`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Mode {
+    OnlyMissing,
+    Overwrite,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::OnlyMissing => write!(f, "only missing phrases"),
+            Mode::Overwrite => write!(f, "all phrases, overwriting existing translations"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ServedReport {
+    provider: String,
+    phrases: usize,
+}
+
+#[derive(Serialize)]
+struct TargetReport<'a> {
+    lang: &'a str,
+    #[serde(flatten)]
+    summary: TranslateSummary,
+}
+
+impl<'a> Report<'a> {
+    /// Adds up the `summaries` of the translated targets.
+    fn new(
+        summaries: Vec<(&'a str, TranslateSummary)>,
+        failed_targets: Vec<&'a str>,
+        served: Vec<(String, usize)>,
+        mode: Mode,
+        elapsed: Duration,
+    ) -> Self {
+        let total = |field: fn(&TranslateSummary) -> usize| {
+            summaries.iter().map(|(_, summary)| field(summary)).sum()
+        };
+
+        Self {
+            keys: total(|summary| summary.keys),
+            translated: total(|summary| summary.translated),
+            cache_hits: total(|summary| summary.cache_hits),
+            requests: total(|summary| summary.requests),
+            characters: total(|summary| summary.characters),
+            failed_keys: total(|summary| summary.failed_keys.len()),
+            served: served
+                .into_iter()
+                .map(|(provider, phrases)| ServedReport { provider, phrases })
+                .collect(),
+            mode,
+            elapsed: elapsed.as_secs_f64(),
+            targets: summaries
+                .into_iter()
+                .map(|(lang, summary)| TargetReport { lang, summary })
+                .collect(),
+            failed_targets,
+        }
+    }
+
+    /// Prints the totals, to stderr if `to_stderr` is set, followed by the
+    /// failed keys.
+    fn print(&self, to_stderr: bool) {
+        let totals = format!(
+            "Total: {} keys, {} strings translated, {} cache hits, {} API requests, \
+             {} characters sent, {} failed keys in {:.1}s",
+            self.keys,
+            self.translated,
+            self.cache_hits,
+            self.requests,
+            self.characters,
+            self.failed_keys,
+            self.elapsed
+        );
+        match to_stderr {
+            true => eprintln!("{totals}"),
+            false => println!("{totals}"),
+        }
+        if !self.served.is_empty() {
+            let served: Vec<String> = self
+                .served
+                .iter()
+                .map(|served| format!("{} {}", served.provider, served.phrases))
+                .collect();
+            let served = format!("Phrases per provider: {}", served.join(", "));
+            match to_stderr {
+                true => eprintln!("{served}"),
+                false => println!("{served}"),
+            }
+        }
+        for target in &self.targets {
+            for key_path in &target.summary.failed_keys {
+                let message = format!("{}: failed to translate {key_path}", target.lang);
+                eprintln!("{}", paint(&message, Style::new().red(), Stream::Stderr));
+            }
+        }
+    }
+}
+
+/// Creates the provider selected with `--provider`, followed by those of
+/// `--fallback`.
+fn create_provider(args: &Args) -> Result<FallbackProvider, QTranslateError> {
+    let prompt = match &args.prompt_file {
+        Some(path) => Some(fs::read_to_string(path)?),
+        None => None,
+    };
+    let provider = args.provider.unwrap_or_default().create(&ProviderSettings {
+        api_key: args.api_key.clone(),
+        api_key_env: args.api_key_env.clone(),
+        endpoint: args.endpoint.clone(),
+        model: args.model.clone(),
+        prompt: prompt.clone(),
+        timeout: Duration::from_secs(args.request_timeout_secs),
+        text_format: args.text_format,
+    })?;
+    let primary = provider_name(args.provider.unwrap_or_default());
+    let mut provider = FallbackProvider::new(&primary, cached(args, &primary, provider));
+    for &fallback in &args.fallback {
+        let settings = ProviderSettings {
+            model: args.model.clone(),
+            prompt: prompt.clone(),
+            timeout: Duration::from_secs(args.request_timeout_secs),
+            text_format: args.text_format,
+            ..Default::default()
+        };
+        let name = provider_name(fallback);
+        let backend = cached(args, &name, fallback.create(&settings)?);
+        provider = provider.with_fallback(name, backend);
+    }
+    Ok(provider)
+}
+
+/// Wraps the backend `provider`, named `name`, in a [`CachedProvider`] if
+/// `--cache-batches` is given. The model and text format are part of the
+/// name, since they change the responses.
+fn cached(
+    args: &Args,
+    name: &str,
+    provider: Box<dyn TranslationProvider>,
+) -> Box<dyn TranslationProvider> {
+    if !args.cache_batches {
+        return provider;
+    }
+    let model = args.model.as_deref().unwrap_or_default();
+    let name = format!("{name}:{model}:{:?}", args.text_format);
+    Box::new(CachedProvider::new(name, provider, DEFAULT_BATCH_CACHE_DIR))
+}
+
+/// Name of `provider` as given on the command line, e.g. `deepl`.
+fn provider_name(provider: Provider) -> String {
+    provider
+        .to_possible_value()
+        .expect("providers are not skipped")
+        .get_name()
+        .to_string()
+}
+
+/// Parses the `LANG:FILE` value of `--termbase`.
+fn parse_termbase(value: &str) -> Result<(String, String), String> {
+    match value.split_once(':') {
+        Some((lang, path)) if !lang.is_empty() && !path.is_empty() => {
+            Ok((lang.to_string(), path.to_string()))
+        }
+        _ => Err(format!("expected LANG:FILE, e.g. de:termbase-de.json, got {value:?}")),
+    }
+}
+
+/// Path shown in errors about a source file read from stdin.
+const STDIN_PATH: &str = "<stdin>";
+
+/// Returns the directory containing the locale files.
+///
+/// An explicitly configured `assets_dir` is used as-is after checking that it
+/// exists. Otherwise `src/assets/i18n` and `assets/i18n` are probed in order.
+fn resolve_assets_dir(assets_dir: Option<&str>) -> Result<String, QTranslateError> {
+    match assets_dir {
+        Some(dir) if fs::exists(dir)? => Ok(dir.to_string()),
+        Some(dir) => Err(QTranslateError::AssetsDirMissing(dir.to_string())),
+        None if fs::exists("src/assets")? => Ok("src/assets/i18n".to_string()),
+        None if fs::exists("assets")? => Ok("assets/i18n".to_string()),
+        None => Err(QTranslateError::AssetsNotFound),
+    }
+}
+
+/// Location and format of the locale files of a run.
+///
+/// The format is detected from the source file; target files are written with
+/// the same extension, e.g. `de.yml` for an `en.yml` source, unless they are
+/// written as `.po` files.
+struct LocaleFiles {
+    dir: String,
+    source_path: String,
+    extension: &'static str,
+    format: Format,
+    output_format: OutputFormat,
+    /// Source file read from stdin with `--stdin`; there are no target files
+    /// then.
+    stdin: Option<String>,
+    /// Whether translated files are written to stdout instead of their path.
+    stdout: bool,
+    /// Target file named with `--target-file`, used instead of the file of
+    /// the single target language in `dir`.
+    target_file: Option<String>,
+    /// File named with `--output`, written instead of the target file of the
+    /// single target language, which is still read.
+    output: Option<String>,
+    /// Column of a CSV file holding the source strings.
+    source_column: String,
+    /// Column of a CSV file named with `--target-column`, used instead of
+    /// the column of the single target language.
+    target_column: Option<String>,
+}
+
+impl LocaleFiles {
+    /// Finds the `source_lang` file in `dir`, probing the extensions listed in
+    /// [`Format::EXTENSIONS`] in order, or only those of `input_format`.
+    fn locate(
+        dir: String,
+        source_lang: &str,
+        input_format: Option<Format>,
+        output_format: OutputFormat,
+    ) -> Result<Self, QTranslateError> {
+        let candidates: Vec<_> = Format::EXTENSIONS
+            .into_iter()
+            .filter(|(_, format)| input_format.is_none_or(|input| input == *format))
+            .collect();
+
+        for &(extension, format) in &candidates {
+            let source_path = format!("{dir}/{source_lang}.{extension}");
+
+            if fs::exists(&source_path)? {
+                return Ok(LocaleFiles {
+                    dir,
+                    source_path,
+                    extension,
+                    format,
+                    output_format,
+                    stdin: None,
+                    stdout: false,
+                    target_file: None,
+                    output: None,
+                    source_column: String::new(),
+                    target_column: None,
+                });
+            }
+        }
+
+        let extensions: Vec<_> = candidates.iter().map(|(extension, _)| *extension).collect();
+        let extensions = extensions.join(",");
+        Err(QTranslateError::SourceMissing(format!("{dir}/{source_lang}.{{{extensions}}}")))
+    }
+
+    /// Uses an explicitly named source file; target files are still written to
+    /// `dir`, with the extension of the source file or of `input_format`.
+    fn for_source_file(
+        dir: String,
+        source_file: &str,
+        input_format: Option<Format>,
+        output_format: OutputFormat,
+    ) -> Result<Self, QTranslateError> {
+        if !fs::exists(source_file)? {
+            return Err(QTranslateError::SourceMissing(source_file.to_string()));
+        }
+
+        let detected = input_format.or_else(|| Format::from_path(source_file));
+        let (extension, format) = Format::EXTENSIONS
+            .into_iter()
+            .find(|(_, format)| detected == Some(*format))
+            .ok_or_else(|| QTranslateError::UnsupportedFormat(source_file.to_string()))?;
+
+        let source_path = source_file.to_string();
+
+        Ok(LocaleFiles {
+            dir,
+            source_path,
+            extension,
+            format,
+            output_format,
+            stdin: None,
+            stdout: false,
+            target_file: None,
+            output: None,
+            source_column: String::new(),
+            target_column: None,
+        })
+    }
+
+    /// Reads the source file from stdin, in `input_format` or else as JSON.
+    /// The assets directory is not used at all.
+    fn from_stdin(
+        input_format: Option<Format>,
+        output_format: OutputFormat,
+    ) -> Result<Self, QTranslateError> {
+        let format = input_format.unwrap_or(Format::Json);
+        let (extension, format) = Format::EXTENSIONS
+            .into_iter()
+            .find(|(_, known)| *known == format)
+            .expect("Every format has an extension");
+
+        Ok(LocaleFiles {
+            dir: String::new(),
+            source_path: STDIN_PATH.to_string(),
+            extension,
+            format,
+            output_format,
+            stdin: Some(io::read_to_string(io::stdin())?),
+            stdout: true,
+            target_file: None,
+            output: None,
+            source_column: String::new(),
+            target_column: None,
+        })
+    }
+
+    /// Path of the locale file for `lang`, or the file named with
+    /// `--target-file`. All languages of a CSV file share the source file.
+    fn path(&self, lang: &str) -> String {
+        if let Some(target_file) = &self.target_file {
+            return target_file.clone();
+        }
+        if self.format == Format::Csv {
+            return self.source_path.clone();
+        }
+        let extension = match self.output_format {
+            OutputFormat::Source => self.extension,
+            OutputFormat::Po => "po",
+        };
+
+        format!("{}/{}.{}", self.dir, lang, extension)
+    }
+
+    /// Path the translated locale file for `lang` is written to.
+    fn output_path(&self, lang: &str) -> String {
+        self.output.clone().unwrap_or_else(|| self.path(lang))
+    }
+
+    /// Reads and parses the locale file for `lang`.
+    fn read(&self, lang: &str) -> Result<Value, QTranslateError> {
+        self.read_path(&self.path(lang))
+    }
+
+    /// Reads and parses the locale file at `path`.
+    fn read_path(&self, path: &str) -> Result<Value, QTranslateError> {
+        self.parse(path, &fs::read_to_string(path)?)
+    }
+
+    /// Parses the `contents` of the locale file at `path`.
+    fn parse(&self, path: &str, contents: &str) -> Result<Value, QTranslateError> {
+        self.format
+            .parse(contents)
+            .map_err(|source| QTranslateError::InvalidFile { path: path.to_string(), source })
+    }
+
+    /// Reads and parses the source file.
+    fn read_source(&self) -> Result<Value, QTranslateError> {
+        let source = match &self.stdin {
+            Some(contents) => self.parse(&self.source_path, contents)?,
+            None => self.read_path(&self.source_path)?,
+        };
+        self.source_strings(&self.source_path, source)
+    }
+
+    /// Returns the strings to translate in `source`, the parsed source file at
+    /// `path`: the source column of a CSV file, or else all of `source`.
+    fn source_strings(&self, path: &str, source: Value) -> Result<Value, QTranslateError> {
+        match (self.format, &source) {
+            (Format::Csv, Value::Object(rows)) => csv::column(rows, &self.source_column)
+                .map(Value::Object)
+                .map_err(|source| QTranslateError::InvalidFile { path: path.to_string(), source }),
+            _ => Ok(source),
+        }
+    }
+
+    /// Column of a CSV file that the translations into `lang` are written to.
+    fn target_column<'a>(&'a self, lang: &'a str) -> &'a str {
+        self.target_column.as_deref().unwrap_or(lang)
+    }
+
+    /// Reads the rows of the CSV file that the `lang` column is written to,
+    /// which is the source file unless another existing target file is named.
+    fn read_rows(&self, lang: &str) -> Result<Map<String, Value>, QTranslateError> {
+        let path = self.path(lang);
+        let rows = match self.stdin.is_none() && fs::exists(&path)? {
+            true => self.read_path(&path)?,
+            false => self.parse(&self.source_path, &self.source_contents()?)?,
+        };
+        match rows {
+            Value::Object(rows) => Ok(rows),
+            _ => Ok(Map::new()),
+        }
+    }
+
+    /// Contents of the source file.
+    fn source_contents(&self) -> io::Result<String> {
+        match &self.stdin {
+            Some(contents) => Ok(contents.clone()),
+            None => fs::read_to_string(&self.source_path),
+        }
+    }
+
+    /// Reads the existing locale file for `lang`, or returns an empty object if
+    /// it does not exist yet.
+    ///
+    /// A `.po` file is turned into an object by placing its translations at
+    /// the key paths of their phrases in `source`.
+    fn read_or_empty(
+        &self,
+        lang: &str,
+        source: &Value,
+    ) -> Result<Map<String, Value>, QTranslateError> {
+        // A CSV file without the target column yet has no translations.
+        if self.format == Format::Csv {
+            return match csv::column(&self.read_rows(lang)?, self.target_column(lang)) {
+                Err(FormatError::MissingColumn(_)) => Ok(Map::new()),
+                result => result.map_err(|source| QTranslateError::InvalidFile {
+                    path: self.path(lang),
+                    source,
+                }),
+            };
+        }
+        if self.stdin.is_some() || !fs::exists(self.path(lang))? {
+            return Ok(Map::new());
+        }
+
+        if self.output_format == OutputFormat::Po {
+            let translations = po::parse(&fs::read_to_string(self.path(lang))?);
+            let mut target = Map::new();
+            let filter = KeyFilter::default();
+            utils::apply_translations(source, &mut target, &translations, false, &filter);
+            return Ok(target);
+        }
+
+        match self.read(lang)? {
+            Value::Object(map) => Ok(map),
+            Value::Null => Ok(Map::new()),
+            _ => Err(QTranslateError::InvalidFile {
+                path: self.path(lang),
+                source: FormatError::NotAnObject,
+            }),
+        }
+    }
+
+    /// Serializes `target`, the translation of `source`, and writes it to the
+    /// locale file for `lang`, to the file of `--output`, or to stdout. Properties files follow the
+    /// layout of the source file, JSON files are laid out in `json_style`,
+    /// JSONC files keep the comments of the source file as well, and only the
+    /// column of `lang` is replaced in CSV files.
+    /// Every file ends with a newline if `final_newline` is set.
+    fn write(
+        &self,
+        lang: &str,
+        source: &Value,
+        target: Map<String, Value>,
+        json_style: JsonStyle,
+        final_newline: bool,
+    ) -> Result<(), QTranslateError> {
+        let path = self.output_path(lang);
+        let contents = match self.output_format {
+            // Keep the comments and blank lines of the source file.
+            OutputFormat::Source if self.format == Format::Properties => {
+                properties::serialize(&target, Some(&self.source_contents()?))
+            }
+            OutputFormat::Source if self.format == Format::Jsonc => {
+                let template = self.source_contents()?;
+                jsonc::serialize(&Value::Object(target), Some(&template), json_style)
+                    .map_err(|source| QTranslateError::InvalidFile { path: path.clone(), source })?
+            }
+            OutputFormat::Source if self.format == Format::Csv => {
+                let mut rows = self.read_rows(lang)?;
+                csv::set_column(&mut rows, self.target_column(lang), &target);
+                csv::serialize(&rows)
+                    .map_err(|source| QTranslateError::InvalidFile { path: path.clone(), source })?
+            }
+            OutputFormat::Source => self
+                .format
+                .serialize_with(&Value::Object(target), json_style)
+                .map_err(|source| QTranslateError::InvalidFile { path: path.clone(), source })?,
+            OutputFormat::Po => po::render(source, &target, lang),
+        };
+        let contents = with_final_newline(contents, final_newline);
+
+        match self.stdout {
+            true => Ok(io::stdout().write_all(contents.as_bytes())?),
+            false => Ok(write_atomic(&path, contents)?),
+        }
+    }
+}
+
+/// Translates `count` phrases of `source` picked at random into every target
+/// language and prints them side by side with their translations. Neither
+/// the target files nor the cache are read or written.
+async fn print_sample(
+    source: &Value,
+    options: &TranslationOptions<'_>,
+    target_codes: &[String],
+    count: usize,
+    args: &Args,
+) -> Result<(), QTranslateError> {
+    let phrases = utils::sample_phrases(source, options.filter, count, args.seed);
+
+    for (target_lang, code) in args.target_lang.iter().zip(target_codes) {
+        let mut translations: HashMap<String, String> = phrases
+            .iter()
+            .map(|phrase| (phrase.clone(), String::new()))
+            .collect();
+        let order: Vec<&str> = phrases.iter().map(String::as_str).collect();
+        let stats = utils::perform_translations(
+            &mut translations,
+            &order,
+            options,
+            None,
+            code,
+            |_, _| {},
+        )
+        .await
+        .map_err(|err| QTranslateError::Translation(err.to_string()))?;
+
+        println!("{target_lang}:");
+        for phrase in &phrases {
+            match translations.get(phrase) {
+                Some(translated) if !stats.failed.contains(phrase) => {
+                    println!("  {phrase:?}\n  -> {translated:?}");
+                }
+                _ => println!("  {phrase:?}\n  -> (failed)"),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints the source phrases missing from the `target_lang` file together with
+/// the number of requests and characters the selected provider would need to
+/// translate them, and whether the characters exceed `--max-chars-total`.
+///
+/// With `--overwrite`, every source phrase is reported. Phrases whose source
+/// changed since the last run according to `hashes` count as missing. With
+/// `--prune`, the stale keys that would be removed are listed as well.
+fn report_missing(
+    source_json: &Value,
+    locales: &LocaleFiles,
+    target_lang: &str,
+    hashes: Option<&SourceHashes>,
+    args: &Args,
+) -> Result<(), QTranslateError> {
+    let mut target_json = args.convert_layout(locales.read_or_empty(target_lang, source_json)?);
+    if let Some(hashes) = hashes {
+        hashes.remove_outdated(source_json, &mut target_json, target_lang, &args.ignore);
+    }
+    args.remove_changed_since(source_json, &mut target_json);
     let mut translations: HashMap<String, String> = HashMap::default();
 
-    utils::gather_translations(&source_json, &mut target_json, &String::default(), &args.target_lang, &mut translations);
-    utils::perform_translations(&mut translations, &args.target_lang).await.unwrap();
-    utils::apply_translations(&source_json, &mut target_json, &String::default(), 0, &args.target_lang, &translations);
+    let filter = args.key_filter();
+    utils::gather_translations(
+        source_json,
+        &target_json,
+        &mut translations,
+        args.overwrite,
+        &filter,
+    )
+    .map_err(|source| QTranslateError::InvalidFile {
+        path: locales.path(target_lang),
+        source,
+    })?;
+
+    let mut missing: Vec<&String> = translations
+        .iter()
+        .filter(|(phrase, translated_phrase)| {
+            translated_phrase.is_empty() && !phrase.trim().is_empty()
+        })
+        .map(|(phrase, _)| phrase)
+        .collect();
+    missing.sort();
+    let characters: usize = missing.iter().map(|phrase| phrase.chars().count()).sum();
+
+    println!(
+        "{target_lang}: {} untranslated phrases, {} requests with {characters} characters \
+         would be sent",
+        missing.len(),
+        split_batches(&missing, args.batch_size(), args.max_chars_per_request()).len()
+    );
+    if let Some(cap) = args.max_chars_total.filter(|cap| characters > *cap) {
+        println!("{target_lang}: {characters} characters exceed --max-chars-total {cap}");
+    }
+    for phrase in missing {
+        println!("  {phrase:?}");
+    }
+
+    if args.prune {
+        let mut preview = target_json;
+        for key_path in utils::prune_stale_keys(source_json, &mut preview, &args.ignore) {
+            println!("  would prune {key_path}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints every key path of a source string without translation in the
+/// `target_lang` file, or whose translation is outdated according to
+/// `hashes`, and returns their number.
+fn verify_target(
+    source_json: &Value,
+    locales: &LocaleFiles,
+    target_lang: &str,
+    hashes: Option<&SourceHashes>,
+    args: &Args,
+) -> Result<usize, QTranslateError> {
+    let target_json = args.convert_layout(locales.read_or_empty(target_lang, source_json)?);
+    let filter = args.key_filter();
+
+    let missing = utils::missing_key_paths(source_json, &target_json, &filter);
+    for key_path in &missing {
+        println!("{target_lang}: missing {key_path}");
+    }
+
+    let outdated: Vec<String> = utils::source_strings(source_json, &filter)
+        .into_iter()
+        .filter(|(key_path, phrase)| {
+            !missing.contains(key_path)
+                && hashes.is_some_and(|hashes| hashes.is_outdated(target_lang, key_path, phrase))
+        })
+        .map(|(key_path, _)| key_path)
+        .collect();
+    for key_path in &outdated {
+        println!("{target_lang}: outdated {key_path}");
+    }
+
+    println!(
+        "{target_lang}: {} missing, {} outdated translations",
+        missing.len(),
+        outdated.len()
+    );
+
+    Ok(missing.len() + outdated.len())
+}
+
+/// Returns the key paths of the strings of `source_json` that were added or
+/// changed since the git revision `rev`, comparing it with the source files
+/// as they were in that revision. Source files missing from it count as
+/// empty.
+fn changed_since(
+    rev: &str,
+    source_json: &Value,
+    locales: &LocaleFiles,
+    args: &Args,
+) -> Result<HashSet<String>, QTranslateError> {
+    let paths = match args.source_file.is_empty() {
+        true => vec![locales.source_path.clone()],
+        false => args.source_file.clone(),
+    };
+    let mut before = Map::new();
+    for path in &paths {
+        let Some(contents) = git::read_at_revision(rev, path)? else {
+            continue;
+        };
+        if let Value::Object(map) = locales.source_strings(path, locales.parse(path, &contents)?)? {
+            layout::merge(&mut before, map);
+        }
+    }
 
-    let mut target_file = File::create(&target_path)?;
-    target_file.write_all(serde_json::to_string_pretty(&target_json)?.as_bytes())?;
+    let after = source_json.as_object().cloned().unwrap_or_default();
+    let diff = TargetDiff::new(args.convert_layout(before), after);
+    Ok(diff.added.into_iter().chain(diff.changed).map(|change| change.key).collect())
+}
 
+/// Makes sure the directory of the `--output` file exists, creating it and
+/// its parents with `--mkdir`.
+fn check_output_dir(output: &str, mkdir: bool) -> Result<(), QTranslateError> {
+    let Some(dir) = Path::new(output).parent().filter(|dir| !dir.as_os_str().is_empty()) else {
+        return Ok(());
+    };
+    if mkdir {
+        fs::create_dir_all(dir)?;
+    } else if !fs::exists(dir)? {
+        let message = format!(
+            "directory {} of --output does not exist; pass --mkdir to create it",
+            dir.display()
+        );
+        return Err(io::Error::new(io::ErrorKind::NotFound, message).into());
+    }
     Ok(())
 }
+
+/// Deep-merges the source file at `path` into `source`, warning about every
+/// key whose value it replaces.
+fn merge_source(
+    source: Value,
+    path: &str,
+    locales: &LocaleFiles,
+) -> Result<Value, QTranslateError> {
+    if !fs::exists(path)? {
+        return Err(QTranslateError::SourceMissing(path.to_string()));
+    }
+
+    match (source, locales.source_strings(path, locales.read_path(path)?)?) {
+        (Value::Object(mut map), Value::Object(other)) => {
+            for key in layout::merge(&mut map, other) {
+                eprintln!("{path}: {key} overrides the value of an earlier source file");
+            }
+            Ok(Value::Object(map))
+        }
+        _ => Err(QTranslateError::InvalidFile {
+            path: path.to_string(),
+            source: FormatError::NotAnObject,
+        }),
+    }
+}
+
+/// Exits with a usage error if `--source-column` or `--target-column` is given
+/// for other than CSV files, or if CSV files are combined with options that
+/// do not apply to their rows.
+fn check_csv_args(locales: &LocaleFiles, args: &Args) {
+    let conflict = match locales.format {
+        Format::Csv if args.output_format == OutputFormat::Po => {
+            "--output-format po cannot be used with CSV files, whose translations are \
+             written to a column"
+        }
+        Format::Csv if !args.translate_keys.is_empty() => {
+            "--translate-keys cannot be used with CSV files, whose keys are not written"
+        }
+        Format::Csv => return,
+        _ if args.source_column.is_some() || args.target_column.is_some() => {
+            "--source-column and --target-column only apply to CSV files"
+        }
+        _ => return,
+    };
+    usage_error(ErrorKind::ArgumentConflict, conflict);
+}
+
+/// Builds the translation context from `--context` and the key notes of
+/// `--context-file`, or of the `.context.json` file next to the source file
+/// if there is one.
+fn read_context(
+    source_json: &Value,
+    locales: &LocaleFiles,
+    args: &Args,
+) -> Result<TranslationContext, QTranslateError> {
+    let context = TranslationContext::new(args.context.clone());
+    let notes_path = match &args.context_file {
+        Some(path) => PathBuf::from(path),
+        None if locales.stdin.is_some() => return Ok(context),
+        None => Path::new(&locales.source_path).with_extension("context.json"),
+    };
+
+    if args.context_file.is_none() && !fs::exists(&notes_path)? {
+        return Ok(context);
+    }
+    Ok(context.with_key_notes(source_json, &read_key_notes(notes_path)?))
+}
+
+/// Returns the progress bar of `target_lang`.
+fn progress_bar(target_lang: &str) -> ProgressBar {
+    ProgressBar::no_length()
+        .with_style(
+            ProgressStyle::with_template("{prefix} [{bar:40}] {pos}/{len} phrases translated")
+                .expect("Progress bar template is valid")
+                .progress_chars("=> "),
+        )
+        .with_prefix(target_lang.to_string())
+}
+
+/// Prints the `summary` of `target_lang` and writes the translated
+/// `target_json` to its locale file.
+///
+/// With `--prune`, keys missing from the source are removed and reported.
+/// With `--sort-keys`, the keys are written in alphabetical order. With
+/// `--backup`, the previous file is copied to a backup first.
+/// With `--diff`, the `previous` contents are compared with what is written.
+fn finish_target(
+    source_json: &Value,
+    locales: &LocaleFiles,
+    target_lang: &str,
+    mut target_json: Map<String, Value>,
+    previous: Option<Map<String, Value>>,
+    summary: &TranslateSummary,
+    args: &Args,
+) -> Result<Option<TargetDiff>, QTranslateError> {
+    status!(
+        args,
+        "{target_lang}: {} keys, {} unique phrases, {} API calls saved",
+        summary.keys,
+        summary.unique,
+        summary.saved()
+    );
+    if summary.limited > 0 {
+        let reason = match args.timed_out {
+            true => "--timeout-total",
+            false => "--limit",
+        };
+        status!(
+            args,
+            "{target_lang}: {} phrases left untranslated by {reason}",
+            summary.limited
+        );
+    }
+
+    if args.prune {
+        // Translated keys are not stale, so compare with a source renamed alike.
+        let renamed = summary.key_translations.as_ref().map(|keys| match source_json {
+            Value::Object(map) => Value::Object(keys.translate(map.clone())),
+            other => other.clone(),
+        });
+        let source_json = renamed.as_ref().unwrap_or(source_json);
+        for key_path in utils::prune_stale_keys(source_json, &mut target_json, &args.ignore) {
+            status!(args, "{target_lang}: pruned {key_path}");
+        }
+    }
+    if args.sort_keys {
+        target_json = layout::sort_keys(target_json);
+    }
+    // Nothing is overwritten when writing to stdout.
+    if (args.backup || args.backup_dir.is_some()) && !locales.stdout {
+        let backup_dir = args.backup_dir.as_deref().map(Path::new);
+        if let Some(backup_path) = backup(locales.output_path(target_lang), backup_dir)? {
+            status!(args, "{target_lang}: backed up to {}", backup_path.display());
+        }
+    }
+
+    let diff = previous.map(|previous| TargetDiff::new(previous, target_json.clone()));
+    let final_newline = !args.no_final_newline;
+    locales.write(target_lang, source_json, target_json, args.json_style(), final_newline)?;
+    Ok(diff)
+}
+
+/// Prints the keys of `target_lang` listed in `diff`, with `--diff`.
+fn print_diff(target_lang: &str, diff: &TargetDiff, args: &Args) {
+    if !args.diff {
+        return;
+    }
+    status!(
+        args,
+        "{target_lang}: {} added, {} changed, {} removed",
+        diff.added.len(),
+        diff.changed.len(),
+        diff.removed.len()
+    );
+    let changes = [
+        (&diff.added, Style::new().green()),
+        (&diff.changed, Style::new().yellow()),
+        (&diff.removed, Style::new().red()),
+    ];
+    for (changes, style) in changes {
+        for change in changes {
+            status!(args, "  {}", paint(&change.to_string(), style, args.status_stream()));
+        }
+    }
+}
+
+/// Returns `text` in `style` if `stream` is a terminal and `NO_COLOR` is not
+/// set, or with `--pretty-diff`, so piped and CI output stays plain.
+fn paint(text: &str, style: Style, stream: Stream) -> String {
+    text.if_supports_color(stream, |text| text.style(style)).to_string()
+}
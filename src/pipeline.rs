@@ -0,0 +1,490 @@
+use crate::cache::TranslationCache;
+use crate::error::QTranslateError;
+use crate::files::write_atomic;
+use crate::format::{Format, FormatError};
+use crate::filter::KeyFilter;
+use crate::keys::{self, KeyTranslations};
+use crate::utils::{self, OnError, TranslationOptions, TranslationStats};
+use futures::StreamExt;
+use futures::stream::{self, FuturesUnordered};
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Phrase counts and statistics of a finished translation run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TranslateSummary {
+    /// Phrases that were missing from the target and have been translated.
+    pub translated: usize,
+    /// Phrases already present in the target, which were left as they are.
+    pub skipped: usize,
+    /// Phrases whose translation request failed.
+    pub failed: usize,
+    /// Missing phrases left untranslated because of
+    /// [`TranslationOptions::limit`].
+    pub limited: usize,
+    /// String values in the source selected by the key filter, counting every
+    /// key and array element.
+    pub keys: usize,
+    /// Distinct phrases among those values.
+    pub unique: usize,
+    /// Phrases taken from the translation cache.
+    pub cache_hits: usize,
+    /// Requests sent to the provider, not counting retries.
+    pub requests: usize,
+    /// Characters sent to the provider, not counting retries.
+    pub characters: usize,
+    /// Time spent translating, serialized in seconds.
+    #[serde(serialize_with = "seconds")]
+    pub elapsed: Duration,
+    /// Key paths whose phrase failed to translate.
+    pub failed_keys: Vec<String>,
+    /// Object keys renamed in the target, with
+    /// [`TranslationOptions::translate_keys`].
+    #[serde(skip)]
+    pub key_translations: Option<KeyTranslations>,
+}
+
+impl TranslateSummary {
+    /// Translations not requested because the phrase occurs under several
+    /// keys and is translated only once.
+    pub fn saved(&self) -> usize {
+        self.keys - self.unique
+    }
+}
+
+fn seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// Translates the locale file at `source_path` into `target_lang` and writes
+/// the result to `target_path`.
+///
+/// This runs the whole pipeline of the `q-translate` binary for a single
+/// target: both files are read in the format given by their extension, the
+/// missing phrases are translated with [`translate_json`] and the
+/// reconstructed target is written back atomically. A missing target file is
+/// created from scratch.
+///
+/// # Examples
+///
+/// ```no_run
+/// use q_translate::filter::KeyFilter;
+/// use q_translate::pipeline::translate_file;
+/// use q_translate::placeholders::Placeholders;
+/// use q_translate::providers::{Provider, ProviderSettings};
+/// use q_translate::utils::{OnError, TranslationOptions};
+///
+/// # async fn example() -> Result<(), q_translate::error::QTranslateError> {
+/// let provider = Provider::Google.create(&ProviderSettings::default())?;
+/// let options = TranslationOptions {
+///     provider: provider.as_ref(),
+///     placeholders: &Placeholders::default(),
+///     source_lang: "en",
+///     concurrency: 5,
+///     batch_size: None,
+///     max_chars_per_request: None,
+///     overwrite: false,
+///     filter: &KeyFilter::default(),
+///     on_error: OnError::KeepSource,
+///     rate_limiter: None,
+///     context: None,
+///     limit: None,
+///     max_chars_total: None,
+///     preserve_newlines: false,
+///     normalize_whitespace: false,
+///     strict_placeholders: false,
+///     match_case: false,
+///     translate_keys: None,
+/// };
+///
+/// let summary = translate_file("i18n/en.json", "i18n/de.json", "de", &options).await?;
+/// println!("{} phrases translated", summary.translated);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if either file has an unsupported extension, including
+/// `.csv`, or cannot be parsed, if the target is not an object, or if reading or writing fails.
+pub async fn translate_file(
+    source_path: impl AsRef<Path>,
+    target_path: impl AsRef<Path>,
+    target_lang: &str,
+    options: &TranslationOptions<'_>,
+) -> Result<TranslateSummary, QTranslateError> {
+    translate_file_with_cache(source_path.as_ref(), target_path.as_ref(), target_lang, options, None)
+        .await
+}
+
+/// Translates the locale files at the paths of `files` into `target_lang`,
+/// writing each to the target path next to it, like [`translate_file`].
+///
+/// Up to `max_concurrent` files are translated at the same time, sharing
+/// `options.rate_limiter` and `cache`. Directories of target paths are
+/// created as needed. Every file succeeds or fails on its own; returns the
+/// summary or error of every file, in the order of `files`.
+pub async fn translate_files(
+    files: &[(PathBuf, PathBuf)],
+    target_lang: &str,
+    options: &TranslationOptions<'_>,
+    cache: Option<&TranslationCache>,
+    max_concurrent: usize,
+) -> Vec<Result<TranslateSummary, QTranslateError>> {
+    stream::iter(files)
+        .map(|(source_path, target_path)| async move {
+            if let Some(dir) = target_path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            translate_file_with_cache(source_path, target_path, target_lang, options, cache).await
+        })
+        .buffered(max_concurrent)
+        .collect()
+        .await
+}
+
+/// Runs [`translate_file`] with `cache`.
+async fn translate_file_with_cache(
+    source_path: &Path,
+    target_path: &Path,
+    target_lang: &str,
+    options: &TranslationOptions<'_>,
+    cache: Option<&TranslationCache>,
+) -> Result<TranslateSummary, QTranslateError> {
+    let source_json = read_file(source_path)?;
+    let mut target_json = match fs::exists(target_path)? {
+        true => match read_file(target_path)? {
+            Value::Object(map) => map,
+            Value::Null => Map::new(),
+            _ => {
+                return Err(QTranslateError::InvalidFile {
+                    path: target_path.display().to_string(),
+                    source: FormatError::NotAnObject,
+                });
+            }
+        },
+        false => Map::new(),
+    };
+
+    let summary = translate_json(
+        &source_json,
+        &mut target_json,
+        target_lang,
+        options,
+        cache,
+        |_, _| {},
+    )
+    .await
+    .map_err(|err| match err {
+        QTranslateError::InvalidTarget { source, .. } => QTranslateError::InvalidFile {
+            path: target_path.display().to_string(),
+            source,
+        },
+        err => err,
+    })?;
+
+    let contents = format_of(target_path)?
+        .serialize(&Value::Object(target_json))
+        .map_err(|source| QTranslateError::InvalidFile {
+            path: target_path.display().to_string(),
+            source,
+        })?;
+    write_atomic(target_path, contents)?;
+
+    Ok(summary)
+}
+
+/// Translates the already parsed `source` into `target_lang`, filling in the
+/// phrases missing from `target`.
+///
+/// Runs [`utils::gather_translations`], [`utils::perform_translations`] and
+/// [`utils::apply_translations`] in order. `cache` and `on_progress` are
+/// passed on to [`utils::perform_translations`].
+///
+/// Every distinct phrase is translated at most once, no matter how many keys
+/// or array elements of `source` contain it.
+///
+/// # Errors
+///
+/// Returns [`QTranslateError::Translation`] if the translation requests cannot
+/// be performed, and [`QTranslateError::InvalidTarget`] if `target` holds
+/// something else than a string where `source` holds one.
+pub async fn translate_json(
+    source: &Value,
+    target: &mut Map<String, Value>,
+    target_lang: &str,
+    options: &TranslationOptions<'_>,
+    cache: Option<&TranslationCache>,
+    on_progress: impl FnMut(usize, usize),
+) -> Result<TranslateSummary, QTranslateError> {
+    let strings = utils::source_strings(source, options.filter);
+    translate_collected(
+        source,
+        &strings,
+        target,
+        target_lang,
+        options,
+        cache,
+        on_progress,
+    )
+    .await
+}
+
+/// Translates the strings of `input`, a flat map of keys to source strings,
+/// into `target_lang` and returns them by the same keys.
+///
+/// This runs [`utils::perform_translations`] without a locale file: every
+/// distinct string is translated once, in batches with the concurrency and
+/// limits of `options`, while its key filter and `overwrite` do not apply.
+/// Keys whose string failed to translate are kept or left out according to
+/// `options.on_error`, and so are those left out by `options.limit`. Having no
+/// order of their own, the strings are limited in alphabetical order.
+///
+/// # Examples
+///
+/// ```no_run
+/// use q_translate::pipeline::translate_map;
+/// use std::collections::HashMap;
+/// # use q_translate::utils::TranslationOptions;
+///
+/// # async fn example(options: TranslationOptions<'_>) -> Result<(), q_translate::error::QTranslateError> {
+/// let input = HashMap::from([("product.42.name".to_string(), "Blue mug".to_string())]);
+///
+/// let translated = translate_map(input, "de", &options).await?;
+/// println!("{}", translated["product.42.name"]);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns [`QTranslateError::Translation`] if the translation requests cannot
+/// be performed.
+pub async fn translate_map(
+    input: HashMap<String, String>,
+    target_lang: &str,
+    options: &TranslationOptions<'_>,
+) -> Result<HashMap<String, String>, QTranslateError> {
+    let mut translations: HashMap<String, String> = input
+        .values()
+        .map(|text| (text.clone(), String::new()))
+        .collect();
+
+    utils::perform_translations(
+        &mut translations,
+        &[],
+        options,
+        None,
+        target_lang,
+        |_, _| {},
+    )
+    .await
+    .map_err(|err| QTranslateError::Translation(err.to_string()))?;
+
+    Ok(input
+        .into_iter()
+        .filter_map(|(key, text)| Some((key, translations.get(&text)?.clone())))
+        .collect())
+}
+
+/// Translates the already parsed `source` into all `targets` at the same time,
+/// filling in the phrases missing from each target map.
+///
+/// The string values of `source` are collected once and shared by all
+/// targets, and the requests of all targets run concurrently. They share
+/// `options.rate_limiter`; give it a [`RateLimiter::with_max_in_flight`] limit
+/// to cap the number of requests in flight over all targets together.
+///
+/// `cache` is shared by all targets, so a target can reuse what another one
+/// with the same language code has already translated, and holds the
+/// translations of all of them afterwards. `on_progress` is called with the
+/// index of a target in `targets` and its progress.
+///
+/// Returns the summary or error of every target, in the order of `targets`. A
+/// failing target does not stop the others, unless `options.on_error` is
+/// [`OnError::Fail`]: then the first failure cancels the targets still
+/// running, which are reported as failed too.
+///
+/// [`RateLimiter::with_max_in_flight`]: crate::rate_limit::RateLimiter::with_max_in_flight
+pub async fn translate_targets(
+    source: &Value,
+    targets: &mut [(&str, Map<String, Value>)],
+    options: &TranslationOptions<'_>,
+    cache: Option<&TranslationCache>,
+    on_progress: impl Fn(usize, usize, usize),
+) -> Vec<Result<TranslateSummary, QTranslateError>> {
+    let strings = utils::source_strings(source, options.filter);
+
+    let (strings, on_progress) = (&strings, &on_progress);
+    let mut runs: FuturesUnordered<_> = targets
+        .iter_mut()
+        .enumerate()
+        .map(|(i, (target_lang, target))| async move {
+            let result = translate_collected(
+                source,
+                strings,
+                target,
+                target_lang,
+                options,
+                cache,
+                move |done, total| on_progress(i, done, total),
+            )
+            .await;
+            (i, target_lang, result)
+        })
+        .collect();
+
+    let mut results: Vec<Option<Result<TranslateSummary, QTranslateError>>> =
+        (0..runs.len()).map(|_| None).collect();
+    while let Some((i, target_lang, result)) = runs.next().await {
+        let abort = result.is_err() && options.on_error == OnError::Fail;
+        results[i] = Some(result);
+        if abort {
+            let cancelled = format!("cancelled after translating into {target_lang} failed");
+            return results
+                .into_iter()
+                .map(|result| {
+                    result.unwrap_or_else(|| Err(QTranslateError::Translation(cancelled.clone())))
+                })
+                .collect();
+        }
+    }
+    results.into_iter().flatten().collect()
+}
+
+/// Runs [`translate_json`] with the `strings` of `source` collected by
+/// [`utils::source_strings`].
+async fn translate_collected(
+    source: &Value,
+    strings: &[(String, &str)],
+    target: &mut Map<String, Value>,
+    target_lang: &str,
+    options: &TranslationOptions<'_>,
+    cache: Option<&TranslationCache>,
+    on_progress: impl FnMut(usize, usize),
+) -> Result<TranslateSummary, QTranslateError> {
+    let started = Instant::now();
+    let mut translations: HashMap<String, String> = HashMap::default();
+
+    let (key_translations, key_stats) = match options.translate_keys {
+        Some(filter) => {
+            let (keys, stats) = translate_keys(source, filter, options, cache, target_lang).await?;
+            *target = keys.untranslate(mem::take(target));
+            (Some(keys), stats)
+        }
+        None => (None, TranslationStats::default()),
+    };
+
+    utils::gather_translations(
+        source,
+        target,
+        &mut translations,
+        options.overwrite,
+        options.filter,
+    )
+    .map_err(|source| QTranslateError::InvalidTarget {
+        lang: target_lang.to_string(),
+        source,
+    })?;
+
+    let phrases = translations.len();
+    let missing = translations.values().filter(|t| t.is_empty()).count();
+
+    let order: Vec<&str> = strings.iter().map(|(_, phrase)| *phrase).collect();
+    let stats = utils::perform_translations(
+        &mut translations,
+        &order,
+        options,
+        cache,
+        target_lang,
+        on_progress,
+    )
+    .await
+    .map_err(|err| QTranslateError::Translation(err.to_string()))?;
+
+    utils::apply_translations(
+        source,
+        target,
+        &translations,
+        options.overwrite,
+        options.filter,
+    );
+
+    if let Some(keys) = &key_translations {
+        *target = keys.translate(mem::take(target));
+    }
+
+    let failed: HashSet<&str> = stats.failed.iter().map(String::as_str).collect();
+    let failed_keys = strings
+        .iter()
+        .filter(|(_, phrase)| failed.contains(phrase))
+        .map(|(path, _)| path.clone())
+        .collect();
+
+    Ok(TranslateSummary {
+        translated: missing - failed.len() - stats.limited,
+        skipped: phrases - missing,
+        failed: failed.len() + key_stats.failed.len(),
+        limited: stats.limited,
+        keys: strings.len(),
+        unique: phrases,
+        cache_hits: stats.cache_hits + key_stats.cache_hits,
+        requests: stats.requests + key_stats.requests,
+        characters: stats.characters + key_stats.characters,
+        elapsed: started.elapsed(),
+        failed_keys,
+        key_translations,
+    })
+}
+
+/// Translates the object keys of `source` selected by `filter`, see
+/// [`TranslationOptions::translate_keys`].
+async fn translate_keys(
+    source: &Value,
+    filter: &KeyFilter,
+    options: &TranslationOptions<'_>,
+    cache: Option<&TranslationCache>,
+    target_lang: &str,
+) -> Result<(KeyTranslations, TranslationStats), QTranslateError> {
+    let mut translations = HashMap::new();
+    keys::gather_keys(source, filter, &mut translations);
+
+    let stats = utils::perform_translations(
+        &mut translations,
+        &[],
+        options,
+        cache,
+        target_lang,
+        |_, _| {},
+    )
+    .await
+    .map_err(|err| QTranslateError::Translation(err.to_string()))?;
+    for key in &stats.failed {
+        translations.remove(key);
+    }
+
+    Ok((KeyTranslations::new(source, filter, &translations), stats))
+}
+
+/// Reads and parses the locale file at `path`.
+fn read_file(path: &Path) -> Result<Value, QTranslateError> {
+    format_of(path)?
+        .parse(&fs::read_to_string(path)?)
+        .map_err(|source| QTranslateError::InvalidFile {
+            path: path.display().to_string(),
+            source,
+        })
+}
+
+/// Detects the format of `path` from its extension. CSV files are not
+/// supported, since they hold every language in a column of their own.
+fn format_of(path: &Path) -> Result<Format, QTranslateError> {
+    Format::from_path(path)
+        .filter(|format| *format != Format::Csv)
+        .ok_or_else(|| QTranslateError::UnsupportedFormat(path.display().to_string()))
+}
@@ -0,0 +1,689 @@
+use regex::Regex;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::iter;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// Placeholder patterns protected by default.
+///
+/// Double-brace placeholders are listed before single-brace ones so that
+/// `{{count}}` is matched as a whole rather than as `{count}` wrapped in braces.
+pub const DEFAULT_PATTERNS: [&str; 5] = [
+    r"\{\{[^{}]*\}\}",
+    r"\{[^{}]*\}",
+    r"%s",
+    r"%d",
+    r"\$t\([^()]*\)",
+];
+
+/// Pattern matching a single HTML tag including its attributes, e.g.
+/// `<a href="/x">`, `</a>` or `<br/>`.
+///
+/// Listed before [`DEFAULT_PATTERNS`] when translating HTML, so that
+/// placeholders inside attribute values are protected as part of their tag.
+pub const HTML_TAG_PATTERN: &str = r"<[^<>]*>";
+
+/// Pattern matching a web address, e.g. `https://example.com/help`.
+pub const URL_PATTERN: &str = r"(?i)(?:https?|ftp)://\S+|www\.\S+";
+
+/// Phrases matching one of these patterns as a whole carry no language and are
+/// copied verbatim by default: web addresses, e-mail addresses, numbers
+/// (`42`, `-1,000.50`, `15%`), version numbers (`v1.2.3`) and hex codes
+/// (`#ff0000`, `0x1F`).
+pub const VERBATIM_PATTERNS: [&str; 5] = [
+    URL_PATTERN,
+    r"(?i)(?:mailto:)?[\w.+-]+@[\w-]+(?:\.[\w-]+)+",
+    r"[-+]?\d[\d.,]*%?",
+    r"(?i)v?\d+(?:\.\d+)+(?:[-+][\w.-]+)?",
+    r"#[0-9a-fA-F]{3,8}|0[xX][0-9a-fA-F]+",
+];
+
+/// Start of an ICU `plural`, `selectordinal` or `select` argument, up to the
+/// comma before its first option, e.g. `{count, plural,`.
+static ICU_ARGUMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\{\s*[\w.]+\s*,\s*(plural|selectordinal|select)\s*,")
+        .expect("ICU argument pattern is valid")
+});
+
+/// Sentinel tokens in the form produced by [`Placeholders::protect`], e.g.
+/// `__QT0__` or `__QTX3__`.
+static SENTINEL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"__QTX*\d+__").expect("Sentinel pattern is valid"));
+
+/// Protects interpolation placeholders (`{name}`, `{{count}}`, `%s`, `%d`,
+/// `$t(key)`, ...) from being altered by the translation API.
+///
+/// Before a phrase is sent for translation every placeholder is replaced by a
+/// sentinel token (`__QT0__`, `__QT1__`, ...). Once the translation is
+/// returned, the sentinels are swapped back for the original placeholders.
+///
+/// Glossary terms added with [`Placeholders::with_glossary`] are protected the
+/// same way, so brand names and technical terms are kept verbatim. Terms of a
+/// termbase added with [`Placeholders::with_termbase`] are protected as well,
+/// but replaced by their forced translation.
+///
+/// ICU MessageFormat `plural`, `selectordinal` and `select` arguments such as
+/// `{count, plural, one {# item} other {# items}}` are recognized, including
+/// nested ones. Only the text of their sub-messages is translated; the
+/// argument name, keywords, selectors, braces and `#` are protected and
+/// reassembled afterwards.
+///
+/// Phrases matching a verbatim pattern as a whole (see
+/// [`Placeholders::with_verbatim`]) are not translated at all.
+#[derive(Debug, Clone)]
+pub struct Placeholders {
+    patterns: Vec<String>,
+    regex: Regex,
+    verbatim: Option<Regex>,
+    /// Termbases by the target language they apply to.
+    termbases: Vec<(String, Termbase)>,
+}
+
+/// Forced translations of terms into one target language.
+#[derive(Debug, Clone)]
+struct Termbase {
+    /// Translations by the lowercase term.
+    terms: HashMap<String, String>,
+    regex: Regex,
+}
+
+/// A phrase with its placeholders replaced by sentinel tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Protected {
+    /// The text that should be sent to the translation API.
+    pub text: String,
+    tokens: Vec<(String, String)>,
+    /// Whitespace around the phrase, which providers tend to trim or alter.
+    leading: String,
+    trailing: String,
+}
+
+/// Placeholders that a translation lost or gained compared to its source
+/// phrase, as found by [`Placeholders::check`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlaceholderMismatch {
+    /// Placeholders of the source phrase missing from the translation.
+    pub missing: Vec<String>,
+    /// Placeholders of the translation that the source phrase does not
+    /// contain, or contains fewer times.
+    pub added: Vec<String>,
+}
+
+impl fmt::Display for PlaceholderMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |placeholders: &[String]| {
+            placeholders
+                .iter()
+                .map(|placeholder| format!("{placeholder:?}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        match (self.missing.is_empty(), self.added.is_empty()) {
+            (false, false) => write!(
+                f,
+                "is missing {} and has extra {}",
+                list(&self.missing),
+                list(&self.added)
+            ),
+            (false, true) => write!(f, "is missing {}", list(&self.missing)),
+            (true, _) => write!(f, "has extra {}", list(&self.added)),
+        }
+    }
+}
+
+impl Default for Placeholders {
+    fn default() -> Self {
+        Self::new(&DEFAULT_PATTERNS)
+            .and_then(|placeholders| placeholders.with_verbatim(&VERBATIM_PATTERNS))
+            .expect("Default placeholder patterns are valid")
+    }
+}
+
+impl Placeholders {
+    /// Creates a placeholder matcher from a list of regular expressions.
+    ///
+    /// When several patterns match at the same position, the one listed first
+    /// wins. No phrase is considered verbatim until
+    /// [`Placeholders::with_verbatim`] is called.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the patterns is not a valid regular expression.
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Self, regex::Error> {
+        Self::compile(patterns.iter().map(|p| p.as_ref().to_string()).collect())
+    }
+
+    /// Additionally protects every term of a do-not-translate glossary.
+    ///
+    /// Terms are matched as whole words, longest first, so that `GitHub
+    /// Actions` wins over `GitHub`. With `case_sensitive` set to `false`,
+    /// `github` matches the term `GitHub` as well; the original spelling of
+    /// the phrase is always preserved.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use q_translate::placeholders::Placeholders;
+    ///
+    /// let placeholders = Placeholders::default().with_glossary(&["Qumo"], true).unwrap();
+    /// assert_eq!(placeholders.protect("Welcome to Qumo").text, "Welcome to __QT0__");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the combined expression exceeds the regex size limit.
+    pub fn with_glossary<S: AsRef<str>>(
+        self,
+        terms: &[S],
+        case_sensitive: bool,
+    ) -> Result<Self, regex::Error> {
+        let mut terms: Vec<&str> = terms
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|term| !term.is_empty())
+            .collect();
+        terms.sort_by_key(|term| Reverse(term.len()));
+
+        let mut patterns = self.patterns;
+        patterns.extend(terms.into_iter().map(|term| term_pattern(term, case_sensitive)));
+
+        Ok(Self {
+            verbatim: self.verbatim,
+            termbases: self.termbases,
+            ..Self::compile(patterns)?
+        })
+    }
+
+    /// Forces the translation of terms into `target_lang`, such as `cart`
+    /// into `Warenkorb` for German, in addition to the terms given before.
+    ///
+    /// Terms are matched as whole words, longest first and ignoring case. They
+    /// are protected like placeholders and restored as their translation, so
+    /// the provider cannot choose another one; a term starting with an
+    /// uppercase letter gets its translation capitalized. The termbase applies
+    /// to phrases protected with [`Placeholders::protect_for`] for
+    /// `target_lang` or a regional variant of it, e.g. `de-AT` for `de`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use q_translate::placeholders::Placeholders;
+    ///
+    /// let placeholders = Placeholders::default()
+    ///     .with_termbase("de", &[("cart", "Warenkorb")])
+    ///     .unwrap();
+    ///
+    /// let protected = placeholders.protect_for("Your cart is empty", "de");
+    /// assert_eq!(protected.text, "Your __QT0__ is empty");
+    /// assert_eq!(protected.restore("Ihr __QT0__ ist leer"), "Ihr Warenkorb ist leer");
+    /// assert_eq!(placeholders.protect_for("Your cart is empty", "fr").text, "Your cart is empty");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the combined expression exceeds the regex size limit.
+    pub fn with_termbase<S: AsRef<str>>(
+        mut self,
+        target_lang: &str,
+        terms: &[(S, S)],
+    ) -> Result<Self, regex::Error> {
+        let position = self
+            .termbases
+            .iter()
+            .position(|(lang, _)| lang.eq_ignore_ascii_case(target_lang));
+        let mut merged = match position {
+            Some(position) => self.termbases.remove(position).1.terms,
+            None => HashMap::new(),
+        };
+        for (term, translation) in terms {
+            if !term.as_ref().is_empty() {
+                merged.insert(term.as_ref().to_lowercase(), translation.as_ref().to_string());
+            }
+        }
+
+        let mut sorted: Vec<&String> = merged.keys().collect();
+        sorted.sort_by_key(|term| Reverse(term.len()));
+        let alternation = match sorted.is_empty() {
+            true => r"[^\s\S]".to_string(),
+            false => sorted
+                .iter()
+                .map(|term| format!("(?:{})", term_pattern(term, false)))
+                .collect::<Vec<_>>()
+                .join("|"),
+        };
+        let regex = Regex::new(&alternation)?;
+
+        self.termbases.push((target_lang.to_string(), Termbase { terms: merged, regex }));
+        Ok(self)
+    }
+
+    /// Copies phrases matching one of `patterns` as a whole verbatim instead of
+    /// translating them, replacing any patterns given before.
+    ///
+    /// Leading and trailing whitespace is ignored. Unlike placeholders, the
+    /// patterns are not protected inside longer phrases.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use q_translate::placeholders::{Placeholders, VERBATIM_PATTERNS};
+    ///
+    /// let placeholders = Placeholders::default().with_verbatim(&VERBATIM_PATTERNS).unwrap();
+    /// assert!(placeholders.is_verbatim("https://example.com"));
+    /// assert!(!placeholders.is_verbatim("Visit https://example.com"));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the patterns is not a valid regular expression.
+    pub fn with_verbatim<S: AsRef<str>>(self, patterns: &[S]) -> Result<Self, regex::Error> {
+        let verbatim = match patterns.is_empty() {
+            true => None,
+            false => {
+                let alternation = patterns
+                    .iter()
+                    .map(|pattern| format!("(?:{})", pattern.as_ref()))
+                    .collect::<Vec<_>>()
+                    .join("|");
+                Some(Regex::new(&format!(r"^\s*(?:{alternation})\s*$"))?)
+            }
+        };
+
+        Ok(Self { verbatim, ..self })
+    }
+
+    /// Returns `true` if `phrase` as a whole matches a verbatim pattern, so it
+    /// is copied instead of translated.
+    pub fn is_verbatim(&self, phrase: &str) -> bool {
+        self.verbatim
+            .as_ref()
+            .is_some_and(|verbatim| verbatim.is_match(phrase))
+    }
+
+    /// Checks that `translated`, the translation of the `protected` text
+    /// before [`Protected::restore`], holds every placeholder of the source
+    /// phrase exactly once and no others.
+    ///
+    /// # Errors
+    ///
+    /// Returns the placeholders that are missing, duplicated or new, such as
+    /// sentinel tokens the provider made up or placeholders it added to the
+    /// text.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use q_translate::placeholders::Placeholders;
+    ///
+    /// let placeholders = Placeholders::default();
+    /// let protected = placeholders.protect("Hello {name}, you have %d messages");
+    /// assert_eq!(protected.text, "Hello __QT0__, you have __QT1__ messages");
+    ///
+    /// assert!(placeholders.check(&protected, "Hallo __QT0__, du hast __QT1__ Nachrichten").is_ok());
+    ///
+    /// let mismatch = placeholders.check(&protected, "Hallo, du hast %s Nachrichten").unwrap_err();
+    /// assert_eq!(mismatch.missing, ["{name}", "%d"]);
+    /// assert_eq!(mismatch.added, ["%s"]);
+    /// ```
+    pub fn check(&self, protected: &Protected, translated: &str) -> Result<(), PlaceholderMismatch> {
+        let mut mismatch = PlaceholderMismatch::default();
+
+        for (sentinel, original) in &protected.tokens {
+            match translated.matches(sentinel.as_str()).count() {
+                0 => mismatch.missing.push(original.clone()),
+                count => mismatch.added.extend(iter::repeat_n(original.clone(), count - 1)),
+            }
+        }
+        let unknown = SENTINEL
+            .find_iter(translated)
+            .map(|sentinel| sentinel.as_str())
+            .filter(|found| !protected.tokens.iter().any(|(sentinel, _)| sentinel == found));
+        mismatch.added.extend(unknown.map(str::to_string));
+        // The text sent had all placeholders replaced, so any in the
+        // translation were added by the provider.
+        let added = self.regex.find_iter(translated).map(|found| found.as_str().to_string());
+        mismatch.added.extend(added);
+
+        match mismatch.missing.is_empty() && mismatch.added.is_empty() {
+            true => Ok(()),
+            false => Err(mismatch),
+        }
+    }
+
+    fn compile(patterns: Vec<String>) -> Result<Self, regex::Error> {
+        let alternation = match patterns.is_empty() {
+            // An empty alternation would match the empty string everywhere.
+            true => r"[^\s\S]".to_string(),
+            false => patterns
+                .iter()
+                .map(|pattern| format!("(?:{pattern})"))
+                .collect::<Vec<_>>()
+                .join("|"),
+        };
+
+        Ok(Self {
+            regex: Regex::new(&alternation)?,
+            patterns,
+            verbatim: None,
+            termbases: vec![],
+        })
+    }
+
+    /// Replaces all placeholders in `phrase` with sentinel tokens.
+    ///
+    /// The sentinel prefix is chosen so that it never occurs in the original
+    /// phrase, which guarantees that [`Protected::restore`] only touches tokens
+    /// introduced here. Leading and trailing whitespace is not sent either;
+    /// it is put back around the translation by [`Protected::restore`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use q_translate::placeholders::Placeholders;
+    ///
+    /// let protected = Placeholders::default().protect("Hello {name}!");
+    /// assert_eq!(protected.text, "Hello __QT0__!");
+    /// assert_eq!(protected.restore("Hallo __QT0__!"), "Hallo {name}!");
+    ///
+    /// let protected = Placeholders::default().protect("{n, plural, one {# file} other {# files}}");
+    /// assert_eq!(protected.text, "__QT0__ file__QT1__ files__QT2__");
+    ///
+    /// let protected = Placeholders::default().protect(" Loading… ");
+    /// assert_eq!(protected.text, "Loading…");
+    /// assert_eq!(protected.restore("Wird geladen…"), " Wird geladen… ");
+    /// ```
+    pub fn protect(&self, phrase: &str) -> Protected {
+        self.protect_with(phrase, None)
+    }
+
+    /// Replaces all placeholders in `phrase` with sentinel tokens like
+    /// [`Placeholders::protect`], and the terms of the termbase of
+    /// `target_lang` as well, see [`Placeholders::with_termbase`].
+    pub fn protect_for(&self, phrase: &str, target_lang: &str) -> Protected {
+        self.protect_with(phrase, self.termbase(target_lang))
+    }
+
+    /// Returns the termbase of `target_lang`, or else of its language without
+    /// the region.
+    fn termbase(&self, target_lang: &str) -> Option<&Termbase> {
+        let base = target_lang.split(['-', '_']).next().unwrap_or(target_lang);
+        let find = |lang: &str| {
+            self.termbases
+                .iter()
+                .find(|(termbase_lang, _)| termbase_lang.eq_ignore_ascii_case(lang))
+                .map(|(_, termbase)| termbase)
+        };
+        find(target_lang).or_else(|| find(base))
+    }
+
+    fn protect_with(&self, phrase: &str, termbase: Option<&Termbase>) -> Protected {
+        let trimmed = phrase.trim_start();
+        let leading = phrase[..phrase.len() - trimmed.len()].to_string();
+        let trailing = trimmed[trimmed.trim_end().len()..].to_string();
+        let phrase = trimmed.trim_end();
+
+        let mut tag = "QT".to_string();
+        while phrase.contains(&format!("__{tag}")) {
+            tag.push('X');
+        }
+
+        let mut tokens = vec![];
+        let mut text = String::new();
+        let mut skeleton = String::new();
+
+        for (segment, is_skeleton) in icu_segments(phrase) {
+            if is_skeleton {
+                skeleton.push_str(segment);
+                continue;
+            }
+
+            if !skeleton.is_empty() {
+                text.push_str(&push_token(&tag, &mut tokens, std::mem::take(&mut skeleton)));
+            }
+            let mut last = 0;
+            for found in self.regex.find_iter(segment) {
+                let before = &segment[last..found.start()];
+                text.push_str(&replace_terms(before, termbase, &tag, &mut tokens));
+                text.push_str(&push_token(&tag, &mut tokens, found.as_str().to_string()));
+                last = found.end();
+            }
+            text.push_str(&replace_terms(&segment[last..], termbase, &tag, &mut tokens));
+        }
+        if !skeleton.is_empty() {
+            text.push_str(&push_token(&tag, &mut tokens, skeleton));
+        }
+
+        Protected {
+            text,
+            tokens,
+            leading,
+            trailing,
+        }
+    }
+}
+
+/// Records `original` as the next token and returns its sentinel.
+fn push_token(tag: &str, tokens: &mut Vec<(String, String)>, original: String) -> String {
+    let sentinel = format!("__{}{}__", tag, tokens.len());
+    tokens.push((sentinel.clone(), original));
+    sentinel
+}
+
+/// Replaces the terms of `termbase` in `text` with sentinel tokens standing
+/// for their translations.
+fn replace_terms(
+    text: &str,
+    termbase: Option<&Termbase>,
+    tag: &str,
+    tokens: &mut Vec<(String, String)>,
+) -> String {
+    let Some(termbase) = termbase else {
+        return text.to_string();
+    };
+
+    termbase
+        .regex
+        .replace_all(text, |captures: &regex::Captures| {
+            let term = &captures[0];
+            let translation = termbase
+                .terms
+                .get(&term.to_lowercase())
+                .map_or(term, String::as_str);
+            let mut chars = translation.chars();
+            let translation = match (term.starts_with(char::is_uppercase), chars.next()) {
+                (true, Some(first)) => first.to_uppercase().chain(chars).collect(),
+                _ => translation.to_string(),
+            };
+            push_token(tag, tokens, translation)
+        })
+        .into_owned()
+}
+
+/// Splits `text` into translatable text and the skeleton of the ICU
+/// arguments it contains. Skeleton segments are flagged with `true`.
+fn icu_segments(text: &str) -> Vec<(&str, bool)> {
+    let mut segments = vec![];
+    split_icu(text, false, &mut segments);
+    segments
+}
+
+/// Appends the segments of the ICU message `text` to `segments`. Inside the
+/// sub-messages of a `plural`, `#` stands for the number and is skeleton.
+fn split_icu<'a>(text: &'a str, in_plural: bool, segments: &mut Vec<(&'a str, bool)>) {
+    let mut plain = 0;
+    let mut i = 0;
+
+    while i < text.len() {
+        match text.as_bytes()[i] {
+            b'#' if in_plural => {
+                push_segment(segments, &text[plain..i], false);
+                push_segment(segments, &text[i..i + 1], true);
+                i += 1;
+                plain = i;
+            }
+            b'{' => {
+                let Some(close) = matching_brace(text, i) else {
+                    break;
+                };
+                let argument = &text[i..=close];
+
+                // Anything else in braces is a simple placeholder, left to the patterns.
+                if let Some(header) = ICU_ARGUMENT.captures(argument) {
+                    let plural = in_plural || &header[1] != "select";
+
+                    push_segment(segments, &text[plain..i], false);
+                    split_options(argument, header[0].len(), plural, segments);
+                    plain = close + 1;
+                }
+                i = close + 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    push_segment(segments, &text[plain..], false);
+}
+
+/// Appends the segments of an ICU `argument` whose options start at
+/// `options`, e.g. ` one {# item} other {# items}}`.
+fn split_options<'a>(
+    argument: &'a str,
+    options: usize,
+    plural: bool,
+    segments: &mut Vec<(&'a str, bool)>,
+) {
+    let mut start = options;
+    push_segment(segments, &argument[..options], true);
+
+    while let Some(open) = argument[start..].find('{').map(|offset| start + offset) {
+        let Some(close) = matching_brace(argument, open) else {
+            break;
+        };
+
+        push_segment(segments, &argument[start..=open], true);
+        split_icu(&argument[open + 1..close], plural, segments);
+        start = close;
+    }
+
+    push_segment(segments, &argument[start..], true);
+}
+
+/// Returns the index of the brace closing the one at `open`.
+fn matching_brace(text: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+
+    for (i, byte) in text.bytes().enumerate().skip(open) {
+        match byte {
+            b'{' => depth += 1,
+            b'}' if depth == 1 => return Some(i),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn push_segment<'a>(segments: &mut Vec<(&'a str, bool)>, segment: &'a str, is_skeleton: bool) {
+    if !segment.is_empty() {
+        segments.push((segment, is_skeleton));
+    }
+}
+
+impl Protected {
+    /// Returns `true` if nothing but placeholders, glossary and termbase
+    /// terms, whitespace and punctuation is left in the phrase, so there is
+    /// nothing to translate.
+    pub fn is_fully_protected(&self) -> bool {
+        !self.tokens.is_empty() && self.stripped().chars().all(|c| !c.is_alphanumeric())
+    }
+
+    /// The text with its sentinel tokens left out.
+    pub(crate) fn stripped(&self) -> String {
+        self.tokens
+            .iter()
+            .fold(self.text.clone(), |text, (sentinel, _)| text.replace(sentinel, ""))
+    }
+
+    /// Restores the original placeholders in a translated text, and the
+    /// leading and trailing whitespace of the original phrase in place of
+    /// whatever whitespace surrounds the translation.
+    pub fn restore(&self, translated: &str) -> String {
+        let restored = self.tokens.iter().fold(
+            translated.trim().to_string(),
+            |text, (sentinel, original)| text.replace(sentinel, original),
+        );
+
+        format!("{}{restored}{}", self.leading, self.trailing)
+    }
+}
+
+/// Reads a do-not-translate glossary from `path`.
+///
+/// The file is either a JSON array of strings or a plain text file with one
+/// term per line. In the plain text form, blank lines and lines starting with
+/// `#` are ignored.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read.
+pub fn read_glossary(path: impl AsRef<Path>) -> io::Result<Vec<String>> {
+    let contents = fs::read_to_string(path)?;
+
+    if let Ok(terms) = serde_json::from_str::<Vec<String>>(&contents) {
+        return Ok(terms);
+    }
+
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Reads a termbase from `path`: a JSON object mapping source terms to their
+/// forced translations, see [`Placeholders::with_termbase`].
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or is not such an object.
+pub fn read_termbase(path: impl AsRef<Path>) -> io::Result<Vec<(String, String)>> {
+    let contents = fs::read_to_string(path)?;
+    let terms: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(&contents).map_err(io::Error::from)?;
+
+    terms
+        .into_iter()
+        .map(|(term, translation)| match translation {
+            serde_json::Value::String(translation) => Ok((term, translation)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the translation of {term:?} is not a string"),
+            )),
+        })
+        .collect()
+}
+
+/// Builds a whole-word pattern matching `term` literally.
+fn term_pattern(term: &str, case_sensitive: bool) -> String {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    let start = match term.starts_with(is_word_char) {
+        true => r"\b",
+        false => "",
+    };
+    let end = match term.ends_with(is_word_char) {
+        true => r"\b",
+        false => "",
+    };
+    let flags = match case_sensitive {
+        true => "",
+        false => "(?i)",
+    };
+
+    format!("{flags}{start}{}{end}", regex::escape(term))
+}
@@ -0,0 +1,186 @@
+use crate::error::QTranslateError;
+use crate::providers::{
+    AUTO_DETECT, BatchResult, DEFAULT_TIMEOUT, TextFormat, TranslationProvider, http_client,
+    log_detected_languages, send_json,
+};
+use crate::translate::{api_key, env_var};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Endpoint of the global Azure AI Translator service.
+pub const DEFAULT_ENDPOINT: &str = "https://api.cognitive.microsofttranslator.com/translate";
+
+/// Environment variable holding the Azure Translator subscription key.
+pub const API_KEY_VAR: &str = "AZURE_TRANSLATOR_KEY";
+
+/// Environment variable holding the Azure region of the Translator resource.
+pub const REGION_VAR: &str = "AZURE_TRANSLATOR_REGION";
+
+/// Azure accepts up to 1,000 texts per request, but batches are kept well
+/// below that so failed requests stay cheap to retry.
+pub const MAX_BATCH_SIZE: usize = 100;
+
+/// Azure accepts at most 50,000 characters per request.
+pub const MAX_CHARS_PER_REQUEST: usize = 50_000;
+
+const API_VERSION: &str = "3.0";
+
+#[derive(Debug, Serialize)]
+struct AzureText<'a> {
+    #[serde(rename = "Text")]
+    text: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct AzureResult {
+    translations: Vec<AzureTranslation>,
+    /// Only present when the source language is detected.
+    #[serde(rename = "detectedLanguage")]
+    detected_language: Option<DetectedLanguage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AzureTranslation {
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DetectedLanguage {
+    language: String,
+}
+
+/// Azure AI Translator (Cognitive Services Translator v3) backend.
+///
+/// The subscription key is read from the `AZURE_TRANSLATOR_KEY` environment
+/// variable. Regional and multi-service resources additionally need their
+/// region in `AZURE_TRANSLATOR_REGION`; global resources leave it unset.
+pub struct AzureProvider {
+    endpoint: String,
+    api_key: String,
+    region: Option<String>,
+    timeout: Duration,
+    text_format: TextFormat,
+}
+
+impl AzureProvider {
+    /// Creates a provider authenticating with `api_key` and, for regional
+    /// resources, `region`.
+    pub fn new(api_key: impl Into<String>, region: Option<String>) -> Self {
+        Self {
+            endpoint: DEFAULT_ENDPOINT.to_string(),
+            api_key: api_key.into(),
+            region,
+            timeout: DEFAULT_TIMEOUT,
+            text_format: TextFormat::Text,
+        }
+    }
+
+    /// Creates the provider using the `AZURE_TRANSLATOR_KEY` and
+    /// `AZURE_TRANSLATOR_REGION` environment variables.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QTranslateError::MissingApiKey`] if `AZURE_TRANSLATOR_KEY` is
+    /// not set.
+    pub fn from_env() -> Result<Self, QTranslateError> {
+        Self::from_env_var(API_KEY_VAR)
+    }
+
+    /// Creates the provider using the subscription key stored in the
+    /// environment variable `var` and the region in `AZURE_TRANSLATOR_REGION`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QTranslateError::MissingApiKey`] if `var` is not set.
+    pub fn from_env_var(var: &str) -> Result<Self, QTranslateError> {
+        Ok(Self::with_region_from_env(api_key(var)?))
+    }
+
+    /// Creates a provider authenticating with `api_key`, in the region of
+    /// `AZURE_TRANSLATOR_REGION` if it is set.
+    pub fn with_region_from_env(api_key: impl Into<String>) -> Self {
+        Self::new(api_key, env_var(REGION_VAR))
+    }
+
+    /// Sends the requests to `endpoint` instead of [`DEFAULT_ENDPOINT`].
+    pub fn with_endpoint(self, endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            ..self
+        }
+    }
+
+    /// Abandons every request after `timeout` instead of [`DEFAULT_TIMEOUT`].
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Sends phrases in `text_format` instead of plain text.
+    pub fn with_text_format(self, text_format: TextFormat) -> Self {
+        Self {
+            text_format,
+            ..self
+        }
+    }
+}
+
+#[async_trait]
+impl TranslationProvider for AzureProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> BatchResult {
+        let mut query = vec![
+            ("api-version", API_VERSION),
+            ("to", target_lang),
+            ("textType", self.text_format.as_str()),
+        ];
+        if source_lang != AUTO_DETECT {
+            query.push(("from", source_lang));
+        }
+
+        let body: Vec<AzureText> = phrases
+            .iter()
+            .map(|phrase| AzureText { text: phrase })
+            .collect();
+
+        let mut request = http_client()
+            .post(&self.endpoint)
+            .timeout(self.timeout)
+            .query(&query)
+            .header("Ocp-Apim-Subscription-Key", &self.api_key)
+            .json(&body);
+        if let Some(region) = &self.region {
+            request = request.header("Ocp-Apim-Subscription-Region", region);
+        }
+        let response: Vec<AzureResult> = send_json(request, self.timeout).await?;
+
+        if source_lang == AUTO_DETECT {
+            log_detected_languages(
+                response
+                    .iter()
+                    .filter_map(|result| result.detected_language.as_ref())
+                    .map(|detected| detected.language.as_str()),
+            );
+        }
+
+        // Every result holds exactly one translation, since a single `to`
+        // language is requested.
+        Ok(response
+            .into_iter()
+            .filter_map(|result| result.translations.into_iter().next())
+            .map(|t| t.text)
+            .collect())
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_BATCH_SIZE
+    }
+
+    fn max_chars_per_request(&self) -> usize {
+        MAX_CHARS_PER_REQUEST
+    }
+}
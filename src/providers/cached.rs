@@ -0,0 +1,158 @@
+use crate::files::write_atomic;
+use crate::hashes::hash;
+use crate::providers::{BatchContext, BatchResult, TranslationProvider};
+use async_trait::async_trait;
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// Default directory of the responses stored by [`CachedProvider`].
+pub const DEFAULT_BATCH_CACHE_DIR: &str = ".q-translate-cache/batches";
+
+/// Backend whose responses are stored on disk by the hash of the whole
+/// request, so a batch sent again, e.g. when re-running a run that crashed
+/// halfway, is answered without calling the API.
+///
+/// Unlike the phrases of a [`TranslationCache`](crate::cache::TranslationCache),
+/// a response is only replayed for the same phrases in the same order, between
+/// the same languages, with the same hints, sent to a backend of the same
+/// `name`. Every response is stored as a JSON array in `{dir}/{hash}.json`;
+/// failed batches are not stored.
+///
+/// # Examples
+///
+/// ```no_run
+/// use q_translate::providers::{CachedProvider, DEFAULT_BATCH_CACHE_DIR};
+/// use q_translate::providers::google::GoogleProvider;
+///
+/// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let provider = CachedProvider::new(
+///     "google",
+///     Box::new(GoogleProvider::from_env()?),
+///     DEFAULT_BATCH_CACHE_DIR,
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub struct CachedProvider {
+    name: String,
+    provider: Box<dyn TranslationProvider>,
+    dir: PathBuf,
+}
+
+impl CachedProvider {
+    /// Wraps `provider`, storing its responses in `dir`. `name` identifies
+    /// the backend and any setting changing its responses, such as the model.
+    pub fn new(
+        name: impl Into<String>,
+        provider: Box<dyn TranslationProvider>,
+        dir: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            provider,
+            dir: dir.into(),
+        }
+    }
+
+    /// Path of the stored response to a batch of `phrases`.
+    fn path(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+        context: Option<&BatchContext<'_>>,
+    ) -> PathBuf {
+        let request = json!({
+            "provider": self.name,
+            "source_lang": source_lang,
+            "target_lang": target_lang,
+            "phrases": phrases,
+            "context": context.map(|context| json!({
+                "description": context.description,
+                "notes": context.notes,
+            })),
+        });
+        self.dir.join(format!("{}.json", hash(&request.to_string())))
+    }
+
+    /// Returns the response stored at `path` if it holds a translation for
+    /// each of `count` phrases.
+    fn load(path: &Path, count: usize) -> Option<Vec<String>> {
+        let contents = fs::read_to_string(path).ok()?;
+        match serde_json::from_str::<Vec<String>>(&contents) {
+            Ok(translated) if translated.len() == count => Some(translated),
+            _ => {
+                warn!(path = %path.display(), "ignoring malformed cached batch");
+                None
+            }
+        }
+    }
+
+    /// Stores a successful `result` at `path`. Failing to do so is logged,
+    /// as the translations themselves are fine.
+    fn store(&self, path: &Path, result: BatchResult) -> BatchResult {
+        if let Ok(translated) = &result {
+            let stored = fs::create_dir_all(&self.dir)
+                .and_then(|()| write_atomic(path, serde_json::to_string(translated)?));
+            if let Err(err) = stored {
+                warn!(path = %path.display(), error = %err, "failed to cache batch");
+            }
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl TranslationProvider for CachedProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> BatchResult {
+        let path = self.path(phrases, source_lang, target_lang, None);
+        if let Some(translated) = Self::load(&path, phrases.len()) {
+            debug!(path = %path.display(), "replaying cached batch");
+            return Ok(translated);
+        }
+        let result = self
+            .provider
+            .translate_batch(phrases, source_lang, target_lang)
+            .await;
+        self.store(&path, result)
+    }
+
+    async fn translate_batch_with_context(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+        context: &BatchContext<'_>,
+    ) -> BatchResult {
+        let hints = Some(context).filter(|context| !context.is_empty());
+        let path = self.path(phrases, source_lang, target_lang, hints);
+        if let Some(translated) = Self::load(&path, phrases.len()) {
+            debug!(path = %path.display(), "replaying cached batch");
+            return Ok(translated);
+        }
+        let result = self
+            .provider
+            .translate_batch_with_context(phrases, source_lang, target_lang, context)
+            .await;
+        self.store(&path, result)
+    }
+
+    fn supports_context(&self) -> bool {
+        self.provider.supports_context()
+    }
+
+    fn max_batch_size(&self) -> usize {
+        self.provider.max_batch_size()
+    }
+
+    fn max_chars_per_request(&self) -> usize {
+        self.provider.max_chars_per_request()
+    }
+}
@@ -0,0 +1,150 @@
+use crate::error::QTranslateError;
+use crate::providers::{
+    AUTO_DETECT, BatchResult, DEFAULT_TIMEOUT, TextFormat, TranslationProvider, http_client,
+    log_detected_languages, send_json,
+};
+use crate::translate::api_key;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const DEEPL_URL: &str = "https://api-free.deepl.com/v2/translate";
+
+/// Environment variable holding the DeepL API key.
+pub const API_KEY_VAR: &str = "DEEPL_API_KEY";
+
+/// DeepL accepts at most 50 texts per request.
+pub const MAX_BATCH_SIZE: usize = 50;
+
+/// DeepL limits the request body to 128 KiB, which 30,000 characters stay
+/// below even for multi-byte scripts.
+pub const MAX_CHARS_PER_REQUEST: usize = 30_000;
+
+#[derive(Debug, Serialize)]
+struct DeepLRequest<'a> {
+    text: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_lang: Option<String>,
+    target_lang: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag_handling: Option<&'static str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeepLResponse {
+    translations: Vec<DeepLTranslation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeepLTranslation {
+    text: String,
+    detected_source_language: Option<String>,
+}
+
+/// DeepL API Free backend.
+///
+/// The API key is read from the `DEEPL_API_KEY` environment variable (for
+/// example using a `.env` file).
+pub struct DeepLProvider {
+    api_key: String,
+    timeout: Duration,
+    text_format: TextFormat,
+}
+
+impl DeepLProvider {
+    /// Creates a provider authenticating with `api_key`.
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            timeout: DEFAULT_TIMEOUT,
+            text_format: TextFormat::Text,
+        }
+    }
+
+    /// Creates the provider using the `DEEPL_API_KEY` environment variable.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QTranslateError::MissingApiKey`] if `DEEPL_API_KEY` is not set.
+    pub fn from_env() -> Result<Self, QTranslateError> {
+        Self::from_env_var(API_KEY_VAR)
+    }
+
+    /// Creates the provider using the API key stored in the environment
+    /// variable `var`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QTranslateError::MissingApiKey`] if `var` is not set.
+    pub fn from_env_var(var: &str) -> Result<Self, QTranslateError> {
+        Ok(Self::new(api_key(var)?))
+    }
+
+    /// Abandons every request after `timeout` instead of [`DEFAULT_TIMEOUT`].
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Sends phrases in `text_format` instead of plain text.
+    pub fn with_text_format(self, text_format: TextFormat) -> Self {
+        Self {
+            text_format,
+            ..self
+        }
+    }
+}
+
+#[async_trait]
+impl TranslationProvider for DeepLProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> BatchResult {
+        let request = http_client()
+            .post(DEEPL_URL)
+            .timeout(self.timeout)
+            .header("Authorization", format!("DeepL-Auth-Key {}", self.api_key))
+            .json(&DeepLRequest {
+                text: phrases,
+                source_lang: source_language_code(source_lang),
+                target_lang: target_lang.to_uppercase(),
+                tag_handling: match self.text_format {
+                    TextFormat::Text => None,
+                    TextFormat::Html => Some("html"),
+                },
+            });
+        let response: DeepLResponse = send_json(request, self.timeout).await?;
+
+        if source_lang == AUTO_DETECT {
+            log_detected_languages(
+                response
+                    .translations
+                    .iter()
+                    .filter_map(|t| t.detected_source_language.as_deref()),
+            );
+        }
+
+        Ok(response.translations.into_iter().map(|t| t.text).collect())
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_BATCH_SIZE
+    }
+
+    fn max_chars_per_request(&self) -> usize {
+        MAX_CHARS_PER_REQUEST
+    }
+}
+
+/// Converts a source language code to the form expected by DeepL.
+///
+/// DeepL does not accept regional variants for the source language, so
+/// `en-US` is sent as `EN`. Auto-detection is requested by omitting the field.
+fn source_language_code(source_lang: &str) -> Option<String> {
+    match source_lang {
+        AUTO_DETECT => None,
+        lang => lang.split(['-', '_']).next().map(str::to_uppercase),
+    }
+}
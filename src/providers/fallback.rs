@@ -0,0 +1,132 @@
+use crate::providers::{BatchContext, BatchResult, TranslationProvider};
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Chain of backends: a batch that the first one fails after all retries is
+/// sent to the second one, and so on, before its phrases count as failed.
+///
+/// The chain counts the phrases every backend translated, see
+/// [`FallbackProvider::served`]. Batches are sized for the smallest limits of
+/// all backends, so a failed batch can be sent to the next one as it is.
+/// Hints are passed to every backend in the chain that supports them.
+///
+/// # Examples
+///
+/// ```no_run
+/// use q_translate::providers::FallbackProvider;
+/// use q_translate::providers::deepl::DeepLProvider;
+/// use q_translate::providers::google::GoogleProvider;
+///
+/// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let provider = FallbackProvider::new("deepl", Box::new(DeepLProvider::from_env()?))
+///     .with_fallback("google", Box::new(GoogleProvider::from_env()?));
+/// # Ok(())
+/// # }
+/// ```
+pub struct FallbackProvider {
+    name: String,
+    provider: Box<dyn TranslationProvider>,
+    /// Phrases translated by `provider`.
+    served: AtomicUsize,
+    fallback: Option<Box<FallbackProvider>>,
+}
+
+impl FallbackProvider {
+    /// Creates a chain of the single backend `provider`, reported as `name`.
+    pub fn new(name: impl Into<String>, provider: Box<dyn TranslationProvider>) -> Self {
+        Self {
+            name: name.into(),
+            provider,
+            served: AtomicUsize::new(0),
+            fallback: None,
+        }
+    }
+
+    /// Appends `provider`, reported as `name`, to the end of the chain.
+    pub fn with_fallback(
+        mut self,
+        name: impl Into<String>,
+        provider: Box<dyn TranslationProvider>,
+    ) -> Self {
+        self.fallback = Some(Box::new(match self.fallback.take() {
+            Some(fallback) => fallback.with_fallback(name, provider),
+            None => FallbackProvider::new(name, provider),
+        }));
+        self
+    }
+
+    /// Names of the backends with the number of phrases each translated so
+    /// far, in the order of the chain.
+    pub fn served(&self) -> Vec<(String, usize)> {
+        let mut served = vec![(self.name.clone(), self.served.load(Ordering::Relaxed))];
+        if let Some(fallback) = &self.fallback {
+            served.extend(fallback.served());
+        }
+        served
+    }
+
+    /// Counts the phrases of a successful `result`.
+    fn count(&self, result: BatchResult) -> BatchResult {
+        if let Ok(translated) = &result {
+            self.served.fetch_add(translated.len(), Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl TranslationProvider for FallbackProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> BatchResult {
+        let result = self
+            .provider
+            .translate_batch(phrases, source_lang, target_lang)
+            .await;
+        self.count(result)
+    }
+
+    async fn translate_batch_with_context(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+        context: &BatchContext<'_>,
+    ) -> BatchResult {
+        let result = self
+            .provider
+            .translate_batch_with_context(phrases, source_lang, target_lang, context)
+            .await;
+        self.count(result)
+    }
+
+    fn supports_context(&self) -> bool {
+        self.provider.supports_context()
+            || self.fallback.as_ref().is_some_and(|f| f.supports_context())
+    }
+
+    fn max_batch_size(&self) -> usize {
+        let fallback = self
+            .fallback
+            .as_ref()
+            .map_or(usize::MAX, |f| f.max_batch_size());
+        self.provider.max_batch_size().min(fallback)
+    }
+
+    fn max_chars_per_request(&self) -> usize {
+        let fallback = self
+            .fallback
+            .as_ref()
+            .map_or(usize::MAX, |f| f.max_chars_per_request());
+        self.provider.max_chars_per_request().min(fallback)
+    }
+
+    fn fallback(&self) -> Option<&dyn TranslationProvider> {
+        self.fallback
+            .as_deref()
+            .map(|fallback| fallback as &dyn TranslationProvider)
+    }
+}
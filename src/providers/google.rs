@@ -0,0 +1,116 @@
+use crate::error::QTranslateError;
+use crate::providers::{
+    AUTO_DETECT, BatchResult, DEFAULT_TIMEOUT, TextFormat, TranslationProvider,
+    log_detected_languages,
+};
+use crate::translate::{API_KEY_VAR, DEFAULT_ENDPOINT, api_key, endpoint, request_translations};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Google Translate v2 accepts at most 128 text segments per request.
+pub const MAX_BATCH_SIZE: usize = 128;
+
+/// Google recommends sending at most 5,000 characters per request; longer
+/// requests are rejected with `400 Bad Request`.
+pub const MAX_CHARS_PER_REQUEST: usize = 5_000;
+
+/// Google Translate v2 backend.
+///
+/// Uses the same request as [`crate::translate::translate_phrases`]. The API
+/// key is read from the `GOOGLE_TRANSLATE_API_KEY` environment variable (for
+/// example using a `.env` file), and requests are sent to the URL in
+/// `GOOGLE_TRANSLATE_BASE_URL` if it is set.
+pub struct GoogleProvider {
+    endpoint: String,
+    api_key: String,
+    timeout: Duration,
+    text_format: TextFormat,
+}
+
+impl GoogleProvider {
+    /// Creates a provider authenticating with `api_key`.
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            endpoint: DEFAULT_ENDPOINT.to_string(),
+            api_key: api_key.into(),
+            timeout: DEFAULT_TIMEOUT,
+            text_format: TextFormat::Text,
+        }
+    }
+
+    /// Creates the provider using the `GOOGLE_TRANSLATE_API_KEY` environment
+    /// variable.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QTranslateError::MissingApiKey`] if `GOOGLE_TRANSLATE_API_KEY`
+    /// is not set.
+    pub fn from_env() -> Result<Self, QTranslateError> {
+        Self::from_env_var(API_KEY_VAR)
+    }
+
+    /// Creates the provider using the API key stored in the environment
+    /// variable `var` and the endpoint in `GOOGLE_TRANSLATE_BASE_URL`, if set.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QTranslateError::MissingApiKey`] if `var` is not set.
+    pub fn from_env_var(var: &str) -> Result<Self, QTranslateError> {
+        Ok(Self::new(api_key(var)?).with_endpoint(endpoint()))
+    }
+
+    /// Sends the requests to `endpoint` instead of [`DEFAULT_ENDPOINT`].
+    pub fn with_endpoint(self, endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            ..self
+        }
+    }
+
+    /// Abandons every request after `timeout` instead of [`DEFAULT_TIMEOUT`].
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Sends phrases in `text_format` instead of plain text.
+    pub fn with_text_format(self, text_format: TextFormat) -> Self {
+        Self {
+            text_format,
+            ..self
+        }
+    }
+}
+
+#[async_trait]
+impl TranslationProvider for GoogleProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> BatchResult {
+        let translations = request_translations(
+            &self.endpoint,
+            &self.api_key,
+            phrases,
+            source_lang,
+            target_lang,
+            self.timeout,
+            self.text_format,
+        )
+        .await?;
+
+        if source_lang == AUTO_DETECT {
+            log_detected_languages(
+                translations
+                    .iter()
+                    .filter_map(|t| t.detected_source_language.as_deref()),
+            );
+        }
+
+        Ok(translations
+            .into_iter()
+            .map(|t| t.translated_text)
+            .collect())
+    }
+}
@@ -0,0 +1,137 @@
+use crate::providers::{
+    AUTO_DETECT, BatchResult, DEFAULT_TIMEOUT, TextFormat, TranslationProvider, http_client,
+    log_detected_languages, send_json,
+};
+use crate::translate::api_key;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Environment variable holding the optional LibreTranslate API key.
+pub const API_KEY_VAR: &str = "LIBRETRANSLATE_API_KEY";
+
+/// Endpoint of a LibreTranslate instance running locally with default settings.
+pub const DEFAULT_ENDPOINT: &str = "http://localhost:5000/translate";
+
+/// LibreTranslate has no fixed limit, but instances usually cap the request
+/// size, so batches are kept small.
+pub const MAX_BATCH_SIZE: usize = 50;
+
+/// Hosted instances commonly limit requests to 5,000 characters.
+pub const MAX_CHARS_PER_REQUEST: usize = 5_000;
+
+#[derive(Debug, Serialize)]
+struct LibreRequest<'a> {
+    q: &'a [String],
+    source: &'a str,
+    target: &'a str,
+    format: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_key: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LibreResponse {
+    #[serde(rename = "translatedText")]
+    translated_text: Vec<String>,
+    /// Only present when the source language is detected.
+    #[serde(rename = "detectedLanguage")]
+    detected_language: Option<Vec<DetectedLanguage>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DetectedLanguage {
+    language: String,
+}
+
+/// LibreTranslate backend for hosted or self-hosted instances.
+///
+/// The API key is optional, since many self-hosted instances do not require
+/// one. [`LibreProvider::from_env`] reads it from the `LIBRETRANSLATE_API_KEY`
+/// environment variable.
+pub struct LibreProvider {
+    endpoint: String,
+    api_key: Option<String>,
+    timeout: Duration,
+    text_format: TextFormat,
+}
+
+impl LibreProvider {
+    /// Creates a provider sending its requests to `endpoint`, e.g.
+    /// `http://localhost:5000/translate`.
+    pub fn new(endpoint: impl Into<String>, api_key: Option<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            api_key,
+            timeout: DEFAULT_TIMEOUT,
+            text_format: TextFormat::Text,
+        }
+    }
+
+    /// Creates a provider for `endpoint`, taking the API key from the
+    /// `LIBRETRANSLATE_API_KEY` environment variable if it is set.
+    pub fn from_env(endpoint: impl Into<String>) -> Self {
+        Self::from_env_var(endpoint, API_KEY_VAR)
+    }
+
+    /// Creates a provider for `endpoint`, taking the API key from the
+    /// environment variable `var` if it is set.
+    pub fn from_env_var(endpoint: impl Into<String>, var: &str) -> Self {
+        Self::new(endpoint, api_key(var).ok())
+    }
+
+    /// Abandons every request after `timeout` instead of [`DEFAULT_TIMEOUT`].
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Sends phrases in `text_format` instead of plain text.
+    pub fn with_text_format(self, text_format: TextFormat) -> Self {
+        Self {
+            text_format,
+            ..self
+        }
+    }
+}
+
+#[async_trait]
+impl TranslationProvider for LibreProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> BatchResult {
+        let request = http_client()
+            .post(&self.endpoint)
+            .timeout(self.timeout)
+            .json(&LibreRequest {
+                q: phrases,
+                source: source_lang,
+                target: target_lang,
+                format: self.text_format.as_str(),
+                api_key: self.api_key.as_deref(),
+            });
+        let response: LibreResponse = send_json(request, self.timeout).await?;
+
+        if source_lang == AUTO_DETECT {
+            log_detected_languages(
+                response
+                    .detected_language
+                    .iter()
+                    .flatten()
+                    .map(|detected| detected.language.as_str()),
+            );
+        }
+
+        Ok(response.translated_text)
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_BATCH_SIZE
+    }
+
+    fn max_chars_per_request(&self) -> usize {
+        MAX_CHARS_PER_REQUEST
+    }
+}
@@ -0,0 +1,536 @@
+use crate::error::{QTranslateError, TranslateError};
+use async_trait::async_trait;
+use clap::ValueEnum;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, NoProxy, Proxy, RequestBuilder};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+use tracing::info;
+
+#[cfg(feature = "azure")]
+pub mod azure;
+mod cached;
+#[cfg(feature = "deepl")]
+pub mod deepl;
+mod fallback;
+#[cfg(feature = "google")]
+pub mod google;
+#[cfg(feature = "libre")]
+pub mod libre;
+#[cfg(feature = "openai")]
+pub mod openai;
+
+#[cfg(not(any(
+    feature = "google",
+    feature = "deepl",
+    feature = "libre",
+    feature = "azure",
+    feature = "openai"
+)))]
+compile_error!("at least one provider feature must be enabled: google, deepl, libre, azure or openai");
+
+pub use cached::{CachedProvider, DEFAULT_BATCH_CACHE_DIR};
+pub use fallback::FallbackProvider;
+
+/// Source language value that lets the provider detect the language itself.
+pub const AUTO_DETECT: &str = "auto";
+
+/// `tracing` target of the info events reporting the source languages
+/// detected with [`AUTO_DETECT`].
+pub const DETECTED_LANGUAGES_TARGET: &str = "q_translate::detected_languages";
+
+/// Phrases per request assumed for backends that do not declare a limit of
+/// their own.
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 128;
+
+/// Characters per request assumed for backends that do not declare a limit of
+/// their own.
+pub const DEFAULT_MAX_CHARS_PER_REQUEST: usize = 5_000;
+
+/// Time after which a single translation request is abandoned.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// HTTP client shared by every request of the process.
+///
+/// `Client` keeps a pool of keep-alive connections, so batches sent to the same
+/// host reuse established connections instead of a new TLS handshake each.
+/// Unless [`set_proxy`] is called first, it is created on the first request
+/// with the proxies of the `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and
+/// `NO_PROXY` environment variables, in upper or lower case.
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Result of translating a single batch of phrases.
+pub type BatchResult = Result<Vec<String>, TranslateError>;
+
+/// A translation backend capable of translating batches of phrases.
+///
+/// Implementations receive a batch of source phrases and must return the
+/// translated phrases in the same order as the input.
+#[async_trait]
+pub trait TranslationProvider: Send + Sync {
+    /// Translates `phrases` from `source_lang` into `target_lang`.
+    ///
+    /// A `source_lang` of [`AUTO_DETECT`] asks the backend to detect the source
+    /// language. The returned vector must have the same length and order as
+    /// `phrases`; otherwise the whole batch fails with
+    /// [`TranslateError::CountMismatch`].
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> BatchResult;
+
+    /// Translates `phrases` like [`translate_batch`], taking the hints of
+    /// `context` into account.
+    ///
+    /// Backends that cannot use the hints keep this default, which ignores
+    /// them, along with the default of [`supports_context`].
+    ///
+    /// [`translate_batch`]: TranslationProvider::translate_batch
+    /// [`supports_context`]: TranslationProvider::supports_context
+    async fn translate_batch_with_context(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+        context: &BatchContext<'_>,
+    ) -> BatchResult {
+        let _ = context;
+        self.translate_batch(phrases, source_lang, target_lang)
+            .await
+    }
+
+    /// Returns `true` if the backend makes use of the hints passed to
+    /// [`translate_batch_with_context`].
+    ///
+    /// [`translate_batch_with_context`]: TranslationProvider::translate_batch_with_context
+    fn supports_context(&self) -> bool {
+        false
+    }
+
+    /// Maximum number of phrases accepted by the backend in a single request.
+    fn max_batch_size(&self) -> usize {
+        DEFAULT_MAX_BATCH_SIZE
+    }
+
+    /// Maximum number of characters accepted by the backend in a single
+    /// request, over all phrases together.
+    fn max_chars_per_request(&self) -> usize {
+        DEFAULT_MAX_CHARS_PER_REQUEST
+    }
+
+    /// Backend a batch is sent to once this one failed it after all retries,
+    /// see [`FallbackProvider`].
+    fn fallback(&self) -> Option<&dyn TranslationProvider> {
+        None
+    }
+}
+
+/// Hints about what the phrases of a batch are used for, see
+/// [`crate::context::TranslationContext`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchContext<'a> {
+    /// Description of the whole project, e.g. `"mobile banking app UI"`.
+    pub description: Option<&'a str>,
+    /// Note on every phrase of the batch, in the order of the phrases.
+    pub notes: Vec<Option<&'a str>>,
+}
+
+impl BatchContext<'_> {
+    /// Returns `true` if the context holds no hint at all.
+    pub fn is_empty(&self) -> bool {
+        self.description.is_none() && self.notes.iter().all(Option::is_none)
+    }
+}
+
+/// Available translation backends, selectable via the `--provider` CLI flag or
+/// the `provider` key of the configuration file.
+///
+/// Every backend is compiled in only with the Cargo feature of the same
+/// name; `google` is enabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    /// Google Translate v2 (`GOOGLE_TRANSLATE_API_KEY`)
+    #[cfg(feature = "google")]
+    Google,
+    /// DeepL API Free (`DEEPL_API_KEY`)
+    #[cfg(feature = "deepl")]
+    #[value(name = "deepl")]
+    DeepL,
+    /// LibreTranslate, hosted or self-hosted (optional `LIBRETRANSLATE_API_KEY`)
+    #[cfg(feature = "libre")]
+    Libre,
+    /// Azure AI Translator (`AZURE_TRANSLATOR_KEY`, optional `AZURE_TRANSLATOR_REGION`)
+    #[cfg(feature = "azure")]
+    Azure,
+    /// OpenAI Chat Completions (`OPENAI_API_KEY`)
+    #[cfg(feature = "openai")]
+    #[value(name = "openai")]
+    OpenAi,
+}
+
+impl Default for Provider {
+    /// Google, or the first other backend compiled in without it.
+    fn default() -> Self {
+        Self::value_variants()[0]
+    }
+}
+
+impl Provider {
+    /// Maximum number of phrases the backend accepts in a single request.
+    ///
+    /// Available without instantiating the backend, so no API key is needed.
+    pub fn max_batch_size(self) -> usize {
+        match self {
+            #[cfg(feature = "google")]
+            Provider::Google => google::MAX_BATCH_SIZE,
+            #[cfg(feature = "deepl")]
+            Provider::DeepL => deepl::MAX_BATCH_SIZE,
+            #[cfg(feature = "libre")]
+            Provider::Libre => libre::MAX_BATCH_SIZE,
+            #[cfg(feature = "azure")]
+            Provider::Azure => azure::MAX_BATCH_SIZE,
+            #[cfg(feature = "openai")]
+            Provider::OpenAi => openai::MAX_BATCH_SIZE,
+        }
+    }
+
+    /// Maximum number of characters the backend accepts in a single request.
+    ///
+    /// Available without instantiating the backend, so no API key is needed.
+    pub fn max_chars_per_request(self) -> usize {
+        match self {
+            #[cfg(feature = "google")]
+            Provider::Google => google::MAX_CHARS_PER_REQUEST,
+            #[cfg(feature = "deepl")]
+            Provider::DeepL => deepl::MAX_CHARS_PER_REQUEST,
+            #[cfg(feature = "libre")]
+            Provider::Libre => libre::MAX_CHARS_PER_REQUEST,
+            #[cfg(feature = "azure")]
+            Provider::Azure => azure::MAX_CHARS_PER_REQUEST,
+            #[cfg(feature = "openai")]
+            Provider::OpenAi => openai::MAX_CHARS_PER_REQUEST,
+        }
+    }
+
+    /// Environment variable the backend reads its API key from by default.
+    pub fn api_key_env(self) -> &'static str {
+        match self {
+            #[cfg(feature = "google")]
+            Provider::Google => crate::translate::API_KEY_VAR,
+            #[cfg(feature = "deepl")]
+            Provider::DeepL => deepl::API_KEY_VAR,
+            #[cfg(feature = "libre")]
+            Provider::Libre => libre::API_KEY_VAR,
+            #[cfg(feature = "azure")]
+            Provider::Azure => azure::API_KEY_VAR,
+            #[cfg(feature = "openai")]
+            Provider::OpenAi => openai::API_KEY_VAR,
+        }
+    }
+
+    /// Instantiates the backend represented by this variant.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QTranslateError::MissingApiKey`] if the API key required by
+    /// the backend is neither given in the settings nor set in its
+    /// environment variable.
+    pub fn create(
+        self,
+        settings: &ProviderSettings,
+    ) -> Result<Box<dyn TranslationProvider>, QTranslateError> {
+        let api_key_env = settings
+            .api_key_env
+            .as_deref()
+            .unwrap_or(self.api_key_env());
+        let api_key = || match &settings.api_key {
+            Some(api_key) => Ok(api_key.clone()),
+            None => crate::translate::api_key(api_key_env),
+        };
+        let ProviderSettings {
+            timeout,
+            text_format,
+            ..
+        } = *settings;
+
+        Ok(match self {
+            #[cfg(feature = "google")]
+            Provider::Google => {
+                let endpoint = settings
+                    .endpoint
+                    .clone()
+                    .unwrap_or_else(crate::translate::endpoint);
+                Box::new(
+                    google::GoogleProvider::new(api_key()?)
+                        .with_endpoint(endpoint)
+                        .with_timeout(timeout)
+                        .with_text_format(text_format),
+                )
+            }
+            #[cfg(feature = "deepl")]
+            Provider::DeepL => Box::new(
+                deepl::DeepLProvider::new(api_key()?)
+                    .with_timeout(timeout)
+                    .with_text_format(text_format),
+            ),
+            #[cfg(feature = "libre")]
+            Provider::Libre => Box::new(
+                libre::LibreProvider::new(
+                    settings
+                        .endpoint
+                        .as_deref()
+                        .unwrap_or(libre::DEFAULT_ENDPOINT),
+                    api_key().ok(),
+                )
+                .with_timeout(timeout)
+                .with_text_format(text_format),
+            ),
+            #[cfg(feature = "azure")]
+            Provider::Azure => Box::new(
+                azure::AzureProvider::with_region_from_env(api_key()?)
+                    .with_endpoint(
+                        settings
+                            .endpoint
+                            .as_deref()
+                            .unwrap_or(azure::DEFAULT_ENDPOINT),
+                    )
+                    .with_timeout(timeout)
+                    .with_text_format(text_format),
+            ),
+            #[cfg(feature = "openai")]
+            Provider::OpenAi => {
+                let mut provider = openai::OpenAiProvider::new(api_key()?)
+                    .with_endpoint(
+                        settings
+                            .endpoint
+                            .as_deref()
+                            .unwrap_or(openai::DEFAULT_ENDPOINT),
+                    )
+                    .with_timeout(timeout)
+                    .with_text_format(text_format);
+                if let Some(model) = &settings.model {
+                    provider = provider.with_model(model);
+                }
+                if let Some(prompt) = &settings.prompt {
+                    provider = provider.with_prompt(prompt);
+                }
+                Box::new(provider)
+            }
+        })
+    }
+}
+
+/// How the providers interpret the phrases they translate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TextFormat {
+    /// Phrases are plain text
+    #[default]
+    Text,
+    /// Phrases contain inline HTML markup, which is kept intact
+    Html,
+}
+
+impl TextFormat {
+    /// Name of the format as used by the translation APIs.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TextFormat::Text => "text",
+            TextFormat::Html => "html",
+        }
+    }
+}
+
+/// Connection settings passed to [`Provider::create`].
+///
+/// The [`Debug`](fmt::Debug) output leaves out the API key, so the settings
+/// can be logged safely.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ProviderSettings {
+    /// API key used instead of the one in the environment variable.
+    pub api_key: Option<String>,
+    /// Environment variable holding the API key, instead of
+    /// [`Provider::api_key_env`].
+    pub api_key_env: Option<String>,
+    /// URL the requests are sent to. Defaults to the value of
+    /// `GOOGLE_TRANSLATE_BASE_URL` or [`crate::translate::DEFAULT_ENDPOINT`]
+    /// for Google, [`libre::DEFAULT_ENDPOINT`], [`azure::DEFAULT_ENDPOINT`]
+    /// and [`openai::DEFAULT_ENDPOINT`]. Ignored by DeepL.
+    pub endpoint: Option<String>,
+    /// Model used by OpenAI, defaults to [`openai::DEFAULT_MODEL`]. Ignored by
+    /// the other backends.
+    pub model: Option<String>,
+    /// System prompt used by OpenAI, defaults to [`openai::DEFAULT_PROMPT`].
+    /// Ignored by the other backends.
+    pub prompt: Option<String>,
+    /// Time after which every request is abandoned.
+    pub timeout: Duration,
+    /// Whether phrases are sent as plain text or HTML.
+    pub text_format: TextFormat,
+}
+
+impl Default for ProviderSettings {
+    fn default() -> Self {
+        Self {
+            api_key: None,
+            api_key_env: None,
+            endpoint: None,
+            model: None,
+            prompt: None,
+            timeout: DEFAULT_TIMEOUT,
+            text_format: TextFormat::Text,
+        }
+    }
+}
+
+impl fmt::Debug for ProviderSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProviderSettings")
+            .field("api_key", &self.api_key.as_ref().map(|_| "<redacted>"))
+            .field("api_key_env", &self.api_key_env)
+            .field("endpoint", &self.endpoint)
+            .field("model", &self.model)
+            .field("prompt", &self.prompt)
+            .field("timeout", &self.timeout)
+            .field("text_format", &self.text_format)
+            .finish()
+    }
+}
+
+/// Returns `true` if the language codes `a` and `b` name the same language,
+/// ignoring case and regional variants, so `en-US`, `en_gb` and `EN` are all
+/// the same language.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::providers::is_same_language;
+///
+/// assert!(is_same_language("en-US", "en"));
+/// assert!(!is_same_language("en", "de"));
+/// ```
+pub fn is_same_language(a: &str, b: &str) -> bool {
+    let primary = |code: &str| code.split(['-', '_']).next().unwrap_or(code).to_lowercase();
+
+    primary(a) == primary(b)
+}
+
+/// Logs the distinct source languages detected by a provider for one batch,
+/// as an info event of [`DETECTED_LANGUAGES_TARGET`].
+#[cfg(any(
+    feature = "google",
+    feature = "deepl",
+    feature = "libre",
+    feature = "azure"
+))]
+pub(crate) fn log_detected_languages<'a>(detected: impl Iterator<Item = &'a str>) {
+    let mut languages: Vec<&str> = detected.collect();
+    languages.sort_unstable();
+    languages.dedup();
+
+    if !languages.is_empty() {
+        info!(
+            target: DETECTED_LANGUAGES_TARGET,
+            languages = %languages.join(", "),
+            "detected source language"
+        );
+    }
+}
+
+/// Returns a handle to the shared HTTP client.
+///
+/// Cloning a `Client` is cheap, all clones use the same connection pool.
+pub(crate) fn http_client() -> Client {
+    HTTP_CLIENT.get_or_init(Client::new).clone()
+}
+
+/// Sends the requests of every provider through the proxy at `url`, such as
+/// `http://proxy.example.com:8080`, instead of the proxies of the
+/// environment. Hosts listed in `NO_PROXY` are still reached directly.
+///
+/// All requests share one client, so this has to be called before the first
+/// request.
+///
+/// # Errors
+///
+/// Returns [`QTranslateError::InvalidProxy`] if `url` is not a valid proxy
+/// URL or a request has already been sent.
+pub fn set_proxy(url: &str) -> Result<(), QTranslateError> {
+    let invalid = |message: String| QTranslateError::InvalidProxy {
+        url: url.to_string(),
+        message,
+    };
+    let proxy = Proxy::all(url)
+        .map_err(|_| invalid("not a valid URL".to_string()))?
+        .no_proxy(NoProxy::from_env());
+    let client = Client::builder()
+        .proxy(proxy)
+        .build()
+        .map_err(|err| invalid(err.to_string()))?;
+
+    HTTP_CLIENT
+        .set(client)
+        .map_err(|_| invalid("the HTTP client is already in use".to_string()))
+}
+
+/// Parses the value of a `Retry-After` header, either a number of seconds or
+/// an HTTP date, into the delay to wait before retrying. A date in the past
+/// asks for no delay.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::providers::parse_retry_after;
+/// use std::time::Duration;
+///
+/// assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+/// assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+/// assert_eq!(parse_retry_after("soon"), None);
+/// ```
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// Sends `request` and parses the JSON body of the response.
+///
+/// A response with an unsuccessful status is reported as
+/// [`TranslateError::Api`] together with its body, which usually explains
+/// what went wrong, and a body that does not match `T` as
+/// [`TranslateError::Parse`].
+pub(crate) async fn send_json<T: DeserializeOwned>(
+    request: RequestBuilder,
+    timeout: Duration,
+) -> Result<T, TranslateError> {
+    let read = async {
+        let response = request.send().await?;
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        Ok((status, retry_after, response.text().await?))
+    };
+    let (status, retry_after, body) = read
+        .await
+        .map_err(|err| TranslateError::from_reqwest(err, timeout))?;
+
+    if !status.is_success() {
+        return Err(TranslateError::Api {
+            status: status.as_u16(),
+            body,
+            retry_after,
+        });
+    }
+    serde_json::from_str(&body).map_err(|err| TranslateError::Parse(Arc::new(err)))
+}
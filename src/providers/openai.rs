@@ -0,0 +1,275 @@
+use crate::error::{QTranslateError, TranslateError};
+use crate::providers::{
+    AUTO_DETECT, BatchContext, BatchResult, DEFAULT_TIMEOUT, TextFormat, TranslationProvider,
+    http_client, send_json,
+};
+use crate::translate::api_key;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Endpoint of the OpenAI Chat Completions API.
+pub const DEFAULT_ENDPOINT: &str = "https://api.openai.com/v1/chat/completions";
+
+/// Environment variable holding the OpenAI API key.
+pub const API_KEY_VAR: &str = "OPENAI_API_KEY";
+
+/// Model used unless another one is selected with [`OpenAiProvider::with_model`].
+pub const DEFAULT_MODEL: &str = "gpt-4o-mini";
+
+/// Phrases sent in a single completion; larger batches make the model more
+/// likely to merge or drop array elements.
+pub const MAX_BATCH_SIZE: usize = 50;
+
+/// Token budget of the phrases sent in a single completion. The translated
+/// answer is about as long, so the budget keeps it well within the output
+/// limit of the model.
+pub const MAX_TOKENS_PER_REQUEST: usize = 2_000;
+
+/// Batches are limited by characters, at roughly four characters per token.
+pub const MAX_CHARS_PER_REQUEST: usize = MAX_TOKENS_PER_REQUEST * 4;
+
+/// System prompt sent with every batch. `{source_lang}` and `{target_lang}`
+/// are replaced with the language codes of the batch.
+pub const DEFAULT_PROMPT: &str = "You are a professional translator of software user \
+interfaces. The user message is a JSON array of strings in {source_lang}. Translate every \
+string into {target_lang}. Keep placeholders such as {name}, {{count}}, %s and $t(key), \
+tokens such as __QT0__ and HTML tags exactly as they are. Reply with a JSON array of the \
+translated strings only, with the same number of elements in the same order.";
+
+#[derive(Debug, Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: [ChatMessage; 2],
+    temperature: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+/// OpenAI Chat Completions backend.
+///
+/// Every batch is sent as a JSON array in a single user message, and the
+/// model is instructed by the system prompt to answer with the translated
+/// array. The API key is read from the `OPENAI_API_KEY` environment variable.
+pub struct OpenAiProvider {
+    endpoint: String,
+    api_key: String,
+    model: String,
+    prompt: String,
+    timeout: Duration,
+    text_format: TextFormat,
+}
+
+impl OpenAiProvider {
+    /// Creates a provider authenticating with `api_key`.
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            endpoint: DEFAULT_ENDPOINT.to_string(),
+            api_key: api_key.into(),
+            model: DEFAULT_MODEL.to_string(),
+            prompt: DEFAULT_PROMPT.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            text_format: TextFormat::Text,
+        }
+    }
+
+    /// Creates the provider using the `OPENAI_API_KEY` environment variable.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QTranslateError::MissingApiKey`] if `OPENAI_API_KEY` is not
+    /// set.
+    pub fn from_env() -> Result<Self, QTranslateError> {
+        Self::from_env_var(API_KEY_VAR)
+    }
+
+    /// Creates the provider using the API key stored in the environment
+    /// variable `var`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QTranslateError::MissingApiKey`] if `var` is not set.
+    pub fn from_env_var(var: &str) -> Result<Self, QTranslateError> {
+        Ok(Self::new(api_key(var)?))
+    }
+
+    /// Sends the requests to `endpoint` instead of [`DEFAULT_ENDPOINT`], e.g.
+    /// to another OpenAI-compatible server.
+    pub fn with_endpoint(self, endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            ..self
+        }
+    }
+
+    /// Translates with `model` instead of [`DEFAULT_MODEL`].
+    pub fn with_model(self, model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            ..self
+        }
+    }
+
+    /// Sends `prompt` as system prompt instead of [`DEFAULT_PROMPT`]. The
+    /// `{source_lang}` and `{target_lang}` variables are replaced as in the
+    /// default prompt.
+    pub fn with_prompt(self, prompt: impl Into<String>) -> Self {
+        Self {
+            prompt: prompt.into(),
+            ..self
+        }
+    }
+
+    /// Abandons every request after `timeout` instead of [`DEFAULT_TIMEOUT`].
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Sends phrases in `text_format` instead of plain text.
+    pub fn with_text_format(self, text_format: TextFormat) -> Self {
+        Self {
+            text_format,
+            ..self
+        }
+    }
+
+    /// System prompt for a batch from `source_lang` into `target_lang`, with
+    /// the hints of `context` appended.
+    fn system_prompt(
+        &self,
+        source_lang: &str,
+        target_lang: &str,
+        context: &BatchContext,
+    ) -> String {
+        let source_lang = match source_lang {
+            AUTO_DETECT => "any language",
+            lang => lang,
+        };
+        let mut prompt = self
+            .prompt
+            .replace("{source_lang}", source_lang)
+            .replace("{target_lang}", target_lang);
+
+        if self.text_format == TextFormat::Html {
+            prompt.push_str(" The strings contain HTML markup; translate only the visible text.");
+        }
+        if let Some(description) = context.description {
+            prompt.push_str(&format!(" The strings are used in: {description}."));
+        }
+        if context.notes.iter().any(Option::is_some) {
+            prompt.push_str("\n\nNotes on single strings, by their index in the array:");
+            for (i, note) in context.notes.iter().enumerate() {
+                if let Some(note) = note {
+                    prompt.push_str(&format!("\n{i}: {note}"));
+                }
+            }
+        }
+        prompt
+    }
+
+    /// Sends `phrases` in a single completion request.
+    async fn complete(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+        context: &BatchContext<'_>,
+    ) -> BatchResult {
+        let chat = ChatRequest {
+            model: &self.model,
+            messages: [
+                ChatMessage {
+                    role: "system".to_string(),
+                    content: self.system_prompt(source_lang, target_lang, context),
+                },
+                ChatMessage {
+                    role: "user".to_string(),
+                    content: serde_json::to_string(phrases)
+                        .expect("strings always serialize to JSON"),
+                },
+            ],
+            temperature: 0.0,
+        };
+
+        let request = http_client()
+            .post(&self.endpoint)
+            .timeout(self.timeout)
+            .bearer_auth(&self.api_key)
+            .json(&chat);
+        let response: ChatResponse = send_json(request, self.timeout).await?;
+
+        let content = response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content)
+            .ok_or_else(|| TranslateError::InvalidResponse("no completion returned".to_string()))?;
+
+        parse_array(&content)
+    }
+}
+
+#[async_trait]
+impl TranslationProvider for OpenAiProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> BatchResult {
+        self.complete(phrases, source_lang, target_lang, &BatchContext::default())
+            .await
+    }
+
+    async fn translate_batch_with_context(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+        context: &BatchContext<'_>,
+    ) -> BatchResult {
+        self.complete(phrases, source_lang, target_lang, context)
+            .await
+    }
+
+    fn supports_context(&self) -> bool {
+        true
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_BATCH_SIZE
+    }
+
+    fn max_chars_per_request(&self) -> usize {
+        MAX_CHARS_PER_REQUEST
+    }
+}
+
+/// Parses the JSON array of translations answered by the model, which may
+/// be wrapped in a Markdown code block.
+fn parse_array(content: &str) -> BatchResult {
+    let content = content.trim();
+    let content = content
+        .strip_prefix("```json")
+        .or_else(|| content.strip_prefix("```"))
+        .and_then(|inner| inner.strip_suffix("```"))
+        .unwrap_or(content);
+
+    serde_json::from_str(content).map_err(|err| {
+        TranslateError::InvalidResponse(format!("expected a JSON array of strings: {err}"))
+    })
+}
@@ -0,0 +1,129 @@
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Throttles translation requests to stay under a provider's quota.
+///
+/// Each configured limit is a token bucket refilled continuously at its rate
+/// and holding at most one second worth of tokens, so short bursts up to the
+/// limit are sent right away. Requests exceeding the budget wait until enough
+/// tokens have been refilled instead of failing.
+///
+/// A single limiter is shared by all batches in flight, so the aggregate rate
+/// stays under the limit regardless of the concurrency. For the same reason it
+/// can cap the number of requests in flight over several concurrent runs, such
+/// as the target languages of [`translate_targets`].
+///
+/// [`translate_targets`]: crate::pipeline::translate_targets
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::rate_limit::RateLimiter;
+/// use std::num::NonZeroU32;
+///
+/// let limiter = RateLimiter::default()
+///     .with_requests_per_second(NonZeroU32::new(10).unwrap())
+///     .with_characters_per_second(NonZeroU32::new(5_000).unwrap())
+///     .with_max_in_flight(NonZeroU32::new(5).unwrap());
+/// ```
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    requests: Option<Mutex<Bucket>>,
+    characters: Option<Mutex<Bucket>>,
+    in_flight: Option<Semaphore>,
+}
+
+/// Permission to send a request, returned by [`RateLimiter::acquire`]. The
+/// request counts as in flight until the permit is dropped.
+#[derive(Debug)]
+#[must_use = "the request is no longer in flight once the permit is dropped"]
+pub struct Permit<'a> {
+    _in_flight: Option<SemaphorePermit<'a>>,
+}
+
+impl RateLimiter {
+    /// Sends at most `limit` requests per second.
+    pub fn with_requests_per_second(self, limit: NonZeroU32) -> Self {
+        Self {
+            requests: Some(Mutex::new(Bucket::new(limit))),
+            ..self
+        }
+    }
+
+    /// Sends at most `limit` characters per second, counted over all phrases
+    /// of a request.
+    pub fn with_characters_per_second(self, limit: NonZeroU32) -> Self {
+        Self {
+            characters: Some(Mutex::new(Bucket::new(limit))),
+            ..self
+        }
+    }
+
+    /// Sends at most `limit` requests at the same time.
+    pub fn with_max_in_flight(self, limit: NonZeroU32) -> Self {
+        Self {
+            in_flight: Some(Semaphore::new(limit.get() as usize)),
+            ..self
+        }
+    }
+
+    /// Waits until a request carrying `phrases` fits into every limit. The
+    /// request is in flight until the returned permit is dropped.
+    pub async fn acquire(&self, phrases: &[String]) -> Permit<'_> {
+        let permit = match &self.in_flight {
+            Some(in_flight) => Some(
+                in_flight
+                    .acquire()
+                    .await
+                    .expect("Semaphore is never closed"),
+            ),
+            None => None,
+        };
+        let characters = phrases.iter().map(|phrase| phrase.chars().count()).sum();
+
+        let wait = [(&self.requests, 1), (&self.characters, characters)]
+            .into_iter()
+            .filter_map(|(bucket, cost)| Some(bucket.as_ref()?.lock().unwrap().reserve(cost)))
+            .max()
+            .unwrap_or_default();
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        Permit { _in_flight: permit }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(limit: NonZeroU32) -> Self {
+        Self {
+            rate: limit.get().into(),
+            tokens: limit.get().into(),
+            updated: Instant::now(),
+        }
+    }
+
+    /// Takes `cost` tokens and returns how long the caller has to wait for
+    /// them. The tokens may go negative, which queues later callers behind it.
+    fn reserve(&mut self, cost: usize) -> Duration {
+        let now = Instant::now();
+        let refilled = self.tokens + now.duration_since(self.updated).as_secs_f64() * self.rate;
+
+        self.tokens = refilled.min(self.rate) - cost as f64;
+        self.updated = now;
+
+        match self.tokens < 0.0 {
+            true => Duration::from_secs_f64(-self.tokens / self.rate),
+            false => Duration::ZERO,
+        }
+    }
+}
@@ -1,34 +1,100 @@
+use crate::error::{QTranslateError, TranslateError};
+use crate::providers::{BatchResult, TranslationProvider};
+#[cfg(feature = "google")]
+use crate::providers::{AUTO_DETECT, DEFAULT_TIMEOUT, TextFormat, http_client, send_json};
+use crate::rate_limit::RateLimiter;
 use dotenv::dotenv;
 use futures::stream::{self, Stream, StreamExt};
-use reqwest::Client;
+#[cfg(feature = "google")]
 use serde::Deserialize;
 use std::env;
+use std::time::{Duration, Instant};
+use tracing::{Instrument, debug, error, info_span, warn};
 
+/// Environment variable holding the Google Translate API key.
+#[cfg(feature = "google")]
+pub const API_KEY_VAR: &str = "GOOGLE_TRANSLATE_API_KEY";
+
+/// URL of the Google Translate v2 API.
+#[cfg(feature = "google")]
+pub const DEFAULT_ENDPOINT: &str = "https://translation.googleapis.com/language/translate/v2";
+
+/// Environment variable overriding [`DEFAULT_ENDPOINT`], e.g. to send the
+/// requests through a proxy or to a mock server.
+#[cfg(feature = "google")]
+pub const BASE_URL_VAR: &str = "GOOGLE_TRANSLATE_BASE_URL";
+
+/// Number of attempts made for a batch before it is reported as failed.
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry of a batch; doubled for every further retry.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest delay before a retry taken from a `Retry-After` header, so a
+/// provider asking for hours does not stall the run.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+#[cfg(feature = "google")]
 #[derive(Debug, Deserialize)]
 struct TranslateResponse {
     data: TranslateData,
 }
 
+#[cfg(feature = "google")]
 #[derive(Debug, Deserialize)]
 struct TranslateData {
     translations: Vec<Translation>,
 }
 
+/// Body of an unsuccessful Google response, e.g. `{"error": {"code": 400,
+/// "message": "API key not valid.", "status": "INVALID_ARGUMENT"}}`.
+#[cfg(feature = "google")]
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetails,
+}
+
+#[cfg(feature = "google")]
 #[derive(Debug, Deserialize)]
-struct Translation {
+struct ErrorDetails {
+    message: String,
+    status: Option<String>,
+}
+
+#[cfg(feature = "google")]
+#[derive(Debug, Deserialize)]
+pub(crate) struct Translation {
     #[serde(rename = "translatedText")]
-    translated_text: String,
+    pub(crate) translated_text: String,
+    /// Only present when no source language was sent with the request.
+    #[serde(rename = "detectedSourceLanguage")]
+    pub(crate) detected_source_language: Option<String>,
+}
+
+/// A phrase translated by [`translate_phrases_detailed`].
+#[cfg(feature = "google")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetailedTranslation {
+    /// The source phrase.
+    pub phrase: String,
+    /// The translation of `phrase`.
+    pub translated: String,
+    /// Language the API detected `phrase` to be in. Only reported when the
+    /// source language is `"auto"`.
+    pub detected_source_language: Option<String>,
 }
 
 /// Translates a text strings into the target language using Google Translate API.
 ///
 /// This function sends a request to the Google Translate v2 API and returns
-/// the translated texts. The API key must be provided via the
+/// the translated texts. The source language is sent along with the request
+/// unless it is `"auto"`, in which case Google detects it. The API key must be provided via the
 /// `GOOGLE_TRANSLATE_API_KEY` environment variable (for example using a `.env` file).
 ///
 /// # Arguments
 ///
 /// * `texts` - Vector of texts to translate
+/// * `source_lang` - Source language code, or `"auto"` to let the API detect it
 /// * `target_lang` - Target language code (e.g. `"en"`, `"de"`, `"pl"`)
 ///
 /// # Returns
@@ -37,107 +103,549 @@ struct Translation {
 ///
 /// # Errors
 ///
-/// Returns an error if:
-/// - The HTTP request fails
-/// - The API responds with a non-success status
-/// - The response body cannot be parsed
+/// Returns a [`TranslateError`] if:
+/// - The `GOOGLE_TRANSLATE_API_KEY` environment variable is not set
+///   ([`TranslateError::MissingApiKey`])
+/// - The HTTP request fails ([`TranslateError::Http`] or
+///   [`TranslateError::Timeout`])
+/// - The API responds with a non-success status ([`TranslateError::Api`])
+/// - The response body cannot be parsed ([`TranslateError::Parse`])
+/// - The API returns a different number of translations than `phrases`
+///   ([`TranslateError::CountMismatch`])
 ///
-/// # Panics
+/// # Examples
+///
+/// ```no_run
+/// # use q_translate::translate::translate_phrases;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let phrases = vec!["Hallo Welt".to_string()];
+/// let translated = translate_phrases(&phrases, "auto", "en").await?;
+/// assert_eq!(translated, vec![("Hallo Welt".to_string(), "Hello world".to_string())]);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "google")]
+pub async fn translate_phrases(
+    phrases: &[String],
+    source_lang: &str,
+    target_lang: &str,
+) -> Result<Vec<(String, String)>, TranslateError> {
+    let translations = translate_phrases_detailed(phrases, source_lang, target_lang).await?;
+
+    let translation_pairs: Vec<(String, String)> = translations
+        .into_iter()
+        .map(|t| (t.phrase, t.translated))
+        .collect();
+
+    Ok(translation_pairs)
+}
+
+/// Translates a single phrase like [`translate_phrases`], returning only its
+/// translation.
+///
+/// # Errors
 ///
-/// Panics if the `GOOGLE_TRANSLATE_API_KEY` environment variable is not set.
+/// Fails in the same cases as [`translate_phrases`].
 ///
 /// # Examples
 ///
 /// ```no_run
+/// # use q_translate::translate::translate_phrase;
 /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
-/// let phrases = vec!["Hallo Welt"];
-/// let translated = translate_phrases(phrases, "en").await?;
-/// assert_eq!(translated, vec!["Hello world"]);
+/// assert_eq!(translate_phrase("Hallo Welt", "de", "en").await?, "Hello world");
 /// # Ok(())
 /// # }
 /// ```
+#[cfg(feature = "google")]
+pub async fn translate_phrase(
+    phrase: &str,
+    source_lang: &str,
+    target_lang: &str,
+) -> Result<String, TranslateError> {
+    let translations = translate_phrases(&[phrase.to_string()], source_lang, target_lang).await?;
 
-pub async fn translate_phrases(
-    phrases: &Vec<String>,
+    Ok(translations
+        .into_iter()
+        .map(|(_, translated)| translated)
+        .next()
+        .expect("the number of translations is checked"))
+}
+
+/// Translates phrases like [`translate_phrases`], additionally returning the
+/// source language the API detected for every phrase.
+///
+/// Languages are only detected with a `source_lang` of `"auto"`; otherwise
+/// [`DetailedTranslation::detected_source_language`] is always `None`. Phrases
+/// detected in another language than expected, e.g. in the target language
+/// already, point at source files that are partly translated.
+///
+/// # Errors
+///
+/// Fails in the same cases as [`translate_phrases`].
+///
+/// # Examples
+///
+/// ```no_run
+/// # use q_translate::translate::translate_phrases_detailed;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let phrases = vec!["Hello".to_string(), "Willkommen".to_string()];
+///
+/// for translation in translate_phrases_detailed(&phrases, "auto", "de").await? {
+///     if translation.detected_source_language.as_deref() == Some("de") {
+///         println!("{:?} is already German", translation.phrase);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "google")]
+pub async fn translate_phrases_detailed(
+    phrases: &[String],
+    source_lang: &str,
     target_lang: &str,
-) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+) -> Result<Vec<DetailedTranslation>, TranslateError> {
+    let api_key = api_key(API_KEY_VAR)
+        .map_err(|_| TranslateError::MissingApiKey(API_KEY_VAR.to_string()))?;
+    let translations = request_translations(
+        &endpoint(),
+        &api_key,
+        phrases,
+        source_lang,
+        target_lang,
+        DEFAULT_TIMEOUT,
+        TextFormat::Text,
+    )
+    .await?;
+    check_count(phrases, translations.len())?;
+
+    Ok(phrases
+        .iter()
+        .zip(translations)
+        .map(|(phrase, t)| DetailedTranslation {
+            phrase: phrase.to_owned(),
+            translated: t.translated_text,
+            detected_source_language: t.detected_source_language,
+        })
+        .collect())
+}
+
+/// Reads an API key from the environment variable `var` at runtime.
+///
+/// A `.env` file in the working directory is loaded first, if present.
+///
+/// # Errors
+///
+/// Returns [`QTranslateError::MissingApiKey`] if `var` is not set.
+pub(crate) fn api_key(var: &str) -> Result<String, QTranslateError> {
+    env_var(var).ok_or_else(|| QTranslateError::MissingApiKey(var.to_string()))
+}
+
+/// Reads the environment variable `var` at runtime, after loading a `.env`
+/// file in the working directory, if present.
+pub(crate) fn env_var(var: &str) -> Option<String> {
     dotenv().ok();
 
-    let api_key = env!("GOOGLE_TRANSLATE_API_KEY");
-    let client = Client::new();
-    let url = "https://translation.googleapis.com/language/translate/v2";
+    env::var(var).ok()
+}
 
+/// Returns the URL of the Google Translate API: the value of
+/// `GOOGLE_TRANSLATE_BASE_URL` if set, [`DEFAULT_ENDPOINT`] otherwise.
+///
+/// Like the API key, the variable may be set in a `.env` file in the working
+/// directory, see [`env_var`].
+#[cfg(feature = "google")]
+pub(crate) fn endpoint() -> String {
+    env_var(BASE_URL_VAR).unwrap_or_else(|| DEFAULT_ENDPOINT.to_string())
+}
+
+/// Sends `phrases` to the Google Translate v2 API at `endpoint` and returns the raw
+/// translation entries, including the detected source language if the API
+/// reported one. The request is abandoned after `timeout`; `text_format`
+/// tells the API whether the phrases contain HTML markup.
+///
+/// Errors reported by the API are returned as [`TranslateError::Api`] with
+/// the message of the error body, e.g. `API key not valid.
+/// (INVALID_ARGUMENT)`, instead of the raw JSON.
+#[cfg(feature = "google")]
+pub(crate) async fn request_translations(
+    endpoint: &str,
+    api_key: &str,
+    phrases: &[String],
+    source_lang: &str,
+    target_lang: &str,
+    timeout: Duration,
+    text_format: TextFormat,
+) -> Result<Vec<Translation>, TranslateError> {
     let mut params = vec![
         ("key", api_key.to_string()),
         ("target", target_lang.to_string()),
+        ("format", text_format.as_str().to_string()),
     ];
 
+    if source_lang != AUTO_DETECT {
+        params.push(("source", source_lang.to_string()));
+    }
+
     for text in phrases {
         params.push(("q", text.to_owned()));
     }
 
-    let response = client
-        .post(url)
-        .query(&params)
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<TranslateResponse>()
-        .await?;
+    let request = http_client()
+        .post(endpoint)
+        .timeout(timeout)
+        .query(&params);
+    let response: TranslateResponse = send_json(request, timeout)
+        .await
+        .map_err(describe_error)?;
 
-    let translation_pairs: Vec<(String, String)> = phrases
-        .iter()
-        .cloned()
-        .zip(
-            response
-                .data
-                .translations
-                .into_iter()
-                .map(|t| t.translated_text),
-        )
-        .collect();
-
-    Ok(translation_pairs)
+    Ok(response.data.translations)
 }
 
+/// Replaces the body of an API error with the message of Google's error
+/// response, if it is one.
+#[cfg(feature = "google")]
+fn describe_error(err: TranslateError) -> TranslateError {
+    let TranslateError::Api {
+        status,
+        body,
+        retry_after,
+    } = err
+    else {
+        return err;
+    };
+
+    let body = match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(ErrorResponse {
+            error: ErrorDetails {
+                message,
+                status: Some(reason),
+            },
+        }) => format!("{message} ({reason})"),
+        Ok(ErrorResponse { error }) => error.message,
+        Err(_) => body,
+    };
+    TranslateError::Api {
+        status,
+        body,
+        retry_after,
+    }
+}
 
 /// Translates a collection of phrases into the target language using a concurrent stream.
 ///
 /// This function optimizes API usage by:
-/// * **Batching**: Grouping phrases into chunks of 128 (Google API limit).
-/// * **Concurrency**: Executing up to 5 translation requests simultaneously.
+/// * **Batching**: Grouping phrases into chunks of at most `batch_size` phrases (e.g. 128 for Google,
+///   50 for DeepL) and `max_chars` characters, see [`split_batches`].
+/// * **Concurrency**: Executing up to `concurrency` translation requests simultaneously.
 /// * **Ordering**: Uses `buffer_unordered` for maximum throughput; results are emitted as soon as they are ready.
+/// * **Tracing**: Every batch runs in a `batch` span recording its size and index; the elapsed
+///   time is logged when it finishes and failed requests are logged as errors.
+/// * **Retrying**: Batches failing with a retryable error (e.g. a timeout) are sent again, up to
+///   [`MAX_ATTEMPTS`] times in total, so a single slow batch does not fail the whole run.
+/// * **Rate limiting**: With a `rate_limiter`, every attempt waits for its turn first, so the
+///   requests of all concurrent batches together stay under the limits.
 ///
 /// # Arguments
+/// * `provider` - The translation backend used for every batch.
 /// * `phrases` - A vector of strings to be translated.
+/// * `source_lang` - Source language code, or `"auto"` to let the provider detect it.
 /// * `target_lang` - Target language code (e.g., "en", "pl").
+/// * `batch_size` - Maximum number of phrases sent in a single request.
+/// * `max_chars` - Maximum number of characters sent in a single request.
+/// * `concurrency` - Maximum number of requests in flight at the same time.
+/// * `rate_limiter` - Optional limit on the requests and characters sent per second.
 ///
 /// # Returns
-/// A `Stream` of `(original, translated)` string pairs. If a batch fails,
-/// the second element will contain `"Error"`.
-pub fn translate_stream(
+/// A `Stream` of `(original, result)` pairs. If a batch fails, every phrase of
+/// that batch is paired with the error of its last attempt, so callers can
+/// tell failures apart from real translations.
+///
+/// # Panics
+/// Panics if `batch_size`, `max_chars` or `concurrency` is zero.
+#[allow(clippy::too_many_arguments)]
+pub fn translate_stream<'a>(
+    provider: &'a dyn TranslationProvider,
     phrases: Vec<String>,
+    source_lang: String,
     target_lang: String,
-) -> impl Stream<Item = (String, String)> {
-    let mut it = phrases.into_iter();
+    batch_size: usize,
+    max_chars: usize,
+    concurrency: usize,
+    rate_limiter: Option<&'a RateLimiter>,
+) -> impl Stream<Item = (String, Result<String, TranslateError>)> + 'a {
+    assert!(batch_size > 0, "Batch size must be at least 1");
+    assert!(max_chars > 0, "Character budget must be at least 1");
+    assert!(concurrency > 0, "Concurrency must be at least 1");
+
+    let chunks: Vec<Vec<String>> = split_batches(&phrases, batch_size, max_chars)
+        .into_iter()
+        .map(<[String]>::to_vec)
+        .collect();
+
+    stream::iter(chunks.into_iter().enumerate())
+        .map(move |(index, chunk)| {
+            let source_lang = source_lang.clone();
+            let target_lang = target_lang.clone();
 
-    let mut chunks = Vec::new();
-    while it.as_slice().len() > 0 {
-        let chunk: Vec<String> = it.by_ref().take(128).collect();
-        chunks.push(chunk);
-    }
-    stream::iter(chunks)
-        .map(move |chunk| {
-            let lang = target_lang.clone();
             async move {
-                translate_phrases(&chunk, &lang).await.unwrap_or_else(|_| {
-                    chunk
-                        .into_iter()
-                        .map(|s| (s, "Error".to_string()))
-                        .collect()
-                })
+                let result = translate_batch_logged(
+                    provider,
+                    rate_limiter,
+                    &chunk,
+                    &source_lang,
+                    &target_lang,
+                    index,
+                )
+                .await;
+                pair_results(chunk, result)
             }
         })
-        .buffer_unordered(5)
+        .buffer_unordered(concurrency)
         .flat_map(stream::iter)
 }
+
+/// Translates the `(key_path, source_string)` pairs of `entries` and yields
+/// `(key_path, result)` pairs, with the batching, retries, concurrency and
+/// rate limiting of [`translate_stream`].
+///
+/// Unlike [`translate_stream`], `entries` is consumed lazily: a batch is only
+/// taken from the iterator once a request slot is free, so rows from a
+/// database or a huge file never have to be held in memory at once. With
+/// `ordered`, the pairs are yielded in the order of `entries`; otherwise
+/// every batch is yielded as soon as it is ready. Equal strings are sent as
+/// often as they occur.
+///
+/// # Panics
+/// Panics if `batch_size`, `max_chars` or `concurrency` is zero.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use q_translate::providers::google::GoogleProvider;
+/// # use q_translate::translate::translate_iter;
+/// # use futures::StreamExt;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let provider = GoogleProvider::from_env()?;
+/// let rows = vec![
+///     ("greeting".to_string(), "Hallo".to_string()),
+///     ("farewell".to_string(), "Tschüss".to_string()),
+/// ];
+/// let mut stream = translate_iter(&provider, rows, "de", "en", 128, 5_000, 4, None, true);
+///
+/// while let Some((key, result)) = stream.next().await {
+///     println!("{key}: {}", result?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn translate_iter<'a, I>(
+    provider: &'a dyn TranslationProvider,
+    entries: I,
+    source_lang: &'a str,
+    target_lang: &'a str,
+    batch_size: usize,
+    max_chars: usize,
+    concurrency: usize,
+    rate_limiter: Option<&'a RateLimiter>,
+    ordered: bool,
+) -> impl Stream<Item = (String, Result<String, TranslateError>)> + 'a
+where
+    I: IntoIterator<Item = (String, String)>,
+    I::IntoIter: 'a,
+{
+    assert!(batch_size > 0, "Batch size must be at least 1");
+    assert!(max_chars > 0, "Character budget must be at least 1");
+    assert!(concurrency > 0, "Concurrency must be at least 1");
+
+    let mut entries = entries.into_iter().peekable();
+    let chunks = std::iter::from_fn(move || {
+        let mut chunk = vec![];
+        let mut chars = 0;
+
+        while let Some((_, phrase)) = entries.peek() {
+            let len = phrase.chars().count();
+            if !chunk.is_empty() && (chunk.len() == batch_size || chars + len > max_chars) {
+                break;
+            }
+            chars += len;
+            chunk.extend(entries.next());
+        }
+        (!chunk.is_empty()).then_some(chunk)
+    });
+
+    let batches = stream::iter(chunks.enumerate()).map(move |(index, chunk)| async move {
+        let (keys, phrases): (Vec<String>, Vec<String>) = chunk.into_iter().unzip();
+        let result = translate_batch_logged(
+            provider,
+            rate_limiter,
+            &phrases,
+            source_lang,
+            target_lang,
+            index,
+        )
+        .await;
+        pair_results(keys, result)
+    });
+
+    match ordered {
+        true => batches.buffered(concurrency).left_stream(),
+        false => batches.buffer_unordered(concurrency).right_stream(),
+    }
+    .flat_map(stream::iter)
+}
+
+/// Translates the batch with the given `index` in a `batch` span, logging
+/// the elapsed time once it finishes. A batch failed by `provider` after all
+/// retries is sent to its [`TranslationProvider::fallback`], if any, and so on
+/// down the chain.
+async fn translate_batch_logged(
+    provider: &dyn TranslationProvider,
+    rate_limiter: Option<&RateLimiter>,
+    phrases: &[String],
+    source_lang: &str,
+    target_lang: &str,
+    index: usize,
+) -> BatchResult {
+    let span = info_span!("batch", index, size = phrases.len(), %target_lang);
+
+    async {
+        let started = Instant::now();
+        let mut provider = provider;
+        let mut result =
+            translate_with_retry(provider, rate_limiter, phrases, source_lang, target_lang).await;
+        while let (Err(err), Some(fallback)) = (&result, provider.fallback()) {
+            warn!(error = %err, "batch failed, trying the fallback provider");
+            provider = fallback;
+            result = translate_with_retry(provider, rate_limiter, phrases, source_lang, target_lang)
+                .await;
+        }
+        debug!(
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            ok = result.is_ok(),
+            "batch finished"
+        );
+        result
+    }
+    .instrument(span)
+    .await
+}
+
+/// Pairs every element of `keys` with its translation, or with the error of
+/// the batch if it failed.
+fn pair_results(
+    keys: Vec<String>,
+    result: BatchResult,
+) -> Vec<(String, Result<String, TranslateError>)> {
+    match result {
+        Ok(translated) => keys.into_iter().zip(translated.into_iter().map(Ok)).collect(),
+        Err(err) => keys.into_iter().map(|key| (key, Err(err.clone()))).collect(),
+    }
+}
+
+/// Splits `phrases` into consecutive batches of at most `batch_size` phrases
+/// and `max_chars` characters each.
+///
+/// Batches are filled greedily in order: a new batch is started as soon as the
+/// next phrase would exceed either limit. A single phrase longer than
+/// `max_chars` cannot be split and is sent in a batch of its own.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::translate::split_batches;
+///
+/// let phrases = ["Hello", "World", "A much longer sentence", "Bye"];
+/// let batches = split_batches(&phrases, 3, 12);
+///
+/// assert_eq!(batches, [&["Hello", "World"][..], &["A much longer sentence"], &["Bye"]]);
+/// ```
+pub fn split_batches<S: AsRef<str>>(
+    phrases: &[S],
+    batch_size: usize,
+    max_chars: usize,
+) -> Vec<&[S]> {
+    let mut batches = vec![];
+    let mut start = 0;
+    let mut chars = 0;
+
+    for (i, phrase) in phrases.iter().enumerate() {
+        let len = phrase.as_ref().chars().count();
+
+        if i > start && (i - start == batch_size || chars + len > max_chars) {
+            batches.push(&phrases[start..i]);
+            start = i;
+            chars = 0;
+        }
+        chars += len;
+    }
+    if start < phrases.len() {
+        batches.push(&phrases[start..]);
+    }
+
+    batches
+}
+
+/// Translates a single batch, retrying with exponential backoff as long as the
+/// provider reports a retryable error. Every attempt waits for `rate_limiter`.
+///
+/// A delay the provider asks for with a `Retry-After` header, e.g. along with
+/// a `429 Too Many Requests`, replaces the backoff, up to [`MAX_RETRY_AFTER`].
+async fn translate_with_retry(
+    provider: &dyn TranslationProvider,
+    rate_limiter: Option<&RateLimiter>,
+    phrases: &[String],
+    source_lang: &str,
+    target_lang: &str,
+) -> BatchResult {
+    let mut attempt = 1;
+
+    loop {
+        let permit = match rate_limiter {
+            Some(rate_limiter) => Some(rate_limiter.acquire(phrases).await),
+            None => None,
+        };
+        let result = provider
+            .translate_batch(phrases, source_lang, target_lang)
+            .await
+            .and_then(|translated| {
+                check_count(phrases, translated.len())?;
+                Ok(translated)
+            });
+        drop(permit);
+
+        match result {
+            Err(err) if err.is_retryable() && attempt < MAX_ATTEMPTS => {
+                let delay = match err.retry_after() {
+                    Some(retry_after) => retry_after.min(MAX_RETRY_AFTER),
+                    None => RETRY_DELAY * 2u32.pow(attempt - 1),
+                };
+                warn!(attempt, status = err.status_code(), error = %err, ?delay, "retrying batch");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(err) => {
+                error!(attempt, status = err.status_code(), error = %err, "batch failed");
+                return Err(err);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Ensures a provider returned exactly one translation per phrase, so that no
+/// phrase is paired with the translation of another.
+///
+/// # Errors
+///
+/// Returns [`TranslateError::CountMismatch`] if `translated` differs from the
+/// number of `phrases`.
+fn check_count(phrases: &[String], translated: usize) -> Result<(), TranslateError> {
+    match phrases.len() == translated {
+        true => Ok(()),
+        false => Err(TranslateError::CountMismatch {
+            expected: phrases.len(),
+            actual: translated,
+        }),
+    }
+}
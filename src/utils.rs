@@ -1,245 +1,1291 @@
-use crate::translate::translate_stream;
+use crate::cache::TranslationCache;
+use crate::casing::match_case;
+use crate::context::{InContext, TranslationContext};
+use crate::filter::{IgnoreRules, KeyFilter};
+use crate::format::FormatError;
+use crate::placeholders::{Placeholders, Protected};
+use crate::providers::TranslationProvider;
+use crate::rate_limit::RateLimiter;
+use crate::translate::{split_batches, translate_stream};
+use crate::whitespace::normalize_whitespace;
+use clap::ValueEnum;
 use futures::StreamExt;
-use serde_json::{json, Map, Value};
-use std::collections::HashMap;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use serde_json::{Map, Value, json};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use tracing::{info, warn};
 
-/// Recursively walks a JSON value and builds a translated target structure.
+/// Reconstructs `target` from `source`, filling in the phrases from `translations`.
 ///
-/// This function traverses `source` depth-first and mirrors its structure into
-/// `target`. When a string value is encountered, it is translated into
-/// `target_lang` and inserted at the same logical position. Non-string primitive
-/// values (`Null`, `Bool`, `Number`, `Array`) are cloned without modification.
+/// This is the entry point of the reconstruction and the last step of the
+/// pipeline:
 ///
-/// Object insertion order is preserved by inserting entries at the provided
-/// `index`.
+/// 1. [`gather_translations`] collects all source phrases,
+/// 2. [`perform_translations`] translates the missing ones,
+/// 3. [`apply_translations`] writes them into `target`.
 ///
-/// # Arguments
+/// Values already present in `target` are kept untouched and missing keys are
+/// filled in. With `overwrite` set, the values of existing keys are replaced
+/// by their translation as well.
+///
+/// The keys of every object in the result, including objects nested in
+/// arrays, come in exactly the order of the matching `source` object,
+/// regardless of their previous order in `target`. Keys of `target` missing
+/// from `source` follow after them, in their previous order.
+///
+/// When `target` starts out empty (e.g. the target file does not exist yet),
+/// the result is structurally identical to `source`: the same keys in the
+/// same order at every nesting level, every string replaced by its
+/// translation and all other values copied unchanged.
+///
+/// Keys whose phrase has no entry in `translations`, such as phrases skipped
+/// after a failed request (see [`OnError::Skip`]), are left out of `target`.
+/// Strings inside arrays keep their source text instead, so the array keeps
+/// the length of the source array.
+///
+/// Only the key paths selected by `filter` are written; all other values of
+/// `target` are left untouched and no empty objects are created for them.
 ///
-/// * `source` - The source JSON value to traverse
-/// * `target` - The target JSON object being constructed
-/// * `key` - The key under which the current value should be inserted
-/// * `index` - Position at which the value should be inserted in the target object
-/// * `target_lang` - Target language code used for string translation
-/// * `translations` - HashMap with translated phrases
+/// # Examples
 ///
-/// # Panics
+/// ```
+/// use q_translate::filter::KeyFilter;
+/// use q_translate::utils::apply_translations;
+/// use serde_json::{json, Map, Value};
+/// use std::collections::HashMap;
 ///
-/// Panics if:
-/// - `key` is `None` when a non-root value is processed
-/// - The target JSON structure does not match expected object layouts
-/// - Translation fails (errors from `translate_phrase` are unwrapped)
+/// let source = json!({"title": "Welcome", "count": 3});
+/// let translations = HashMap::from([("Welcome".to_string(), "Willkommen".to_string())]);
+/// let mut target = Map::new();
 ///
-/// # Async behavior
+/// apply_translations(&source, &mut target, &translations, false, &KeyFilter::default());
 ///
-/// This function performs asynchronous network calls when translating string
-/// values. Recursive calls are explicitly boxed to allow async recursion.
+/// assert_eq!(Value::Object(target), json!({"title": "Willkommen", "count": 3}));
+/// ```
 pub fn apply_translations(
     source: &Value,
-    mut target: &mut Map<String, Value>,
-    key: &String,
-    index: usize,
-    target_lang: &str,
+    target: &mut Map<String, Value>,
     translations: &HashMap<String, String>,
+    overwrite: bool,
+    filter: &KeyFilter,
 ) {
+    let merge = Merge {
+        translations,
+        overwrite,
+        filter,
+    };
+
+    if let Value::Object(source) = source {
+        *target = apply_object(source, std::mem::take(target), "", &merge);
+    }
+}
+
+/// Settings shared by every level of [`apply_object`].
+#[derive(Clone, Copy)]
+struct Merge<'a> {
+    /// Translated phrases.
+    translations: &'a HashMap<String, String>,
+    /// Whether values already present in the target are replaced.
+    overwrite: bool,
+    /// Key paths that are written.
+    filter: &'a KeyFilter,
+}
+
+/// Builds the translated counterpart of the `source` object from the existing
+/// `target` object.
+///
+/// The result is built in a single pass over `source`, so its keys come in
+/// source order. Every value is merged with the value under the same key in
+/// `target` by [`apply_value`]. Keys of `target` missing from `source` follow
+/// at the end in their previous order, so `--prune` can report them.
+///
+/// # Arguments
+///
+/// * `source` - The source JSON object to traverse
+/// * `target` - The existing target object, consumed to build the result
+/// * `path` - The key path of `source`
+/// * `merge` - Translated phrases and how to merge them
+fn apply_object(
+    source: &Map<String, Value>,
+    mut target: Map<String, Value>,
+    path: &str,
+    merge: &Merge,
+) -> Map<String, Value> {
+    let mut object = Map::with_capacity(source.len());
+
+    for (key, value) in source {
+        let existing = target.get_mut(key).map(Value::take);
+
+        if let Some(value) = apply_value(value, existing, &key_path(path, key), merge) {
+            object.insert(key.to_owned(), value);
+        }
+    }
+
+    for (key, value) in target {
+        if !source.contains_key(&key) {
+            object.insert(key, value);
+        }
+    }
+
+    object
+}
+
+/// Merges a single `source` value into the `existing` target value at `path`.
+///
+/// Objects are rebuilt recursively with [`apply_object`], replacing existing
+/// values that are not objects. A string is replaced by its translation from
+/// `translations`; strings without a translation are left out. Arrays are
+/// rebuilt element by element with [`apply_array`]. Other primitive values
+/// (`Null`, `Bool`, `Number`) are cloned without modification.
+///
+/// Existing values are kept unless `overwrite` is set, and values whose key
+/// path is not selected by the filter are left as they are. Returns `None` if
+/// the key is to be left out of the target.
+fn apply_value(
+    source: &Value,
+    existing: Option<Value>,
+    path: &str,
+    merge: &Merge,
+) -> Option<Value> {
     match source {
         Value::Object(value) => {
-            target = extract_or_instantiate_object_under_key(target, key);
+            let existed = existing.is_some();
+            let object = match existing {
+                Some(Value::Object(object)) => object,
+                _ => Map::new(),
+            };
+            let object = apply_object(value, object, path, merge);
 
-            for (i, (key, v)) in value.iter().enumerate() {
-                apply_translations(v, &mut target, key, i, target_lang, translations)
+            // Do not leave behind empty objects for filtered out sections.
+            match !existed && !value.is_empty() && object.is_empty() {
+                true => None,
+                false => Some(Value::Object(object)),
             }
         }
-        Value::String(value) => {
-            if target.get(key).is_none() {
-                let translated = translations
-                    .get(value)
-                    .expect(format!("Translation for phrase {}, not found!", value).as_str());
+        _ if !merge.filter.matches(path) => existing,
+        Value::Array(items) => {
+            let existing = existing.filter(|_| !merge.overwrite);
+            let existing = existing.as_ref().and_then(Value::as_array);
 
-                insert_at(target, index, key, json!(translated))
-            }
+            Some(Value::Array(apply_array(items, existing, path, merge)))
         }
-        other => {
-            // if  Null, Bool, Number or Array - simply clone;
-            if target.get(key).is_none() {
-                insert_at(target, index, key, other.to_owned())
+        Value::String(value) => match translation_of(value, merge.translations) {
+            Some(translated) if merge.overwrite || existing.is_none() => Some(translated),
+            _ => existing,
+        },
+        other => match merge.overwrite || existing.is_none() {
+            // if  Null, Bool or Number - simply clone;
+            true => Some(other.to_owned()),
+            false => existing,
+        },
+    }
+}
+
+/// Rebuilds a source array in the target language.
+///
+/// The result always has the length and order of `items`. Elements already
+/// present at the same index of the `existing` target array are kept, missing
+/// strings are translated, or copied if they have no translation, and nested
+/// objects and arrays are rebuilt recursively. `path` is the key path of the
+/// array; its elements are not filtered individually.
+fn apply_array(
+    items: &[Value],
+    existing: Option<&Vec<Value>>,
+    path: &str,
+    merge: &Merge,
+) -> Vec<Value> {
+    let all = KeyFilter::default();
+    let merge = &Merge {
+        filter: &all,
+        ..*merge
+    };
+
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let existing = existing.and_then(|existing| existing.get(i));
+            let path = key_path(path, i);
+
+            match (item, existing) {
+                (Value::Object(value), existing) => {
+                    let object = existing
+                        .and_then(Value::as_object)
+                        .cloned()
+                        .unwrap_or_default();
+
+                    Value::Object(apply_object(value, object, &path, merge))
+                }
+                (Value::Array(items), existing) => Value::Array(apply_array(
+                    items,
+                    existing.and_then(Value::as_array),
+                    &path,
+                    merge,
+                )),
+                (_, Some(existing)) => existing.to_owned(),
+                (Value::String(value), None) => {
+                    translation_of(value, merge.translations).unwrap_or_else(|| item.to_owned())
+                }
+                (other, None) => other.to_owned(),
             }
+        })
+        .collect()
+}
+
+/// Looks up the translation of `phrase`, if it has one.
+fn translation_of(phrase: &String, translations: &HashMap<String, String>) -> Option<Value> {
+    translations.get(phrase).map(|translated| json!(translated))
+}
+
+/// Collects translation entries for all string values of `source`.
+///
+/// This is the entry point of the traversal: it walks `source` from its root
+/// and fills `translations` with one entry per source phrase, including
+/// strings nested in arrays. Phrases that already have a value in the existing
+/// `target` locale map are seeded with that value, so only missing phrases are
+/// later sent for translation. With `overwrite` set, existing target values are
+/// ignored and every phrase is seeded as missing.
+///
+/// Only strings whose key path is selected by `filter` are collected.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::filter::KeyFilter;
+/// use q_translate::utils::gather_translations;
+/// use serde_json::{json, Map};
+/// use std::collections::HashMap;
+///
+/// let source = json!({"title": "Welcome", "menu": {"file": "File"}});
+/// let target: Map<_, _> = json!({"title": "Willkommen"}).as_object().unwrap().clone();
+/// let mut translations = HashMap::new();
+///
+/// gather_translations(&source, &target, &mut translations, false, &KeyFilter::default()).unwrap();
+///
+/// assert_eq!(translations["Welcome"], "Willkommen");
+/// assert_eq!(translations["File"], "");
+/// ```
+///
+/// # Errors
+/// Returns [`FormatError::NotAString`] if a target value corresponding to a
+/// source string is not a string.
+pub fn gather_translations(
+    source: &Value,
+    target: &Map<String, Value>,
+    translations: &mut HashMap<String, String>,
+    overwrite: bool,
+    filter: &KeyFilter,
+) -> Result<(), FormatError> {
+    match source {
+        Value::Object(value) => {
+            for (key, v) in value.iter() {
+                let target = target.get(key).filter(|_| !overwrite);
+                gather(v, target, key, translations, filter)?;
+            }
+            Ok(())
         }
+        other => gather(other, None, "", translations, filter),
     }
 }
 
 /// Recursively traverses a source JSON structure and collects translation
 /// entries for all string values.
 ///
-/// The function mirrors the object structure from `source` into `target`,
-/// creating missing intermediate objects as needed.
+/// `target` is the value at the same position in the existing target
+/// structure, if there is one. Objects are followed by key and arrays by
+/// index. `path` is the key path of `source`; values not selected by `filter`
+/// are skipped, arrays as a whole.
 ///
 /// For each string in `source`:
 /// - If a corresponding value exists in `target`, it is inserted into
 ///   `translations`.
-/// - Otherwise, an empty string is inserted as a placeholder.
+/// - Otherwise, an empty string is inserted as a placeholder, unless the
+///   phrase already has a translation from another key.
 ///
-/// Non-object and non-string values are ignored.
+/// Non-string primitive values are ignored.
 ///
-/// # Panics
-/// May panic if `key` is `None` when processing a string
-/// or if the target structure is not an object where expected.
-pub fn gather_translations(
+/// # Errors
+/// Returns [`FormatError::NotAString`] with the key path if the target value
+/// corresponding to a source string is not a string.
+fn gather(
     source: &Value,
-    mut target: &mut Map<String, Value>,
-    key: &String,
-    target_lang: &str,
+    target: Option<&Value>,
+    path: &str,
     translations: &mut HashMap<String, String>,
-) {
+    filter: &KeyFilter,
+) -> Result<(), FormatError> {
     match source {
         Value::Object(value) => {
-            target = extract_or_instantiate_object_under_key(target, key);
-
             for (key, v) in value.iter() {
-                gather_translations(v, &mut target, key, target_lang, translations)
+                let target = target.and_then(|target| target.get(key));
+                gather(v, target, &key_path(path, key), translations, filter)?;
             }
         }
-        Value::String(value) => match target.get(key) {
+        _ if !filter.matches(path) => {}
+        Value::Array(items) => {
+            let all = KeyFilter::default();
+
+            for (i, v) in items.iter().enumerate() {
+                let target = target.and_then(|target| target.get(i));
+                gather(v, target, &key_path(path, i), translations, &all)?;
+            }
+        }
+        Value::String(value) => match target {
             None => {
-                translations.insert(value.clone(), String::default());
+                translations.entry(value.clone()).or_default();
             }
-            Some(target_value) => {
-                let target_value = target_value.as_str().expect(format!("Value {target_value} is not a String").as_str());
-                translations.insert(value.clone(), target_value.to_string());
+            Some(Value::String(target_value)) => {
+                translations.insert(value.clone(), target_value.clone());
             }
+            Some(_) => return Err(FormatError::NotAString(path.to_string())),
         },
         _ => {}
     }
+    Ok(())
 }
 
-/// Translates all missing entries in the provided `translations` map.
+/// Lists the key paths of the source strings that have no translation in
+/// `target`, without calling any API.
 ///
-/// Collects phrases whose translation value is empty (`""`), sends them
-/// in batches to `translate_phrases`, and updates the map with the
-/// returned translations.
+/// A string counts as missing if `target` has no value at its key path, or an
+/// empty or non-string one. Strings inside arrays are checked element by
+/// element. Only key paths selected by `filter` are checked, and empty source
+/// strings never count as missing.
 ///
-/// Translations are processed in batches of 128 for the most effective API usage.
+/// # Examples
 ///
-/// # Errors
-/// Returns an error if the underlying translation request fails.
+/// ```
+/// use q_translate::filter::KeyFilter;
+/// use q_translate::utils::missing_key_paths;
+/// use serde_json::{json, Map};
 ///
-/// # Behavior
-/// - Only entries with empty values are translated.
-/// - The `translations` map is updated in place.
-/// - Already translated entries are skipped.
-pub async fn perform_translations(
-    translations: &mut HashMap<String, String>,
-    target_lang: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut phrases = vec![];
+/// let source = json!({"title": "Welcome", "menu": {"file": "File", "edit": "Edit"}});
+/// let target: Map<_, _> = json!({"title": "Willkommen", "menu": {"file": ""}})
+///     .as_object()
+///     .unwrap()
+///     .clone();
+///
+/// assert_eq!(
+///     missing_key_paths(&source, &target, &KeyFilter::default()),
+///     ["menu.file", "menu.edit"]
+/// );
+/// ```
+pub fn missing_key_paths(
+    source: &Value,
+    target: &Map<String, Value>,
+    filter: &KeyFilter,
+) -> Vec<String> {
+    let mut missing = vec![];
 
-    for (phrase, translated_phrase) in translations.iter() {
-        if *translated_phrase == String::default() {
-            phrases.push(phrase.to_owned());
+    if let Value::Object(source) = source {
+        for (key, value) in source {
+            find_missing(value, target.get(key), key, filter, &mut missing);
         }
     }
+    missing
+}
 
+/// Appends the key paths below `path` whose source string has no translation in
+/// `target`, the value at the same position of the target structure.
+fn find_missing(
+    source: &Value,
+    target: Option<&Value>,
+    path: &str,
+    filter: &KeyFilter,
+    missing: &mut Vec<String>,
+) {
+    match source {
+        Value::Object(value) => {
+            for (key, v) in value {
+                let target = target.and_then(|target| target.get(key));
+                find_missing(v, target, &key_path(path, key), filter, missing)
+            }
+        }
+        _ if !filter.matches(path) => {}
+        Value::Array(items) => {
+            let all = KeyFilter::default();
 
-    let mut stream = translate_stream(phrases, target_lang.to_string());
-    while let Some((phrase,translated_phrase)) = stream.next().await {
-        translations.insert(phrase, translated_phrase);
+            for (i, v) in items.iter().enumerate() {
+                let target = target.and_then(|target| target.get(i));
+                find_missing(v, target, &key_path(path, i), &all, missing)
+            }
+        }
+        Value::String(value)
+            if !value.is_empty() && target.and_then(Value::as_str).is_none_or(str::is_empty) =>
+        {
+            missing.push(path.to_string())
+        }
+        _ => {}
     }
-    Ok(())
 }
 
-/// Returns a mutable reference to a JSON object stored under the given `key`.
+/// Returns the key path and value of every string in `source` selected by
+/// `filter`, including nested ones, in source order.
 ///
-/// If the key does not exist in `target`, a new empty JSON object is inserted.
-/// If the key exists but the value is not a JSON object, it will be replaced
-/// with a new empty object.
+/// # Examples
 ///
-/// If `key` is empty, the function returns `target` unchanged.
+/// ```
+/// use q_translate::filter::KeyFilter;
+/// use q_translate::utils::source_strings;
+/// use serde_json::json;
 ///
-/// # Behavior
+/// let source = json!({"menu": {"file": "File"}, "steps": ["Open"], "count": 3});
 ///
-/// - Ensures that `target[key]` exists and is a JSON object.
-/// - Overwrites non-object values under the key.
-/// - Never returns `None`.
+/// assert_eq!(
+///     source_strings(&source, &KeyFilter::default()),
+///     [("menu.file".to_string(), "File"), ("steps.0".to_string(), "Open")]
+/// );
+/// ```
+pub fn source_strings<'a>(source: &'a Value, filter: &KeyFilter) -> Vec<(String, &'a str)> {
+    let mut strings = vec![];
+    collect_strings(source, "", filter, &mut strings);
+    strings
+}
+
+/// Picks up to `count` distinct, non-blank phrases at random from the string
+/// values of `source` selected by `filter`, returned in source order. With a
+/// `seed`, the same phrases are picked on every run.
 ///
-/// # Arguments
+/// # Examples
 ///
-/// * `target` - The parent JSON object (`serde_json::Map`) to operate on.
-/// * `key` - The key under which an object should exist.
+/// ```
+/// use q_translate::filter::KeyFilter;
+/// use q_translate::utils::sample_phrases;
+/// use serde_json::json;
 ///
-/// # Returns
+/// let source = json!({"a": "Open", "b": "Close", "c": "Open", "d": " "});
+/// let sample = sample_phrases(&source, &KeyFilter::default(), 5, Some(7));
 ///
-/// A mutable reference to the JSON object stored under `key`,
-/// or to `target` itself if `key` is empty.
+/// assert_eq!(sample, ["Open", "Close"]);
+/// assert_eq!(sample_phrases(&source, &KeyFilter::default(), 1, Some(7)).len(), 1);
+/// ```
+pub fn sample_phrases(
+    source: &Value,
+    filter: &KeyFilter,
+    count: usize,
+    seed: Option<u64>,
+) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let phrases: Vec<&str> = source_strings(source, filter)
+        .into_iter()
+        .map(|(_, phrase)| phrase)
+        .filter(|phrase| !phrase.trim().is_empty() && seen.insert(*phrase))
+        .collect();
+
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    };
+    let mut picked = rand::seq::index::sample(&mut rng, phrases.len(), count.min(phrases.len()))
+        .into_vec();
+    picked.sort_unstable();
+
+    picked.into_iter().map(|i| phrases[i].to_string()).collect()
+}
+
+fn collect_strings<'a>(
+    value: &'a Value,
+    path: &str,
+    filter: &KeyFilter,
+    strings: &mut Vec<(String, &'a str)>,
+) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                collect_strings(value, &key_path(path, key), filter, strings);
+            }
+        }
+        _ if !filter.matches(path) => {}
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                collect_strings(item, &key_path(path, i), &KeyFilter::default(), strings);
+            }
+        }
+        Value::String(phrase) => strings.push((path.to_string(), phrase)),
+        _ => {}
+    }
+}
+
+/// Removes the values of `target` whose source string has changed, so they are
+/// translated again.
 ///
-/// # Example
+/// `changed` is called with the key path and the current text of every source
+/// string. A changed string inside an array removes the whole array, since
+/// array elements are only merged by position. Returns the key paths of the
+/// removed values.
 ///
-/// ```rust
-/// use serde_json::{Map, Value};
+/// # Examples
 ///
-/// let mut root = Map::new();
+/// ```
+/// use q_translate::utils::remove_changed;
+/// use serde_json::{json, Value};
 ///
-/// let child = extract_or_instantiate_object_under_key(
-///     &mut root,
-///     &"config".to_string(),
-/// );
+/// let source = json!({"title": "Welcome back", "menu": {"file": "File"}});
+/// let mut target = json!({"title": "Willkommen", "menu": {"file": "Datei"}})
+///     .as_object()
+///     .unwrap()
+///     .clone();
 ///
-/// child.insert("enabled".to_string(), Value::Bool(true));
+/// let removed = remove_changed(&source, &mut target, |path, _| path == "title");
 ///
-/// assert!(root["config"].is_object());
+/// assert_eq!(removed, ["title"]);
+/// assert_eq!(Value::Object(target), json!({"menu": {"file": "Datei"}}));
 /// ```
-fn extract_or_instantiate_object_under_key<'a>(
-    target: &'a mut Map<String, Value>,
-    key: &String,
-) -> &'a mut Map<String, Value> {
-    if key.is_empty() {
-        return target;
+pub fn remove_changed(
+    source: &Value,
+    target: &mut Map<String, Value>,
+    changed: impl Fn(&str, &str) -> bool,
+) -> Vec<String> {
+    let mut removed = vec![];
+    if let Value::Object(source) = source {
+        remove_changed_in(source, target, "", &changed, &mut removed);
     }
+    removed
+}
 
-    let value = target
-        .entry(key.to_owned())
-        .or_insert_with(|| Value::Object(Map::new()));
+/// Removes the changed values of the `target` object at `path`, recursing
+/// into nested objects.
+fn remove_changed_in(
+    source: &Map<String, Value>,
+    target: &mut Map<String, Value>,
+    path: &str,
+    changed: &impl Fn(&str, &str) -> bool,
+    removed: &mut Vec<String>,
+) {
+    for (key, value) in source {
+        let path = key_path(path, key);
+
+        match (value, target.get_mut(key)) {
+            (Value::Object(source), Some(Value::Object(target))) => {
+                remove_changed_in(source, target, &path, changed, removed)
+            }
+            (Value::Array(_), Some(_)) | (Value::String(_), Some(_)) => {
+                let mut strings = vec![];
+                collect_strings(value, &path, &KeyFilter::default(), &mut strings);
 
-    if !value.is_object() {
-        *value = Value::Object(Map::new());
+                if strings.iter().any(|(path, phrase)| changed(path, phrase)) {
+                    target.remove(key);
+                    removed.push(path);
+                }
+            }
+            _ => {}
+        }
     }
+}
 
-    value.as_object_mut().unwrap()
+/// Removes the keys of `target` that no longer exist in `source`, except for
+/// the key paths ignored by `ignore`.
+///
+/// Nested objects are pruned recursively, as are objects inside arrays. The
+/// removed entries are returned as key paths, with nested keys and array
+/// indices separated by dots (e.g. `menu.old` or `steps.0.label`). A stale
+/// object holding ignored keys keeps them and loses only its other keys.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::filter::IgnoreRules;
+/// use q_translate::utils::prune_stale_keys;
+/// use serde_json::{json, Value};
+///
+/// let source = json!({"menu": {"file": "File"}});
+/// let mut target = json!({"menu": {"file": "Datei", "old": "Alt"}, "gone": 1})
+///     .as_object()
+///     .unwrap()
+///     .clone();
+///
+/// let pruned = prune_stale_keys(&source, &mut target, &IgnoreRules::default());
+///
+/// assert_eq!(pruned, ["menu.old", "gone"]);
+/// assert_eq!(Value::Object(target), json!({"menu": {"file": "Datei"}}));
+/// ```
+pub fn prune_stale_keys(
+    source: &Value,
+    target: &mut Map<String, Value>,
+    ignore: &IgnoreRules,
+) -> Vec<String> {
+    let mut pruned = vec![];
+    prune_object(source.as_object(), target, "", ignore, &mut pruned);
+    pruned
 }
 
-/// Inserts a key–value pair into a [`serde_json::Map`] at the given position.
+/// Removes the entries of `target` missing from `source` and recurses into the
+/// remaining ones. `path` is the key path of `target` itself.
+fn prune_object(
+    source: Option<&Map<String, Value>>,
+    target: &mut Map<String, Value>,
+    path: &str,
+    ignore: &IgnoreRules,
+    pruned: &mut Vec<String>,
+) {
+    target.retain(|key, value| {
+        let key_path = key_path(path, key);
+
+        match source.and_then(|source| source.get(key)) {
+            Some(source_value) => {
+                prune(source_value, value, &key_path, ignore, pruned);
+                true
+            }
+            None if ignore.is_ignored(&key_path) => true,
+            None => {
+                // Prune the object key by key, in case it holds ignored keys.
+                let before = pruned.len();
+                if let Value::Object(object) = value {
+                    prune_object(None, object, &key_path, ignore, pruned);
+                }
+                if value.as_object().is_some_and(|object| !object.is_empty()) {
+                    return true;
+                }
+                pruned.truncate(before);
+                pruned.push(key_path);
+                false
+            }
+        }
+    });
+}
+
+/// Prunes `target` if it has the same shape as `source`; values of differing
+/// types are left alone.
+fn prune(
+    source: &Value,
+    target: &mut Value,
+    path: &str,
+    ignore: &IgnoreRules,
+    pruned: &mut Vec<String>,
+) {
+    match (source, target) {
+        (Value::Object(source), Value::Object(target)) => {
+            prune_object(Some(source), target, path, ignore, pruned)
+        }
+        (Value::Array(source), Value::Array(target)) => {
+            for (i, (source, target)) in source.iter().zip(target.iter_mut()).enumerate() {
+                prune(source, target, &key_path(path, i), ignore, pruned)
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Joins the key path `parent` and a key or array index with a dot.
+pub(crate) fn key_path(parent: &str, key: impl Display) -> String {
+    match parent.is_empty() {
+        true => key.to_string(),
+        false => format!("{parent}.{key}"),
+    }
+}
+
+/// Settings shared by every [`perform_translations`] call of a run.
+#[derive(Clone, Copy)]
+pub struct TranslationOptions<'a> {
+    /// Backend used to translate the phrases.
+    pub provider: &'a dyn TranslationProvider,
+    /// Placeholders protected from translation.
+    pub placeholders: &'a Placeholders,
+    /// Source language code, or `"auto"` to let the provider detect it.
+    pub source_lang: &'a str,
+    /// Maximum number of requests in flight at the same time.
+    pub concurrency: usize,
+    /// Maximum number of phrases per request, at least 1, or `None` for the
+    /// provider's maximum. Larger sizes are capped at
+    /// [`TranslationProvider::max_batch_size`] with a warning.
+    pub batch_size: Option<usize>,
+    /// Maximum number of characters per request, or `None` for the provider's
+    /// maximum. Never exceeds [`TranslationProvider::max_chars_per_request`].
+    pub max_chars_per_request: Option<usize>,
+    /// Whether values already present in the target are translated again.
+    pub overwrite: bool,
+    /// Key paths taking part in the translation.
+    pub filter: &'a KeyFilter,
+    /// What happens to phrases whose request failed.
+    pub on_error: OnError,
+    /// Limit on the requests and characters sent per second, if any.
+    pub rate_limiter: Option<&'a RateLimiter>,
+    /// Hints about what the phrases are used for, passed to providers that
+    /// support them.
+    pub context: Option<&'a TranslationContext>,
+    /// Maximum number of phrases sent to the provider, or `None` to send all
+    /// missing phrases.
+    pub limit: Option<usize>,
+    /// Maximum number of characters sent to the provider in total. If the
+    /// missing phrases add up to more, nothing is sent at all.
+    pub max_chars_total: Option<usize>,
+    /// Whether phrases spanning several lines are translated line by line,
+    /// keeping their line breaks and blank lines.
+    pub preserve_newlines: bool,
+    /// Whether runs of whitespace inside phrases are collapsed to a single
+    /// space before translating, see [`normalize_whitespace`].
+    pub normalize_whitespace: bool,
+    /// Whether a translation that lost or gained a placeholder fails the
+    /// translation, instead of being handled like a failed phrase.
+    pub strict_placeholders: bool,
+    /// Whether translations get the casing of their source phrase, see
+    /// [`match_case`].
+    pub match_case: bool,
+    /// Key paths whose object keys are translated along with the values, or
+    /// `None` to keep every key, see [`KeyTranslations`](crate::keys::KeyTranslations).
+    pub translate_keys: Option<&'a KeyFilter>,
+}
+
+/// Policy for phrases whose translation request failed after all retries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnError {
+    /// Use the source text as the translation
+    #[default]
+    KeepSource,
+    /// Leave the key out of the target
+    Skip,
+    /// Abort with an error
+    Fail,
+}
+
+/// What [`perform_translations`] did to fill in the missing phrases.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TranslationStats {
+    /// Source phrases whose request failed.
+    pub failed: Vec<String>,
+    /// Phrases taken from the cache.
+    pub cache_hits: usize,
+    /// Requests sent to the provider, not counting retries.
+    pub requests: usize,
+    /// Characters sent to the provider, not counting retries.
+    pub characters: usize,
+    /// Source phrases left untranslated because of `limit`.
+    pub limited: usize,
+}
+
+/// Translates all missing entries in the provided `translations` map.
 ///
-/// This function preserves insertion order by temporarily draining the map,
-/// inserting the new entry at `index`, and rebuilding the map.
+/// Collects phrases whose translation value is empty (`""`), sends them
+/// in batches to the given `provider`, and updates the map with the
+/// returned translations.
 ///
-/// # Arguments
+/// Translations are processed in batches of `options.batch_size`, by default
+/// the provider's maximum batch size for the most effective API usage, with at most `options.concurrency`
+/// requests in flight at the same time. A batch is cut short before its phrases
+/// exceed `options.max_chars_per_request` characters, by default the provider's
+/// limit.
 ///
-/// * `map` - The JSON map to modify
-/// * `index` - Zero-based position at which to insert the new entry
-/// * `key` - The key to insert
-/// * `value` - The value to insert
+/// Interpolation placeholders matched by `options.placeholders` are replaced with
+/// sentinel tokens before sending and restored in the returned translations.
+/// Phrases that differ only in their placeholders are translated once, and
+/// phrases consisting solely of placeholders or glossary terms are copied
+/// verbatim without being sent at all. So are empty or whitespace-only
+/// phrases and phrases without any language, such as URLs or numbers,
+/// recognized by [`Placeholders::is_verbatim`].
 ///
-/// # Panics
+/// With an `options.context`, the hints for the phrases of every batch are
+/// passed along to providers that support them, see
+/// [`TranslationProvider::supports_context`]; other providers ignore them.
 ///
-/// Panics if `index` is greater than the number of elements in the map.
+/// With an `options.limit`, only that many phrases are sent, taken in the
+/// order of `order`, the phrases of the source file, and then in alphabetical
+/// order for phrases missing from it. Phrases translated without a request,
+/// such as cache hits, do not count, and a phrase counts once however many
+/// lines it is split into. The entries of the others are removed from
+/// `translations`, so they are left out of the target and translated on a
+/// later run.
 ///
-/// # Examples
+/// With an `options.max_chars_total`, the characters of all phrases to send
+/// are added up first, and the translation fails without sending a single
+/// request if they exceed it.
 ///
-/// ```
-/// use serde_json::{Map, Value};
+/// With `options.preserve_newlines`, phrases containing `\n` are split into
+/// their lines, which are translated like single phrases, and joined again
+/// with the original line breaks. Cache hits and progress then count lines;
+/// a phrase with a failed line is reported as failed as a whole.
 ///
-/// let mut map = Map::new();
-/// map.insert("a".to_string(), Value::from(1));
-/// map.insert("b".to_string(), Value::from(2));
+/// With `options.normalize_whitespace`, phrases are translated with their
+/// runs of whitespace collapsed, see [`normalize_whitespace`], keeping the
+/// line breaks with `options.preserve_newlines`. The translation of the
+/// normalized phrase becomes that of every phrase normalized to it, and is
+/// cached under the normalized phrase.
 ///
-/// insert_at(&mut map, 1, "x".to_string(), Value::from(42));
+/// With `options.match_case`, every translation gets the casing of its
+/// source phrase once it has arrived, see [`match_case`]. The cache keeps the
+/// translations as the provider returned them.
 ///
-/// let keys: Vec<_> = map.keys().cloned().collect();
-/// assert_eq!(keys, vec!["a", "x", "b"]);
-/// ```
-fn insert_at(map: &mut Map<String, Value>, index: usize, key: &String, value: Value) {
-    let old = std::mem::take(map);
+/// When a `cache` is given, phrases already present in it are taken from the
+/// cache instead of being sent to the provider, and newly translated phrases
+/// are added to it as they arrive. The cache may be shared with other
+/// translations running at the same time.
+///
+/// `on_progress` is called with `(translated, total)` phrase counts whenever
+/// translations arrive. Since batches resolve out of order, the counts grow by
+/// whole batches at a time.
+///
+/// Every translation is checked with [`Placeholders::check`]. A translation
+/// that lost or gained a placeholder counts as failed, with a warning.
+///
+/// Returns the phrases whose batch failed to translate along with the number
+/// of cache hits, requests and characters sent. Failed phrases are never added
+/// to the cache; depending on `options.on_error` their entry is set to the
+/// source phrase, removed from `translations`, or the translation is aborted.
+///
+/// # Errors
+/// Returns an error if `options.batch_size` is zero or the phrases exceed
+/// `options.max_chars_total`, and the error of the first failed request if
+/// `options.on_error` is [`OnError::Fail`]. With that policy or
+/// `options.strict_placeholders`, the first placeholder mismatch is returned
+/// as an error as well.
+///
+/// # Behavior
+/// - Only entries with empty values are translated.
+/// - The `translations` map is updated in place.
+/// - Already translated entries are skipped.
+pub async fn perform_translations(
+    translations: &mut HashMap<String, String>,
+    order: &[&str],
+    options: &TranslationOptions<'_>,
+    cache: Option<&TranslationCache>,
+    target_lang: &str,
+    on_progress: impl FnMut(usize, usize),
+) -> Result<TranslationStats, Box<dyn std::error::Error>> {
+    let limited = match options.limit {
+        Some(limit) => limit_phrases(translations, order, limit, options, cache, target_lang),
+        None => vec![],
+    };
+
+    let missing: Vec<String> = match options.match_case {
+        true => translations
+            .iter()
+            .filter(|(_, translated)| translated.is_empty())
+            .map(|(phrase, _)| phrase.clone())
+            .collect(),
+        false => vec![],
+    };
+
+    let normalized = options
+        .normalize_whitespace
+        .then(|| NormalizedPhrases::new(translations, options.preserve_newlines));
+
+    let mut stats = match options.preserve_newlines {
+        true => {
+            let lines = SplitLines::new(translations);
+            let result =
+                translate_missing(translations, options, cache, target_lang, on_progress).await;
+            let mut stats = result?;
+            lines.join(translations, &mut stats);
+            stats
+        }
+        false => translate_missing(translations, options, cache, target_lang, on_progress).await?,
+    };
+
+    if let Some(normalized) = normalized {
+        normalized.restore(translations, &mut stats);
+    }
+
+    for phrase in missing {
+        if let Some(translated) = translations.get_mut(&phrase) {
+            *translated = match_case(options.placeholders, &phrase, translated);
+        }
+    }
+    stats.limited = limited.len();
+    Ok(stats)
+}
+
+/// Removes the untranslated phrases of `translations` beyond the first
+/// `limit` that need a request and returns them. Phrases are taken in the
+/// order of `order` first, and the others in alphabetical order.
+///
+/// Phrases translated without a request do not count, and neither do those
+/// whose requests are all made for an earlier phrase anyway, such as a phrase
+/// differing from it only in its placeholders.
+fn limit_phrases(
+    translations: &mut HashMap<String, String>,
+    order: &[&str],
+    limit: usize,
+    options: &TranslationOptions<'_>,
+    cache: Option<&TranslationCache>,
+    target_lang: &str,
+) -> Vec<String> {
+    // Reversed, so the first occurrence of a phrase wins.
+    let ranks: HashMap<&str, usize> = order
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(rank, phrase)| (*phrase, rank))
+        .collect();
+    let mut missing: Vec<&String> = translations
+        .iter()
+        .filter(|(_, translated)| translated.is_empty())
+        .map(|(phrase, _)| phrase)
+        .collect();
+    missing.sort_by_key(|phrase| {
+        let rank = ranks.get(phrase.as_str()).copied().unwrap_or(usize::MAX);
+        (rank, phrase.as_str())
+    });
+
+    let mut sent = HashSet::new();
+    let mut kept = 0;
+    let mut limited = vec![];
+
+    for phrase in missing {
+        let requests = requests_for(translations, phrase, options, cache, target_lang);
+        if requests.iter().all(|request| sent.contains(request)) {
+            continue;
+        }
+        match kept < limit {
+            true => {
+                kept += 1;
+                sent.extend(requests);
+            }
+            false => limited.push(phrase.clone()),
+        }
+    }
+    for phrase in &limited {
+        translations.remove(phrase);
+    }
+    limited
+}
+
+/// Protected texts sent to translate the untranslated `phrase`: those of its
+/// lines with `options.preserve_newlines`, with normalized whitespace with
+/// `options.normalize_whitespace`, leaving out the lines translated without
+/// a request by [`translate_missing`] or already translated in
+/// `translations`.
+fn requests_for(
+    translations: &HashMap<String, String>,
+    phrase: &str,
+    options: &TranslationOptions<'_>,
+    cache: Option<&TranslationCache>,
+    target_lang: &str,
+) -> Vec<String> {
+    let normalized = match options.normalize_whitespace {
+        true => normalize_whitespace(phrase, options.preserve_newlines),
+        false => phrase.to_owned(),
+    };
+    let lines: Vec<&str> = match options.preserve_newlines {
+        true => normalized.split('\n').collect(),
+        false => vec![&normalized],
+    };
+
+    lines
+        .into_iter()
+        .filter(|line| *line == phrase || translations.get(*line).is_none_or(String::is_empty))
+        .filter(|line| !line.trim().is_empty() && !options.placeholders.is_verbatim(line))
+        .filter(|line| {
+            cache
+                .and_then(|cache| cache.get(line, target_lang))
+                .is_none()
+        })
+        .map(|line| options.placeholders.protect_for(line, target_lang))
+        .filter(|protected| !protected.is_fully_protected())
+        .map(|protected| protected.text)
+        .collect()
+}
+
+/// Untranslated phrases replaced by their form with normalized whitespace in
+/// a translations map, see [`perform_translations`].
+struct NormalizedPhrases {
+    /// The untranslated phrases that change when normalized, along with
+    /// their normalized form.
+    phrases: Vec<(String, String)>,
+    /// Normalized forms added to the map that were not phrases of their own.
+    added: HashSet<String>,
+}
 
-    let mut entries: Vec<_> = old.into_iter().collect();
-    entries.insert(index, (key.to_owned(), value));
+impl NormalizedPhrases {
+    /// Replaces the untranslated phrases of `translations` with untranslated
+    /// entries for their normalized forms.
+    fn new(translations: &mut HashMap<String, String>, keep_newlines: bool) -> Self {
+        let phrases: Vec<(String, String)> = translations
+            .iter()
+            .filter(|(_, translated)| translated.is_empty())
+            .map(|(phrase, _)| (phrase.clone(), normalize_whitespace(phrase, keep_newlines)))
+            .filter(|(phrase, normalized)| phrase != normalized)
+            .collect();
+        let mut added = HashSet::new();
+
+        for (phrase, _) in &phrases {
+            translations.remove(phrase);
+        }
+        // A normalized form never changes when normalized again, so it is not
+        // among the removed phrases.
+        for (_, normalized) in &phrases {
+            if !translations.contains_key(normalized) {
+                translations.insert(normalized.clone(), String::new());
+                added.insert(normalized.clone());
+            }
+        }
+        Self { phrases, added }
+    }
+
+    /// Gives every replaced phrase the translation of its normalized form,
+    /// leaving out those whose normalized form is missing from
+    /// `translations`, and reports the phrases instead of their normalized
+    /// forms in `stats`.
+    fn restore(self, translations: &mut HashMap<String, String>, stats: &mut TranslationStats) {
+        let failed: HashSet<String> = stats.failed.drain(..).collect();
+
+        for (phrase, normalized) in self.phrases {
+            if failed.contains(&normalized) {
+                stats.failed.push(phrase.clone());
+            }
+            if let Some(translated) = translations.get(&normalized) {
+                let translated = translated.clone();
+                translations.insert(phrase, translated);
+            }
+        }
+
+        stats.failed.extend(
+            failed
+                .into_iter()
+                .filter(|phrase| !self.added.contains(phrase)),
+        );
+        for normalized in &self.added {
+            translations.remove(normalized);
+        }
+    }
+}
 
-    *map = entries.into_iter().collect();
+/// Multi-line phrases replaced by their lines in a translations map, see
+/// [`perform_translations`].
+struct SplitLines {
+    /// The untranslated phrases containing line breaks.
+    phrases: Vec<String>,
+    /// Lines added to the map that were not phrases of their own.
+    added: HashSet<String>,
+}
+
+impl SplitLines {
+    /// Replaces the untranslated multi-line phrases of `translations` with
+    /// untranslated entries for their lines.
+    fn new(translations: &mut HashMap<String, String>) -> Self {
+        let phrases: Vec<String> = translations
+            .iter()
+            .filter(|(phrase, translated)| translated.is_empty() && phrase.contains('\n'))
+            .map(|(phrase, _)| phrase.clone())
+            .collect();
+        let mut added = HashSet::new();
+
+        for phrase in &phrases {
+            translations.remove(phrase);
+        }
+        for line in phrases.iter().flat_map(|phrase| phrase.split('\n')) {
+            if !translations.contains_key(line) {
+                translations.insert(line.to_string(), String::new());
+                added.insert(line.to_string());
+            }
+        }
+        Self { phrases, added }
+    }
+
+    /// Joins the translated lines of every split phrase, leaving out phrases
+    /// with a line missing from `translations`, and reports the phrases with
+    /// failed lines in `stats` instead of the lines.
+    fn join(self, translations: &mut HashMap<String, String>, stats: &mut TranslationStats) {
+        let failed: HashSet<String> = stats.failed.drain(..).collect();
+
+        for phrase in self.phrases {
+            let lines: Option<Vec<&str>> = phrase
+                .split('\n')
+                .map(|line| translations.get(line).map(String::as_str))
+                .collect();
+            if phrase.split('\n').any(|line| failed.contains(line)) {
+                stats.failed.push(phrase.clone());
+            }
+            if let Some(lines) = lines {
+                let joined = lines.join("\n");
+                translations.insert(phrase, joined);
+            }
+        }
+
+        stats.failed.extend(
+            failed
+                .into_iter()
+                .filter(|phrase| !self.added.contains(phrase)),
+        );
+        for line in &self.added {
+            translations.remove(line);
+        }
+    }
+}
+
+/// Translates the untranslated entries of `translations` as described for
+/// [`perform_translations`], without splitting multi-line phrases.
+async fn translate_missing(
+    translations: &mut HashMap<String, String>,
+    options: &TranslationOptions<'_>,
+    cache: Option<&TranslationCache>,
+    target_lang: &str,
+    mut on_progress: impl FnMut(usize, usize),
+) -> Result<TranslationStats, Box<dyn std::error::Error>> {
+    let TranslationOptions {
+        provider,
+        placeholders,
+        source_lang,
+        concurrency,
+        batch_size,
+        max_chars_per_request,
+        on_error,
+        rate_limiter,
+        context,
+        max_chars_total,
+        strict_placeholders,
+        ..
+    } = *options;
+    let max_batch_size = provider.max_batch_size();
+    let batch_size = match batch_size {
+        Some(0) => return Err("Batch size must be at least 1".into()),
+        Some(size) if size > max_batch_size => {
+            warn!(
+                batch_size = size,
+                max_batch_size, "batch size exceeds the provider's maximum, using the maximum"
+            );
+            max_batch_size
+        }
+        Some(size) => size,
+        None => max_batch_size,
+    };
+    let max_chars = max_chars_per_request.map_or(provider.max_chars_per_request(), |chars| {
+        chars.min(provider.max_chars_per_request())
+    });
+
+    let mut protected: HashMap<String, Vec<(String, Protected)>> = HashMap::new();
+    let mut cache_hits = 0;
+
+    for (phrase, translated_phrase) in translations.iter_mut() {
+        if *translated_phrase == String::default() {
+            if phrase.trim().is_empty() || placeholders.is_verbatim(phrase) {
+                *translated_phrase = phrase.to_owned();
+                continue;
+            }
+
+            if let Some(cached) = cache.and_then(|cache| cache.get(phrase, target_lang)) {
+                *translated_phrase = cached;
+                cache_hits += 1;
+                continue;
+            }
+
+            let protected_phrase = placeholders.protect_for(phrase, target_lang);
+
+            // Restoring puts in the forced translations of termbase terms.
+            if protected_phrase.is_fully_protected() {
+                *translated_phrase = protected_phrase.restore(&protected_phrase.text);
+                continue;
+            }
+
+            protected
+                .entry(protected_phrase.text.clone())
+                .or_default()
+                .push((phrase.to_owned(), protected_phrase));
+        }
+    }
+
+    // Sorted, so identical runs send identical batches, see `--cache-batches`.
+    let mut phrases: Vec<String> = protected.keys().cloned().collect();
+    phrases.sort();
+
+    let total = phrases.iter().map(|phrase| protected[phrase].len()).sum();
+    let mut stats = TranslationStats {
+        cache_hits,
+        requests: split_batches(&phrases, batch_size, max_chars).len(),
+        characters: phrases.iter().map(|phrase| phrase.chars().count()).sum(),
+        ..TranslationStats::default()
+    };
+
+    if let Some(cap) = max_chars_total.filter(|cap| stats.characters > *cap) {
+        return Err(format!(
+            "{} characters to translate into {target_lang}, more than the maximum of {cap}",
+            stats.characters
+        )
+        .into());
+    }
+
+    if cache.is_some() {
+        info!(
+            target_lang,
+            hits = cache_hits,
+            misses = total,
+            "cache lookup"
+        );
+    }
+    let in_context;
+    let provider = match context {
+        Some(context) if provider.supports_context() => {
+            in_context = InContext::new(provider, context, &protected);
+            &in_context as &dyn TranslationProvider
+        }
+        Some(_) => {
+            info!(
+                target_lang,
+                "provider does not support context, ignoring it"
+            );
+            provider
+        }
+        None => provider,
+    };
+    let mut done = 0;
+
+    on_progress(done, total);
+
+    let mut stream = translate_stream(
+        provider,
+        phrases,
+        source_lang.to_string(),
+        target_lang.to_string(),
+        batch_size,
+        max_chars,
+        concurrency,
+        rate_limiter,
+    );
+    while let Some((sent_phrase, result)) = stream.next().await {
+        let originals = &protected[&sent_phrase];
+
+        match result {
+            Ok(translated_phrase) => {
+                for (phrase, protected_phrase) in originals {
+                    if let Err(mismatch) = placeholders.check(protected_phrase, &translated_phrase)
+                    {
+                        let message = format!(
+                            "Translation of {phrase:?} into {target_lang} {mismatch}: \
+                             {translated_phrase:?}"
+                        );
+                        if strict_placeholders || on_error == OnError::Fail {
+                            return Err(message.into());
+                        }
+                        warn!("{message}");
+                        fail_phrase(translations, &mut stats, phrase, on_error);
+                        continue;
+                    }
+                    let translated_phrase = protected_phrase.restore(&translated_phrase);
+
+                    if let Some(cache) = cache {
+                        cache.insert(phrase.to_owned(), target_lang, translated_phrase.clone());
+                    }
+                    translations.insert(phrase.to_owned(), translated_phrase);
+                }
+            }
+            Err(err) => {
+                if on_error == OnError::Fail {
+                    return Err(err.into());
+                }
+
+                for (phrase, _) in originals {
+                    fail_phrase(translations, &mut stats, phrase, on_error);
+                }
+            }
+        }
+
+        done += originals.len();
+        on_progress(done, total);
+    }
+    Ok(stats)
+}
+
+/// Records `phrase` as failed, keeping its source text or leaving it out of
+/// `translations` according to `on_error`.
+fn fail_phrase(
+    translations: &mut HashMap<String, String>,
+    stats: &mut TranslationStats,
+    phrase: &str,
+    on_error: OnError,
+) {
+    match on_error {
+        OnError::Skip => translations.remove(phrase),
+        _ => translations.insert(phrase.to_owned(), phrase.to_owned()),
+    };
+    stats.failed.push(phrase.to_owned());
 }
@@ -0,0 +1,31 @@
+/// Collapses every run of whitespace inside `phrase`, such as a double space
+/// or a tab, to a single space. Leading and trailing whitespace is kept as it
+/// is, since it is put back around the translation anyway.
+///
+/// Line breaks count as whitespace, unless `keep_newlines` is set: then every
+/// line is normalized on its own and the line breaks are kept.
+///
+/// # Examples
+///
+/// ```
+/// use q_translate::whitespace::normalize_whitespace;
+///
+/// assert_eq!(normalize_whitespace(" Save  your\tchanges ", false), " Save your changes ");
+/// assert_eq!(normalize_whitespace("First  line\n\nSecond\n line", false), "First line Second line");
+/// assert_eq!(normalize_whitespace("First  line\n\nSecond\n line", true), "First line\n\nSecond\n line");
+/// ```
+pub fn normalize_whitespace(phrase: &str, keep_newlines: bool) -> String {
+    if keep_newlines {
+        let lines: Vec<String> = phrase
+            .split('\n')
+            .map(|line| normalize_whitespace(line, false))
+            .collect();
+        return lines.join("\n");
+    }
+
+    let trimmed = phrase.trim_start();
+    let leading = &phrase[..phrase.len() - trimmed.len()];
+    let trailing = &trimmed[trimmed.trim_end().len()..];
+    let words: Vec<&str> = trimmed.split_whitespace().collect();
+    format!("{leading}{}{trailing}", words.join(" "))
+}
@@ -0,0 +1,4423 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use q_translate::cache::TranslationCache;
+use q_translate::casing::Casing;
+use q_translate::config::Config;
+use q_translate::context::TranslationContext;
+use q_translate::diff::TargetDiff;
+use q_translate::error::{QTranslateError, TranslateError};
+use q_translate::files;
+use q_translate::filter::{IgnoreRules, KeyFilter};
+use q_translate::format::{Format, FormatError, JsonStyle, csv, jsonc, po, properties};
+use q_translate::git;
+use q_translate::hashes::SourceHashes;
+use q_translate::languages;
+use q_translate::layout;
+use q_translate::pipeline::{
+    translate_file, translate_files, translate_json, translate_map, translate_targets,
+};
+use q_translate::placeholders::{
+    DEFAULT_PATTERNS, HTML_TAG_PATTERN, Placeholders, read_glossary, read_termbase,
+};
+use q_translate::providers::azure::AzureProvider;
+use q_translate::providers::google::GoogleProvider;
+use q_translate::providers::libre::LibreProvider;
+use q_translate::providers::openai::OpenAiProvider;
+use q_translate::providers::{
+    BatchResult, CachedProvider, FallbackProvider, Provider, ProviderSettings, TextFormat,
+    TranslationProvider, is_same_language, set_proxy,
+};
+use q_translate::rate_limit::RateLimiter;
+use q_translate::translate::{
+    split_batches, translate_iter, translate_phrase, translate_phrases, translate_stream,
+};
+use q_translate::utils::{self, OnError, TranslationOptions};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::process::Command;
+
+/// Provider that "translates" by uppercasing and records every batch it receives.
+#[derive(Default)]
+struct StubProvider {
+    batches: Mutex<Vec<Vec<String>>>,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+#[async_trait]
+impl TranslationProvider for StubProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        _source_lang: &str,
+        _target_lang: &str,
+    ) -> BatchResult {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        self.batches.lock().unwrap().push(phrases.to_vec());
+        Ok(phrases.iter().map(|phrase| phrase.to_uppercase()).collect())
+    }
+}
+
+/// Options translating with `provider` one request at a time, with every
+/// other setting at its default.
+fn options(provider: &dyn TranslationProvider) -> TranslationOptions<'_> {
+    static PLACEHOLDERS: LazyLock<Placeholders> = LazyLock::new(Placeholders::default);
+    static FILTER: LazyLock<KeyFilter> = LazyLock::new(KeyFilter::default);
+
+    TranslationOptions {
+        provider,
+        placeholders: &PLACEHOLDERS,
+        source_lang: "en",
+        concurrency: 1,
+        batch_size: None,
+        max_chars_per_request: None,
+        overwrite: false,
+        filter: &FILTER,
+        on_error: OnError::KeepSource,
+        rate_limiter: None,
+        context: None,
+        limit: None,
+        max_chars_total: None,
+        preserve_newlines: false,
+        normalize_whitespace: false,
+        strict_placeholders: false,
+        match_case: false,
+        translate_keys: None,
+    }
+}
+
+/// Returns a fresh, empty directory under the system temp dir for `name`.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("q-translate-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Reads one HTTP request from `socket` and returns its JSON body, or `None`
+/// once the client has closed the connection.
+///
+/// Requests without a body, such as those of the Google provider, return
+/// their query parameters instead, as an object mapping every name to the
+/// array of its values.
+async fn read_request(socket: &mut BufReader<TcpStream>) -> Option<Value> {
+    let mut request_line = String::new();
+    if socket.read_line(&mut request_line).await.unwrap() == 0 {
+        return None;
+    }
+
+    let mut content_length = 0;
+
+    loop {
+        let mut line = String::new();
+        if socket.read_line(&mut line).await.unwrap() == 0 {
+            return None;
+        }
+        if line == "\r\n" {
+            break;
+        }
+        if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+            content_length = length.trim().parse().unwrap();
+        }
+    }
+
+    if content_length == 0 {
+        let target = request_line.split(' ').nth(1).unwrap();
+        let url = reqwest::Url::parse(&format!("http://localhost{target}")).unwrap();
+
+        let mut params = serde_json::Map::new();
+        for (name, value) in url.query_pairs() {
+            params
+                .entry(name.to_string())
+                .or_insert_with(|| json!([]))
+                .as_array_mut()
+                .unwrap()
+                .push(json!(value));
+        }
+        return Some(Value::Object(params));
+    }
+
+    let mut body = vec![0; content_length];
+    socket.read_exact(&mut body).await.unwrap();
+    Some(serde_json::from_slice(&body).unwrap())
+}
+
+/// Writes an HTTP `200 OK` response with `body` as JSON to `socket`.
+async fn write_response(socket: &mut BufReader<TcpStream>, body: &Value) {
+    write_status_response(socket, "200 OK", body).await;
+}
+
+/// Writes an HTTP response with `status` and `body` as JSON to `socket`.
+async fn write_status_response(socket: &mut BufReader<TcpStream>, status: &str, body: &Value) {
+    let body = body.to_string();
+    let reply = format!(
+        "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+        body.len()
+    );
+    socket.write_all(reply.as_bytes()).await.unwrap();
+}
+
+/// Serves a single HTTP request on a local port with `response` as JSON body.
+///
+/// Returns the URL of the server and a handle resolving to the body of the
+/// received request.
+async fn serve_once(response: Value) -> (String, tokio::task::JoinHandle<Value>) {
+    serve_once_with_status("200 OK", response).await
+}
+
+/// Serves a single HTTP request like [`serve_once`], answering with `status`.
+async fn serve_once_with_status(
+    status: &'static str,
+    response: Value,
+) -> (String, tokio::task::JoinHandle<Value>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/translate", listener.local_addr().unwrap());
+
+    let handle = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut socket = BufReader::new(socket);
+
+        let request = read_request(&mut socket).await.unwrap();
+        write_status_response(&mut socket, status, &response).await;
+        request
+    });
+
+    (url, handle)
+}
+
+/// Runs the `q-translate` binary in `dir` with `args`, translating from
+/// English with the Google mock server at `url`, and feeds it `stdin` if
+/// given. Color, proxy and Google settings of the environment are cleared
+/// before `env` is set.
+async fn run_cli(
+    dir: &Path,
+    url: &str,
+    args: &[&str],
+    env: &[(&str, &str)],
+    stdin: Option<&str>,
+) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_q-translate"));
+    command
+        .current_dir(dir)
+        .args([
+            "--api-key",
+            "test-key",
+            "--endpoint",
+            url,
+            "--source-lang",
+            "en",
+        ])
+        .args(args)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    for name in [
+        "NO_COLOR",
+        "FORCE_COLOR",
+        "CLICOLOR_FORCE",
+        "IGNORE_IS_TERMINAL",
+        "HTTP_PROXY",
+        "HTTPS_PROXY",
+        "ALL_PROXY",
+        "http_proxy",
+        "https_proxy",
+        "all_proxy",
+        "NO_PROXY",
+        "no_proxy",
+        "GOOGLE_TRANSLATE_API_KEY",
+        "GOOGLE_TRANSLATE_BASE_URL",
+        "RUST_LOG",
+    ] {
+        command.env_remove(name);
+    }
+    command.envs(env.iter().copied());
+
+    let mut child = command.spawn().unwrap();
+    if let Some(stdin) = stdin {
+        let mut pipe = child.stdin.take().unwrap();
+        pipe.write_all(stdin.as_bytes()).await.unwrap();
+    }
+    child.wait_with_output().await.unwrap()
+}
+
+/// Google-like mock server started by [`serve_google`].
+struct MockGoogle {
+    url: String,
+    /// Query parameters of every received request, in order of arrival.
+    requests: Arc<Mutex<Vec<Value>>>,
+    /// Highest number of requests handled at the same time.
+    max_in_flight: Arc<AtomicUsize>,
+}
+
+/// Serves Google Translate v2 requests on a local port, translating every
+/// phrase by uppercasing it after `delay`. The first `rate_limited` requests
+/// are answered with `429 Too Many Requests` instead.
+async fn serve_google(rate_limited: usize, delay: Duration) -> MockGoogle {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!(
+        "http://{}/language/translate/v2",
+        listener.local_addr().unwrap()
+    );
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let max_in_flight = Arc::new(AtomicUsize::new(0));
+    let in_flight = Arc::new(AtomicUsize::new(0));
+
+    let (received, max) = (requests.clone(), max_in_flight.clone());
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (received, max, in_flight) = (received.clone(), max.clone(), in_flight.clone());
+
+            tokio::spawn(async move {
+                let mut socket = BufReader::new(socket);
+                while let Some(request) = read_request(&mut socket).await {
+                    let index = {
+                        let mut received = received.lock().unwrap();
+                        received.push(request.clone());
+                        received.len() - 1
+                    };
+                    max.fetch_max(
+                        in_flight.fetch_add(1, Ordering::SeqCst) + 1,
+                        Ordering::SeqCst,
+                    );
+                    tokio::time::sleep(delay).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                    if index < rate_limited {
+                        let error = json!({"error": {"code": 429, "message": "Rate limit"}});
+                        write_status_response(&mut socket, "429 Too Many Requests", &error).await;
+                        continue;
+                    }
+
+                    let translations: Vec<Value> = request["q"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|phrase| json!({"translatedText": phrase.as_str().unwrap().to_uppercase()}))
+                        .collect();
+                    write_response(
+                        &mut socket,
+                        &json!({"data": {"translations": translations}}),
+                    )
+                    .await;
+                }
+            });
+        }
+    });
+
+    MockGoogle {
+        url,
+        requests,
+        max_in_flight,
+    }
+}
+
+/// HTTP proxy started by [`serve_proxy`].
+struct MockProxy {
+    url: String,
+    /// Method and target, without the query, of the first request of every
+    /// connection, in order of arrival.
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+/// Serves as an HTTP proxy on a local port, passing every connection on to
+/// the server at `upstream`, e.g. a [`serve_google`] server, whatever host it
+/// asks for. `CONNECT` requests are answered with `502 Bad Gateway`.
+async fn serve_proxy(upstream: &str) -> MockProxy {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let upstream = reqwest::Url::parse(upstream).unwrap();
+    let upstream = format!(
+        "{}:{}",
+        upstream.host_str().unwrap(),
+        upstream.port().unwrap()
+    );
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let received = requests.clone();
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (received, upstream) = (received.clone(), upstream.clone());
+
+            tokio::spawn(async move {
+                let mut socket = BufReader::new(socket);
+                let mut request_line = String::new();
+                socket.read_line(&mut request_line).await.unwrap();
+                let request = request_line.split(['?', ' ']).take(2).collect::<Vec<_>>();
+                received.lock().unwrap().push(request.join(" "));
+
+                if request_line.starts_with("CONNECT ") {
+                    let response = "HTTP/1.1 502 Bad Gateway\r\ncontent-length: 0\r\n\r\n";
+                    let _ = socket.write_all(response.as_bytes()).await;
+                    return;
+                }
+                let mut server = TcpStream::connect(&upstream).await.unwrap();
+                server.write_all(request_line.as_bytes()).await.unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut socket, &mut server).await;
+            });
+        }
+    });
+
+    MockProxy { url, requests }
+}
+
+/// Serializes the tests reading or writing the Google environment variables.
+static GOOGLE_ENV: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[test]
+fn protects_multiple_placeholders() {
+    let placeholders = Placeholders::default();
+    let protected = placeholders
+        .protect("Hello {username}, you have %d messages and {{count}} $t(common.items)");
+
+    assert_eq!(
+        protected.text,
+        "Hello __QT0__, you have __QT1__ messages and __QT2__ __QT3__"
+    );
+    assert_eq!(
+        protected.restore("Hallo __QT0__, du hast __QT1__ Nachrichten und __QT2__ __QT3__"),
+        "Hallo {username}, du hast %d Nachrichten und {{count}} $t(common.items)"
+    );
+}
+
+#[test]
+fn protects_placeholders_adjacent_to_punctuation() {
+    let protected = Placeholders::default().protect("(%s), {a}.{b}!");
+
+    assert_eq!(protected.text, "(__QT0__), __QT1__.__QT2__!");
+    assert_eq!(
+        protected.restore("(__QT0__), __QT1__.__QT2__!"),
+        "(%s), {a}.{b}!"
+    );
+}
+
+#[test]
+fn sentinel_does_not_collide_with_phrase_content() {
+    let protected = Placeholders::default().protect("__QT0__ stays, {name} goes");
+
+    assert_eq!(protected.text, "__QT0__ stays, __QTX0__ goes");
+    assert_eq!(
+        protected.restore("__QT0__ bleibt, __QTX0__ geht"),
+        "__QT0__ bleibt, {name} geht"
+    );
+}
+
+#[test]
+fn phrase_without_placeholders_is_unchanged() {
+    let protected = Placeholders::default().protect("Nothing to protect");
+
+    assert_eq!(protected.text, "Nothing to protect");
+    assert_eq!(
+        protected.restore("Nichts zu schützen"),
+        "Nichts zu schützen"
+    );
+}
+
+#[test]
+fn surrounding_whitespace_is_kept_around_the_translation() {
+    let placeholders = Placeholders::default();
+
+    let cases = [
+        ("\tLoading\t", "Wird geladen", "\tWird geladen\t"),
+        ("   Loading  ", " Wird geladen ", "   Wird geladen  "),
+        ("\nNotice\n\n", "Hinweis\n", "\nHinweis\n\n"),
+        (
+            " {name} joined",
+            "{name} ist beigetreten",
+            " {name} ist beigetreten",
+        ),
+    ];
+    for (phrase, translated, expected) in cases {
+        let protected = placeholders.protect(phrase);
+        assert_eq!(protected.text, protected.text.trim(), "{phrase:?}");
+
+        let translated = translated.replace("{name}", "__QT0__");
+        assert_eq!(protected.restore(&translated), expected, "{phrase:?}");
+    }
+}
+
+#[tokio::test]
+async fn padded_phrases_are_sent_trimmed_and_translated_once() {
+    let provider = StubProvider::default();
+    let source = json!({"a": " Loading… ", "b": "Loading…", "c": "\tLoading…\n"});
+    let mut target = Map::new();
+    let options = options(&provider);
+
+    translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+
+    assert_eq!(*provider.batches.lock().unwrap(), [["Loading…"]]);
+    assert_eq!(
+        Value::Object(target),
+        json!({"a": " LOADING… ", "b": "LOADING…", "c": "\tLOADING…\n"})
+    );
+}
+
+#[test]
+fn custom_patterns_are_protected() {
+    let placeholders = Placeholders::new(&[r":\w+"]).unwrap();
+    let protected = placeholders.protect("Welcome :name");
+
+    assert_eq!(protected.text, "Welcome __QT0__");
+    assert_eq!(protected.restore("Willkommen __QT0__"), "Willkommen :name");
+}
+
+#[test]
+fn icu_plural_only_sends_sub_messages() {
+    let protected = Placeholders::default()
+        .protect("{count, plural, =0 {No items} one {# item} other {# items}}");
+
+    assert_eq!(
+        protected.text,
+        "__QT0__No items__QT1__ item__QT2__ items__QT3__"
+    );
+    assert_eq!(
+        protected.restore("__QT0__Keine Artikel__QT1__ Artikel__QT2__ Artikel__QT3__"),
+        "{count, plural, =0 {Keine Artikel} one {# Artikel} other {# Artikel}}"
+    );
+}
+
+#[test]
+fn icu_select_keeps_placeholders_and_surrounding_text() {
+    let protected = Placeholders::default()
+        .protect("Hi {name}, {gender, select, female {she} male {he} other {they}} replied.");
+
+    assert_eq!(
+        protected.text,
+        "Hi __QT0__, __QT1__she__QT2__he__QT3__they__QT4__ replied."
+    );
+    assert_eq!(
+        protected.restore(&protected.text),
+        "Hi {name}, {gender, select, female {she} male {he} other {they}} replied."
+    );
+}
+
+#[test]
+fn nested_icu_messages_are_protected() {
+    let phrase = "{host, select, female {{guests, plural, offset:1 =0 {She is alone} one {She invited {guest}} other {She invited # people}}} other {They invited # people}}";
+    let protected = Placeholders::default().protect(phrase);
+
+    assert_eq!(
+        protected.text,
+        "__QT0__She is alone__QT1__She invited __QT2____QT3__She invited __QT4__ people__QT5__They invited # people__QT6__"
+    );
+    assert_eq!(protected.restore(&protected.text), phrase);
+}
+
+#[test]
+fn cache_round_trips_through_disk() {
+    let path = temp_dir("cache").join("cache.json");
+
+    let cache = TranslationCache::load(&path).unwrap();
+    assert_eq!(cache.get("Save", "de"), None);
+
+    cache.insert("Save".to_string(), "de", "Speichern".to_string());
+    cache.insert("Save".to_string(), "pl", "Zapisz".to_string());
+    cache.save(&path).unwrap();
+
+    let cache = TranslationCache::load(&path).unwrap();
+    assert_eq!(cache.get("Save", "de").as_deref(), Some("Speichern"));
+    assert_eq!(cache.get("Save", "pl").as_deref(), Some("Zapisz"));
+    assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+}
+
+#[tokio::test]
+async fn translate_stream_respects_batch_size_and_concurrency() {
+    let provider = StubProvider::default();
+    let phrases: Vec<String> = (0..25).map(|i| format!("phrase {i}")).collect();
+
+    let results: Vec<_> = translate_stream(
+        &provider,
+        phrases.clone(),
+        "en".to_string(),
+        "de".to_string(),
+        4,
+        usize::MAX,
+        2,
+        None,
+    )
+    .collect()
+    .await;
+    let mut translated: Vec<(String, String)> = results
+        .into_iter()
+        .map(|(phrase, result)| (phrase, result.unwrap()))
+        .collect();
+    translated.sort();
+
+    let mut expected: Vec<(String, String)> = phrases
+        .iter()
+        .map(|p| (p.clone(), p.to_uppercase()))
+        .collect();
+    expected.sort();
+
+    assert_eq!(translated, expected);
+    let batches = provider.batches.lock().unwrap();
+    assert_eq!(batches.len(), 7);
+    assert!(batches.iter().all(|batch| batch.len() <= 4));
+    assert!(provider.max_in_flight.load(Ordering::SeqCst) <= 2);
+}
+
+#[tokio::test]
+async fn translate_iter_pulls_entries_lazily_and_keeps_their_order() {
+    let provider = StubProvider::default();
+    let pulled = AtomicUsize::new(0);
+    let entries = (0..200).map(|i| {
+        pulled.fetch_add(1, Ordering::SeqCst);
+        (format!("row.{i}"), format!("phrase {i}"))
+    });
+
+    let mut stream = translate_iter(&provider, entries, "en", "de", 4, usize::MAX, 2, None, true);
+    let first = stream.next().await.unwrap();
+    assert_eq!(first.0, "row.0");
+    assert_eq!(first.1.unwrap(), "PHRASE 0");
+    assert!(
+        pulled.load(Ordering::SeqCst) < 20,
+        "the iterator was drained eagerly"
+    );
+
+    let rest: Vec<_> = stream.collect().await;
+    let keys: Vec<String> = rest.iter().map(|(key, _)| key.clone()).collect();
+    let expected: Vec<String> = (1..200).map(|i| format!("row.{i}")).collect();
+    assert_eq!(keys, expected);
+    assert!(rest.iter().all(|(key, result)| {
+        result.as_ref().unwrap() == &format!("PHRASE {}", &key["row.".len()..])
+    }));
+    assert!(provider.max_in_flight.load(Ordering::SeqCst) <= 2);
+
+    let provider = StubProvider::default();
+    let entries = [("a", "Bonjour"), ("b", "Bonjour"), ("c", "Salut")]
+        .map(|(key, phrase)| (key.to_string(), phrase.to_string()));
+    let mut unordered: Vec<_> =
+        translate_iter(&provider, entries, "fr", "en", 2, 100, 4, None, false)
+            .map(|(key, result)| (key, result.unwrap()))
+            .collect()
+            .await;
+    unordered.sort();
+    assert_eq!(
+        unordered,
+        [("a", "BONJOUR"), ("b", "BONJOUR"), ("c", "SALUT")]
+            .map(|(key, phrase)| (key.to_string(), phrase.to_string()))
+    );
+    assert_eq!(provider.batches.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn batches_stay_under_phrase_and_character_limits() {
+    let provider = StubProvider::default();
+    let phrases: Vec<String> = (0..40)
+        .map(|i| match i % 5 {
+            0 => "long paragraph ".repeat(10),
+            3 => "x".repeat(150),
+            _ => format!("short {i}"),
+        })
+        .collect();
+
+    let results: Vec<_> = translate_stream(
+        &provider,
+        phrases.clone(),
+        "en".to_string(),
+        "de".to_string(),
+        6,
+        200,
+        4,
+        None,
+    )
+    .collect()
+    .await;
+    assert!(results.iter().all(|(_, result)| result.is_ok()));
+
+    let batches = provider.batches.lock().unwrap();
+    let mut sent: Vec<&String> = batches.iter().flatten().collect();
+    sent.sort();
+    let mut expected: Vec<&String> = phrases.iter().collect();
+    expected.sort();
+    assert_eq!(sent, expected);
+
+    for batch in batches.iter() {
+        let chars: usize = batch.iter().map(|phrase| phrase.chars().count()).sum();
+        assert!(batch.len() <= 6, "{batch:?} has too many phrases");
+        assert!(chars <= 200, "{batch:?} has {chars} characters");
+    }
+}
+
+#[test]
+fn oversized_phrase_is_sent_alone() {
+    let phrases = ["a", "b".repeat(30).as_str(), "c", "d", "e"].map(String::from);
+
+    let batches = split_batches(&phrases, 2, 10);
+
+    assert_eq!(
+        batches,
+        [
+            &phrases[0..1],
+            &phrases[1..2],
+            &phrases[2..4],
+            &phrases[4..5]
+        ]
+    );
+}
+
+#[tokio::test]
+async fn translate_stream_stays_under_the_rate_limit() {
+    let provider = StubProvider::default();
+    let phrases: Vec<String> = (0..15).map(|i| format!("phrase {i}")).collect();
+    let limiter = RateLimiter::default().with_requests_per_second(NonZeroU32::new(10).unwrap());
+
+    let started = Instant::now();
+    let results: Vec<_> = translate_stream(
+        &provider,
+        phrases,
+        "en".to_string(),
+        "de".to_string(),
+        1,
+        usize::MAX,
+        4,
+        Some(&limiter),
+    )
+    .collect()
+    .await;
+
+    // The first ten requests fit into the burst, the other five are spread
+    // over the following half second.
+    assert_eq!(results.len(), 15);
+    assert!(started.elapsed() >= Duration::from_millis(450));
+}
+
+#[tokio::test]
+async fn repeated_phrases_are_translated_once() {
+    let provider = StubProvider::default();
+    let source = json!({
+        "save": "Save",
+        "toolbar": {"save": "Save", "open": "Open"},
+        "dialog": {"buttons": ["Save", "Cancel", {"label": "Save"}]},
+        "menu": [["Save", "Open"]],
+    });
+    let mut target = Map::new();
+    let options = TranslationOptions {
+        concurrency: 2,
+        batch_size: Some(1),
+        ..options(&provider)
+    };
+
+    let summary = translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+
+    let mut sent: Vec<String> = provider.batches.lock().unwrap().concat();
+    sent.sort();
+    assert_eq!(sent, ["Cancel", "Open", "Save"]);
+    assert_eq!((summary.keys, summary.unique, summary.saved()), (8, 3, 5));
+    assert_eq!(target["dialog"]["buttons"][2]["label"], "SAVE");
+}
+
+#[test]
+fn recognizes_values_without_language() {
+    let placeholders = Placeholders::default();
+
+    for value in [
+        "https://example.com/help?page=2",
+        "www.example.com",
+        "support@acme.com",
+        "42",
+        " -1,000.50 ",
+        "15%",
+        "v1.2.3",
+        "2.0.0-beta.1",
+        "#ff0000",
+        "0x1F",
+    ] {
+        assert!(placeholders.is_verbatim(value), "{value:?}");
+    }
+    for value in [
+        "Hello",
+        "Visit https://example.com",
+        "3 items",
+        "Version 2",
+        "face",
+    ] {
+        assert!(!placeholders.is_verbatim(value), "{value:?}");
+    }
+
+    let custom = Placeholders::new(&DEFAULT_PATTERNS)
+        .unwrap()
+        .with_verbatim(&[r"[A-Z]{3}-\d+"])
+        .unwrap();
+    assert!(custom.is_verbatim("ABC-123"));
+    assert!(!custom.is_verbatim("https://example.com"));
+}
+
+#[tokio::test]
+async fn values_without_language_are_copied_verbatim() {
+    let provider = StubProvider::default();
+    let source = json!({
+        "title": "Hello",
+        "links": {"docs": "https://example.com/docs", "mail": "support@acme.com"},
+        "version": "v1.2.3",
+        "limit": "100",
+        "color": "#ff0000"
+    });
+    let mut target = Map::new();
+    let options = options(&provider);
+
+    translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+
+    assert_eq!(*provider.batches.lock().unwrap(), [["Hello"]]);
+    assert_eq!(
+        Value::Object(target),
+        json!({
+            "title": "HELLO",
+            "links": {"docs": "https://example.com/docs", "mail": "support@acme.com"},
+            "version": "v1.2.3",
+            "limit": "100",
+            "color": "#ff0000"
+        })
+    );
+}
+
+#[tokio::test]
+async fn empty_source_strings_are_copied_without_api_calls() {
+    let provider = StubProvider::default();
+    let source = json!({"empty": "", "blank": " \n", "items": [""]});
+    let mut target = Map::new();
+    let options = options(&provider);
+
+    let summary = translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+
+    assert!(provider.batches.lock().unwrap().is_empty());
+    assert_eq!(summary.requests, 0);
+    assert_eq!(Value::Object(target), source);
+}
+
+#[tokio::test]
+async fn batch_size_is_validated_and_capped_at_the_provider_maximum() {
+    let provider = StubProvider::default();
+    let mut translations: HashMap<String, String> = (0..200)
+        .map(|i| (format!("Phrase {i}"), String::new()))
+        .collect();
+    let mut options = TranslationOptions {
+        batch_size: Some(0),
+        ..options(&provider)
+    };
+
+    let result =
+        utils::perform_translations(&mut translations, &[], &options, None, "de", |_, _| {});
+    assert!(result.await.is_err());
+    assert!(provider.batches.lock().unwrap().is_empty());
+
+    options.batch_size = Some(1000);
+    let stats =
+        utils::perform_translations(&mut translations, &[], &options, None, "de", |_, _| {})
+            .await
+            .unwrap();
+
+    let sizes: Vec<usize> = provider
+        .batches
+        .lock()
+        .unwrap()
+        .iter()
+        .map(Vec::len)
+        .collect();
+    assert_eq!(sizes.iter().max(), Some(&provider.max_batch_size()));
+    assert_eq!(stats.requests, 2);
+}
+
+#[tokio::test]
+async fn preserve_newlines_translates_lines_separately() {
+    let provider = StubProvider::default();
+    let source = json!({
+        "notice": "First paragraph.\n\nSecond paragraph.\nLast line.\n",
+        "title": "Last line.",
+    });
+    let mut target = Map::new();
+    let options = TranslationOptions {
+        preserve_newlines: true,
+        ..options(&provider)
+    };
+
+    translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+
+    let mut sent = provider.batches.lock().unwrap().concat();
+    sent.sort();
+    assert_eq!(
+        sent,
+        ["First paragraph.", "Last line.", "Second paragraph."]
+    );
+    assert_eq!(
+        Value::Object(target),
+        json!({
+            "notice": "FIRST PARAGRAPH.\n\nSECOND PARAGRAPH.\nLAST LINE.\n",
+            "title": "LAST LINE.",
+        })
+    );
+}
+
+#[tokio::test]
+async fn max_chars_total_aborts_before_any_request() {
+    let provider = StubProvider::default();
+    let source = json!({"a": "Apple", "b": "Banana"});
+    let mut options = TranslationOptions {
+        max_chars_total: Some(10),
+        ..options(&provider)
+    };
+
+    let err = translate_json(&source, &mut Map::new(), "de", &options, None, |_, _| {})
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("11 characters"), "{err}");
+    assert!(provider.batches.lock().unwrap().is_empty());
+
+    options.max_chars_total = Some(11);
+    let summary = translate_json(&source, &mut Map::new(), "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+    assert_eq!(summary.characters, 11);
+}
+
+#[tokio::test]
+async fn limit_caps_the_phrases_sent() {
+    let provider = StubProvider::default();
+    let source = json!({"c": "Cherry", "a": "Apple", "d": "Date", "b": "Banana", "e": "Elder"});
+    let mut target = Map::new();
+    let options = TranslationOptions {
+        limit: Some(2),
+        ..options(&provider)
+    };
+
+    let summary = translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+
+    // The first phrases of the source, sent in alphabetical order.
+    assert_eq!(*provider.batches.lock().unwrap(), [["Apple", "Cherry"]]);
+    assert_eq!((summary.translated, summary.limited), (2, 3));
+    assert_eq!(Value::Object(target), json!({"c": "CHERRY", "a": "APPLE"}));
+}
+
+#[tokio::test]
+async fn limit_takes_phrases_in_the_given_order() {
+    let provider = StubProvider::default();
+    let mut translations: HashMap<String, String> = ["Apple", "Banana", "Cherry", "Date"]
+        .into_iter()
+        .map(|phrase| (phrase.to_string(), String::new()))
+        .collect();
+    let cache = TranslationCache::default();
+    cache.insert("Date".to_string(), "de", "Dattel".to_string());
+    let options = TranslationOptions {
+        limit: Some(2),
+        ..options(&provider)
+    };
+    let order = ["Date", "Cherry", "Apple", "Cherry", "Banana"];
+
+    let stats = utils::perform_translations(
+        &mut translations,
+        &order,
+        &options,
+        Some(&cache),
+        "de",
+        |_, _| {},
+    )
+    .await
+    .unwrap();
+
+    // The cache hit does not count towards the limit.
+    assert_eq!(*provider.batches.lock().unwrap(), [["Apple", "Cherry"]]);
+    assert_eq!(stats.limited, 1);
+    assert_eq!(
+        translations,
+        HashMap::from([
+            ("Apple".to_string(), "APPLE".to_string()),
+            ("Cherry".to_string(), "CHERRY".to_string()),
+            ("Date".to_string(), "Dattel".to_string()),
+        ])
+    );
+}
+
+#[tokio::test]
+async fn limit_counts_phrases_not_lines_when_preserving_newlines() {
+    let provider = StubProvider::default();
+    let source = json!({"a": "one\ntwo\nthree\nfour", "b": "five"});
+    let mut target = Map::new();
+    let options = TranslationOptions {
+        limit: Some(1),
+        preserve_newlines: true,
+        ..options(&provider)
+    };
+
+    let summary = translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+
+    // Every line of the first phrase is sent, and none of the second.
+    assert_eq!(
+        *provider.batches.lock().unwrap(),
+        [["four", "one", "three", "two"]]
+    );
+    assert_eq!((summary.translated, summary.limited), (1, 1));
+    assert_eq!(target.keys().collect::<Vec<_>>(), ["a"]);
+}
+
+#[tokio::test]
+async fn limited_and_translated_count_source_phrases_sharing_lines() {
+    let provider = StubProvider::default();
+    let source = json!({
+        "a": "one\ntwo",
+        "b": "two\n  three",
+        "c": "two",
+        "d": "four",
+    });
+    let mut target = Map::new();
+    let options = TranslationOptions {
+        limit: Some(1),
+        preserve_newlines: true,
+        normalize_whitespace: true,
+        ..options(&provider)
+    };
+
+    let summary = translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+
+    // "c" needs no request of its own, as its line is sent for "a" anyway.
+    assert_eq!(*provider.batches.lock().unwrap(), [["one", "two"]]);
+    assert_eq!((summary.translated, summary.limited), (2, 2));
+    assert_eq!(Value::Object(target), json!({"a": "ONE\nTWO", "c": "TWO"}));
+}
+
+#[tokio::test]
+async fn summary_reports_requests_characters_and_cache_hits() {
+    let provider = StubProvider::default();
+    let source = json!({"title": "Hello", "menu": {"open": "Open", "save": "Save"}});
+    let mut target = Map::new();
+    let cache = TranslationCache::default();
+    cache.insert("Save".to_string(), "de", "Speichern".to_string());
+    let options = TranslationOptions {
+        batch_size: Some(1),
+        ..options(&provider)
+    };
+
+    let summary = translate_json(
+        &source,
+        &mut target,
+        "de",
+        &options,
+        Some(&cache),
+        |_, _| {},
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(summary.cache_hits, 1);
+    assert_eq!(summary.requests, 2);
+    assert_eq!(summary.characters, "Hello".len() + "Open".len());
+    assert!(summary.failed_keys.is_empty());
+    assert_eq!(target["menu"]["save"], "Speichern");
+}
+
+#[tokio::test]
+async fn targets_are_translated_concurrently_under_a_shared_limit() {
+    let provider = StubProvider::default();
+    let source = json!({"a": "One", "b": "Two", "c": {"d": "Three"}});
+    let limiter = RateLimiter::default().with_max_in_flight(NonZeroU32::new(2).unwrap());
+    let cache = TranslationCache::default();
+    cache.insert("One".to_string(), "de", "Eins".to_string());
+    let options = TranslationOptions {
+        concurrency: 2,
+        batch_size: Some(1),
+        rate_limiter: Some(&limiter),
+        ..options(&provider)
+    };
+    let mut targets = vec![("de", Map::new()), ("fr", Map::new()), ("pl", Map::new())];
+
+    let results =
+        translate_targets(&source, &mut targets, &options, Some(&cache), |_, _, _| {}).await;
+
+    let summaries: Vec<_> = results.into_iter().map(Result::unwrap).collect();
+    assert_eq!(
+        summaries.iter().map(|s| s.requests).collect::<Vec<_>>(),
+        [2, 3, 3]
+    );
+    assert_eq!(provider.batches.lock().unwrap().len(), 8);
+    assert_eq!(provider.max_in_flight.load(Ordering::SeqCst), 2);
+    assert_eq!(targets[0].1["a"], "Eins");
+    assert_eq!(targets[2].1["c"]["d"], "THREE");
+    assert_eq!(cache.get("One", "de").unwrap(), "Eins");
+    assert_eq!(cache.get("Two", "fr").unwrap(), "TWO");
+}
+
+/// Provider failing every request into `fr` at once and answering the
+/// others only after a minute.
+struct FrenchFailsProvider;
+
+#[async_trait]
+impl TranslationProvider for FrenchFailsProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> BatchResult {
+        if target_lang == "fr" {
+            return FailingProvider
+                .translate_batch(phrases, source_lang, target_lang)
+                .await;
+        }
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        Ok(phrases.to_vec())
+    }
+}
+
+#[tokio::test]
+async fn failing_fast_cancels_the_other_targets() {
+    let source = json!({"title": "hello"});
+    let options = TranslationOptions {
+        on_error: OnError::Fail,
+        ..options(&FrenchFailsProvider)
+    };
+    let mut targets = vec![("de", Map::new()), ("fr", Map::new())];
+
+    let run = translate_targets(&source, &mut targets, &options, None, |_, _, _| {});
+    let results = tokio::time::timeout(Duration::from_secs(5), run)
+        .await
+        .expect("the German target was not cancelled");
+
+    assert!(
+        matches!(&results[0], Err(QTranslateError::Translation(message)) if message.contains("cancelled after translating into fr failed"))
+    );
+    assert!(results[1].is_err());
+}
+
+/// Provider answering batches with `hello` at once and hanging on the others.
+struct HangingProvider;
+
+#[async_trait]
+impl TranslationProvider for HangingProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        _source_lang: &str,
+        _target_lang: &str,
+    ) -> BatchResult {
+        if !phrases.iter().any(|phrase| phrase == "hello") {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        }
+        Ok(phrases.iter().map(|phrase| phrase.to_uppercase()).collect())
+    }
+}
+
+#[tokio::test]
+async fn phrases_translated_before_a_timeout_can_be_written_from_the_cache() {
+    let source = json!({"greeting": "hello", "farewell": "goodbye"});
+    let cache = TranslationCache::default();
+    let options = TranslationOptions {
+        concurrency: 2,
+        batch_size: Some(1),
+        ..options(&HangingProvider)
+    };
+    let mut targets = vec![("de", Map::new())];
+
+    let run = translate_targets(&source, &mut targets, &options, Some(&cache), |_, _, _| {});
+    assert!(
+        tokio::time::timeout(Duration::from_millis(200), run)
+            .await
+            .is_err()
+    );
+
+    let cached_only = TranslationOptions {
+        provider: &FailingProvider,
+        limit: Some(0),
+        ..options
+    };
+    let mut targets = vec![("de", Map::new())];
+    let results = translate_targets(
+        &source,
+        &mut targets,
+        &cached_only,
+        Some(&cache),
+        |_, _, _| {},
+    )
+    .await;
+
+    let summary = results[0].as_ref().unwrap();
+    assert_eq!(
+        (summary.cache_hits, summary.limited, summary.failed),
+        (1, 1, 0)
+    );
+    assert_eq!(
+        Value::Object(targets.remove(0).1),
+        json!({"greeting": "HELLO"})
+    );
+}
+
+#[tokio::test]
+async fn summary_lists_failed_keys() {
+    let source = json!({"title": "hello", "steps": ["hello"]});
+    let mut target = Map::new();
+    let options = options(&FailingProvider);
+
+    let summary = translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+
+    assert_eq!(summary.failed, 1);
+    assert_eq!(summary.failed_keys, ["title", "steps.0"]);
+}
+
+#[test]
+fn key_filter_matches_globs_with_exclude_precedence() {
+    let filter = KeyFilter::new(&["checkout.**", "*.title"], &["checkout.legal.**"]);
+
+    assert!(filter.matches("checkout.button.pay"));
+    assert!(filter.matches("home.title"));
+    assert!(!filter.matches("home.header.title"));
+    assert!(!filter.matches("checkout.legal.terms"));
+    assert!(!filter.matches("checkoutx.button"));
+
+    let any_depth = KeyFilter::new(&["**.title"], &[] as &[&str]);
+    assert!(any_depth.matches("title"));
+    assert!(any_depth.matches("home.header.title"));
+}
+
+#[tokio::test]
+async fn filtered_keys_are_left_untouched() {
+    let provider = StubProvider::default();
+    let source = json!({
+        "home": {"title": "Home"},
+        "checkout": {"pay": "Pay", "steps": ["One"], "legal": {"terms": "Terms"}},
+    });
+    let mut target = json!({"home": {"title": "Start"}, "checkout": {"legal": {"terms": "AGB"}}})
+        .as_object()
+        .unwrap()
+        .clone();
+    let filter = KeyFilter::new(&["checkout.**"], &["checkout.legal.**"]);
+    let options = TranslationOptions {
+        overwrite: true,
+        filter: &filter,
+        ..options(&provider)
+    };
+
+    let summary = translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+
+    let mut sent: Vec<String> = provider.batches.lock().unwrap().concat();
+    sent.sort();
+    assert_eq!(sent, ["One", "Pay"]);
+    assert_eq!(summary.keys, 2);
+    assert_eq!(
+        Value::Object(target),
+        json!({
+            "home": {"title": "Start"},
+            "checkout": {"legal": {"terms": "AGB"}, "pay": "PAY", "steps": ["ONE"]},
+        })
+    );
+}
+
+#[tokio::test]
+async fn namespaces_restrict_translation_to_top_level_keys() {
+    let provider = StubProvider::default();
+    let source = json!({
+        "auth": {"login": "Log in"},
+        "billing": {"pay": "Pay", "plans": ["Free"]},
+        "dashboard": {"title": "Home"},
+        "billing.flat": "Invoice",
+    });
+    let mut target = json!({"auth": {"login": "Anmelden"}})
+        .as_object()
+        .unwrap()
+        .clone();
+    let filter = KeyFilter::default().with_namespaces(&["billing", "dashboard"]);
+    let options = TranslationOptions {
+        overwrite: true,
+        filter: &filter,
+        ..options(&provider)
+    };
+
+    translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+
+    let mut sent: Vec<String> = provider.batches.lock().unwrap().concat();
+    sent.sort();
+    assert_eq!(sent, ["Free", "Home", "Invoice", "Pay"]);
+    assert_eq!(target["auth"], json!({"login": "Anmelden"}));
+    assert_eq!(target["billing"], json!({"pay": "PAY", "plans": ["FREE"]}));
+}
+
+#[test]
+fn po_files_round_trip_translations() {
+    let source = json!({
+        "menu": {"save": "Save", "quote": "Say \"hi\"\nthen go"},
+        "toolbar": ["Save", "Open"],
+    });
+    let target = json!({
+        "menu": {"save": "Speichern", "quote": "Sag \"hallo\"\ndann geh"},
+        "toolbar": ["Speichern"],
+    });
+
+    let rendered = po::render(&source, target.as_object().unwrap(), "de");
+
+    assert!(rendered.starts_with("msgid \"\"\nmsgstr \"\"\n"));
+    assert!(rendered.contains("\"Language: de\\n\"\n"));
+    assert!(
+        rendered.contains("#: menu.save\n#: toolbar.0\nmsgid \"Save\"\nmsgstr \"Speichern\"\n")
+    );
+    assert!(rendered.contains("msgid \"Say \\\"hi\\\"\\nthen go\"\n"));
+    assert!(rendered.contains("#: toolbar.1\nmsgid \"Open\"\nmsgstr \"\"\n"));
+
+    assert_eq!(
+        po::parse(&rendered),
+        HashMap::from([
+            ("Save".to_string(), "Speichern".to_string()),
+            (
+                "Say \"hi\"\nthen go".to_string(),
+                "Sag \"hallo\"\ndann geh".to_string()
+            ),
+        ])
+    );
+}
+
+#[test]
+fn properties_files_are_parsed_like_java() {
+    let contents = "# Comment\n! Also a comment\n\nkey1=value1\nkey2 : value2\nkey3 value3\nspaced\\ key = \\ leading space\ncolon\\:key=a\\=b\nlong = first,\\\n       second\nunicode=Caf\\u00e9 \\ud83d\\ude00\ntabs=a\\tb\n";
+
+    assert_eq!(
+        Value::Object(properties::parse(contents).unwrap()),
+        json!({
+            "key1": "value1",
+            "key2": "value2",
+            "key3": "value3",
+            "spaced key": " leading space",
+            "colon:key": "a=b",
+            "long": "first,second",
+            "unicode": "Café 😀",
+            "tabs": "a\tb",
+        })
+    );
+    assert!(matches!(
+        properties::parse("a=b\nbroken=\\u00g1"),
+        Err(FormatError::MalformedEscape(2))
+    ));
+}
+
+#[test]
+fn properties_keep_the_comments_of_the_template() {
+    let template = "# Buttons\nsave=Save\n\n# Messages\nwelcome = Welcome, {0}!\nstale=Old\n";
+    let translated =
+        json!({"save": "Speichern", "welcome": "=Willkommen: {0}!", "new key": "Neu ü"});
+
+    let written = properties::serialize(translated.as_object().unwrap(), Some(template));
+
+    assert_eq!(
+        written,
+        "# Buttons\nsave=Speichern\n\n# Messages\nwelcome=\\=Willkommen: {0}!\nnew\\ key=Neu \\u00fc\n"
+    );
+    assert_eq!(
+        Value::Object(properties::parse(&written).unwrap()),
+        json!({"save": "Speichern", "welcome": "=Willkommen: {0}!", "new key": "Neu ü"})
+    );
+}
+
+#[test]
+fn detects_format_from_extension() {
+    assert_eq!(Format::from_path("assets/i18n/en.json"), Some(Format::Json));
+    assert_eq!(Format::from_path("assets/i18n/en.yaml"), Some(Format::Yaml));
+    assert_eq!(Format::from_path("assets/i18n/en.YML"), Some(Format::Yaml));
+    assert_eq!(
+        Format::from_path("res/messages_en.properties"),
+        Some(Format::Properties)
+    );
+    assert_eq!(Format::from_path("assets/i18n/en.toml"), None);
+}
+
+#[test]
+fn json_is_written_in_the_selected_style() {
+    let value = json!({"title": "Hallo", "menu": {"file": "Datei"}});
+
+    assert_eq!(
+        Format::Json
+            .serialize_with(&value, JsonStyle::Compact)
+            .unwrap(),
+        r#"{"title":"Hallo","menu":{"file":"Datei"}}"#
+    );
+    assert_eq!(
+        Format::Json
+            .serialize_with(&value, JsonStyle::Pretty { indent: 4 })
+            .unwrap(),
+        "{\n    \"title\": \"Hallo\",\n    \"menu\": {\n        \"file\": \"Datei\"\n    }\n}\n"
+    );
+    assert_eq!(
+        Format::Json.serialize(&value).unwrap(),
+        format!("{}\n", serde_json::to_string_pretty(&value).unwrap())
+    );
+}
+
+#[test]
+fn yaml_nested_maps_keep_key_order() {
+    let source = Format::Yaml
+        .parse("menu:\n  file: File\n  edit: Edit\ntitle: Welcome\ncount: 3\n")
+        .unwrap();
+
+    assert_eq!(
+        source,
+        json!({"menu": {"file": "File", "edit": "Edit"}, "title": "Welcome", "count": 3})
+    );
+
+    let target = Map::new();
+    let mut translations = HashMap::new();
+    utils::gather_translations(
+        &source,
+        &target,
+        &mut translations,
+        false,
+        &KeyFilter::default(),
+    )
+    .unwrap();
+
+    let translations: HashMap<String, String> = translations
+        .into_keys()
+        .map(|phrase| (phrase.clone(), format!("{phrase} (de)")))
+        .collect();
+    let mut target = Map::new();
+    utils::apply_translations(
+        &source,
+        &mut target,
+        &translations,
+        false,
+        &KeyFilter::default(),
+    );
+
+    assert_eq!(
+        Format::Yaml.serialize(&Value::Object(target)).unwrap(),
+        "menu:\n  file: File (de)\n  edit: Edit (de)\ntitle: Welcome (de)\ncount: 3\n"
+    );
+}
+
+#[test]
+fn yaml_multi_line_scalars_round_trip() {
+    let source = Format::Yaml
+        .parse("notice: |\n  First line\n  Second line\nfolded: >\n  Folded\n  text\n")
+        .unwrap();
+
+    assert_eq!(source["notice"], "First line\nSecond line\n");
+    assert_eq!(source["folded"], "Folded text\n");
+
+    let serialized = Format::Yaml.serialize(&source).unwrap();
+    assert_eq!(Format::Yaml.parse(&serialized).unwrap(), source);
+}
+
+#[test]
+fn gather_translations_seeds_existing_target_values() {
+    let source = json!({
+        "title": "Welcome",
+        "menu": {"file": "File", "edit": "Edit"},
+        "count": 3
+    });
+    let target = json!({"menu": {"file": "Datei"}})
+        .as_object()
+        .unwrap()
+        .clone();
+    let mut translations = HashMap::new();
+
+    utils::gather_translations(
+        &source,
+        &target,
+        &mut translations,
+        false,
+        &KeyFilter::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        translations,
+        HashMap::from([
+            ("Welcome".to_string(), String::new()),
+            ("File".to_string(), "Datei".to_string()),
+            ("Edit".to_string(), String::new()),
+        ])
+    );
+}
+
+#[test]
+fn reconstructs_target_from_source_end_to_end() {
+    let source = json!({
+        "title": "Welcome",
+        "menu": {"file": "File", "edit": "Edit"},
+        "enabled": true,
+        "limit": 10
+    });
+    let mut target = json!({"menu": {"edit": "Bearbeiten"}})
+        .as_object()
+        .unwrap()
+        .clone();
+
+    let mut translations = HashMap::new();
+    utils::gather_translations(
+        &source,
+        &target,
+        &mut translations,
+        false,
+        &KeyFilter::default(),
+    )
+    .unwrap();
+
+    let stub = HashMap::from([("Welcome", "Willkommen"), ("File", "Datei")]);
+    for (phrase, translated) in translations.iter_mut() {
+        if translated.is_empty() {
+            *translated = stub[phrase.as_str()].to_string();
+        }
+    }
+
+    utils::apply_translations(
+        &source,
+        &mut target,
+        &translations,
+        false,
+        &KeyFilter::default(),
+    );
+
+    assert_eq!(
+        serde_json::to_string(&target).unwrap(),
+        r#"{"title":"Willkommen","menu":{"file":"Datei","edit":"Bearbeiten"},"enabled":true,"limit":10}"#
+    );
+}
+
+#[test]
+fn glossary_terms_are_protected_as_whole_words() {
+    let placeholders = Placeholders::default()
+        .with_glossary(&["GitHub", "GitHub Actions", "iOS"], true)
+        .unwrap();
+
+    let protected = placeholders.protect("Run GitHub Actions on iOS, not GitHubby or ios");
+    assert_eq!(
+        protected.text,
+        "Run __QT0__ on __QT1__, not GitHubby or ios"
+    );
+    assert_eq!(
+        protected.restore("Führe __QT0__ auf __QT1__ aus"),
+        "Führe GitHub Actions auf iOS aus"
+    );
+}
+
+#[test]
+fn glossary_can_match_case_insensitively() {
+    let placeholders = Placeholders::default()
+        .with_glossary(&["Qumo"], false)
+        .unwrap();
+
+    let protected = placeholders.protect("qumo and QUMO");
+    assert_eq!(protected.text, "__QT0__ and __QT1__");
+    assert_eq!(protected.restore("__QT0__ und __QT1__"), "qumo und QUMO");
+}
+
+#[test]
+fn reads_glossary_as_json_or_lines() {
+    let dir = temp_dir("glossary");
+    fs::write(dir.join("terms.json"), r#"["GitHub", "Qumo"]"#).unwrap();
+    fs::write(dir.join("terms.txt"), "# brands\nGitHub\n\n  Qumo  \n").unwrap();
+
+    assert_eq!(
+        read_glossary(dir.join("terms.json")).unwrap(),
+        ["GitHub", "Qumo"]
+    );
+    assert_eq!(
+        read_glossary(dir.join("terms.txt")).unwrap(),
+        ["GitHub", "Qumo"]
+    );
+}
+
+#[tokio::test]
+async fn phrases_matching_a_glossary_term_are_not_sent() {
+    let provider = StubProvider::default();
+    let placeholders = Placeholders::default()
+        .with_glossary(&["Qumo"], true)
+        .unwrap();
+    let options = TranslationOptions {
+        placeholders: &placeholders,
+        ..options(&provider)
+    };
+    let mut translations = HashMap::from([
+        ("Qumo".to_string(), String::new()),
+        ("{count}".to_string(), String::new()),
+        ("Open Qumo".to_string(), String::new()),
+    ]);
+
+    utils::perform_translations(&mut translations, &[], &options, None, "de", |_, _| {})
+        .await
+        .unwrap();
+
+    assert_eq!(translations["Qumo"], "Qumo");
+    assert_eq!(translations["{count}"], "{count}");
+    assert_eq!(translations["Open Qumo"], "OPEN Qumo");
+    assert_eq!(*provider.batches.lock().unwrap(), [["Open __QT0__"]]);
+}
+
+/// Runs gather/apply against `target`, translating every missing phrase with `translate`.
+fn reconstruct(
+    source: &Value,
+    target: &mut Map<String, Value>,
+    translate: impl Fn(&str) -> String,
+) {
+    let mut translations = HashMap::new();
+    utils::gather_translations(
+        source,
+        target,
+        &mut translations,
+        false,
+        &KeyFilter::default(),
+    )
+    .unwrap();
+
+    for (phrase, translated) in translations.iter_mut() {
+        if translated.is_empty() {
+            *translated = translate(phrase);
+        }
+    }
+
+    utils::apply_translations(source, target, &translations, false, &KeyFilter::default());
+}
+
+#[test]
+fn fresh_target_mirrors_source_structure() {
+    let source = json!({
+        "title": "Welcome",
+        "nested": {
+            "level2": {
+                "level3": {"deep": "Deep", "count": 1},
+                "after": "After"
+            },
+            "flag": false
+        },
+        "tags": ["new", "sale"],
+        "empty": {},
+        "nothing": null,
+        "last": "Last"
+    });
+
+    let mut target = Map::new();
+    reconstruct(&source, &mut target, |phrase| format!("[{phrase}]"));
+
+    assert_eq!(
+        serde_json::to_string(&target).unwrap(),
+        serde_json::to_string(&json!({
+            "title": "[Welcome]",
+            "nested": {
+                "level2": {
+                    "level3": {"deep": "[Deep]", "count": 1},
+                    "after": "[After]"
+                },
+                "flag": false
+            },
+            "tags": ["[new]", "[sale]"],
+            "empty": {},
+            "nothing": null,
+            "last": "[Last]"
+        }))
+        .unwrap()
+    );
+}
+
+#[test]
+fn translates_strings_inside_arrays() {
+    let source = json!({
+        "steps": ["Open", {"label": "Save", "order": 2}, ["Nested", 3, null]],
+        "ids": [1, 2]
+    });
+
+    let mut target = Map::new();
+    reconstruct(&source, &mut target, |phrase| format!("[{phrase}]"));
+
+    assert_eq!(
+        Value::Object(target),
+        json!({
+            "steps": ["[Open]", {"label": "[Save]", "order": 2}, ["[Nested]", 3, null]],
+            "ids": [1, 2]
+        })
+    );
+}
+
+#[test]
+fn existing_array_elements_are_kept() {
+    let source = json!({"steps": ["Open", "Save", "Close"]});
+    let mut target = json!({"steps": ["Offnen"]}).as_object().unwrap().clone();
+
+    let mut translations = HashMap::new();
+    utils::gather_translations(
+        &source,
+        &target,
+        &mut translations,
+        false,
+        &KeyFilter::default(),
+    )
+    .unwrap();
+    assert_eq!(translations["Open"], "Offnen");
+    assert_eq!(translations["Save"], "");
+
+    for (phrase, translated) in translations.iter_mut() {
+        if translated.is_empty() {
+            *translated = phrase.to_uppercase();
+        }
+    }
+    utils::apply_translations(
+        &source,
+        &mut target,
+        &translations,
+        false,
+        &KeyFilter::default(),
+    );
+
+    assert_eq!(target["steps"], json!(["Offnen", "SAVE", "CLOSE"]));
+}
+
+#[test]
+fn overwrite_replaces_existing_target_values() {
+    let source =
+        json!({"title": "Welcome", "steps": ["Open"], "limit": 10, "menu": {"file": "File"}});
+    let mut target = json!({"menu": {"file": "Datei", "extra": "Extra"}, "title": "Hallo", "steps": ["Offnen"], "limit": 5})
+        .as_object()
+        .unwrap()
+        .clone();
+
+    let mut translations = HashMap::new();
+    utils::gather_translations(
+        &source,
+        &target,
+        &mut translations,
+        true,
+        &KeyFilter::default(),
+    )
+    .unwrap();
+    assert!(translations.values().all(String::is_empty));
+
+    for (phrase, translated) in translations.iter_mut() {
+        *translated = phrase.to_uppercase();
+    }
+    utils::apply_translations(
+        &source,
+        &mut target,
+        &translations,
+        true,
+        &KeyFilter::default(),
+    );
+
+    assert_eq!(
+        serde_json::to_string(&target).unwrap(),
+        serde_json::to_string(&json!({
+            "title": "WELCOME",
+            "steps": ["OPEN"],
+            "limit": 10,
+            "menu": {"file": "FILE", "extra": "Extra"}
+        }))
+        .unwrap()
+    );
+}
+
+/// Lists the key paths of every object in `value` in serialization order.
+fn key_sequence(value: &Value, path: &str, keys: &mut Vec<String>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                let path = format!("{path}/{key}");
+                keys.push(path.clone());
+                key_sequence(value, &path, keys);
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                key_sequence(item, &format!("{path}/{i}"), keys);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns the key paths of every object in `value`, see [`key_sequence`].
+fn key_sequence_of(value: &Value) -> Vec<String> {
+    let mut keys = vec![];
+    key_sequence(value, "", &mut keys);
+    keys
+}
+
+#[test]
+fn existing_target_is_reordered_like_source() {
+    let source = json!({
+        "title": "Welcome",
+        "menu": {
+            "file": "File",
+            "edit": {"copy": "Copy", "paste": "Paste"},
+            "view": "View"
+        },
+        "steps": [{"label": "Open", "hint": "Click"}],
+        "count": 3
+    });
+    let mut target = json!({
+        "count": 3,
+        "stale": "Alt",
+        "steps": [{"hint": "Klicken", "label": "Offnen"}],
+        "menu": {
+            "view": "Ansicht",
+            "edit": {"paste": "Einfugen", "copy": "Kopieren"}
+        },
+        "title": "Willkommen"
+    })
+    .as_object()
+    .unwrap()
+    .clone();
+
+    reconstruct(&source, &mut target, |phrase| format!("[{phrase}]"));
+
+    assert_eq!(
+        key_sequence_of(&Value::Object(target.clone())),
+        [
+            "/title",
+            "/menu",
+            "/menu/file",
+            "/menu/edit",
+            "/menu/edit/copy",
+            "/menu/edit/paste",
+            "/menu/view",
+            "/steps",
+            "/steps/0/label",
+            "/steps/0/hint",
+            "/count",
+            "/stale"
+        ]
+    );
+    assert_eq!(target["menu"]["file"], "[File]");
+    assert_eq!(target["menu"]["view"], "Ansicht");
+
+    utils::prune_stale_keys(&source, &mut target, &IgnoreRules::default());
+    assert_eq!(
+        key_sequence_of(&Value::Object(target)),
+        key_sequence_of(&source)
+    );
+}
+
+#[test]
+fn sort_keys_orders_every_level_alphabetically() {
+    let target = json!({
+        "title": "Willkommen",
+        "menu": {"save": "Speichern", "open": "Öffnen"},
+        "steps": [{"two": "Zwei", "one": "Eins"}],
+        "about": "Über",
+    });
+
+    let sorted = Value::Object(layout::sort_keys(target.as_object().unwrap().clone()));
+
+    assert_eq!(sorted, target);
+    assert_eq!(
+        key_sequence_of(&sorted),
+        [
+            "/about",
+            "/menu",
+            "/menu/open",
+            "/menu/save",
+            "/steps",
+            "/steps/0/one",
+            "/steps/0/two",
+            "/title"
+        ]
+    );
+}
+
+#[test]
+fn filtered_and_overwritten_keys_keep_source_order() {
+    let source = json!({"a": "A", "b": {"c": "C", "d": "D"}, "e": "E"});
+    let existing = json!({"e": "e", "b": {"d": "d", "c": "c"}, "a": "a"});
+
+    for (overwrite, filter) in [
+        (false, KeyFilter::default()),
+        (true, KeyFilter::default()),
+        (true, KeyFilter::new(&["b.*"], &["b.d"])),
+    ] {
+        let mut target = existing.as_object().unwrap().clone();
+        let translations = HashMap::from(
+            ["A", "C", "D", "E"].map(|phrase| (phrase.to_string(), phrase.to_lowercase())),
+        );
+
+        utils::apply_translations(&source, &mut target, &translations, overwrite, &filter);
+
+        assert_eq!(
+            key_sequence_of(&Value::Object(target)),
+            key_sequence_of(&source)
+        );
+    }
+}
+
+#[test]
+fn lists_missing_key_paths_without_translating() {
+    let source = json!({
+        "title": "Welcome",
+        "menu": {"file": "File", "edit": "Edit", "count": 2},
+        "steps": ["Open", {"label": "Save"}],
+        "blank": "",
+        "legal": {"terms": "Terms"}
+    });
+    let target = json!({
+        "title": "Willkommen",
+        "menu": {"file": "", "count": 2},
+        "steps": ["Offnen", {"label": 3}]
+    })
+    .as_object()
+    .unwrap()
+    .clone();
+
+    assert_eq!(
+        utils::missing_key_paths(&source, &target, &KeyFilter::default()),
+        ["menu.file", "menu.edit", "steps.1.label", "legal.terms"]
+    );
+    assert_eq!(
+        utils::missing_key_paths(
+            &source,
+            &target,
+            &KeyFilter::new(&["menu.*"], &["menu.edit"])
+        ),
+        ["menu.file"]
+    );
+}
+
+#[test]
+fn changed_source_strings_are_translated_again() {
+    let original = json!({
+        "title": "Welcome",
+        "menu": {"file": "File", "edit": "Edit"},
+        "steps": ["Open", "Save"]
+    });
+    let mut hashes = SourceHashes::default();
+    hashes.record(&original, "de", &KeyFilter::default());
+
+    let path = temp_dir("hashes").join("hashes.json");
+    hashes.save(&path).unwrap();
+    let hashes = SourceHashes::load(&path).unwrap();
+
+    let source = json!({
+        "title": "Welcome back",
+        "menu": {"file": "File", "edit": "Edit", "view": "View"},
+        "steps": ["Open", "Store"]
+    });
+    assert!(hashes.is_outdated("de", "title", "Welcome back"));
+    assert!(!hashes.is_outdated("de", "menu.view", "View"));
+    assert!(!hashes.is_outdated("pl", "title", "Welcome back"));
+
+    let mut target = json!({
+        "title": "Willkommen",
+        "menu": {"file": "Datei", "edit": "Bearbeiten"},
+        "steps": ["Offnen", "Speichern"]
+    })
+    .as_object()
+    .unwrap()
+    .clone();
+
+    assert_eq!(
+        hashes.remove_outdated(&source, &mut target, "de", &IgnoreRules::default()),
+        ["title", "steps"]
+    );
+    reconstruct(&source, &mut target, |phrase| format!("[{phrase}]"));
+    assert_eq!(
+        Value::Object(target),
+        json!({
+            "title": "[Welcome back]",
+            "menu": {"file": "Datei", "edit": "Bearbeiten", "view": "[View]"},
+            "steps": ["[Open]", "[Store]"]
+        })
+    );
+}
+
+#[test]
+fn prunes_stale_keys_recursively() {
+    let source = json!({
+        "title": "Welcome",
+        "menu": {"file": "File"},
+        "steps": [{"label": "Open"}]
+    });
+    let mut target = json!({
+        "title": "Willkommen",
+        "old": "Alt",
+        "menu": {"file": "Datei", "legacy": {"a": "A"}},
+        "steps": [{"label": "Offnen", "hint": "Tipp"}]
+    })
+    .as_object()
+    .unwrap()
+    .clone();
+
+    let pruned = utils::prune_stale_keys(&source, &mut target, &IgnoreRules::default());
+
+    assert_eq!(pruned, ["old", "menu.legacy", "steps.0.hint"]);
+    assert_eq!(
+        Value::Object(target),
+        json!({
+            "title": "Willkommen",
+            "menu": {"file": "Datei"},
+            "steps": [{"label": "Offnen"}]
+        })
+    );
+}
+
+#[tokio::test]
+async fn translate_file_runs_the_whole_pipeline() {
+    let dir = temp_dir("translate-file");
+    let source_path = dir.join("en.json");
+    let target_path = dir.join("de.yaml");
+    fs::write(
+        &source_path,
+        r#"{"title": "Welcome", "menu": {"file": "File"}}"#,
+    )
+    .unwrap();
+    fs::write(&target_path, "title: Willkommen\n").unwrap();
+
+    let provider = StubProvider::default();
+    let placeholders = Placeholders::default();
+    let options = TranslationOptions {
+        placeholders: &placeholders,
+        ..options(&provider)
+    };
+
+    let summary = translate_file(&source_path, &target_path, "de", &options)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        (summary.translated, summary.skipped, summary.failed),
+        (1, 1, 0)
+    );
+    assert_eq!((summary.keys, summary.unique), (2, 2));
+    assert_eq!(
+        Format::Yaml
+            .parse(&fs::read_to_string(&target_path).unwrap())
+            .unwrap(),
+        json!({"title": "Willkommen", "menu": {"file": "FILE"}})
+    );
+}
+
+#[tokio::test]
+async fn libre_provider_posts_batch_to_configured_endpoint() {
+    let (url, request) = serve_once(json!({"translatedText": ["Hallo", "Welt"]})).await;
+    let provider = LibreProvider::new(url, None);
+
+    let translated = provider
+        .translate_batch(&["Hello".to_string(), "World".to_string()], "en", "de")
+        .await
+        .unwrap();
+
+    assert_eq!(translated, ["Hallo", "Welt"]);
+    assert_eq!(
+        request.await.unwrap(),
+        json!({"q": ["Hello", "World"], "source": "en", "target": "de", "format": "text"})
+    );
+}
+
+#[tokio::test]
+async fn azure_provider_sends_text_objects_and_reads_translations() {
+    let (url, request) = serve_once(json!([
+        {"translations": [{"text": "Hallo", "to": "de"}]},
+        {"translations": [{"text": "Welt", "to": "de"}]}
+    ]))
+    .await;
+    let provider = AzureProvider::new("key", Some("westeurope".to_string())).with_endpoint(url);
+
+    let translated = provider
+        .translate_batch(&["Hello".to_string(), "World".to_string()], "en", "de")
+        .await
+        .unwrap();
+
+    assert_eq!(translated, ["Hallo", "Welt"]);
+    assert_eq!(
+        request.await.unwrap(),
+        json!([{"Text": "Hello"}, {"Text": "World"}])
+    );
+}
+
+#[tokio::test]
+async fn google_provider_sends_requests_to_custom_endpoint() {
+    let (url, request) = serve_once(json!({"data": {"translations": [
+        {"translatedText": "Hallo"},
+        {"translatedText": "Welt"}
+    ]}}))
+    .await;
+    let provider = GoogleProvider::new("key").with_endpoint(url);
+
+    let translated = provider
+        .translate_batch(&["Hello".to_string(), "World".to_string()], "en", "de")
+        .await
+        .unwrap();
+
+    assert_eq!(translated, ["Hallo", "Welt"]);
+    assert_eq!(
+        request.await.unwrap(),
+        json!({
+            "key": ["key"],
+            "target": ["de"],
+            "format": ["text"],
+            "source": ["en"],
+            "q": ["Hello", "World"]
+        })
+    );
+}
+
+#[tokio::test]
+async fn openai_provider_sends_phrases_as_json_array() {
+    let (url, request) = serve_once(json!({"choices": [{"message": {
+        "role": "assistant",
+        "content": "```json\n[\"Hallo {name}\", \"Welt\"]\n```"
+    }}]}))
+    .await;
+    let provider = OpenAiProvider::new("key")
+        .with_endpoint(url)
+        .with_model("test-model")
+        .with_prompt("Translate from {source_lang} to {target_lang}.");
+
+    let translated = provider
+        .translate_batch(
+            &["Hello {name}".to_string(), "World".to_string()],
+            "en",
+            "de",
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(translated, ["Hallo {name}", "Welt"]);
+    let request = request.await.unwrap();
+    assert_eq!(request["model"], "test-model");
+    assert_eq!(
+        request["messages"],
+        json!([
+            {"role": "system", "content": "Translate from en to de."},
+            {"role": "user", "content": "[\"Hello {name}\",\"World\"]"}
+        ])
+    );
+}
+
+#[tokio::test]
+async fn openai_answer_without_json_array_fails_the_batch() {
+    let (url, _request) = serve_once(json!({"choices": [{"message": {
+        "role": "assistant",
+        "content": "Hallo"
+    }}]}))
+    .await;
+    let provider = OpenAiProvider::new("key").with_endpoint(url);
+
+    let err = provider
+        .translate_batch(&["Hello".to_string()], "en", "de")
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, TranslateError::InvalidResponse(_)));
+    assert!(!err.is_retryable());
+}
+
+#[tokio::test]
+async fn context_hints_are_added_to_the_openai_prompt() {
+    let (url, request) = serve_once(json!({"choices": [{"message": {
+        "role": "assistant",
+        "content": "[\"Veröffentlichen\"]"
+    }}]}))
+    .await;
+    let provider = OpenAiProvider::new("key")
+        .with_endpoint(url)
+        .with_prompt("Translate.");
+    let source = json!({"feed": {"post": "Post"}});
+    let key_notes = HashMap::from([("feed.post".to_string(), "verb, publishes".to_string())]);
+    let context = TranslationContext::new(Some("mobile banking app UI".to_string()))
+        .with_key_notes(&source, &key_notes);
+    let options = TranslationOptions {
+        on_error: OnError::Fail,
+        context: Some(&context),
+        ..options(&provider)
+    };
+    let mut translations = HashMap::from([("Post".to_string(), String::new())]);
+
+    utils::perform_translations(&mut translations, &[], &options, None, "de", |_, _| {})
+        .await
+        .unwrap();
+
+    assert_eq!(translations["Post"], "Veröffentlichen");
+    assert_eq!(
+        request.await.unwrap()["messages"][0]["content"],
+        "Translate. The strings are used in: mobile banking app UI.\n\n\
+         Notes on single strings, by their index in the array:\n0: verb, publishes"
+    );
+}
+
+#[tokio::test]
+async fn context_is_ignored_by_providers_without_support() {
+    let provider = StubProvider::default();
+    let context = TranslationContext::new(Some("mobile banking app UI".to_string()));
+    let options = TranslationOptions {
+        on_error: OnError::Fail,
+        context: Some(&context),
+        ..options(&provider)
+    };
+    let mut translations = HashMap::from([("Post".to_string(), String::new())]);
+
+    utils::perform_translations(&mut translations, &[], &options, None, "de", |_, _| {})
+        .await
+        .unwrap();
+
+    assert_eq!(translations["Post"], "POST");
+    assert_eq!(*provider.batches.lock().unwrap(), [["Post"]]);
+}
+
+#[tokio::test]
+async fn request_timeout_is_a_retryable_error() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/translate", listener.local_addr().unwrap());
+    // Accept the connection but never answer.
+    let _server = tokio::spawn(async move {
+        let (_socket, _) = listener.accept().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(10)).await;
+    });
+
+    let timeout = Duration::from_millis(100);
+    let provider = LibreProvider::new(url, None).with_timeout(timeout);
+
+    let err = provider
+        .translate_batch(&["Hello".to_string()], "en", "de")
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, TranslateError::Timeout(t) if t == timeout));
+    assert!(err.is_retryable());
+}
+
+/// Provider that times out on its first call and uppercases afterwards.
+#[derive(Default)]
+struct FlakyProvider {
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl TranslationProvider for FlakyProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        _source_lang: &str,
+        _target_lang: &str,
+    ) -> BatchResult {
+        match self.calls.fetch_add(1, Ordering::SeqCst) {
+            0 => Err(TranslateError::Timeout(Duration::from_secs(30))),
+            _ => Ok(phrases.iter().map(|phrase| phrase.to_uppercase()).collect()),
+        }
+    }
+}
+
+#[tokio::test]
+async fn timed_out_batches_are_retried() {
+    let provider = FlakyProvider::default();
+
+    let results: Vec<_> = translate_stream(
+        &provider,
+        vec!["hello".to_string()],
+        "en".to_string(),
+        "de".to_string(),
+        10,
+        usize::MAX,
+        1,
+        None,
+    )
+    .collect()
+    .await;
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].0, "hello");
+    assert_eq!(results[0].1.as_deref().unwrap(), "HELLO");
+    assert_eq!(provider.calls.load(Ordering::SeqCst), 2);
+}
+
+/// Provider whose requests always fail with a non-retryable error.
+struct FailingProvider;
+
+#[async_trait]
+impl TranslationProvider for FailingProvider {
+    async fn translate_batch(
+        &self,
+        _phrases: &[String],
+        _source_lang: &str,
+        _target_lang: &str,
+    ) -> BatchResult {
+        let err = reqwest::get("not a url").await.unwrap_err();
+        Err(TranslateError::from_reqwest(err, Duration::from_secs(30)))
+    }
+}
+
+/// Provider that drops the last translation of every batch.
+struct ShortProvider;
+
+#[async_trait]
+impl TranslationProvider for ShortProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        _source_lang: &str,
+        _target_lang: &str,
+    ) -> BatchResult {
+        Ok(phrases[1..]
+            .iter()
+            .map(|phrase| phrase.to_uppercase())
+            .collect())
+    }
+}
+
+#[tokio::test]
+async fn short_batch_responses_fail_instead_of_misaligning() {
+    let results: Vec<_> = translate_stream(
+        &ShortProvider,
+        vec!["hello".to_string(), "world".to_string()],
+        "en".to_string(),
+        "de".to_string(),
+        10,
+        usize::MAX,
+        1,
+        None,
+    )
+    .collect()
+    .await;
+
+    assert_eq!(results.len(), 2);
+    for (_, result) in results {
+        assert!(matches!(
+            result,
+            Err(TranslateError::CountMismatch {
+                expected: 2,
+                actual: 1
+            })
+        ));
+    }
+}
+
+#[tokio::test]
+async fn failed_batches_are_reported_per_phrase() {
+    let results: Vec<_> = translate_stream(
+        &FailingProvider,
+        vec!["hello".to_string(), "world".to_string()],
+        "en".to_string(),
+        "de".to_string(),
+        10,
+        usize::MAX,
+        1,
+        None,
+    )
+    .collect()
+    .await;
+
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|(_, result)| result.is_err()));
+
+    let cache = TranslationCache::default();
+    let failed = failing_translations(OnError::KeepSource, Some(&cache))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        failed,
+        HashMap::from([("hello".to_string(), "hello".to_string())])
+    );
+    assert_eq!(cache.get("hello", "de"), None);
+}
+
+#[tokio::test]
+async fn on_error_policy_decides_about_failed_phrases() {
+    let skipped = failing_translations(OnError::Skip, None).await.unwrap();
+    assert!(skipped.is_empty());
+
+    assert!(failing_translations(OnError::Fail, None).await.is_err());
+
+    let source = json!({"title": "hello", "steps": ["hello"]});
+    let mut target = Map::new();
+    utils::apply_translations(&source, &mut target, &skipped, false, &KeyFilter::default());
+    assert_eq!(Value::Object(target), json!({"steps": ["hello"]}));
+}
+
+/// Translates the single phrase `hello` with [`FailingProvider`] and returns
+/// the resulting translations.
+async fn failing_translations(
+    on_error: OnError,
+    cache: Option<&TranslationCache>,
+) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+    let mut translations = HashMap::from([("hello".to_string(), String::new())]);
+    let options = TranslationOptions {
+        on_error,
+        ..options(&FailingProvider)
+    };
+
+    let stats =
+        utils::perform_translations(&mut translations, &[], &options, cache, "de", |_, _| {})
+            .await?;
+    assert_eq!(stats.failed, ["hello"]);
+
+    Ok(translations)
+}
+
+#[tokio::test]
+async fn batches_reuse_pooled_connections() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/translate", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    let accepted = connections.clone();
+    let _server = tokio::spawn(async move {
+        // LibreTranslate-like server uppercasing every phrase it receives.
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            accepted.fetch_add(1, Ordering::SeqCst);
+
+            tokio::spawn(async move {
+                let mut socket = BufReader::new(socket);
+                while let Some(request) = read_request(&mut socket).await {
+                    let translated: Vec<String> = request["q"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|phrase| phrase.as_str().unwrap().to_uppercase())
+                        .collect();
+                    write_response(&mut socket, &json!({"translatedText": translated})).await;
+                }
+            });
+        }
+    });
+
+    let provider = LibreProvider::new(url, None);
+    let phrases: Vec<String> = (0..12).map(|i| format!("phrase {i}")).collect();
+
+    let results: Vec<_> = translate_stream(
+        &provider,
+        phrases,
+        "en".to_string(),
+        "de".to_string(),
+        2,
+        usize::MAX,
+        2,
+        None,
+    )
+    .collect()
+    .await;
+
+    assert_eq!(results.len(), 12);
+    assert!(results.iter().all(|(_, result)| result.is_ok()));
+    assert!(connections.load(Ordering::SeqCst) <= 2);
+}
+
+#[test]
+fn language_codes_are_compared_without_case_and_region() {
+    assert!(is_same_language("en", "en"));
+    assert!(is_same_language("en-US", "EN"));
+    assert!(is_same_language("pt_BR", "pt-PT"));
+    assert!(!is_same_language("en", "de"));
+    assert!(!is_same_language("zh", "zu"));
+}
+
+#[test]
+fn language_codes_are_normalized_or_rejected_with_suggestions() {
+    assert_eq!(languages::normalize("de").unwrap(), "de");
+    assert_eq!(languages::normalize("en_US").unwrap(), "en-US");
+    assert_eq!(languages::normalize("ES-419").unwrap(), "es-419");
+    assert_eq!(languages::normalize("zh_hant").unwrap(), "zh-Hant");
+    // Region-specific locale files such as `pt_br.json` keep their name,
+    // while the provider receives the canonical code.
+    assert_eq!(languages::normalize("pt_br").unwrap(), "pt-BR");
+    assert_eq!(languages::normalize("pt-BR").unwrap(), "pt-BR");
+
+    let err = languages::normalize("german").unwrap_err();
+    assert_eq!(err.suggestions, ["de (German)"]);
+    assert_eq!(
+        err.to_string(),
+        "unknown language code \"german\", did you mean de (German)?"
+    );
+    assert!(
+        languages::normalize("en-USA")
+            .unwrap_err()
+            .suggestions
+            .contains(&"en (English)".to_string())
+    );
+    assert!(
+        languages::normalize("xyz")
+            .unwrap_err()
+            .suggestions
+            .is_empty()
+    );
+}
+
+#[test]
+fn google_api_key_is_read_at_runtime() {
+    let _env = GOOGLE_ENV.blocking_lock();
+
+    // SAFETY: the other tests using this variable hold `GOOGLE_ENV` as well.
+    unsafe { std::env::remove_var("GOOGLE_TRANSLATE_API_KEY") };
+    assert!(matches!(
+        Provider::Google.create(&ProviderSettings::default()),
+        Err(QTranslateError::MissingApiKey(var)) if var == "GOOGLE_TRANSLATE_API_KEY"
+    ));
+
+    // SAFETY: see above.
+    unsafe { std::env::set_var("GOOGLE_TRANSLATE_API_KEY", "runtime-key") };
+    assert!(
+        Provider::Google
+            .create(&ProviderSettings::default())
+            .is_ok()
+    );
+}
+
+#[tokio::test]
+async fn request_errors_are_reported_by_kind() {
+    let phrases = ["Hello".to_string()];
+
+    let server = serve_google(1, Duration::ZERO).await;
+    let provider = GoogleProvider::new("key").with_endpoint(&server.url);
+    let err = provider
+        .translate_batch(&phrases, "en", "de")
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, TranslateError::Api { status: 429, body, .. } if body.contains("Rate limit"))
+    );
+    assert_eq!(err.status_code(), Some(429));
+    assert!(err.is_retryable());
+
+    let (url, _request) = serve_once(json!({"unexpected": []})).await;
+    let provider = GoogleProvider::new("key").with_endpoint(url);
+    let err = provider
+        .translate_batch(&phrases, "en", "de")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, TranslateError::Parse(_)));
+    assert!(!err.is_retryable());
+
+    let _env = GOOGLE_ENV.lock().await;
+    // SAFETY: the other tests using this variable hold `GOOGLE_ENV` as well.
+    unsafe { std::env::remove_var("GOOGLE_TRANSLATE_API_KEY") };
+    assert!(matches!(
+        translate_phrases(&phrases, "en", "de").await,
+        Err(TranslateError::MissingApiKey(var)) if var == "GOOGLE_TRANSLATE_API_KEY"
+    ));
+}
+
+#[tokio::test]
+async fn google_error_messages_are_surfaced() {
+    let error = json!({"error": {
+        "code": 400,
+        "message": "API key not valid. Please pass a valid API key.",
+        "status": "INVALID_ARGUMENT",
+    }});
+    let (url, _request) = serve_once_with_status("400 Bad Request", error).await;
+    let provider = GoogleProvider::new("bad-key").with_endpoint(url);
+
+    let err = provider
+        .translate_batch(&["Hello".to_string()], "en", "de")
+        .await
+        .unwrap_err();
+
+    assert_eq!(
+        err.to_string(),
+        "Provider responded with status 400: \
+         API key not valid. Please pass a valid API key. (INVALID_ARGUMENT)"
+    );
+}
+
+#[tokio::test]
+async fn translate_phrases_sends_query_parameters_to_base_url() {
+    let _env = GOOGLE_ENV.lock().await;
+    let server = serve_google(0, Duration::ZERO).await;
+
+    // SAFETY: the other tests using these variables hold `GOOGLE_ENV` as well.
+    unsafe {
+        std::env::set_var("GOOGLE_TRANSLATE_API_KEY", "test-key");
+        std::env::set_var("GOOGLE_TRANSLATE_BASE_URL", &server.url);
+    }
+    let phrases = vec!["Hello".to_string(), "World".to_string()];
+    let translated = translate_phrases(&phrases, "auto", "de").await;
+    // SAFETY: see above.
+    unsafe { std::env::remove_var("GOOGLE_TRANSLATE_BASE_URL") };
+
+    assert_eq!(
+        translated.unwrap(),
+        [
+            ("Hello".to_string(), "HELLO".to_string()),
+            ("World".to_string(), "WORLD".to_string())
+        ]
+    );
+    // All phrases go out in a single request; `source` is left out so that
+    // Google detects the language.
+    assert_eq!(
+        *server.requests.lock().unwrap(),
+        [json!({
+            "key": ["test-key"],
+            "target": ["de"],
+            "format": ["text"],
+            "q": ["Hello", "World"]
+        })]
+    );
+}
+
+#[tokio::test]
+async fn base_url_is_read_from_a_dotenv_file_along_with_an_api_key_flag() {
+    let server = serve_google(0, Duration::ZERO).await;
+    let dir = temp_dir("cli-dotenv-base-url");
+    let dotenv = format!("GOOGLE_TRANSLATE_BASE_URL={}\n", server.url);
+    fs::write(dir.join(".env"), dotenv).unwrap();
+    fs::write(dir.join("en.json"), r#"{"greeting": "Hello"}"#).unwrap();
+
+    // Without `--endpoint`, unlike `run_cli`.
+    let output = Command::new(env!("CARGO_BIN_EXE_q-translate"))
+        .current_dir(&dir)
+        .args(["--api-key", "test-key", "--source-lang", "en"])
+        .args(["--target-lang", "de", "--assets-dir", "."])
+        .args(["--no-cache", "--no-hashes"])
+        .env_remove("GOOGLE_TRANSLATE_BASE_URL")
+        .env_remove("GOOGLE_TRANSLATE_API_KEY")
+        .output()
+        .await
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert_eq!(server.requests.lock().unwrap().len(), 1);
+    let written = fs::read_to_string(dir.join("de.json")).unwrap();
+    assert_eq!(
+        serde_json::from_str::<Value>(&written).unwrap(),
+        json!({"greeting": "HELLO"})
+    );
+}
+
+#[tokio::test]
+async fn single_phrase_and_batch_entry_points_agree() {
+    let _env = GOOGLE_ENV.lock().await;
+    let server = serve_google(0, Duration::ZERO).await;
+
+    // SAFETY: the other tests using these variables hold `GOOGLE_ENV` as well.
+    unsafe {
+        std::env::set_var("GOOGLE_TRANSLATE_API_KEY", "test-key");
+        std::env::set_var("GOOGLE_TRANSLATE_BASE_URL", &server.url);
+    }
+    let single = translate_phrase("Hello", "en", "de").await;
+    let batch = translate_phrases(&["Hello".to_string()], "en", "de").await;
+    // SAFETY: see above.
+    unsafe {
+        std::env::remove_var("GOOGLE_TRANSLATE_API_KEY");
+        std::env::remove_var("GOOGLE_TRANSLATE_BASE_URL");
+    }
+    let missing_key = translate_phrase("Hello", "en", "de").await;
+
+    assert_eq!(single.unwrap(), "HELLO");
+    assert_eq!(batch.unwrap(), [("Hello".to_string(), "HELLO".to_string())]);
+    let requests = server.requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0], requests[1]);
+    assert!(matches!(
+        missing_key,
+        Err(TranslateError::MissingApiKey(var)) if var == "GOOGLE_TRANSLATE_API_KEY"
+    ));
+}
+
+#[tokio::test]
+async fn google_batches_respect_batch_size_and_concurrency() {
+    let server = serve_google(0, Duration::from_millis(50)).await;
+    let provider = GoogleProvider::new("test-key").with_endpoint(&server.url);
+    let phrases: Vec<String> = (0..9).map(|i| format!("phrase {i}")).collect();
+
+    let results: Vec<_> = translate_stream(
+        &provider,
+        phrases,
+        "en".to_string(),
+        "pl".to_string(),
+        2,
+        usize::MAX,
+        2,
+        None,
+    )
+    .collect()
+    .await;
+
+    let results: HashMap<String, String> = results
+        .into_iter()
+        .map(|(phrase, result)| (phrase, result.unwrap()))
+        .collect();
+    assert_eq!(results.len(), 9);
+    assert_eq!(results["phrase 8"], "PHRASE 8");
+
+    let requests = server.requests.lock().unwrap();
+    let mut sizes: Vec<usize> = requests
+        .iter()
+        .map(|request| request["q"].as_array().unwrap().len())
+        .collect();
+    sizes.sort();
+    assert_eq!(sizes, [1, 2, 2, 2, 2]);
+    assert!(requests.iter().all(|request| {
+        request["key"] == json!(["test-key"])
+            && request["target"] == json!(["pl"])
+            && request["source"] == json!(["en"])
+    }));
+    assert_eq!(server.max_in_flight.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn google_rate_limited_batch_is_retried() {
+    let server = serve_google(1, Duration::ZERO).await;
+    let provider = GoogleProvider::new("test-key").with_endpoint(&server.url);
+
+    let results: Vec<_> = translate_stream(
+        &provider,
+        vec!["Hello".to_string()],
+        "en".to_string(),
+        "de".to_string(),
+        10,
+        usize::MAX,
+        1,
+        None,
+    )
+    .collect()
+    .await;
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].1.as_deref().unwrap(), "HELLO");
+    let requests = server.requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0], requests[1]);
+}
+
+#[test]
+fn final_newline_is_added_or_removed() {
+    let compact = Format::Json
+        .serialize_with(&json!({"a": "b"}), JsonStyle::Compact)
+        .unwrap();
+
+    assert_eq!(
+        files::with_final_newline(compact.clone(), true),
+        "{\"a\":\"b\"}\n"
+    );
+    assert_eq!(
+        files::with_final_newline(compact + "\n\n", false),
+        "{\"a\":\"b\"}"
+    );
+    assert_eq!(
+        files::with_final_newline("key=value\n".to_string(), true),
+        "key=value\n"
+    );
+    assert_eq!(files::with_final_newline(String::new(), true), "");
+}
+
+#[test]
+fn failed_write_leaves_the_previous_file_intact() {
+    let dir = temp_dir("write-failure");
+    let target = dir.join("de.json");
+    fs::write(&target, "{\"title\": \"Hallo\"}").unwrap();
+
+    files::write_atomic(&target, "{\"title\": \"Willkommen\"}").unwrap();
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        "{\"title\": \"Willkommen\"}"
+    );
+
+    // A directory in place of the temporary file makes the write fail.
+    fs::create_dir(dir.join(".de.json.tmp")).unwrap();
+    assert!(files::write_atomic(&target, "{\"tit").is_err());
+
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        "{\"title\": \"Willkommen\"}"
+    );
+}
+
+#[test]
+fn backups_are_written_next_to_the_target_or_into_a_directory() {
+    let dir = temp_dir("backup");
+    let target = dir.join("de.json");
+
+    assert_eq!(files::backup(&target, None).unwrap(), None);
+
+    fs::write(&target, "{\"title\": \"Hallo\"}").unwrap();
+    let sibling = files::backup(&target, None).unwrap().unwrap();
+    let backup_dir = dir.join("backups");
+    let in_dir = files::backup(&target, Some(&backup_dir)).unwrap().unwrap();
+
+    assert_eq!(sibling, dir.join("de.json.bak"));
+    assert_eq!(in_dir, backup_dir.join("de.json.bak"));
+    for backup in [sibling, in_dir] {
+        assert_eq!(
+            fs::read_to_string(backup).unwrap(),
+            "{\"title\": \"Hallo\"}"
+        );
+    }
+}
+
+#[test]
+fn loads_config_file() {
+    let path = temp_dir("config").join("q-translate.toml");
+    fs::write(
+        &path,
+        "provider = \"deepl\"\nconcurrency = 8\ntarget_langs = [\"de\", \"pl\"]\n",
+    )
+    .unwrap();
+
+    let config = Config::load(&path).unwrap();
+
+    assert_eq!(config.provider, Some(Provider::DeepL));
+    assert_eq!(config.concurrency.map(|c| c.get()), Some(8));
+    assert_eq!(
+        config.target_langs,
+        Some(vec!["de".to_string(), "pl".to_string()])
+    );
+    assert_eq!(config.assets_dir, None);
+
+    fs::write(&path, "concurrency = 0\n").unwrap();
+    assert!(matches!(
+        Config::load(&path),
+        Err(QTranslateError::InvalidConfig { .. })
+    ));
+}
+
+#[tokio::test]
+async fn html_markup_survives_translation() {
+    let provider = StubProvider::default();
+    let patterns: Vec<&str> = [HTML_TAG_PATTERN]
+        .into_iter()
+        .chain(DEFAULT_PATTERNS)
+        .collect();
+    let placeholders = Placeholders::new(&patterns).unwrap();
+    let options = TranslationOptions {
+        placeholders: &placeholders,
+        ..options(&provider)
+    };
+    let mut translations = HashMap::from([
+        (
+            r#"Click <a href="/x" title="go"><b>here</b></a> to continue"#.to_string(),
+            String::new(),
+        ),
+        (
+            "Line one<br/>line two<img src=\"{url}\" />".to_string(),
+            String::new(),
+        ),
+        ("<hr>".to_string(), String::new()),
+    ]);
+
+    utils::perform_translations(&mut translations, &[], &options, None, "de", |_, _| {})
+        .await
+        .unwrap();
+
+    assert_eq!(
+        translations[r#"Click <a href="/x" title="go"><b>here</b></a> to continue"#],
+        r#"CLICK <a href="/x" title="go"><b>HERE</b></a> TO CONTINUE"#
+    );
+    assert_eq!(
+        translations["Line one<br/>line two<img src=\"{url}\" />"],
+        "LINE ONE<br/>LINE TWO<img src=\"{url}\" />"
+    );
+    assert_eq!(translations["<hr>"], "<hr>");
+}
+
+#[tokio::test]
+async fn libre_provider_sends_html_format() {
+    let (url, request) = serve_once(json!({"translatedText": ["<b>Hallo</b>"]})).await;
+    let provider = LibreProvider::new(url, None).with_text_format(TextFormat::Html);
+
+    let translated = provider
+        .translate_batch(&["<b>Hello</b>".to_string()], "en", "de")
+        .await
+        .unwrap();
+
+    assert_eq!(translated, ["<b>Hallo</b>"]);
+    assert_eq!(request.await.unwrap()["format"], "html");
+}
+
+#[test]
+fn flattens_and_expands_keys() {
+    let nested = json!({
+        "home": {"header": {"title": "Welcome"}, "tags": ["a", "b"]},
+        "ok": "OK"
+    });
+    let flat = json!({"home/header/title": "Welcome", "home/tags": ["a", "b"], "ok": "OK"});
+
+    let flattened = layout::flatten(nested.as_object().unwrap().clone(), "/");
+    assert_eq!(
+        serde_json::to_string(&flattened).unwrap(),
+        serde_json::to_string(&flat).unwrap()
+    );
+
+    let expanded = layout::expand(flattened, "/");
+    assert_eq!(
+        serde_json::to_string(&expanded).unwrap(),
+        serde_json::to_string(&nested).unwrap()
+    );
+
+    let conflicting = json!({"a": "A", "a.b": "B"});
+    assert_eq!(
+        Value::Object(layout::expand(
+            conflicting.as_object().unwrap().clone(),
+            "."
+        )),
+        conflicting
+    );
+}
+
+#[test]
+fn source_files_are_deep_merged_with_later_values_winning() {
+    let mut common = json!({"ok": "OK", "home": {"title": "Home", "back": "Back"}})
+        .as_object()
+        .unwrap()
+        .clone();
+    let home = json!({"home": {"title": "Start", "intro": "Hi"}, "ok": "OK", "list": ["a"]});
+
+    let conflicts = layout::merge(&mut common, home.as_object().unwrap().clone());
+
+    assert_eq!(
+        Value::Object(common),
+        json!({
+            "ok": "OK",
+            "home": {"title": "Start", "back": "Back", "intro": "Hi"},
+            "list": ["a"]
+        })
+    );
+    assert_eq!(conflicts, ["home.title"]);
+
+    let mut nested = json!({"home": {"title": "Home"}})
+        .as_object()
+        .unwrap()
+        .clone();
+    let conflicts = layout::merge(
+        &mut nested,
+        json!({"home": "Home"}).as_object().unwrap().clone(),
+    );
+    assert_eq!(Value::Object(nested), json!({"home": "Home"}));
+    assert_eq!(conflicts, ["home"]);
+}
+
+#[test]
+fn diff_lists_added_changed_and_removed_keys() {
+    let before = json!({"home": {"title": "Start", "back": "Zurück"}, "old": "Alt"});
+    let after = json!({"home": {"title": "Anfang", "back": "Zurück", "intro": "Hallo"}});
+
+    let diff = TargetDiff::new(
+        before.as_object().unwrap().clone(),
+        after.as_object().unwrap().clone(),
+    );
+
+    assert_eq!(
+        serde_json::to_value(&diff).unwrap(),
+        json!({
+            "added": [{"key": "home.intro", "after": "Hallo"}],
+            "changed": [{"key": "home.title", "before": "Start", "after": "Anfang"}],
+            "removed": [{"key": "old", "before": "Alt"}]
+        })
+    );
+    assert!(!diff.is_empty());
+    assert!(TargetDiff::new(Map::new(), Map::new()).is_empty());
+}
+
+#[test]
+fn ignore_rules_match_nested_paths_and_negations() {
+    let rules = IgnoreRules::parse(
+        "# hand-crafted copy\nmarketing\n!marketing.footer.**\n**.slogan\nlegal.*.title\n",
+    );
+
+    assert!(rules.is_ignored("marketing"));
+    assert!(rules.is_ignored("marketing.hero.title"));
+    assert!(!rules.is_ignored("marketing.footer.links.about"));
+    assert!(rules.is_ignored("slogan"));
+    assert!(rules.is_ignored("home.header.slogan"));
+    assert!(rules.is_ignored("legal.terms.title"));
+    assert!(!rules.is_ignored("legal.terms.body"));
+    assert!(!rules.is_ignored("home.title"));
+
+    // The last matching pattern decides.
+    let rules = IgnoreRules::parse("!home.title\nhome\n");
+    assert!(rules.is_ignored("home.title"));
+}
+
+#[test]
+fn ignored_keys_survive_overwrite_prune_and_outdated_sources() {
+    let rules = IgnoreRules::parse("marketing\n!marketing.cta\nmenu.legacy.keep\n");
+    let filter = KeyFilter::default().with_ignore(rules.clone());
+    let source = json!({
+        "title": "Welcome",
+        "marketing": {"slogan": "Just do it", "cta": "Buy now", "new": "New"}
+    });
+    let mut target = json!({
+        "title": "Willkommen",
+        "marketing": {"slogan": "Einfach machen", "cta": "Kaufen"},
+        "menu": {"legacy": {"keep": "Behalten", "drop": "Weg"}}
+    })
+    .as_object()
+    .unwrap()
+    .clone();
+
+    let mut translations = HashMap::new();
+    utils::gather_translations(&source, &target, &mut translations, true, &filter).unwrap();
+    let mut phrases: Vec<_> = translations.keys().cloned().collect();
+    phrases.sort();
+    assert_eq!(phrases, ["Buy now", "Welcome"]);
+
+    let translations = phrases
+        .into_iter()
+        .map(|phrase| (phrase.clone(), format!("[{phrase}]")))
+        .collect();
+    utils::apply_translations(&source, &mut target, &translations, true, &filter);
+    let pruned = utils::prune_stale_keys(&source, &mut target, &rules);
+
+    assert_eq!(pruned, ["menu.legacy.drop"]);
+    assert_eq!(
+        Value::Object(target.clone()),
+        json!({
+            "title": "[Welcome]",
+            "marketing": {"slogan": "Einfach machen", "cta": "[Buy now]"},
+            "menu": {"legacy": {"keep": "Behalten"}}
+        })
+    );
+
+    let mut hashes = SourceHashes::default();
+    let old_source = json!({"marketing": {"slogan": "Just do it!"}});
+    hashes.record(&old_source, "de", &KeyFilter::default());
+    assert!(
+        hashes
+            .remove_outdated(&source, &mut target, "de", &rules)
+            .is_empty()
+    );
+    assert_eq!(target["marketing"]["slogan"], "Einfach machen");
+}
+
+#[test]
+fn shared_cache_keeps_every_entry_under_concurrent_writers() {
+    let path = temp_dir("cache-stress").join("cache.json");
+    let cache = TranslationCache::default();
+    let langs = ["de", "fr", "pl", "es"];
+
+    std::thread::scope(|scope| {
+        for writer in 0..16 {
+            let cache = &cache;
+            scope.spawn(move || {
+                for i in 0..250 {
+                    let lang = langs[(writer + i) % langs.len()];
+                    let phrase = format!("phrase {writer}-{i}");
+                    cache.insert(phrase.clone(), lang, format!("[{phrase}]"));
+                    assert_eq!(cache.get(&phrase, lang), Some(format!("[{phrase}]")));
+                }
+            });
+        }
+    });
+    cache.save(&path).unwrap();
+
+    let saved: HashMap<String, HashMap<String, String>> =
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved.values().map(HashMap::len).sum::<usize>(), 16 * 250);
+
+    let cache = TranslationCache::load(&path).unwrap();
+    for writer in 0..16 {
+        for i in 0..250 {
+            let lang = langs[(writer + i) % langs.len()];
+            let phrase = format!("phrase {writer}-{i}");
+            assert_eq!(cache.get(&phrase, lang), Some(format!("[{phrase}]")));
+        }
+    }
+}
+
+#[tokio::test]
+async fn targets_with_the_same_language_share_the_cache() {
+    let provider = StubProvider::default();
+    let source: Map<String, Value> = (0..50)
+        .map(|i| (format!("key{i}"), json!(format!("phrase {i}"))))
+        .collect();
+    let source = Value::Object(source);
+    let cache = TranslationCache::default();
+    let options = TranslationOptions {
+        concurrency: 8,
+        batch_size: Some(1),
+        ..options(&provider)
+    };
+    let mut targets: Vec<_> = ["de", "fr", "de", "pl", "fr", "de"]
+        .into_iter()
+        .map(|lang| (lang, Map::new()))
+        .collect();
+
+    let results =
+        translate_targets(&source, &mut targets, &options, Some(&cache), |_, _, _| {}).await;
+
+    assert!(results.into_iter().all(|result| result.is_ok()));
+    for (_, target) in &targets {
+        assert_eq!(target.len(), 50);
+    }
+    for lang in ["de", "fr", "pl"] {
+        for i in 0..50 {
+            assert_eq!(
+                cache.get(&format!("phrase {i}"), lang),
+                Some(format!("PHRASE {i}"))
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn api_key_setting_takes_precedence_and_is_never_shown() {
+    let _env = GOOGLE_ENV.lock().await;
+    let server = serve_google(0, Duration::ZERO).await;
+
+    // SAFETY: the other tests using this variable hold `GOOGLE_ENV` as well.
+    unsafe { std::env::set_var("GOOGLE_TRANSLATE_API_KEY", "env-key") };
+    let settings = ProviderSettings {
+        api_key: Some("flag-key".to_string()),
+        endpoint: Some(server.url.clone()),
+        ..ProviderSettings::default()
+    };
+    let provider = Provider::Google.create(&settings).unwrap();
+    provider
+        .translate_batch(&["Hello".to_string()], "en", "de")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        server.requests.lock().unwrap()[0]["key"],
+        json!(["flag-key"])
+    );
+    assert!(!format!("{settings:?}").contains("flag-key"));
+
+    // No provider needs its environment variable when the key is given.
+    let settings = ProviderSettings {
+        api_key: Some("flag-key".to_string()),
+        api_key_env: Some("Q_TRANSLATE_TEST_UNSET_KEY".to_string()),
+        ..ProviderSettings::default()
+    };
+    let providers = [
+        Provider::Google,
+        Provider::DeepL,
+        Provider::Libre,
+        Provider::Azure,
+        Provider::OpenAi,
+    ];
+    for provider in providers {
+        assert!(provider.create(&settings).is_ok());
+    }
+
+    let unreachable = GoogleProvider::new("flag-key").with_endpoint("http://127.0.0.1:1/translate");
+    let err = unreachable
+        .translate_batch(&["Hello".to_string()], "en", "de")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, TranslateError::Http(_)));
+    assert!(!err.to_string().contains("flag-key"));
+}
+
+/// Provider that loses the first placeholder of every phrase and adds `%s`.
+struct CorruptingProvider;
+
+#[async_trait]
+impl TranslationProvider for CorruptingProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        _source_lang: &str,
+        _target_lang: &str,
+    ) -> BatchResult {
+        Ok(phrases
+            .iter()
+            .map(|phrase| phrase.to_uppercase().replace("__QT0__", "%s"))
+            .collect())
+    }
+}
+
+#[tokio::test]
+async fn placeholder_mismatches_fail_the_phrase_or_the_run() {
+    let cache = TranslationCache::default();
+    let mut options = options(&CorruptingProvider);
+    let phrases = || {
+        HashMap::from([
+            ("Hello {name}".to_string(), String::new()),
+            ("Goodbye".to_string(), String::new()),
+        ])
+    };
+
+    let mut translations = phrases();
+    let stats = utils::perform_translations(
+        &mut translations,
+        &[],
+        &options,
+        Some(&cache),
+        "de",
+        |_, _| {},
+    )
+    .await
+    .unwrap();
+    assert_eq!(stats.failed, ["Hello {name}"]);
+    assert_eq!(translations["Hello {name}"], "Hello {name}");
+    assert_eq!(translations["Goodbye"], "GOODBYE");
+    assert_eq!(cache.get("Hello {name}", "de"), None);
+
+    options.strict_placeholders = true;
+    let err = utils::perform_translations(&mut phrases(), &[], &options, None, "de", |_, _| {})
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Translation of "Hello {name}" into de is missing "{name}" and has extra "%s": "HELLO %s""#
+    );
+}
+
+#[test]
+fn numeric_keys_keep_their_order_and_stay_strings() {
+    let source = Format::Json
+        .parse(
+            r#"{"500": "Server error", "404": "Not found", "200": "OK",
+                "errors": {"10": "Ten", "9": "Nine", "1": "One"}}"#,
+        )
+        .unwrap();
+    let mut target = json!({"errors": {"1": "Eins"}, "200": "OK", "404": "Nicht gefunden"})
+        .as_object()
+        .unwrap()
+        .clone();
+
+    reconstruct(&source, &mut target, |phrase| format!("[{phrase}]"));
+
+    assert_eq!(
+        key_sequence_of(&Value::Object(target.clone())),
+        key_sequence_of(&source)
+    );
+    assert_eq!(
+        Format::Json
+            .serialize_with(&Value::Object(target), JsonStyle::Compact)
+            .unwrap(),
+        r#"{"500":"[Server error]","404":"Nicht gefunden","200":"OK","errors":{"10":"[Ten]","9":"[Nine]","1":"Eins"}}"#
+    );
+
+    // Unquoted numeric YAML keys are read as strings, in file order.
+    let yaml = Format::Yaml
+        .parse("500: Server error\n404: Not found\nerrors:\n  10: Ten\n  9: Nine\n")
+        .unwrap();
+    assert_eq!(
+        key_sequence_of(&yaml),
+        key_sequence_of(&json!({"500": "", "404": "", "errors": {"10": "", "9": ""}}))
+    );
+
+    let flat = layout::flatten(source.as_object().unwrap().clone(), ".");
+    let expanded = layout::expand(flat, ".");
+    assert_eq!(
+        key_sequence_of(&Value::Object(expanded)),
+        key_sequence_of(&source)
+    );
+}
+
+/// Provider that "translates" by lowercasing every word but placeholder
+/// sentinels.
+struct LowercaseProvider;
+
+#[async_trait]
+impl TranslationProvider for LowercaseProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        _source_lang: &str,
+        _target_lang: &str,
+    ) -> BatchResult {
+        let lowercase = |word: &str| match word.starts_with("__") {
+            true => word.to_string(),
+            false => word.to_lowercase(),
+        };
+        Ok(phrases
+            .iter()
+            .map(|phrase| {
+                phrase
+                    .split(' ')
+                    .map(lowercase)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect())
+    }
+}
+
+#[tokio::test]
+async fn matching_case_restores_upper_and_title_case() {
+    assert_eq!(Casing::of("OK"), Casing::Upper);
+    assert_eq!(Casing::of("Save"), Casing::Title);
+    assert_eq!(Casing::of("A"), Casing::Title);
+    assert_eq!(Casing::of("Page 2 Of 10"), Casing::Title);
+    assert_eq!(Casing::of("Terms of Service"), Casing::Other);
+    assert_eq!(Casing::of("404"), Casing::Other);
+    assert_eq!(Casing::of("保存"), Casing::Other);
+    assert_eq!(Casing::Upper.apply("straße"), "STRASSE");
+
+    let cache = TranslationCache::default();
+    let mut options = TranslationOptions {
+        match_case: true,
+        ..options(&LowercaseProvider)
+    };
+    let phrases = || {
+        HashMap::from(
+            [
+                "SAVE {fileName}",
+                "Save File",
+                "Terms of Service",
+                "{Name} Joined",
+            ]
+            .map(|phrase| (phrase.to_string(), String::new())),
+        )
+    };
+
+    let mut translations = phrases();
+    let stats = utils::perform_translations(
+        &mut translations,
+        &[],
+        &options,
+        Some(&cache),
+        "de",
+        |_, _| {},
+    )
+    .await
+    .unwrap();
+    assert!(stats.failed.is_empty());
+    assert_eq!(translations["SAVE {fileName}"], "SAVE {fileName}");
+    assert_eq!(translations["Save File"], "Save File");
+    assert_eq!(translations["Terms of Service"], "terms of service");
+    assert_eq!(translations["{Name} Joined"], "{Name} Joined");
+    assert_eq!(cache.get("Save File", "de").unwrap(), "save file");
+
+    options.match_case = false;
+    let mut translations = phrases();
+    utils::perform_translations(
+        &mut translations,
+        &[],
+        &options,
+        Some(&cache),
+        "de",
+        |_, _| {},
+    )
+    .await
+    .unwrap();
+    assert_eq!(translations["Save File"], "save file");
+}
+
+#[test]
+fn samples_are_distinct_reproducible_and_in_source_order() {
+    let source = json!({
+        "menu": (0..50).map(|i| (format!("item{i}"), json!(format!("Item {i}")))).collect::<Map<_, _>>(),
+        "again": "Item 7",
+        "blank": "",
+    });
+    let filter = KeyFilter::default();
+
+    let sample = utils::sample_phrases(&source, &filter, 10, Some(42));
+    assert_eq!(sample.len(), 10);
+    assert_eq!(
+        sample,
+        utils::sample_phrases(&source, &filter, 10, Some(42))
+    );
+    assert_ne!(
+        sample,
+        utils::sample_phrases(&source, &filter, 10, Some(43))
+    );
+
+    let position = |phrase: &String| phrase[5..].parse::<usize>().unwrap();
+    assert!(
+        sample
+            .windows(2)
+            .all(|pair| position(&pair[0]) < position(&pair[1]))
+    );
+
+    let everything = utils::sample_phrases(&source, &filter, 100, None);
+    assert_eq!(everything.len(), 50);
+    assert_eq!(everything[49], "Item 49");
+}
+
+#[tokio::test]
+async fn selected_keys_are_translated_in_place() {
+    let provider = StubProvider::default();
+    let source = json!({
+        "title": "Jobs",
+        "status": {"active": "Active", "Draft": "Draft", "draft": "Draft", "old": "Old"},
+        "steps": [{"done": "Done"}],
+    });
+    let key_names = KeyFilter::new(&["status.*", "steps.*.*"], &[] as &[&str]);
+    let mut options = TranslationOptions {
+        translate_keys: Some(&key_names),
+        ..options(&provider)
+    };
+    // `old` was translated by hand on an earlier run.
+    let mut target = json!({"status": {"OLD": "Alt"}})
+        .as_object()
+        .unwrap()
+        .clone();
+
+    let summary = translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+
+    // `Draft` and `draft` would both become `DRAFT`, so both keep their name.
+    assert_eq!(
+        Value::Object(target.clone()),
+        json!({
+            "title": "JOBS",
+            "status": {"ACTIVE": "ACTIVE", "Draft": "DRAFT", "draft": "DRAFT", "OLD": "Alt"},
+            "steps": [{"DONE": "DONE"}],
+        })
+    );
+    assert_eq!(
+        key_sequence_of(&Value::Object(target.clone())),
+        key_sequence_of(&json!({
+            "title": "",
+            "status": {"ACTIVE": "", "Draft": "", "draft": "", "OLD": ""},
+            "steps": [{"DONE": ""}],
+        }))
+    );
+    assert!(summary.key_translations.is_some());
+
+    options.translate_keys = None;
+    let mut untouched = Map::new();
+    let summary = translate_json(&source, &mut untouched, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+    assert_eq!(untouched["status"]["active"], "ACTIVE");
+    assert_eq!(summary.key_translations, None);
+}
+
+#[tokio::test]
+async fn byte_order_marks_are_skipped_and_never_written() {
+    let dir = temp_dir("bom");
+    let source_path = dir.join("en.json");
+    let target_path = dir.join("de.yaml");
+    fs::write(
+        &source_path,
+        "\u{feff}{\"title\": \"Welcome\", \"menu\": {\"file\": \"File\"}}",
+    )
+    .unwrap();
+    fs::write(&target_path, "\u{feff}title: Willkommen\n").unwrap();
+
+    let provider = StubProvider::default();
+    let options = options(&provider);
+    translate_file(&source_path, &target_path, "de", &options)
+        .await
+        .unwrap();
+
+    let written = fs::read_to_string(&target_path).unwrap();
+    assert_eq!(written, "title: Willkommen\nmenu:\n  file: FILE\n");
+
+    let bom = "\u{feff}# Labels\ngreeting=Hello\n";
+    let parsed = Format::Properties.parse(bom).unwrap();
+    assert_eq!(parsed, json!({"greeting": "Hello"}));
+    assert_eq!(
+        properties::serialize(parsed.as_object().unwrap(), Some(bom)),
+        "# Labels\ngreeting=Hello\n"
+    );
+    assert!(po::parse("\u{feff}msgid \"Save\"\nmsgstr \"Speichern\"\n").contains_key("Save"));
+}
+
+#[tokio::test]
+async fn failed_batches_are_sent_to_the_fallback_providers() {
+    let provider = FallbackProvider::new("failing", Box::new(FailingProvider))
+        .with_fallback("stub", Box::new(StubProvider::default()));
+    let results: Vec<_> = translate_stream(
+        &provider,
+        vec!["hello".to_string(), "world".to_string()],
+        "en".to_string(),
+        "de".to_string(),
+        10,
+        usize::MAX,
+        1,
+        None,
+    )
+    .collect()
+    .await;
+
+    let translated: Vec<_> = results
+        .into_iter()
+        .map(|(phrase, result)| (phrase, result.unwrap()))
+        .collect();
+    assert_eq!(
+        translated,
+        [
+            ("hello".to_string(), "HELLO".to_string()),
+            ("world".to_string(), "WORLD".to_string())
+        ]
+    );
+    assert_eq!(
+        provider.served(),
+        [("failing".to_string(), 0), ("stub".to_string(), 2)]
+    );
+
+    let provider = FallbackProvider::new("failing", Box::new(FailingProvider))
+        .with_fallback("failing again", Box::new(FailingProvider));
+    let results: Vec<_> = translate_stream(
+        &provider,
+        vec!["hello".to_string()],
+        "en".to_string(),
+        "de".to_string(),
+        10,
+        usize::MAX,
+        1,
+        None,
+    )
+    .collect()
+    .await;
+    assert!(results[0].1.is_err());
+}
+
+#[tokio::test]
+async fn cached_batches_are_replayed_without_calling_the_provider() {
+    let dir = temp_dir("batches");
+    let phrases = vec!["hello".to_string(), "world".to_string()];
+
+    let provider = CachedProvider::new("stub", Box::new(StubProvider::default()), &dir);
+    let translated = provider
+        .translate_batch(&phrases, "en", "de")
+        .await
+        .unwrap();
+    assert_eq!(translated, ["HELLO", "WORLD"]);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    let provider = CachedProvider::new("stub", Box::new(FailingProvider), &dir);
+    let replayed = provider
+        .translate_batch(&phrases, "en", "de")
+        .await
+        .unwrap();
+    assert_eq!(replayed, translated);
+    assert!(
+        provider
+            .translate_batch(&phrases, "en", "fr")
+            .await
+            .is_err()
+    );
+    assert!(
+        provider
+            .translate_batch(&phrases[..1], "en", "de")
+            .await
+            .is_err()
+    );
+
+    let provider = CachedProvider::new("other", Box::new(FailingProvider), &dir);
+    assert!(
+        provider
+            .translate_batch(&phrases, "en", "de")
+            .await
+            .is_err()
+    );
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+}
+
+#[tokio::test]
+async fn csv_columns_are_translated_in_place() {
+    let contents = "key,en,de,note\n\
+                    greeting,\"Hello, world\",,\"say \"\"hi\"\"\"\n\
+                    farewell,Goodbye,Tschüss,\n\
+                    blank,,,x\n";
+    let mut rows = Format::Csv
+        .parse(contents)
+        .unwrap()
+        .as_object()
+        .unwrap()
+        .clone();
+    let source = Value::Object(csv::column(&rows, "en").unwrap());
+    let mut target = csv::column(&rows, "de").unwrap();
+    assert_eq!(
+        source,
+        json!({"greeting": "Hello, world", "farewell": "Goodbye"})
+    );
+
+    let provider = StubProvider::default();
+    let options = options(&provider);
+    translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+    csv::set_column(&mut rows, "de", &target);
+    csv::set_column(&mut rows, "fr", &Map::new());
+
+    assert_eq!(*provider.batches.lock().unwrap(), [["Hello, world"]]);
+    assert_eq!(
+        csv::serialize(&rows).unwrap(),
+        "key,en,de,note,fr\n\
+         greeting,\"Hello, world\",\"HELLO, WORLD\",\"say \"\"hi\"\"\",\n\
+         farewell,Goodbye,Tschüss,,\n\
+         blank,,,x,\n"
+    );
+
+    assert!(matches!(
+        csv::column(&rows, "pl"),
+        Err(FormatError::MissingColumn(column)) if column == "pl"
+    ));
+    assert!(matches!(
+        csv::parse("key,en\nsave,Save\nsave,Store\n"),
+        Err(FormatError::DuplicateKey(key)) if key == "save"
+    ));
+    assert!(matches!(
+        csv::parse("key,en\nsave,Save,extra\n"),
+        Err(FormatError::Csv(_))
+    ));
+}
+
+#[tokio::test]
+async fn source_directories_are_translated_file_by_file() {
+    let dir = temp_dir("source-dir");
+    let source_dir = dir.join("en");
+    fs::create_dir_all(source_dir.join("billing")).unwrap();
+    fs::create_dir_all(source_dir.join(".git")).unwrap();
+    fs::write(source_dir.join("common.json"), r#"{"save": "Save"}"#).unwrap();
+    fs::write(
+        source_dir.join("billing/invoices.yaml"),
+        "title: Invoices\n",
+    )
+    .unwrap();
+    fs::write(source_dir.join("broken.json"), "{").unwrap();
+    fs::write(source_dir.join("notes.txt"), "Save").unwrap();
+    fs::write(source_dir.join(".git/config.json"), "{}").unwrap();
+
+    let files = files::source_files(&source_dir).unwrap();
+    assert_eq!(
+        files,
+        [
+            PathBuf::from("billing/invoices.yaml"),
+            PathBuf::from("broken.json"),
+            PathBuf::from("common.json")
+        ]
+    );
+
+    let jobs: Vec<(PathBuf, PathBuf)> = files
+        .iter()
+        .map(|file| (source_dir.join(file), dir.join("de").join(file)))
+        .collect();
+    let provider = StubProvider::default();
+    let options = options(&provider);
+    let cache = TranslationCache::default();
+    let results = translate_files(&jobs, "de", &options, Some(&cache), 2).await;
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap().translated, 1);
+    assert!(matches!(
+        results[1],
+        Err(QTranslateError::InvalidFile { .. })
+    ));
+    assert_eq!(results[2].as_ref().unwrap().translated, 1);
+    assert_eq!(
+        fs::read_to_string(dir.join("de/billing/invoices.yaml")).unwrap(),
+        "title: INVOICES\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("de/common.json")).unwrap(),
+        "{\n  \"save\": \"SAVE\"\n}\n"
+    );
+    assert!(!dir.join("de/broken.json").exists());
+    assert_eq!(cache.get("Save", "de").as_deref(), Some("SAVE"));
+}
+
+#[tokio::test]
+async fn source_directories_reject_the_flags_of_single_target_files() {
+    let dir = temp_dir("cli-source-dir-flags");
+    fs::create_dir_all(dir.join("en")).unwrap();
+    fs::write(dir.join("en/common.json"), r#"{"save": "Save"}"#).unwrap();
+
+    for flag in [
+        &["--sort-keys"][..],
+        &["--indent", "4"],
+        &["--output-style", "compact"],
+        &["--flatten"],
+        &["--expand"],
+        &["--no-final-newline"],
+        &["--backup"],
+        &["--backup-dir", "backups"],
+        &["--hashes-path", "hashes.json"],
+        &["--context-file", "notes.json"],
+    ] {
+        let args = [&["--target-lang", "de", "--source-dir", "en"][..], flag].concat();
+        let output = run_cli(&dir, "http://127.0.0.1:9", &args, &[], None).await;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(64), "{flag:?}: {stderr}");
+        assert!(stderr.contains("cannot be used with"), "{stderr}");
+        assert!(!dir.join("de").exists());
+    }
+}
+
+#[test]
+fn jsonc_comments_are_carried_over_to_their_keys() {
+    let source = r#"// Strings of the login page
+{
+  // Shown above the form
+  "title": "Welcome", // keep it short
+  "form": { /* fields */
+    "email": "E-mail",
+    /*
+     * Never translate "password" literally.
+     */
+    "password": "Password",
+  },
+  "hints": ["// not a comment", "Forgot it?"],
+}
+"#;
+    let value = Format::Jsonc.parse(source).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "title": "Welcome",
+            "form": {"email": "E-mail", "password": "Password"},
+            "hints": ["// not a comment", "Forgot it?"]
+        })
+    );
+
+    let target = json!({
+        "title": "Willkommen",
+        "form": {"email": "E-Mail", "password": "Passwort", "extra": "Neu"},
+        "hints": ["// kein Kommentar", "Vergessen?"]
+    });
+    let written = jsonc::serialize(&target, Some(source), JsonStyle::default()).unwrap();
+    assert_eq!(
+        written,
+        r#"// Strings of the login page
+{
+  // Shown above the form
+  "title": "Willkommen", // keep it short
+  "form": { /* fields */
+    "email": "E-Mail",
+    /*
+     * Never translate "password" literally.
+     */
+    "password": "Passwort",
+    "extra": "Neu"
+  },
+  "hints": [
+    "// kein Kommentar",
+    "Vergessen?"
+  ]
+}
+"#
+    );
+    assert_eq!(
+        jsonc::serialize(&target, Some(&written), JsonStyle::default()).unwrap(),
+        written
+    );
+
+    // Without comments, the layout is that of plain JSON.
+    let style = JsonStyle::Pretty { indent: 4 };
+    assert_eq!(
+        Format::Jsonc.serialize_with(&target, style).unwrap(),
+        Format::Json.serialize_with(&target, style).unwrap()
+    );
+    assert_eq!(Format::from_path("strings.jsonc"), Some(Format::Jsonc));
+}
+
+#[tokio::test]
+async fn termbase_terms_get_their_forced_translation() {
+    let dir = temp_dir("termbase");
+    let path = dir.join("termbase-de.json");
+    fs::write(
+        &path,
+        r#"{"cart": "Warenkorb", "check out": "zur Kasse gehen"}"#,
+    )
+    .unwrap();
+    let terms = read_termbase(&path).unwrap();
+    let placeholders = Placeholders::default()
+        .with_glossary(&["Qumo"], true)
+        .unwrap()
+        .with_termbase("de", &terms)
+        .unwrap();
+
+    let source = json!({
+        "empty": "Your cart is empty",
+        "title": "Cart",
+        "count": "{n} items in your Qumo cart",
+        "button": "Check out",
+    });
+    let provider = StubProvider::default();
+    let options = TranslationOptions {
+        placeholders: &placeholders,
+        ..options(&provider)
+    };
+
+    let mut german = Map::new();
+    translate_json(&source, &mut german, "de-AT", &options, None, |_, _| {})
+        .await
+        .unwrap();
+    assert_eq!(
+        Value::Object(german),
+        json!({
+            "empty": "YOUR Warenkorb IS EMPTY",
+            "title": "Warenkorb",
+            "count": "{n} ITEMS IN YOUR Qumo Warenkorb",
+            "button": "Zur Kasse gehen",
+        })
+    );
+
+    let mut french = Map::new();
+    translate_json(&source, &mut french, "fr", &options, None, |_, _| {})
+        .await
+        .unwrap();
+    assert_eq!(french["empty"], "YOUR CART IS EMPTY");
+
+    fs::write(&path, r#"{"cart": 1}"#).unwrap();
+    assert!(read_termbase(&path).is_err());
+}
+
+#[test]
+fn incomplete_translations_exit_with_their_own_code() {
+    assert_eq!(QTranslateError::Incomplete { missing: 3 }.exit_code(), 2);
+    assert_eq!(QTranslateError::PhrasesFailed { failed: 3 }.exit_code(), 1);
+    let timed_out = QTranslateError::TimedOut {
+        secs: 5,
+        done: 2,
+        total: 6,
+    };
+    assert_eq!(timed_out.exit_code(), 1);
+    assert_eq!(
+        QTranslateError::TargetsFailed {
+            failed: 1,
+            total: 2
+        }
+        .exit_code(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn flat_maps_are_translated_by_their_keys() {
+    let input = HashMap::from([
+        ("product.1.name".to_string(), "Blue mug".to_string()),
+        ("product.2.name".to_string(), "Blue mug".to_string()),
+        ("product.3.name".to_string(), "Red plate".to_string()),
+        ("product.4.name".to_string(), "{name}".to_string()),
+    ]);
+    let provider = StubProvider::default();
+    let mut options = TranslationOptions {
+        on_error: OnError::Skip,
+        ..options(&provider)
+    };
+
+    let translated = translate_map(input.clone(), "de", &options).await.unwrap();
+    assert_eq!(
+        translated,
+        HashMap::from([
+            ("product.1.name".to_string(), "BLUE MUG".to_string()),
+            ("product.2.name".to_string(), "BLUE MUG".to_string()),
+            ("product.3.name".to_string(), "RED PLATE".to_string()),
+            ("product.4.name".to_string(), "{name}".to_string()),
+        ])
+    );
+    let mut sent = provider.batches.lock().unwrap().concat();
+    sent.sort();
+    assert_eq!(sent, ["Blue mug", "Red plate"]);
+
+    options.provider = &FailingProvider;
+    let translated = translate_map(input.clone(), "de", &options).await.unwrap();
+    assert_eq!(translated.len(), 1);
+
+    options.on_error = OnError::Fail;
+    assert!(matches!(
+        translate_map(input, "de", &options).await,
+        Err(QTranslateError::Translation(_))
+    ));
+}
+
+#[test]
+fn files_are_read_as_they_were_in_a_git_revision() {
+    let dir = temp_dir("git_revision");
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .current_dir(&dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success());
+    };
+    git(&["init", "--quiet"]);
+    fs::write(dir.join("en.json"), r#"{"title": "Hello"}"#).unwrap();
+    git(&["add", "en.json"]);
+    git(&["commit", "--quiet", "-m", "Add en.json"]);
+    fs::write(dir.join("en.json"), r#"{"title": "Hello!"}"#).unwrap();
+
+    assert_eq!(
+        git::read_at_revision("HEAD", dir.join("en.json")).unwrap(),
+        Some(r#"{"title": "Hello"}"#.to_string())
+    );
+    assert_eq!(
+        git::read_at_revision("HEAD", dir.join("de.json")).unwrap(),
+        None
+    );
+    assert!(matches!(
+        git::read_at_revision("no-such-branch", dir.join("en.json")),
+        Err(QTranslateError::InvalidRevision { .. })
+    ));
+}
+
+#[test]
+fn invalid_proxy_urls_are_rejected() {
+    // A valid URL would route the requests of every other test through it.
+    assert!(matches!(
+        set_proxy("::not a url"),
+        Err(QTranslateError::InvalidProxy { .. })
+    ));
+}
+
+/// Provider that is rate limited on the first batch, asking to retry after
+/// `retry_after`, and uppercases the phrases afterwards.
+struct ThrottledProvider {
+    retry_after: Option<Duration>,
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl TranslationProvider for ThrottledProvider {
+    async fn translate_batch(
+        &self,
+        phrases: &[String],
+        _source_lang: &str,
+        _target_lang: &str,
+    ) -> BatchResult {
+        match self.calls.fetch_add(1, Ordering::SeqCst) {
+            0 => Err(TranslateError::Api {
+                status: 429,
+                body: "Rate limit".to_string(),
+                retry_after: self.retry_after,
+            }),
+            _ => Ok(phrases.iter().map(|phrase| phrase.to_uppercase()).collect()),
+        }
+    }
+}
+
+#[tokio::test]
+async fn retries_wait_as_long_as_the_retry_after_header_asks() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/translate", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut socket = BufReader::new(socket);
+        read_request(&mut socket).await.unwrap();
+        let body = r#"{"error": {"code": 429, "message": "Rate limit"}}"#;
+        let reply = format!(
+            "HTTP/1.1 429 Too Many Requests\r\nretry-after: 7\r\ncontent-length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        socket.write_all(reply.as_bytes()).await.unwrap();
+    });
+    let err = GoogleProvider::new("key")
+        .with_endpoint(url)
+        .translate_batch(&["Hello".to_string()], "en", "de")
+        .await
+        .unwrap_err();
+    assert_eq!(err.retry_after(), Some(Duration::from_secs(7)));
+
+    for (retry_after, min, max) in [
+        (
+            Some(Duration::ZERO),
+            Duration::ZERO,
+            Duration::from_millis(300),
+        ),
+        (
+            Some(Duration::from_secs(1)),
+            Duration::from_secs(1),
+            Duration::from_secs(2),
+        ),
+        (None, Duration::from_millis(500), Duration::from_secs(1)),
+    ] {
+        let provider = ThrottledProvider {
+            retry_after,
+            calls: AtomicUsize::new(0),
+        };
+        let started = Instant::now();
+        let results: Vec<_> = translate_stream(
+            &provider,
+            vec!["hello".to_string()],
+            "en".to_string(),
+            "de".to_string(),
+            10,
+            usize::MAX,
+            1,
+            None,
+        )
+        .collect()
+        .await;
+        let elapsed = started.elapsed();
+
+        assert_eq!(results[0].1.as_deref().unwrap(), "HELLO");
+        assert!(
+            elapsed >= min && elapsed < max,
+            "{retry_after:?}: {elapsed:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn whitespace_is_normalized_before_translating_on_request() {
+    let source = json!({
+        "save": "Save  changes",
+        "save_again": "Save\tchanges",
+        "plain": "Save changes",
+        "padded": " Loading  data ",
+        "notice": "First  line\n\n  Second line",
+    });
+    let provider = StubProvider::default();
+    let mut options = TranslationOptions {
+        normalize_whitespace: true,
+        ..options(&provider)
+    };
+
+    let mut target = Map::new();
+    let summary = translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+    assert_eq!(
+        Value::Object(target),
+        json!({
+            "save": "SAVE CHANGES",
+            "save_again": "SAVE CHANGES",
+            "plain": "SAVE CHANGES",
+            "padded": " LOADING DATA ",
+            "notice": "FIRST LINE SECOND LINE",
+        })
+    );
+    assert_eq!(summary.translated, 5);
+    let mut sent = provider.batches.lock().unwrap().concat();
+    sent.sort();
+    assert_eq!(
+        sent,
+        ["First line Second line", "Loading data", "Save changes"]
+    );
+
+    options.preserve_newlines = true;
+    let mut target = Map::new();
+    translate_json(&source, &mut target, "de", &options, None, |_, _| {})
+        .await
+        .unwrap();
+    assert_eq!(target["notice"], "FIRST LINE\n\n  SECOND LINE");
+}
+
+/// Runs the CLI with `--diff` on a fresh German target of a single key and
+/// returns what it printed.
+async fn diff_output(dir: &Path, url: &str, args: &[&str], env: &[(&str, &str)]) -> String {
+    let _ = fs::remove_file(dir.join("de.json"));
+    let diff = [
+        "--target-lang",
+        "de",
+        "--assets-dir",
+        ".",
+        "--no-cache",
+        "--no-hashes",
+        "--diff",
+    ];
+    let args: Vec<&str> = diff.iter().chain(args).copied().collect();
+    let output = run_cli(dir, url, &args, env, None).await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[tokio::test]
+async fn diffs_are_colored_in_terminals_and_with_pretty_diff() {
+    let server = serve_google(0, Duration::ZERO).await;
+    let dir = temp_dir("cli-colors");
+    // Empty lines in a key used to crash the colored diff.
+    let source = r#"{"greeting": "hello", "line\n\nbreak": "x"}"#;
+    fs::write(dir.join("en.json"), source).unwrap();
+    let terminal = [("IGNORE_IS_TERMINAL", "1"), ("TERM", "xterm")];
+    let green = "\u{1b}[32m+ greeting: \"HELLO\"";
+
+    let piped = diff_output(&dir, &server.url, &[], &[]).await;
+    assert!(piped.contains("  + greeting: \"HELLO\""), "{piped}");
+    assert!(!piped.contains('\u{1b}'), "{piped}");
+
+    let colored = diff_output(&dir, &server.url, &[], &terminal).await;
+    assert!(colored.contains(green), "{colored}");
+
+    let no_color = [terminal[0], terminal[1], ("NO_COLOR", "1")];
+    let plain = diff_output(&dir, &server.url, &[], &no_color).await;
+    assert!(!plain.contains('\u{1b}'), "{plain}");
+
+    let pretty = diff_output(&dir, &server.url, &["--pretty-diff"], &[]).await;
+    assert!(pretty.contains(green), "{pretty}");
+
+    let pretty_no_color =
+        diff_output(&dir, &server.url, &["--pretty-diff"], &[("NO_COLOR", "1")]).await;
+    assert!(!pretty_no_color.contains('\u{1b}'), "{pretty_no_color}");
+}
+
+#[test]
+fn key_changes_print_as_diff_lines() {
+    let before = json!({"changed": "a", "removed": "b", "line\n\nbreak": "c"});
+    let after = json!({"changed": "A", "added": "d"});
+    let diff = TargetDiff::new(
+        before.as_object().unwrap().clone(),
+        after.as_object().unwrap().clone(),
+    );
+
+    assert_eq!(diff.added[0].to_string(), "+ added: \"d\"");
+    assert_eq!(diff.changed[0].to_string(), "~ changed: \"a\" -> \"A\"");
+    assert_eq!(diff.removed[0].to_string(), "- removed: \"b\"");
+    assert_eq!(diff.removed[1].to_string(), "- line\n\nbreak: \"c\"");
+}
+
+#[tokio::test]
+async fn targets_with_an_object_in_place_of_a_string_are_invalid() {
+    let source = json!({"menu": {"title": "x"}});
+    let target = json!({"menu": {"title": {"nested": "y"}}});
+    let mut translations = HashMap::new();
+    let err = utils::gather_translations(
+        &source,
+        target.as_object().unwrap(),
+        &mut translations,
+        false,
+        &KeyFilter::default(),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(&err, FormatError::NotAString(path) if path == "menu.title"),
+        "{err}"
+    );
+
+    let provider = StubProvider::default();
+    let mut target = target.as_object().unwrap().clone();
+    let err = translate_json(
+        &source,
+        &mut target,
+        "de",
+        &options(&provider),
+        None,
+        |_, _| {},
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        matches!(&err, QTranslateError::InvalidTarget { lang, .. } if lang == "de"),
+        "{err}"
+    );
+    assert_eq!(
+        err.to_string(),
+        "Invalid target for de: expected a string at menu.title, as in the source file"
+    );
+    assert_eq!(err.exit_code(), 4);
+
+    let server = serve_google(0, Duration::ZERO).await;
+    let dir = temp_dir("cli-invalid-target");
+    fs::write(dir.join("en.json"), r#"{"a": "x"}"#).unwrap();
+    fs::write(dir.join("de.json"), r#"{"a": {"b": "y"}}"#).unwrap();
+    // A file of its own is reported by its path.
+    let (source_path, target_path) = (dir.join("en.json"), dir.join("de.json"));
+    let err = translate_file(&source_path, &target_path, "de", &options(&provider))
+        .await
+        .unwrap_err();
+    let path = target_path.display().to_string();
+    assert!(
+        matches!(&err, QTranslateError::InvalidFile { path: p, .. } if *p == path),
+        "{err}"
+    );
+    let args = [
+        "--target-lang",
+        "de",
+        "--assets-dir",
+        ".",
+        "--no-cache",
+        "--no-hashes",
+    ];
+    let output = run_cli(&dir, &server.url, &args, &[], None).await;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{stderr}");
+    assert!(
+        stderr.contains("Invalid target for de: expected a string at a, as in the source file"),
+        "{stderr}"
+    );
+}
+
+#[tokio::test]
+async fn stdin_is_translated_to_stdout_with_status_on_stderr() {
+    let server = serve_google(0, Duration::ZERO).await;
+    let dir = temp_dir("cli-stdin");
+    let source = r#"{"greeting": "Hello", "menu": {"open": "Open"}}"#;
+    let args = ["--target-lang", "de", "--stdin", "--no-cache"];
+    let output = run_cli(&dir, &server.url, &args, &[], Some(source)).await;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    let translated: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        translated,
+        json!({"greeting": "HELLO", "menu": {"open": "OPEN"}})
+    );
+    assert!(
+        stderr.contains("Total: 2 keys, 2 strings translated"),
+        "{stderr}"
+    );
+    // Nothing is read from or written to the assets directory.
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn stdout_prints_the_target_file_instead_of_writing_it() {
+    let server = serve_google(0, Duration::ZERO).await;
+    let dir = temp_dir("cli-stdout");
+    fs::write(
+        dir.join("en.json"),
+        r#"{"greeting": "Hello", "bye": "Bye"}"#,
+    )
+    .unwrap();
+    fs::write(dir.join("de.json"), r#"{"bye": "Tschüss"}"#).unwrap();
+    let args = [
+        "--target-lang",
+        "de",
+        "--assets-dir",
+        ".",
+        "--stdout",
+        "--no-cache",
+        "--no-hashes",
+    ];
+    let output = run_cli(&dir, &server.url, &args, &[], None).await;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    let translated: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(translated, json!({"greeting": "HELLO", "bye": "Tschüss"}));
+    assert!(
+        stderr.contains("Total: 2 keys, 1 strings translated"),
+        "{stderr}"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("de.json")).unwrap(),
+        r#"{"bye": "Tschüss"}"#
+    );
+}
+
+#[tokio::test]
+async fn output_is_written_outside_the_assets_directory() {
+    let server = serve_google(0, Duration::ZERO).await;
+    let dir = temp_dir("cli-output-assets");
+    let preview = temp_dir("cli-output-preview").join("de.json");
+    fs::write(
+        dir.join("en.json"),
+        r#"{"greeting": "Hello", "bye": "Bye"}"#,
+    )
+    .unwrap();
+    fs::write(dir.join("de.json"), r#"{"bye": "Tschüss"}"#).unwrap();
+    let args = [
+        "--target-lang",
+        "de",
+        "--assets-dir",
+        ".",
+        "--output",
+        preview.to_str().unwrap(),
+        "--no-cache",
+        "--no-hashes",
+    ];
+    let output = run_cli(&dir, &server.url, &args, &[], None).await;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    let written: Value = serde_json::from_str(&fs::read_to_string(&preview).unwrap()).unwrap();
+    assert_eq!(written, json!({"greeting": "HELLO", "bye": "Tschüss"}));
+    assert_eq!(
+        fs::read_to_string(dir.join("de.json")).unwrap(),
+        r#"{"bye": "Tschüss"}"#
+    );
+}
+
+#[tokio::test]
+async fn output_directory_is_only_created_with_mkdir() {
+    let server = serve_google(0, Duration::ZERO).await;
+    let dir = temp_dir("cli-output-mkdir");
+    fs::write(dir.join("en.json"), r#"{"greeting": "Hello"}"#).unwrap();
+    let args = [
+        "--target-lang",
+        "de",
+        "--assets-dir",
+        ".",
+        "--output",
+        "preview/nested/de.json",
+        "--no-cache",
+        "--no-hashes",
+    ];
+
+    let output = run_cli(&dir, &server.url, &args, &[], None).await;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(6), "{stderr}");
+    assert!(
+        stderr.contains(
+            "directory preview/nested of --output does not exist; pass --mkdir to create it"
+        ),
+        "{stderr}"
+    );
+    assert!(!dir.join("preview").exists());
+    assert!(server.requests.lock().unwrap().is_empty());
+
+    let args = [&args[..], &["--mkdir"]].concat();
+    let output = run_cli(&dir, &server.url, &args, &[], None).await;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    let written = fs::read_to_string(dir.join("preview/nested/de.json")).unwrap();
+    assert_eq!(
+        serde_json::from_str::<Value>(&written).unwrap(),
+        json!({"greeting": "HELLO"})
+    );
+    assert!(!dir.join("de.json").exists());
+}
+
+#[tokio::test]
+async fn only_missing_keeps_existing_values_and_all_retranslates_them() {
+    let server = serve_google(0, Duration::ZERO).await;
+    let dir = temp_dir("cli-modes");
+    fs::write(
+        dir.join("en.json"),
+        r#"{"greeting": "Hello", "bye": "Bye"}"#,
+    )
+    .unwrap();
+    let cases = [
+        (
+            None,
+            json!(["Hello"]),
+            json!({"greeting": "HELLO", "bye": "Tschüss"}),
+            "only missing phrases",
+        ),
+        (
+            Some("--only-missing"),
+            json!(["Hello"]),
+            json!({"greeting": "HELLO", "bye": "Tschüss"}),
+            "only missing phrases",
+        ),
+        (
+            Some("--all"),
+            json!(["Bye", "Hello"]),
+            json!({"greeting": "HELLO", "bye": "BYE"}),
+            "all phrases, overwriting existing translations",
+        ),
+        (
+            Some("--overwrite"),
+            json!(["Bye", "Hello"]),
+            json!({"greeting": "HELLO", "bye": "BYE"}),
+            "all phrases, overwriting existing translations",
+        ),
+    ];
+
+    for (flag, sent, expected, mode) in cases {
+        fs::write(dir.join("de.json"), r#"{"bye": "Tschüss"}"#).unwrap();
+        server.requests.lock().unwrap().clear();
+        let mut args = vec![
+            "--target-lang",
+            "de",
+            "--assets-dir",
+            ".",
+            "--no-cache",
+            "--no-hashes",
+        ];
+        args.extend(flag);
+        let output = run_cli(&dir, &server.url, &args, &[], None).await;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{flag:?}: {stderr}");
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1, "{flag:?}");
+        assert_eq!(requests[0]["q"], sent, "{flag:?}");
+        let written = fs::read_to_string(dir.join("de.json")).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&written).unwrap(),
+            expected,
+            "{flag:?}"
+        );
+        assert!(
+            stdout.contains(&format!("Translated 1 of 1 target languages, {mode}.")),
+            "{stdout}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn proxy_environment_variables_are_honored() {
+    let server = serve_google(0, Duration::ZERO).await;
+    let proxy = serve_proxy(&server.url).await;
+    let dir = temp_dir("cli-proxy-env");
+    let args = ["--target-lang", "de", "--stdin", "--no-cache"];
+    let source = r#"{"greeting": "Hello"}"#;
+
+    for name in ["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"] {
+        proxy.requests.lock().unwrap().clear();
+        let env = [(name, proxy.url.as_str())];
+        let output = run_cli(&dir, &server.url, &args, &env, Some(source)).await;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{name}: {stderr}");
+        assert_eq!(
+            serde_json::from_slice::<Value>(&output.stdout).unwrap(),
+            json!({"greeting": "HELLO"})
+        );
+        assert_eq!(
+            *proxy.requests.lock().unwrap(),
+            [format!("POST {}", server.url)],
+            "{name}"
+        );
+    }
+
+    // Hosts listed in `NO_PROXY` are reached directly.
+    proxy.requests.lock().unwrap().clear();
+    let env = [("ALL_PROXY", proxy.url.as_str()), ("NO_PROXY", "127.0.0.1")];
+    let output = run_cli(&dir, &server.url, &args, &env, Some(source)).await;
+    assert!(output.status.success());
+    assert!(proxy.requests.lock().unwrap().is_empty());
+
+    // HTTPS endpoints are reached through a tunnel of the `HTTPS_PROXY`,
+    // which the mock proxy refuses.
+    let env = [("HTTPS_PROXY", proxy.url.as_str())];
+    let endpoint = "https://127.0.0.1:9/language/translate/v2";
+    let output = run_cli(&dir, endpoint, &args, &env, Some(source)).await;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("de: failed to translate greeting"),
+        "{stderr}"
+    );
+    let requests = proxy.requests.lock().unwrap();
+    assert_eq!(requests[0], "CONNECT 127.0.0.1:9");
+}
+
+#[tokio::test]
+async fn proxy_flag_overrides_the_proxy_environment_variables() {
+    let server = serve_google(0, Duration::ZERO).await;
+    let proxy = serve_proxy(&server.url).await;
+    let ignored = serve_proxy(&server.url).await;
+    let dir = temp_dir("cli-proxy-flag");
+    let args = [
+        "--target-lang",
+        "de",
+        "--stdin",
+        "--no-cache",
+        "--proxy",
+        &proxy.url,
+    ];
+    let env = [
+        ("HTTP_PROXY", ignored.url.as_str()),
+        ("ALL_PROXY", ignored.url.as_str()),
+    ];
+    let source = r#"{"greeting": "Hello"}"#;
+    let output = run_cli(&dir, &server.url, &args, &env, Some(source)).await;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert_eq!(
+        serde_json::from_slice::<Value>(&output.stdout).unwrap(),
+        json!({"greeting": "HELLO"})
+    );
+    assert_eq!(
+        *proxy.requests.lock().unwrap(),
+        [format!("POST {}", server.url)]
+    );
+    assert!(ignored.requests.lock().unwrap().is_empty());
+
+    // `NO_PROXY` still applies to the proxy of `--proxy`.
+    proxy.requests.lock().unwrap().clear();
+    let env = [("NO_PROXY", "127.0.0.1")];
+    let output = run_cli(&dir, &server.url, &args, &env, Some(source)).await;
+    assert!(output.status.success());
+    assert!(proxy.requests.lock().unwrap().is_empty());
+}